@@ -0,0 +1,45 @@
+#!/usr/bin/env python3
+"""Generates src/auditwheel/stable-abi.json from CPython's Misc/stable_abi.toml
+
+Usage: python ci/update_stable_abi.py [path or url of stable_abi.toml]
+"""
+
+import json
+import sys
+import tomllib
+import urllib.request
+from pathlib import Path
+
+STABLE_ABI_TOML = (
+    "https://raw.githubusercontent.com/python/cpython/main/Misc/stable_abi.toml"
+)
+# Symbols that only debug builds of libpython export
+DEBUG_BUILD_IFDEFS = {"Py_REF_DEBUG", "Py_TRACE_REFS"}
+
+
+def main():
+    source = sys.argv[1] if len(sys.argv) > 1 else STABLE_ABI_TOML
+    if source.startswith("https://"):
+        with urllib.request.urlopen(source) as response:
+            manifest = tomllib.loads(response.read().decode())
+    else:
+        manifest = tomllib.loads(Path(source).read_text())
+
+    versions = {}
+    # Only functions and data are symbols, the other kinds are macros, structs and constants
+    for kind in ["function", "data"]:
+        for name, item in manifest.get(kind, {}).items():
+            if item.get("ifdef") in DEBUG_BUILD_IFDEFS:
+                continue
+            versions.setdefault(item["added"], []).append(name)
+
+    versions = {
+        version: sorted(versions[version])
+        for version in sorted(versions, key=lambda v: tuple(map(int, v.split("."))))
+    }
+    target = Path(__file__).parent.parent.joinpath("src/auditwheel/stable-abi.json")
+    target.write_text(json.dumps(versions, indent=4) + "\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,10 +1,11 @@
 use super::musllinux::{find_musl_libc, get_musl_version};
-use super::policy::{Policy, MANYLINUX_POLICIES, MUSLLINUX_POLICIES};
+use super::policy::{AuditPolicies, Policy};
 use crate::auditwheel::{find_external_libs, PlatformTag};
 use crate::compile::BuildArtifact;
-use crate::target::Target;
+use crate::target::{Arch, Target};
 use anyhow::{bail, Context, Result};
 use fs_err::File;
+use glob::Pattern;
 use goblin::elf::{sym::STT_FUNC, Elf};
 use lddtree::Library;
 use once_cell::sync::Lazy;
@@ -116,21 +117,42 @@ fn find_incompliant_symbols(
     Ok(symbols)
 }
 
+/// Everything in an elf file that keeps it from complying with a policy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyViolations {
+    /// Linked libraries that aren't whitelisted
+    pub forbidden_libraries: Vec<String>,
+    /// Whitelisted libraries with the symbol versions that are too new, e.g. `GLIBC_2.27`
+    pub too_new_versions: Vec<(String, Vec<String>)>,
+    /// Whitelisted libraries with the blacklisted symbols that are used
+    pub blacklisted_symbols: Vec<(String, Vec<String>)>,
+}
+
+impl PolicyViolations {
+    /// Returns `true` if the policy is satisfied
+    pub fn is_empty(&self) -> bool {
+        self.forbidden_libraries.is_empty()
+            && self.too_new_versions.is_empty()
+            && self.blacklisted_symbols.is_empty()
+    }
+}
+
+/// Collects all violations of a policy, fails only if the architecture isn't covered by it
 #[allow(clippy::result_large_err)]
-fn policy_is_satisfied(
+pub(crate) fn policy_violations(
     policy: &Policy,
     elf: &Elf,
     arch: &str,
     deps: &[String],
     versioned_libraries: &[VersionedLibrary],
     allow_linking_libpython: bool,
-) -> Result<(), AuditWheelError> {
+) -> Result<PolicyViolations, AuditWheelError> {
     let arch_versions = &policy.symbol_versions.get(arch).ok_or_else(|| {
         AuditWheelError::UnsupportedArchitecture(policy.clone(), arch.to_string())
     })?;
     let mut offending_libs = HashSet::new();
-    let mut offending_versioned_syms = HashSet::new();
-    let mut offending_blacklist_syms = HashMap::new();
+    let mut offending_versions = Vec::new();
+    let mut offending_blacklist_syms = Vec::new();
     let undef_symbols: HashSet<String> = elf
         .dynsyms
         .iter()
@@ -158,7 +180,7 @@ fn policy_is_satisfied(
             let mut intersection: Vec<_> = sym_list.intersection(&undef_symbols).cloned().collect();
             if !intersection.is_empty() {
                 intersection.sort();
-                offending_blacklist_syms.insert(dep, intersection);
+                offending_blacklist_syms.push((dep.clone(), intersection));
             }
         }
     }
@@ -175,38 +197,51 @@ fn policy_is_satisfied(
                 .or_default()
                 .insert(version.to_string());
         }
-        for (name, versions_needed) in versions.iter() {
+        let mut names: Vec<_> = versions.keys().collect();
+        names.sort();
+        for name in names {
+            let versions_needed = &versions[name];
             let versions_allowed = &arch_versions[name];
             if !versions_needed.is_subset(versions_allowed) {
-                let offending_versions: Vec<&str> = versions_needed
+                let mut offending_symbol_versions: Vec<String> = versions_needed
                     .difference(versions_allowed)
-                    .map(|v| v.as_ref())
-                    .collect();
-                let offending_symbol_versions: Vec<String> = offending_versions
-                    .iter()
                     .map(|v| format!("{name}_{v}"))
                     .collect();
-                let offending_symbols = find_incompliant_symbols(elf, &offending_symbol_versions)?;
-                let offender = if offending_symbols.is_empty() {
-                    format!(
-                        "{} offending versions: {}",
-                        library.name,
-                        offending_symbol_versions.join(", ")
-                    )
-                } else {
-                    format!(
-                        "{} offending symbols: {}",
-                        library.name,
-                        offending_symbols.join(", ")
-                    )
-                };
-                offending_versioned_syms.insert(offender);
+                offending_symbol_versions.sort();
+                offending_versions.push((library.name.clone(), offending_symbol_versions));
             }
         }
     }
+    let mut forbidden_libraries: Vec<String> = offending_libs.into_iter().collect();
+    forbidden_libraries.sort();
+    Ok(PolicyViolations {
+        forbidden_libraries,
+        too_new_versions: offending_versions,
+        blacklisted_symbols: offending_blacklist_syms,
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn policy_is_satisfied(
+    policy: &Policy,
+    elf: &Elf,
+    arch: &str,
+    deps: &[String],
+    versioned_libraries: &[VersionedLibrary],
+    allow_linking_libpython: bool,
+) -> Result<(), AuditWheelError> {
+    let violations = policy_violations(
+        policy,
+        elf,
+        arch,
+        deps,
+        versioned_libraries,
+        allow_linking_libpython,
+    )?;
     // Check for black-listed symbols
-    if !offending_blacklist_syms.is_empty() {
-        let offenders = offending_blacklist_syms
+    if !violations.blacklisted_symbols.is_empty() {
+        let offenders = violations
+            .blacklisted_symbols
             .into_iter()
             .map(|(lib, syms)| format!("{}: {}", lib, syms.join(", ")))
             .collect();
@@ -216,14 +251,32 @@ fn policy_is_satisfied(
         ));
     }
     // Check for too-recent versioned symbols
-    if !offending_versioned_syms.is_empty() {
+    if !violations.too_new_versions.is_empty() {
+        let mut offenders = Vec::new();
+        for (library, offending_symbol_versions) in violations.too_new_versions {
+            let offending_symbols = find_incompliant_symbols(elf, &offending_symbol_versions)?;
+            let offender = if offending_symbols.is_empty() {
+                format!(
+                    "{} offending versions: {}",
+                    library,
+                    offending_symbol_versions.join(", ")
+                )
+            } else {
+                format!(
+                    "{} offending symbols: {}",
+                    library,
+                    offending_symbols.join(", ")
+                )
+            };
+            offenders.push(offender);
+        }
         return Err(AuditWheelError::VersionedSymbolTooNewError(
             policy.clone(),
-            offending_versioned_syms.into_iter().collect(),
+            offenders,
         ));
     }
     // Check for libpython and forbidden libraries
-    let offenders: Vec<String> = offending_libs.into_iter().collect();
+    let offenders = violations.forbidden_libraries;
     match offenders.as_slice() {
         [] => Ok(()),
         [lib] if IS_LIBPYTHON.is_match(lib) => {
@@ -236,10 +289,11 @@ fn policy_is_satisfied(
     }
 }
 
-fn get_default_platform_policies() -> Vec<Policy> {
+fn get_default_platform_policies(policies: &AuditPolicies) -> Vec<Policy> {
     if let Ok(Some(musl_libc)) = find_musl_libc() {
         if let Ok(Some((major, minor))) = get_musl_version(musl_libc) {
-            return MUSLLINUX_POLICIES
+            return policies
+                .musllinux()
                 .iter()
                 .filter(|policy| {
                     policy.name == "linux" || policy.name == format!("musllinux_{major}_{minor}")
@@ -248,7 +302,7 @@ fn get_default_platform_policies() -> Vec<Policy> {
                 .collect();
         }
     }
-    MANYLINUX_POLICIES.clone()
+    policies.manylinux().to_vec()
 }
 
 /// An reimplementation of auditwheel, which checks elf files for
@@ -262,14 +316,19 @@ fn get_default_platform_policies() -> Vec<Policy> {
 /// a higher version would be possible.
 ///
 /// Does nothing for `platform_tag` set to `Off`/`Linux` or non-linux platforms.
+///
+/// Libraries matching `exclude` are left out of the checks, they're expected to be provided
+/// by the system at runtime.
 #[allow(clippy::result_large_err)]
 pub fn auditwheel_rs(
     artifact: &BuildArtifact,
     target: &Target,
-    platform_tag: Option<PlatformTag>,
+    platform_tag: Option<&PlatformTag>,
+    policies: &AuditPolicies,
     allow_linking_libpython: bool,
+    exclude: &[Pattern],
 ) -> Result<(Policy, bool), AuditWheelError> {
-    if !target.is_linux() || platform_tag == Some(PlatformTag::Linux) {
+    if !target.is_linux() || platform_tag == Some(&PlatformTag::Linux) {
         return Ok((Policy::default(), false));
     }
     let path = &artifact.path;
@@ -280,28 +339,38 @@ pub fn auditwheel_rs(
         .map_err(AuditWheelError::IoError)?;
     let elf = Elf::parse(&buffer).map_err(AuditWheelError::GoblinError)?;
     // This returns essentially the same as ldd
-    let deps: Vec<String> = elf.libraries.iter().map(ToString::to_string).collect();
-    let versioned_libraries = find_versioned_libraries(&elf);
+    let deps: Vec<String> = elf
+        .libraries
+        .iter()
+        .filter(|lib| !is_excluded(lib, exclude))
+        .map(ToString::to_string)
+        .collect();
+    let versioned_libraries: Vec<VersionedLibrary> = find_versioned_libraries(&elf)
+        .into_iter()
+        .filter(|lib| !is_excluded(&lib.name, exclude))
+        .collect();
 
     // Find the highest possible policy, if any
     let platform_policies = match platform_tag {
-        Some(PlatformTag::Manylinux { .. }) => MANYLINUX_POLICIES.clone(),
-        Some(PlatformTag::Musllinux { x, y }) => MUSLLINUX_POLICIES
-            .clone()
-            .into_iter()
+        Some(PlatformTag::Manylinux { .. }) => policies.manylinux().to_vec(),
+        Some(PlatformTag::Musllinux { x, y }) => policies
+            .musllinux()
+            .iter()
             .filter(|policy| policy.name == "linux" || policy.name == format!("musllinux_{x}_{y}"))
+            .cloned()
             .map(|mut policy| {
                 policy.fixup_musl_libc_so_name(target.target_arch());
                 policy
             })
             .collect(),
         None => {
-            let mut policies = get_default_platform_policies();
+            let mut policies = get_default_platform_policies(policies);
             for policy in &mut policies {
                 policy.fixup_musl_libc_so_name(target.target_arch());
             }
             policies
         }
+        Some(PlatformTag::Custom(_)) => policies.custom().to_vec(),
         Some(PlatformTag::Linux) => unreachable!(),
     };
     let mut highest_policy = None;
@@ -338,7 +407,10 @@ pub fn auditwheel_rs(
 
     let policy = if let Some(platform_tag) = platform_tag {
         let tag = platform_tag.to_string();
-        let mut policy = Policy::from_name(&tag).ok_or(AuditWheelError::UndefinedPolicy(tag))?;
+        let mut policy = policies
+            .get(&tag)
+            .cloned()
+            .ok_or(AuditWheelError::UndefinedPolicy(tag))?;
         policy.fixup_musl_libc_so_name(target.target_arch());
 
         if let Some(highest_policy) = highest_policy {
@@ -383,6 +455,133 @@ pub fn auditwheel_rs(
     Ok((policy, should_repair))
 }
 
+/// Audits an elf file without knowing how it was built, e.g. a library from an existing wheel,
+/// and returns the highest priority manylinux/musllinux policy it satisfies.
+///
+/// Libraries in `bundled` ship next to the elf file and are thus allowed as dependencies.
+/// Returns the `linux` policy for architectures not covered by any policy.
+#[allow(clippy::result_large_err)]
+pub fn audit_elf(
+    elf: &Elf,
+    bundled: &HashSet<String>,
+    policies: &AuditPolicies,
+) -> Result<Policy, AuditWheelError> {
+    let Some(arch) = elf_arch(elf) else {
+        return Ok(Policy::default());
+    };
+    let (deps, versioned_libraries) = external_dependencies(elf, bundled);
+    for policy in candidate_policies(elf, arch, policies) {
+        match policy_is_satisfied(
+            &policy,
+            elf,
+            &arch.to_string(),
+            &deps,
+            &versioned_libraries,
+            false,
+        ) {
+            Ok(()) => return Ok(policy),
+            Err(AuditWheelError::LinksForbiddenLibrariesError(..))
+            | Err(AuditWheelError::VersionedSymbolTooNewError(..))
+            | Err(AuditWheelError::BlackListedSymbolsError(..))
+            | Err(AuditWheelError::UnsupportedArchitecture(..)) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Policy::default())
+}
+
+/// Checks an elf file against the manylinux/musllinux policies from highest to lowest priority,
+/// like [audit_elf], but returns the violations of every policy up to the first one it satisfies.
+///
+/// The violations are `None` if the policy doesn't cover the architecture.
+pub fn policy_checks(
+    elf: &Elf,
+    bundled: &HashSet<String>,
+    policies: &AuditPolicies,
+) -> Vec<(Policy, Option<PolicyViolations>)> {
+    let Some(arch) = elf_arch(elf) else {
+        return Vec::new();
+    };
+    let (deps, versioned_libraries) = external_dependencies(elf, bundled);
+    let mut checks = Vec::new();
+    for policy in candidate_policies(elf, arch, policies) {
+        // Everything complies with the `linux` fallback
+        if policy.name == "linux" {
+            continue;
+        }
+        let violations = policy_violations(
+            &policy,
+            elf,
+            &arch.to_string(),
+            &deps,
+            &versioned_libraries,
+            false,
+        )
+        .ok();
+        let satisfied = violations.as_ref().is_some_and(PolicyViolations::is_empty);
+        checks.push((policy, violations));
+        if satisfied {
+            break;
+        }
+    }
+    checks
+}
+
+/// The dependencies and versioned libraries of an elf file, except for the `bundled` ones
+fn external_dependencies(
+    elf: &Elf,
+    bundled: &HashSet<String>,
+) -> (Vec<String>, Vec<VersionedLibrary>) {
+    let deps = elf
+        .libraries
+        .iter()
+        .filter(|lib| !bundled.contains(**lib))
+        .map(ToString::to_string)
+        .collect();
+    let versioned_libraries = find_versioned_libraries(elf)
+        .into_iter()
+        .filter(|lib| !bundled.contains(&lib.name))
+        .collect();
+    (deps, versioned_libraries)
+}
+
+/// The manylinux or musllinux policies, depending on the libc the elf file links
+fn candidate_policies(elf: &Elf, arch: Arch, policies: &AuditPolicies) -> Vec<Policy> {
+    let is_musl = elf
+        .libraries
+        .iter()
+        .any(|lib| lib.starts_with("libc.musl-"));
+    if is_musl {
+        policies
+            .musllinux()
+            .iter()
+            .cloned()
+            .map(|mut policy| {
+                policy.fixup_musl_libc_so_name(arch);
+                policy
+            })
+            .collect()
+    } else {
+        policies.manylinux().to_vec()
+    }
+}
+
+/// The architecture of an elf file, as far as it's covered by the manylinux/musllinux policies
+pub(crate) fn elf_arch(elf: &Elf) -> Option<Arch> {
+    use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_PPC64, EM_S390, EM_X86_64};
+
+    match elf.header.e_machine {
+        EM_X86_64 => Some(Arch::X86_64),
+        EM_386 => Some(Arch::X86),
+        EM_AARCH64 => Some(Arch::Aarch64),
+        EM_ARM => Some(Arch::Armv7L),
+        EM_PPC64 if elf.little_endian => Some(Arch::Powerpc64Le),
+        EM_PPC64 => Some(Arch::Powerpc64),
+        EM_S390 => Some(Arch::S390X),
+        _ => None,
+    }
+}
+
 /// Get sysroot path from target C compiler
 ///
 /// Currently only gcc is supported, clang doesn't have a `--print-sysroot` option
@@ -442,24 +641,31 @@ pub fn get_sysroot_path(target: &Target) -> Result<PathBuf> {
 /// we need to add to repair it
 pub fn get_policy_and_libs(
     artifact: &BuildArtifact,
-    platform_tag: Option<PlatformTag>,
+    platform_tag: Option<&PlatformTag>,
+    policies: &AuditPolicies,
     target: &Target,
     allow_linking_libpython: bool,
+    exclude: &[Pattern],
 ) -> Result<(Policy, Vec<Library>)> {
-    let (policy, should_repair) =
-        auditwheel_rs(artifact, target, platform_tag, allow_linking_libpython).with_context(
-            || {
-                if let Some(platform_tag) = platform_tag {
-                    format!("Error ensuring {platform_tag} compliance")
-                } else {
-                    "Error checking for manylinux/musllinux compliance".to_string()
-                }
-            },
-        )?;
+    let (policy, should_repair) = auditwheel_rs(
+        artifact,
+        target,
+        platform_tag,
+        policies,
+        allow_linking_libpython,
+        exclude,
+    )
+    .with_context(|| {
+        if let Some(platform_tag) = platform_tag {
+            format!("Error ensuring {platform_tag} compliance")
+        } else {
+            "Error checking for manylinux/musllinux compliance".to_string()
+        }
+    })?;
     let external_libs = if should_repair {
         let sysroot = get_sysroot_path(target).unwrap_or_else(|_| PathBuf::from("/"));
         let ld_paths = artifact.linked_paths.iter().map(PathBuf::from).collect();
-        let external_libs = find_external_libs(&artifact.path, &policy, sysroot, ld_paths)
+        let external_libs = find_external_libs(&artifact.path, &policy, sysroot, ld_paths, exclude)
             .with_context(|| {
                 if let Some(platform_tag) = platform_tag {
                     format!("Error repairing wheel for {platform_tag} compliance")
@@ -481,6 +687,28 @@ pub fn get_policy_and_libs(
     Ok((policy, external_libs))
 }
 
+/// Whether the library matches one of the `--exclude-lib` glob patterns
+pub(crate) fn is_excluded(name: &str, exclude: &[Pattern]) -> bool {
+    exclude.iter().any(|pattern| pattern.matches(name))
+}
+
+/// The libraries the elf file links that match one of the `--exclude-lib` glob patterns,
+/// which end up as `Requires-External` in the metadata
+pub fn find_excluded_libs(path: &Path, exclude: &[Pattern]) -> Result<Vec<String>> {
+    if exclude.is_empty() {
+        return Ok(Vec::new());
+    }
+    let buffer = fs_err::read(path)?;
+    let elf = Elf::parse(&buffer)
+        .with_context(|| format!("Failed to parse ELF file {}", path.display()))?;
+    Ok(elf
+        .libraries
+        .iter()
+        .filter(|lib| is_excluded(lib, exclude))
+        .map(ToString::to_string)
+        .collect())
+}
+
 pub fn relpath(to: &Path, from: &Path) -> PathBuf {
     let mut suffix_pos = 0;
     for (f, t) in from.components().zip(to.components()) {
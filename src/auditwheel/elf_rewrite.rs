@@ -0,0 +1,575 @@
+//! An in-process replacement for the `patchelf` operations needed to repair wheels
+//!
+//! Changes that fit into the existing dynamic section and string table are done in place.
+//! Otherwise, like `patchelf`, we append a new `PT_LOAD` segment to the file that contains the
+//! extended copy of the string table, the dynamic section if it needs to grow and the program
+//! headers, which need room for the new segment.
+
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use goblin::elf::dynamic::{
+    DT_NEEDED, DT_NULL, DT_RPATH, DT_RUNPATH, DT_SONAME, DT_STRSZ, DT_STRTAB,
+};
+use goblin::elf::program_header::{
+    ProgramHeader, PF_R, PF_W, PT_DYNAMIC, PT_INTERP, PT_LOAD, PT_PHDR,
+};
+use goblin::elf::section_header::{SHT_DYNAMIC, SHT_STRTAB};
+use goblin::elf::Elf;
+use std::path::Path;
+
+/// The changes to apply to the dynamic section of an ELF file
+#[derive(Debug, Clone, Default)]
+pub struct ElfRewrite {
+    /// The new `DT_SONAME`
+    pub soname: Option<String>,
+    /// Replacements for `DT_NEEDED` entries, as old and new name
+    pub replace_needed: Vec<(String, String)>,
+    /// Remove all `DT_RPATH` and `DT_RUNPATH` entries
+    pub remove_rpath: bool,
+    /// Add a `DT_RPATH` entry
+    pub rpath: Option<String>,
+}
+
+/// Reads and writes the integers of the ELF file with its class and byte order
+struct ElfData {
+    data: Vec<u8>,
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl ElfData {
+    /// The size of addresses and offsets, and of the fields in a dynamic entry
+    fn word(&self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn read(&self, offset: usize, size: usize) -> Result<u64> {
+        let bytes = self
+            .data
+            .get(offset..offset + size)
+            .context("Unexpected end of ELF file")?;
+        let mut buf = [0u8; 8];
+        if self.little_endian {
+            buf[..size].copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        } else {
+            buf[8 - size..].copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        }
+    }
+
+    fn write(&mut self, offset: usize, size: usize, value: u64) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()[..size].to_vec()
+        } else {
+            value.to_be_bytes()[8 - size..].to_vec()
+        };
+        self.data[offset..offset + size].copy_from_slice(&bytes);
+    }
+
+    fn write_phdr(&mut self, offset: usize, phdr: &ProgramHeader) {
+        if self.is_64 {
+            self.write(offset, 4, phdr.p_type.into());
+            self.write(offset + 4, 4, phdr.p_flags.into());
+            self.write(offset + 8, 8, phdr.p_offset);
+            self.write(offset + 16, 8, phdr.p_vaddr);
+            self.write(offset + 24, 8, phdr.p_paddr);
+            self.write(offset + 32, 8, phdr.p_filesz);
+            self.write(offset + 40, 8, phdr.p_memsz);
+            self.write(offset + 48, 8, phdr.p_align);
+        } else {
+            self.write(offset, 4, phdr.p_type.into());
+            self.write(offset + 4, 4, phdr.p_offset);
+            self.write(offset + 8, 4, phdr.p_vaddr);
+            self.write(offset + 12, 4, phdr.p_paddr);
+            self.write(offset + 16, 4, phdr.p_filesz);
+            self.write(offset + 20, 4, phdr.p_memsz);
+            self.write(offset + 24, 4, phdr.p_flags.into());
+            self.write(offset + 28, 4, phdr.p_align);
+        }
+    }
+
+    /// Updates the address, offset and size of a section header
+    fn write_shdr_location(&mut self, offset: usize, addr: u64, file_offset: u64, size: u64) {
+        let word = self.word();
+        // sh_name and sh_type, followed by sh_flags
+        let addr_offset = offset + 8 + word;
+        self.write(addr_offset, word, addr);
+        self.write(addr_offset + word, word, file_offset);
+        self.write(addr_offset + 2 * word, word, size);
+    }
+
+    fn write_dynamic(&mut self, offset: usize, slots: usize, dyns: &[(u64, u64)]) {
+        let word = self.word();
+        for i in 0..slots {
+            let (tag, value) = dyns.get(i).copied().unwrap_or((DT_NULL, 0));
+            self.write(offset + 2 * word * i, word, tag);
+            self.write(offset + 2 * word * i + word, word, value);
+        }
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// Translates a virtual address to an offset in the file using the `PT_LOAD` segments
+fn vaddr_to_offset(phdrs: &[ProgramHeader], vaddr: u64) -> Option<u64> {
+    phdrs
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == PT_LOAD && phdr.p_vaddr <= vaddr && vaddr < phdr.p_vaddr + phdr.p_filesz
+        })
+        .map(|phdr| vaddr - phdr.p_vaddr + phdr.p_offset)
+}
+
+fn read_str(strtab: &[u8], offset: u64) -> Result<String> {
+    let bytes = strtab
+        .get(offset as usize..)
+        .context("String offset outside of the dynamic string table")?;
+    let end = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .context("Unterminated string in the dynamic string table")?;
+    Ok(String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+/// Returns the offset of the string in the string table, appending it if it's missing
+fn add_str(strtab: &mut Vec<u8>, string: &str) -> u64 {
+    let mut needle = string.as_bytes().to_vec();
+    needle.push(0);
+    // Any string followed by a null byte can be reused, also the tail of a longer one
+    if let Some(position) = strtab
+        .windows(needle.len())
+        .position(|window| window == needle)
+    {
+        return position as u64;
+    }
+    let position = strtab.len();
+    strtab.extend_from_slice(&needle);
+    position as u64
+}
+
+/// Applies the changes to the dynamic section of an ELF file
+pub fn rewrite(file: impl AsRef<Path>, changes: &ElfRewrite) -> Result<()> {
+    let file = file.as_ref();
+    let data = fs::read(file)?;
+    let elf = Elf::parse(&data)
+        .with_context(|| format!("Failed to parse ELF file at '{}'", file.display()))?;
+    let header = elf.header;
+    let mut phdrs = elf.program_headers.clone();
+    let shdrs = elf.section_headers.clone();
+    let (is_64, little_endian) = (elf.is_64, elf.little_endian);
+    drop(elf);
+    let mut elf_data = ElfData {
+        data,
+        is_64,
+        little_endian,
+    };
+    let word = elf_data.word();
+
+    let dynamic_index = phdrs
+        .iter()
+        .position(|phdr| phdr.p_type == PT_DYNAMIC)
+        .with_context(|| format!("'{}' has no dynamic section", file.display()))?;
+    let dynamic_offset = phdrs[dynamic_index].p_offset as usize;
+    let dynamic_slots = phdrs[dynamic_index].p_filesz as usize / (2 * word);
+    let mut dyns = Vec::new();
+    for i in 0..dynamic_slots {
+        let tag = elf_data.read(dynamic_offset + 2 * word * i, word)?;
+        if tag == DT_NULL {
+            break;
+        }
+        let value = elf_data.read(dynamic_offset + 2 * word * i + word, word)?;
+        dyns.push((tag, value));
+    }
+    let find_dyn = |tag| {
+        dyns.iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| *value)
+    };
+    let (Some(strtab_vaddr), Some(strtab_size)) = (find_dyn(DT_STRTAB), find_dyn(DT_STRSZ)) else {
+        bail!("'{}' has no dynamic string table", file.display());
+    };
+    let strtab_offset = vaddr_to_offset(&phdrs, strtab_vaddr)
+        .context("The dynamic string table is not mapped")? as usize;
+    let mut strtab = elf_data
+        .data
+        .get(strtab_offset..strtab_offset + strtab_size as usize)
+        .context("The dynamic string table is outside of the file")?
+        .to_vec();
+
+    if let Some(soname) = &changes.soname {
+        let value = add_str(&mut strtab, soname);
+        match dyns.iter_mut().find(|(tag, _)| *tag == DT_SONAME) {
+            Some(entry) => entry.1 = value,
+            None => dyns.insert(0, (DT_SONAME, value)),
+        }
+    }
+    for (tag, value) in &mut dyns {
+        if *tag != DT_NEEDED {
+            continue;
+        }
+        let name = read_str(&strtab, *value)?;
+        if let Some((_, new)) = changes.replace_needed.iter().find(|(old, _)| *old == name) {
+            *value = add_str(&mut strtab, new);
+        }
+    }
+    if changes.remove_rpath {
+        dyns.retain(|(tag, _)| *tag != DT_RPATH && *tag != DT_RUNPATH);
+    }
+    if let Some(rpath) = &changes.rpath {
+        let value = add_str(&mut strtab, rpath);
+        dyns.push((DT_RPATH, value));
+    }
+
+    let strtab_grew = strtab.len() as u64 > strtab_size;
+    // The dynamic section must end with a DT_NULL entry
+    let dynamic_grew = dyns.len() + 1 > dynamic_slots;
+    if !strtab_grew && !dynamic_grew {
+        elf_data.write_dynamic(dynamic_offset, dynamic_slots, &dyns);
+        fs::write(file, elf_data.data)?;
+        return Ok(());
+    }
+
+    // Lay out the new segment with the program headers, the dynamic section and the strings
+    let phentsize = header.e_phentsize as u64;
+    let phnum = phdrs.len() + 1;
+    let mut segment_size = phentsize * phnum as u64;
+    let new_dynamic = if dynamic_grew {
+        segment_size = align(segment_size, 8);
+        let position = segment_size;
+        segment_size += ((dyns.len() + 1) * 2 * word) as u64;
+        Some(position)
+    } else {
+        None
+    };
+    let strtab_position = segment_size;
+    segment_size += strtab.len() as u64;
+
+    let loads: Vec<&ProgramHeader> = phdrs.iter().filter(|p| p.p_type == PT_LOAD).collect();
+    let first_load = loads
+        .iter()
+        .min_by_key(|phdr| phdr.p_vaddr)
+        .with_context(|| format!("'{}' has no loadable segments", file.display()))?;
+    let page_size = loads
+        .iter()
+        .map(|phdr| phdr.p_align)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let vaddr_end = loads
+        .iter()
+        .map(|phdr| phdr.p_vaddr + phdr.p_memsz)
+        .max()
+        .unwrap_or_default();
+    let mut segment_offset = align(elf_data.data.len() as u64, 8);
+    let segment_vaddr = if phdrs.iter().any(|phdr| phdr.p_type == PT_INTERP) {
+        // Older kernels compute the address of the program headers of executables from the
+        // first segment, so the new segment must have the same distance between file offset
+        // and address
+        let delta = first_load
+            .p_vaddr
+            .checked_sub(first_load.p_offset)
+            .context("The first segment has an address below its file offset")?;
+        segment_offset = segment_offset.max(align(vaddr_end, page_size) - delta);
+        segment_offset + delta
+    } else {
+        align(vaddr_end, page_size) + segment_offset % page_size
+    };
+
+    let mut new_load = ProgramHeader::new();
+    new_load.p_type = PT_LOAD;
+    new_load.p_flags = if dynamic_grew { PF_R | PF_W } else { PF_R };
+    new_load.p_offset = segment_offset;
+    new_load.p_vaddr = segment_vaddr;
+    new_load.p_paddr = segment_vaddr;
+    new_load.p_filesz = segment_size;
+    new_load.p_memsz = segment_size;
+    new_load.p_align = page_size;
+    // The loadable segments must be sorted by address
+    let last_load = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_LOAD)
+        .unwrap();
+    phdrs.insert(last_load + 1, new_load);
+    for phdr in &mut phdrs {
+        if phdr.p_type == PT_PHDR {
+            phdr.p_offset = segment_offset;
+            phdr.p_vaddr = segment_vaddr;
+            phdr.p_paddr = segment_vaddr;
+            phdr.p_filesz = phentsize * phnum as u64;
+            phdr.p_memsz = phdr.p_filesz;
+        } else if let (PT_DYNAMIC, Some(position)) = (phdr.p_type, new_dynamic) {
+            phdr.p_offset = segment_offset + position;
+            phdr.p_vaddr = segment_vaddr + position;
+            phdr.p_paddr = segment_vaddr + position;
+            phdr.p_filesz = ((dyns.len() + 1) * 2 * word) as u64;
+            phdr.p_memsz = phdr.p_filesz;
+        }
+    }
+
+    for (tag, value) in &mut dyns {
+        match *tag {
+            DT_STRTAB => *value = segment_vaddr + strtab_position,
+            DT_STRSZ => *value = strtab.len() as u64,
+            _ => {}
+        }
+    }
+
+    elf_data
+        .data
+        .resize((segment_offset + segment_size) as usize, 0);
+    let strtab_start = (segment_offset + strtab_position) as usize;
+    elf_data.data[strtab_start..strtab_start + strtab.len()].copy_from_slice(&strtab);
+    for (i, phdr) in phdrs.iter().enumerate() {
+        elf_data.write_phdr(segment_offset as usize + i * phentsize as usize, phdr);
+    }
+    match new_dynamic {
+        Some(position) => {
+            let offset = (segment_offset + position) as usize;
+            elf_data.write_dynamic(offset, dyns.len() + 1, &dyns);
+        }
+        None => elf_data.write_dynamic(dynamic_offset, dynamic_slots, &dyns),
+    }
+
+    // e_phoff and e_phnum
+    if elf_data.is_64 {
+        elf_data.write(32, 8, segment_offset);
+        elf_data.write(56, 2, phnum as u64);
+    } else {
+        elf_data.write(28, 4, segment_offset);
+        elf_data.write(44, 2, phnum as u64);
+    }
+
+    // Keep the section headers in sync for tools such as readelf and strip
+    for (i, shdr) in shdrs.iter().enumerate() {
+        let offset = header.e_shoff as usize + i * header.e_shentsize as usize;
+        if shdr.sh_type == SHT_STRTAB && shdr.sh_addr == strtab_vaddr && shdr.sh_addr != 0 {
+            elf_data.write_shdr_location(
+                offset,
+                segment_vaddr + strtab_position,
+                segment_offset + strtab_position,
+                strtab.len() as u64,
+            );
+        } else if let (SHT_DYNAMIC, Some(position)) = (shdr.sh_type, new_dynamic) {
+            elf_data.write_shdr_location(
+                offset,
+                segment_vaddr + position,
+                segment_offset + position,
+                ((dyns.len() + 1) * 2 * word) as u64,
+            );
+        }
+    }
+
+    fs::write(file, elf_data.data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auditwheel::patchelf::Patchelf;
+    use pretty_assertions::assert_eq;
+
+    fn copy_fixtures() -> tempfile::TempDir {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/elf");
+        let temp_dir = tempfile::tempdir().unwrap();
+        for name in ["libbar.so.1", "libfoo.so.1", "main"] {
+            fs::copy(fixtures.join(name), temp_dir.path().join(name)).unwrap();
+        }
+        temp_dir
+    }
+
+    /// Returns soname, needed, rpath and runpath
+    fn read_dynamic(path: &Path) -> (Option<String>, Vec<String>, Vec<String>, Vec<String>) {
+        let data = fs::read(path).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let to_strings = |strs: &[&str]| strs.iter().map(|s| s.to_string()).collect();
+        (
+            elf.soname.map(ToString::to_string),
+            to_strings(&elf.libraries),
+            to_strings(&elf.rpaths),
+            to_strings(&elf.runpaths),
+        )
+    }
+
+    #[test]
+    fn test_rewrite_in_place() {
+        let temp_dir = copy_fixtures();
+        let libfoo = temp_dir.path().join("libfoo.so.1");
+        let size = fs::metadata(&libfoo).unwrap().len();
+        // All strings exist already and the dynamic section has spare slots
+        rewrite(
+            &libfoo,
+            &ElfRewrite {
+                soname: Some("libbar.so.1".to_string()),
+                remove_rpath: true,
+                rpath: Some("$ORIGIN".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(fs::metadata(&libfoo).unwrap().len(), size);
+        assert_eq!(
+            read_dynamic(&libfoo),
+            (
+                Some("libbar.so.1".to_string()),
+                vec!["libbar.so.1".to_string()],
+                vec!["$ORIGIN".to_string()],
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn test_rewrite_new_segment() {
+        let temp_dir = copy_fixtures();
+        let libfoo = temp_dir.path().join("libfoo.so.1");
+        rewrite(
+            &libfoo,
+            &ElfRewrite {
+                soname: Some("libfoo-0123abcd.so.1".to_string()),
+                replace_needed: vec![(
+                    "libbar.so.1".to_string(),
+                    "libbar-4567cdef.so.1".to_string(),
+                )],
+                remove_rpath: true,
+                rpath: Some("$ORIGIN/../very/long/path/to/the/libs".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            read_dynamic(&libfoo),
+            (
+                Some("libfoo-0123abcd.so.1".to_string()),
+                vec!["libbar-4567cdef.so.1".to_string()],
+                vec!["$ORIGIN/../very/long/path/to/the/libs".to_string()],
+                vec![]
+            )
+        );
+        // Rewriting again reuses the strings and the segment layout stays valid
+        rewrite(
+            &libfoo,
+            &ElfRewrite {
+                remove_rpath: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (soname, needed, rpath, runpath) = read_dynamic(&libfoo);
+        assert_eq!(soname.as_deref(), Some("libfoo-0123abcd.so.1"));
+        assert_eq!(needed, ["libbar-4567cdef.so.1"]);
+        assert!(rpath.is_empty() && runpath.is_empty());
+    }
+
+    #[test]
+    fn test_patchelf_remove_rpath() {
+        let temp_dir = copy_fixtures();
+        let libfoo = temp_dir.path().join("libfoo.so.1");
+        assert_eq!(read_dynamic(&libfoo).3, ["$ORIGIN"]);
+        Patchelf::Builtin.remove_rpath(&libfoo).unwrap();
+        let (soname, needed, rpath, runpath) = read_dynamic(&libfoo);
+        assert_eq!(soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(needed, ["libbar.so.1"]);
+        assert!(rpath.is_empty() && runpath.is_empty());
+
+        // The external patchelf is optional in the tests
+        if which::which("patchelf").is_ok() {
+            let libbar = temp_dir.path().join("libbar.so.1");
+            Patchelf::Builtin.set_rpath(&libbar, &"/build/lib").unwrap();
+            Patchelf::External.remove_rpath(&libbar).unwrap();
+            let (_, _, rpath, runpath) = read_dynamic(&libbar);
+            assert!(rpath.is_empty() && runpath.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rewrite_grow_dynamic() {
+        let temp_dir = copy_fixtures();
+        let libbar = temp_dir.path().join("libbar.so.1");
+        let data = fs::read(&libbar).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let dynamic = elf
+            .program_headers
+            .iter()
+            .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            .unwrap();
+        let slots = dynamic.p_filesz as usize / 16;
+        let used = elf.dynamic.as_ref().unwrap().dyns.len();
+        drop(elf);
+        // More DT_RPATH entries than there are free slots
+        for i in 0..=slots - used {
+            rewrite(
+                &libbar,
+                &ElfRewrite {
+                    rpath: Some(format!("/rpath{i}")),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+        let (soname, _, rpath, _) = read_dynamic(&libbar);
+        assert_eq!(soname.as_deref(), Some("libbar.so.1"));
+        assert_eq!(rpath.len(), slots - used + 1);
+    }
+
+    /// Renames the libraries like the wheel repair does and checks that the dynamic loader
+    /// accepts the result
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_rewrite_load() {
+        let temp_dir = copy_fixtures();
+        let libs = temp_dir.path().join("main.libs");
+        fs::create_dir(&libs).unwrap();
+        let libbar = libs.join("libbar-4567cdef.so.1");
+        let libfoo = libs.join("libfoo-0123abcd.so.1");
+        fs::rename(temp_dir.path().join("libbar.so.1"), &libbar).unwrap();
+        fs::rename(temp_dir.path().join("libfoo.so.1"), &libfoo).unwrap();
+        let main = temp_dir.path().join("main");
+
+        rewrite(
+            &libbar,
+            &ElfRewrite {
+                soname: Some("libbar-4567cdef.so.1".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        rewrite(
+            &libfoo,
+            &ElfRewrite {
+                soname: Some("libfoo-0123abcd.so.1".to_string()),
+                replace_needed: vec![(
+                    "libbar.so.1".to_string(),
+                    "libbar-4567cdef.so.1".to_string(),
+                )],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        rewrite(
+            &main,
+            &ElfRewrite {
+                replace_needed: vec![(
+                    "libfoo.so.1".to_string(),
+                    "libfoo-0123abcd.so.1".to_string(),
+                )],
+                remove_rpath: true,
+                rpath: Some("$ORIGIN/main.libs".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let status = std::process::Command::new(&main)
+            .env_remove("LD_LIBRARY_PATH")
+            .status()
+            .unwrap();
+        assert!(status.success(), "{status}");
+    }
+}
@@ -0,0 +1,432 @@
+//! Explains which manylinux/musllinux policy an elf file complies with and why it doesn't comply
+//! with the higher priority ones, see `maturin audit --explain`
+use super::audit::{policy_checks, PolicyViolations};
+use super::{AuditPolicies, Policy};
+use anyhow::{Context, Result};
+use fs_err as fs;
+use fs_err::File;
+use gimli::{constants, AttributeValue, EndianSlice, RunTimeEndian, SectionId};
+use goblin::elf::section_header::{SHF_COMPRESSED, SHN_UNDEF, SHT_NOBITS};
+use goblin::elf::Elf;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// A symbol that keeps an elf file from complying with a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffendingSymbol {
+    /// The library the symbol is imported from
+    pub library: String,
+    /// The symbol version that is too new, e.g. `GLIBC_2.27`, or `None` if it's blacklisted
+    pub version: Option<String>,
+    /// The symbol, if it could be determined
+    pub symbol: Option<String>,
+    /// The compilation units (source files or crates) that declare the symbol, as far as the
+    /// debug info tells
+    pub origins: Vec<String>,
+}
+
+/// Why an elf file doesn't comply with a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyExplanation {
+    /// The elf file complies with the policy
+    Satisfied,
+    /// The policy doesn't cover the architecture of the elf file
+    UnsupportedArchitecture,
+    /// The elf file violates the policy
+    Violated {
+        /// Symbols whose version is too new or that are blacklisted
+        symbols: Vec<OffendingSymbol>,
+        /// Linked libraries that aren't whitelisted
+        forbidden_libraries: Vec<String>,
+    },
+}
+
+/// Checks the native libraries in wheels, or native libraries directly, for manylinux/musllinux
+/// compliance and prints the highest priority policy of each.
+///
+/// With `explain`, also prints for all higher priority policies what disqualified them.
+pub fn audit_native_files(
+    files: &[impl AsRef<Path>],
+    explain: bool,
+    policies: &AuditPolicies,
+) -> Result<()> {
+    for file in files {
+        let file = file.as_ref();
+        let is_wheel = file.extension().is_some_and(|extension| extension == "whl");
+        let elf_files = if is_wheel {
+            read_wheel_elf_files(file)?
+        } else {
+            vec![(file.display().to_string(), fs::read(file)?)]
+        };
+        if elf_files.is_empty() {
+            eprintln!("⚠️  Warning: {} contains no ELF files", file.display());
+            continue;
+        }
+        // Libraries that ship in the wheel, e.g. those grafted in by auditwheel repair
+        let bundled: HashSet<String> = elf_files
+            .iter()
+            .filter_map(|(name, _)| name.rsplit('/').next())
+            .map(ToString::to_string)
+            .collect();
+        for (name, bytes) in &elf_files {
+            let elf =
+                Elf::parse(bytes).with_context(|| format!("Failed to parse ELF file {name}"))?;
+            let explanation = explain_policies(&elf, bytes, &bundled, policies);
+            let policy = explanation
+                .iter()
+                .find(|(_, explanation)| *explanation == PolicyExplanation::Satisfied)
+                .map(|(policy, _)| policy.clone())
+                .unwrap_or_default();
+            println!("📦 {name}: {policy}");
+            if explain {
+                print_explanation(&explanation);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks an elf file against the manylinux/musllinux policies from highest to lowest priority
+/// and explains for each, up to the first one it complies with, what disqualified it
+pub fn explain_policies(
+    elf: &Elf,
+    bytes: &[u8],
+    bundled: &HashSet<String>,
+    policies: &AuditPolicies,
+) -> Vec<(Policy, PolicyExplanation)> {
+    let checks = policy_checks(elf, bundled, policies);
+    let versioned = versioned_symbols(elf);
+    let mut wanted = HashSet::new();
+    for (_, violations) in &checks {
+        let Some(violations) = violations else {
+            continue;
+        };
+        for (library, versions) in &violations.too_new_versions {
+            for version in versions {
+                if let Some(symbols) = versioned.get(&(library.clone(), version.clone())) {
+                    wanted.extend(symbols.iter().map(String::as_str));
+                }
+            }
+        }
+        for (_, symbols) in &violations.blacklisted_symbols {
+            wanted.extend(symbols.iter().map(String::as_str));
+        }
+    }
+    let origins = if wanted.is_empty() {
+        HashMap::new()
+    } else {
+        // The debug info is only a bonus, we don't want to fail without it
+        symbol_origins(elf, bytes, &wanted).unwrap_or_default()
+    };
+    let origins_of = |symbol: &str| -> Vec<String> {
+        origins
+            .get(symbol)
+            .map(|origins| origins.iter().cloned().collect())
+            .unwrap_or_default()
+    };
+
+    checks
+        .into_iter()
+        .map(|(policy, violations)| {
+            let explanation = match violations {
+                None => PolicyExplanation::UnsupportedArchitecture,
+                Some(violations) if violations.is_empty() => PolicyExplanation::Satisfied,
+                Some(PolicyViolations {
+                    forbidden_libraries,
+                    too_new_versions,
+                    blacklisted_symbols,
+                }) => {
+                    let mut symbols = Vec::new();
+                    for (library, versions) in too_new_versions {
+                        for version in versions {
+                            match versioned.get(&(library.clone(), version.clone())) {
+                                Some(names) => {
+                                    for name in names {
+                                        symbols.push(OffendingSymbol {
+                                            library: library.clone(),
+                                            version: Some(version.clone()),
+                                            symbol: Some(name.clone()),
+                                            origins: origins_of(name),
+                                        });
+                                    }
+                                }
+                                None => symbols.push(OffendingSymbol {
+                                    library: library.clone(),
+                                    version: Some(version),
+                                    symbol: None,
+                                    origins: Vec::new(),
+                                }),
+                            }
+                        }
+                    }
+                    for (library, names) in blacklisted_symbols {
+                        for name in names {
+                            symbols.push(OffendingSymbol {
+                                library: library.clone(),
+                                version: None,
+                                origins: origins_of(&name),
+                                symbol: Some(name),
+                            });
+                        }
+                    }
+                    PolicyExplanation::Violated {
+                        symbols,
+                        forbidden_libraries,
+                    }
+                }
+            };
+            (policy, explanation)
+        })
+        .collect()
+}
+
+fn print_explanation(explanation: &[(Policy, PolicyExplanation)]) {
+    for (policy, explanation) in explanation {
+        match explanation {
+            PolicyExplanation::Satisfied => println!("    ✅ {policy}"),
+            PolicyExplanation::UnsupportedArchitecture => {
+                println!("    ❌ {policy}: architecture not supported")
+            }
+            PolicyExplanation::Violated {
+                symbols,
+                forbidden_libraries,
+            } => {
+                println!("    ❌ {policy}:");
+                for symbol in symbols {
+                    let name = symbol.symbol.as_deref().unwrap_or("<unknown symbol>");
+                    let reason = match &symbol.version {
+                        Some(version) => format!("{version}: {name}"),
+                        None => format!("blacklisted: {name}"),
+                    };
+                    if symbol.origins.is_empty() {
+                        println!("        {} {reason}", symbol.library);
+                    } else {
+                        println!(
+                            "        {} {reason} (from {})",
+                            symbol.library,
+                            symbol.origins.join(", ")
+                        );
+                    }
+                }
+                for library in forbidden_libraries {
+                    println!("        {library} is not whitelisted");
+                }
+            }
+        }
+    }
+}
+
+/// Reads the elf files in a wheel
+fn read_wheel_elf_files(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .with_context(|| format!("Failed to open {} as zip archive", path.display()))?;
+    let mut elf_files = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        if bytes.starts_with(b"\x7fELF") {
+            elf_files.push((entry.name().to_string(), bytes));
+        }
+    }
+    Ok(elf_files)
+}
+
+/// Maps the library and symbol version, e.g. `libc.so.6` and `GLIBC_2.27`, to the undefined
+/// symbols that need them, e.g. `memfd_create`
+fn versioned_symbols(elf: &Elf) -> HashMap<(String, String), Vec<String>> {
+    let mut versions = HashMap::new();
+    if let Some(verneed) = &elf.verneed {
+        for need_file in verneed.iter() {
+            let Some(library) = elf.dynstrtab.get_at(need_file.vn_file) else {
+                continue;
+            };
+            for need_ver in need_file.iter() {
+                if let Some(version) = elf.dynstrtab.get_at(need_ver.vna_name) {
+                    versions.insert(
+                        need_ver.vna_other,
+                        (library.to_string(), version.to_string()),
+                    );
+                }
+            }
+        }
+    }
+    let mut symbols: HashMap<(String, String), Vec<String>> = HashMap::new();
+    let Some(versym) = &elf.versym else {
+        return symbols;
+    };
+    for (index, sym) in elf.dynsyms.iter().enumerate() {
+        if sym.st_shndx != SHN_UNDEF as usize {
+            continue;
+        }
+        let Some(version) = versym
+            .get_at(index)
+            .and_then(|versym| versions.get(&versym.version()))
+        else {
+            continue;
+        };
+        if let Some(name) = elf.dynstrtab.get_at(sym.st_name) {
+            symbols
+                .entry(version.clone())
+                .or_default()
+                .push(name.to_string());
+        }
+    }
+    for names in symbols.values_mut() {
+        names.sort();
+        names.dedup();
+    }
+    symbols
+}
+
+/// Finds the compilation units that declare the given symbols in the DWARF debug info.
+///
+/// C compilers declare the external functions and variables a source file uses, for rust code
+/// there usually are no such declarations.
+fn symbol_origins(
+    elf: &Elf,
+    bytes: &[u8],
+    symbols: &HashSet<&str>,
+) -> Result<HashMap<String, BTreeSet<String>>> {
+    let endian = if elf.little_endian {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+    let load_section = |id: SectionId| -> Result<EndianSlice<RunTimeEndian>, gimli::Error> {
+        let data = elf
+            .section_headers
+            .iter()
+            .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(id.name()))
+            // Compressed debug info isn't supported
+            .filter(|header| {
+                header.sh_type != SHT_NOBITS && header.sh_flags & u64::from(SHF_COMPRESSED) == 0
+            })
+            .and_then(|header| {
+                let start = usize::try_from(header.sh_offset).ok()?;
+                let end = start.checked_add(usize::try_from(header.sh_size).ok()?)?;
+                bytes.get(start..end)
+            })
+            .unwrap_or_default();
+        Ok(EndianSlice::new(data, endian))
+    };
+    let dwarf = gimli::Dwarf::load(load_section)?;
+
+    let mut origins: HashMap<String, BTreeSet<String>> = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(unit_name) = unit.name else {
+            continue;
+        };
+        let origin = unit_origin(&unit_name.to_string_lossy());
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != constants::DW_TAG_subprogram
+                && entry.tag() != constants::DW_TAG_variable
+            {
+                continue;
+            }
+            if entry.attr_value(constants::DW_AT_declaration)? != Some(AttributeValue::Flag(true)) {
+                continue;
+            }
+            let name = match entry.attr_value(constants::DW_AT_linkage_name)? {
+                Some(name) => Some(name),
+                None => entry.attr_value(constants::DW_AT_name)?,
+            };
+            let Some(name) = name else {
+                continue;
+            };
+            let name = dwarf.attr_string(&unit, name)?;
+            let name = name.to_string_lossy();
+            if symbols.contains(name.as_ref()) {
+                origins
+                    .entry(name.to_string())
+                    .or_default()
+                    .insert(origin.clone());
+            }
+        }
+    }
+    Ok(origins)
+}
+
+/// Rust compilation units are named like `src/lib.rs/@/my_crate.1234abcd-cgu.0`, we show the
+/// crate name for those and the source file for all others
+fn unit_origin(unit_name: &str) -> String {
+    match unit_name.split_once("/@/") {
+        Some((_, codegen_unit)) => {
+            let crate_name = codegen_unit.split('.').next().unwrap_or(codegen_unit);
+            format!("crate {crate_name}")
+        }
+        None => unit_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unit_origin() {
+        assert_eq!(
+            unit_origin("src/lib.rs/@/my_crate.e28d649cba2f1b97-cgu.0"),
+            "crate my_crate"
+        );
+        assert_eq!(
+            unit_origin("vendor/zstd/lib/zstd.c"),
+            "vendor/zstd/lib/zstd.c"
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let bytes = fs::read("test-data/elf/libmemfd.so.1").unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        let explanation =
+            explain_policies(&elf, &bytes, &HashSet::new(), &AuditPolicies::default());
+        let (policy, last) = explanation.last().unwrap();
+        assert_eq!(policy.name, "manylinux_2_27");
+        assert_eq!(*last, PolicyExplanation::Satisfied);
+
+        let (policy, first) = &explanation[0];
+        assert_eq!(policy.name, "manylinux_2_5");
+        assert_eq!(
+            *first,
+            PolicyExplanation::Violated {
+                symbols: vec![OffendingSymbol {
+                    library: "libc.so.6".to_string(),
+                    version: Some("GLIBC_2.27".to_string()),
+                    symbol: Some("memfd_create".to_string()),
+                    origins: vec!["memfd.c".to_string()],
+                }],
+                forbidden_libraries: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_explain_forbidden_library() {
+        let bytes = fs::read("test-data/elf/libfoo.so.1").unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        let explanation =
+            explain_policies(&elf, &bytes, &HashSet::new(), &AuditPolicies::default());
+        assert!(explanation
+            .iter()
+            .all(|(_, explanation)| match explanation {
+                PolicyExplanation::Violated {
+                    forbidden_libraries,
+                    ..
+                } => forbidden_libraries == &["libbar.so.1".to_string()],
+                PolicyExplanation::UnsupportedArchitecture => true,
+                PolicyExplanation::Satisfied => false,
+            }));
+        let bundled = HashSet::from(["libbar.so.1".to_string()]);
+        let explanation = explain_policies(&elf, &bytes, &bundled, &AuditPolicies::default());
+        assert_eq!(explanation.len(), 1);
+        assert_eq!(explanation[0].1, PolicyExplanation::Satisfied);
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use fs_err as fs;
+use goblin::mach::{Mach, MachO, SingleArch};
+use goblin::Object;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// The Python version in which each symbol was added to the stable ABI
+///
+/// Generated from CPython's `Misc/stable_abi.toml` by `ci/update_stable_abi.py`, leaving out the
+/// symbols that only debug builds export.
+static STABLE_ABI: Lazy<HashMap<String, (u8, u8)>> = Lazy::new(|| {
+    let versions: HashMap<String, Vec<String>> =
+        serde_json::from_slice(include_bytes!("stable-abi.json"))
+            .expect("invalid stable abi manifest");
+    let mut symbols = HashMap::new();
+    for (version, names) in versions {
+        let (major, minor) = version
+            .split_once('.')
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+            .expect("invalid python version in stable abi manifest");
+        for name in names {
+            symbols.insert(name, (major, minor));
+        }
+    }
+    symbols
+});
+
+/// What to do when an abi3 wheel uses symbols outside of the limited API
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum LimitedApiCheck {
+    /// Fail the build
+    Error,
+    /// Print a warning and build the wheel anyway, the default
+    #[default]
+    Warn,
+    /// Don't check the symbols
+    Off,
+}
+
+/// A Python symbol used by an abi3 library that the declared minimum Python version doesn't
+/// provide in its stable ABI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitedApiViolation {
+    /// The name of the symbol, without the leading underscore of Mach-O
+    pub symbol: String,
+    /// The Python version that added the symbol to the stable ABI, `None` if it isn't part of it
+    pub added: Option<(u8, u8)>,
+}
+
+impl fmt::Display for LimitedApiViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.added {
+            Some((major, minor)) => write!(f, "{} (added in {}.{})", self.symbol, major, minor),
+            None => write!(f, "{} (not in the stable ABI)", self.symbol),
+        }
+    }
+}
+
+/// Finds the Python symbols the library imports that aren't part of the stable ABI of
+/// Python `major.minor`
+///
+/// ELF, Mach-O and PE files are supported, other files have no violations.
+pub fn find_limited_api_violations(
+    path: &Path,
+    major: u8,
+    minor: u8,
+) -> Result<Vec<LimitedApiViolation>> {
+    let buffer = fs::read(path)?;
+    let symbols = match Object::parse(&buffer)
+        .with_context(|| format!("Failed to parse {}", path.display()))?
+    {
+        Object::Elf(elf) => elf
+            .dynsyms
+            .iter()
+            .filter(|sym| sym.st_shndx == goblin::elf::section_header::SHN_UNDEF as usize)
+            .filter_map(|sym| elf.dynstrtab.get_at(sym.st_name))
+            .map(ToString::to_string)
+            .collect(),
+        Object::Mach(Mach::Binary(macho)) => macho_undefined_symbols(&macho)?,
+        Object::Mach(Mach::Fat(fat)) => {
+            let mut symbols = BTreeSet::new();
+            for arch in fat.into_iter() {
+                if let SingleArch::MachO(macho) = arch? {
+                    symbols.extend(macho_undefined_symbols(&macho)?);
+                }
+            }
+            symbols
+        }
+        Object::PE(pe) => pe
+            .imports
+            .iter()
+            .filter(|import| import.dll.to_ascii_lowercase().starts_with("python3"))
+            .map(|import| import.name.to_string())
+            .collect(),
+        _ => BTreeSet::new(),
+    };
+    Ok(limited_api_violations(symbols, major, minor))
+}
+
+fn macho_undefined_symbols(macho: &MachO) -> Result<BTreeSet<String>> {
+    let mut symbols = BTreeSet::new();
+    for sym in macho.symbols() {
+        let (name, nlist) = sym?;
+        if nlist.is_undefined() {
+            symbols.insert(name.strip_prefix('_').unwrap_or(name).to_string());
+        }
+    }
+    Ok(symbols)
+}
+
+/// Classifies the Python symbols, i.e. those starting with `Py` or `_Py`, against the stable ABI
+fn limited_api_violations(
+    symbols: impl IntoIterator<Item = String>,
+    major: u8,
+    minor: u8,
+) -> Vec<LimitedApiViolation> {
+    let mut violations: Vec<_> = symbols
+        .into_iter()
+        .filter(|symbol| symbol.starts_with("Py") || symbol.starts_with("_Py"))
+        .filter_map(|symbol| {
+            let added = STABLE_ABI.get(&symbol).copied();
+            match added {
+                Some(added) if added <= (major, minor) => None,
+                _ => Some(LimitedApiViolation { symbol, added }),
+            }
+        })
+        .collect();
+    violations.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    violations.dedup();
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_stable_abi_manifest() {
+        assert_eq!(STABLE_ABI.get("PyArg_ParseTuple"), Some(&(3, 2)));
+        assert_eq!(STABLE_ABI.get("PyType_FromSpecWithBases"), Some(&(3, 3)));
+        assert_eq!(STABLE_ABI.get("PyObject_GetBuffer"), Some(&(3, 11)));
+        assert_eq!(STABLE_ABI.get("Py_IsFinalizing"), Some(&(3, 13)));
+        assert_eq!(STABLE_ABI.get("_PyObject_GetDictPtr"), None);
+        // Only exported by debug builds
+        assert_eq!(STABLE_ABI.get("_Py_RefTotal"), None);
+        assert_eq!(STABLE_ABI.get("_Py_NegativeRefcount"), None);
+        assert!(STABLE_ABI.len() > 900);
+    }
+
+    #[test]
+    fn test_limited_api_violations() {
+        let symbols = [
+            "PyObject_GetBuffer",
+            "PyArg_ParseTuple",
+            "_PyObject_GetDictPtr",
+            "PyModule_AddType",
+            "malloc",
+        ]
+        .map(ToString::to_string);
+        let violations = limited_api_violations(symbols.clone(), 3, 7);
+        assert_eq!(
+            violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "PyModule_AddType (added in 3.9)",
+                "PyObject_GetBuffer (added in 3.11)",
+                "_PyObject_GetDictPtr (not in the stable ABI)",
+            ]
+        );
+        let violations = limited_api_violations(symbols, 3, 11);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| violation.symbol.as_str())
+                .collect::<Vec<_>>(),
+            ["_PyObject_GetDictPtr"]
+        );
+    }
+
+    #[test]
+    fn test_find_limited_api_violations_elf() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/elf");
+        let libabi3 = fixtures.join("libabi3.so.1");
+        let violations = find_limited_api_violations(&libabi3, 3, 7).unwrap();
+        assert_eq!(
+            violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "PyObject_GetBuffer (added in 3.11)",
+                "_PyObject_GetDictPtr (not in the stable ABI)",
+            ]
+        );
+        let violations = find_limited_api_violations(&libabi3, 3, 11).unwrap();
+        assert_eq!(
+            violations,
+            [LimitedApiViolation {
+                symbol: "_PyObject_GetDictPtr".to_string(),
+                added: None,
+            }]
+        );
+        // Libraries that don't use Python have no violations
+        assert!(
+            find_limited_api_violations(&fixtures.join("libfoo.so.1"), 3, 7)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,455 @@
+//! A pure Rust equivalent of [delocate](https://github.com/matthew-brett/delocate): Finds the
+//! dylibs a Mach-O file links outside of the system paths and rewrites install names and rpaths
+//! so the copies of those dylibs in the wheel are loaded instead.
+
+use anyhow::{bail, Context, Result};
+use fat_macho::FatWriter;
+use fs_err as fs;
+use goblin::mach::load_command::CommandVariant;
+use goblin::mach::{Mach, MachO};
+use lddtree::Library;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+static IS_LIBPYTHON: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(^|/)(libpython3\.\d+\.dylib|Python\.framework/.*)$").unwrap());
+
+/// Dylibs in these locations are part of macOS, they are in the dyld shared cache and must not
+/// be bundled
+fn is_system_dylib(install_name: &str) -> bool {
+    install_name.starts_with("/usr/lib/") || install_name.starts_with("/System/")
+}
+
+/// The dylibs a Mach-O file links and its rpaths, merged over all architectures
+#[derive(Debug, Default)]
+struct LoadCommands {
+    dylibs: Vec<String>,
+    rpaths: Vec<String>,
+}
+
+impl LoadCommands {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut load_commands = LoadCommands::default();
+        match Mach::parse(data).context("Failed to parse Mach-O file")? {
+            Mach::Binary(macho) => load_commands.add(&macho),
+            Mach::Fat(fat) => {
+                for arch in fat.arches()? {
+                    let macho = MachO::parse(arch.slice(data), 0)
+                        .context("Failed to parse Mach-O file in universal binary")?;
+                    load_commands.add(&macho);
+                }
+            }
+        }
+        Ok(load_commands)
+    }
+
+    fn add(&mut self, macho: &MachO) {
+        // The first entry is the placeholder `self` for the install name of the file itself
+        for dylib in macho.libs.iter().skip(1) {
+            if !self.dylibs.iter().any(|x| x == dylib) {
+                self.dylibs.push(dylib.to_string());
+            }
+        }
+        for rpath in &macho.rpaths {
+            if !self.rpaths.iter().any(|x| x == rpath) {
+                self.rpaths.push(rpath.to_string());
+            }
+        }
+    }
+}
+
+/// Replaces `@loader_path` and `@executable_path` in an rpath or install name
+fn expand_path(path: &str, loader_dir: &Path, executable_dir: &Path) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("@loader_path") {
+        loader_dir.join(rest.trim_start_matches('/'))
+    } else if let Some(rest) = path.strip_prefix("@executable_path") {
+        executable_dir.join(rest.trim_start_matches('/'))
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Finds the file for an install name the way dyld does, falling back to the library search
+/// paths passed to the linker
+fn resolve_dylib(
+    install_name: &str,
+    loader_dir: &Path,
+    executable_dir: &Path,
+    rpaths: &[PathBuf],
+    ld_paths: &[PathBuf],
+) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if let Some(rest) = install_name.strip_prefix("@rpath/") {
+        rpaths.iter().map(|rpath| rpath.join(rest)).collect()
+    } else {
+        vec![expand_path(install_name, loader_dir, executable_dir)]
+    };
+    let file_name = Path::new(install_name).file_name()?;
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.is_absolute())
+        .chain(ld_paths.iter().map(|ld_path| ld_path.join(file_name)))
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| fs::canonicalize(candidate).ok())
+}
+
+/// Finds all dylibs outside of the system paths that a Mach-O file depends on, directly or
+/// through other dylibs
+///
+/// The name of the returned libraries is the install name as it's written in the load command
+/// of the dependent file.
+pub fn find_external_dylibs(
+    artifact: &Path,
+    ld_paths: &[PathBuf],
+    allow_linking_libpython: bool,
+) -> Result<Vec<Library>> {
+    let executable_dir = artifact.parent().unwrap_or(Path::new("."));
+    let load_commands = LoadCommands::parse(&fs::read(artifact)?)
+        .with_context(|| format!("Failed to read the load commands of {}", artifact.display()))?;
+
+    let mut queue = VecDeque::new();
+    let rpaths: Vec<PathBuf> = load_commands
+        .rpaths
+        .iter()
+        .map(|rpath| expand_path(rpath, executable_dir, executable_dir))
+        .collect();
+    for dylib in load_commands.dylibs {
+        queue.push_back((dylib, executable_dir.to_path_buf(), rpaths.clone()));
+    }
+
+    let mut seen = HashSet::new();
+    let mut libraries = Vec::new();
+    while let Some((install_name, loader_dir, rpaths)) = queue.pop_front() {
+        if is_system_dylib(&install_name)
+            || (allow_linking_libpython && IS_LIBPYTHON.is_match(&install_name))
+            || !seen.insert(install_name.clone())
+        {
+            continue;
+        }
+        let realpath = resolve_dylib(
+            &install_name,
+            &loader_dir,
+            executable_dir,
+            &rpaths,
+            ld_paths,
+        );
+        let mut library = Library {
+            name: install_name.clone(),
+            path: realpath
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(&install_name)),
+            realpath: realpath.clone(),
+            needed: Vec::new(),
+            rpath: Vec::new(),
+            runpath: Vec::new(),
+        };
+        if let Some(realpath) = realpath {
+            let load_commands = LoadCommands::parse(&fs::read(&realpath)?).with_context(|| {
+                format!("Failed to read the load commands of {}", realpath.display())
+            })?;
+            let dylib_dir = realpath.parent().unwrap().to_path_buf();
+            // dyld searches the rpaths of the dylib itself first and then the ones of the images
+            // that loaded it
+            let mut dylib_rpaths: Vec<PathBuf> = load_commands
+                .rpaths
+                .iter()
+                .map(|rpath| expand_path(rpath, &dylib_dir, executable_dir))
+                .collect();
+            dylib_rpaths.extend(rpaths);
+            for dylib in &load_commands.dylibs {
+                queue.push_back((dylib.clone(), dylib_dir.clone(), dylib_rpaths.clone()));
+            }
+            library.needed = load_commands.dylibs;
+            library.rpath = load_commands.rpaths;
+        }
+        libraries.push(library);
+    }
+    Ok(libraries)
+}
+
+/// The changes to apply to the load commands of a Mach-O file
+#[derive(Debug, Clone, Default)]
+pub struct MachORewrite {
+    /// The new install name of the dylib itself (`LC_ID_DYLIB`)
+    pub id: Option<String>,
+    /// Pairs of old and new install names of the linked dylibs (`LC_LOAD_DYLIB` and friends)
+    pub install_names: Vec<(String, String)>,
+    /// Remove all rpaths (`LC_RPATH`)
+    pub remove_rpaths: bool,
+}
+
+/// Reads a NUL terminated `lc_str` at `offset` in a load command
+fn read_lc_str(command: &[u8], offset: u32) -> Result<&str> {
+    let bytes = command
+        .get(offset as usize..)
+        .context("Invalid string offset in load command")?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).context("Invalid utf-8 in load command")
+}
+
+/// Creates a copy of a `dylib_command` with a different name
+fn dylib_command(command: &[u8], name: &str, alignment: usize) -> Vec<u8> {
+    // cmd, cmdsize, name offset, timestamp, current_version, compatibility_version
+    let header_size = 24;
+    let cmdsize = (header_size + name.len() + 1 + alignment - 1) / alignment * alignment;
+    let mut new_command = command[..header_size].to_vec();
+    new_command[4..8].copy_from_slice(&(cmdsize as u32).to_le_bytes());
+    new_command[8..12].copy_from_slice(&(header_size as u32).to_le_bytes());
+    new_command.extend_from_slice(name.as_bytes());
+    new_command.resize(cmdsize, 0);
+    new_command
+}
+
+/// Applies the rewrite to a thin (single architecture) Mach-O file
+///
+/// The load commands directly follow the header and may grow into the padding before the first
+/// section, just like `install_name_tool` does. Returns whether the file is code signed.
+fn rewrite_thin(data: &mut [u8], rewrite: &MachORewrite) -> Result<bool> {
+    let macho = MachO::parse(data, 0).context("Failed to parse Mach-O file")?;
+    if !macho.little_endian {
+        bail!("Big endian Mach-O files are not supported");
+    }
+    let (header_size, alignment) = if macho.is_64 { (32, 8) } else { (28, 4) };
+    let sizeofcmds = macho.header.sizeofcmds as usize;
+
+    // The load commands must not overlap with the first section
+    let mut max_size = data.len();
+    for segment in macho.segments.iter() {
+        for (section, _) in segment.sections()? {
+            if section.offset > 0 {
+                max_size = max_size.min(section.offset as usize);
+            }
+        }
+        if segment.filesize > 0 && segment.fileoff > 0 {
+            max_size = max_size.min(segment.fileoff as usize);
+        }
+    }
+
+    let mut signed = false;
+    let mut ncmds = 0u32;
+    let mut commands = Vec::with_capacity(sizeofcmds);
+    for load_command in &macho.load_commands {
+        let command = &data[load_command.offset..][..load_command.command.cmdsize()];
+        match &load_command.command {
+            CommandVariant::IdDylib(_) if rewrite.id.is_some() => {
+                commands.extend(dylib_command(
+                    command,
+                    rewrite.id.as_ref().unwrap(),
+                    alignment,
+                ));
+            }
+            CommandVariant::LoadDylib(dylib)
+            | CommandVariant::LoadWeakDylib(dylib)
+            | CommandVariant::ReexportDylib(dylib)
+            | CommandVariant::LazyLoadDylib(dylib)
+            | CommandVariant::LoadUpwardDylib(dylib) => {
+                let name = read_lc_str(command, dylib.dylib.name)?;
+                match rewrite.install_names.iter().find(|(old, _)| old == name) {
+                    Some((_, new)) => commands.extend(dylib_command(command, new, alignment)),
+                    None => commands.extend_from_slice(command),
+                }
+            }
+            CommandVariant::Rpath(_) if rewrite.remove_rpaths => continue,
+            CommandVariant::CodeSignature(_) => {
+                signed = true;
+                commands.extend_from_slice(command);
+            }
+            _ => commands.extend_from_slice(command),
+        }
+        ncmds += 1;
+    }
+
+    if header_size + commands.len() > max_size {
+        bail!(
+            "Not enough space in the Mach-O header to rewrite the load commands, \
+             relink with `-C link-arg=-Wl,-headerpad_max_install_names`"
+        );
+    }
+    let old_end = header_size + sizeofcmds;
+    data[header_size..old_end.max(header_size + commands.len())].fill(0);
+    data[header_size..header_size + commands.len()].copy_from_slice(&commands);
+    data[16..20].copy_from_slice(&ncmds.to_le_bytes());
+    data[20..24].copy_from_slice(&(commands.len() as u32).to_le_bytes());
+    Ok(signed)
+}
+
+/// Rewrites the install names and rpaths of a (possibly universal) Mach-O file in place
+pub fn rewrite(file: impl AsRef<Path>, rewrite: &MachORewrite) -> Result<()> {
+    let file = file.as_ref();
+    let mut data = fs::read(file)?;
+    let signed = match Mach::parse(&data)
+        .with_context(|| format!("Failed to parse Mach-O file {}", file.display()))?
+    {
+        Mach::Binary(_) => {
+            let signed = rewrite_thin(&mut data, rewrite)
+                .with_context(|| format!("Failed to rewrite {}", file.display()))?;
+            fs::write(file, &data)?;
+            signed
+        }
+        Mach::Fat(fat) => {
+            let mut signed = false;
+            let mut writer = FatWriter::new();
+            for arch in fat.arches()? {
+                let mut thin = arch.slice(&data).to_vec();
+                signed |= rewrite_thin(&mut thin, rewrite)
+                    .with_context(|| format!("Failed to rewrite {}", file.display()))?;
+                writer.add(thin)?;
+            }
+            let mut output = Vec::new();
+            writer.write_to(&mut output)?;
+            fs::write(file, output)?;
+            signed
+        }
+    };
+    if signed {
+        codesign(file);
+    }
+    Ok(())
+}
+
+/// Changing the load commands invalidates the code signature, which macOS on arm64 enforces, so
+/// we replace it with an ad-hoc signature like `install_name_tool` does
+fn codesign(file: &Path) {
+    let result = Command::new("codesign")
+        .args(["--force", "--sign", "-"])
+        .arg(file)
+        .output();
+    match result {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => eprintln!(
+            "⚠️  Warning: Failed to ad-hoc sign {}: {}",
+            file.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(_) => eprintln!(
+            "⚠️  Warning: {} has an invalid code signature after rewriting its install names \
+             and `codesign` is not available, run `codesign --force --sign -` on it on macOS",
+            file.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/macho")
+            .join(path)
+    }
+
+    #[test]
+    fn test_find_external_dylibs() {
+        let libs = find_external_dylibs(&fixture("module.dylib"), &[], false).unwrap();
+        let names: Vec<_> = libs.iter().map(|lib| lib.name.as_str()).collect();
+        assert_eq!(names, ["@rpath/libfoo.dylib", "@loader_path/libbar.dylib"]);
+        let lib_dir = fs::canonicalize(fixture("lib")).unwrap();
+        assert_eq!(libs[0].realpath, Some(lib_dir.join("libfoo.dylib")));
+        assert_eq!(libs[1].realpath, Some(lib_dir.join("libbar.dylib")));
+        assert_eq!(
+            libs[0].needed,
+            ["/usr/lib/libSystem.B.dylib", "@loader_path/libbar.dylib"]
+        );
+        assert_eq!(libs[1].rpath, ["/usr/local/lib"]);
+    }
+
+    #[test]
+    fn test_find_external_dylibs_ld_paths() {
+        // libbar doesn't link anything outside the system
+        let libs = find_external_dylibs(&fixture("lib/libbar.dylib"), &[], false).unwrap();
+        assert!(libs.is_empty());
+
+        // Without the rpath, libfoo is found through the linker search paths
+        let temp_dir = tempfile::tempdir().unwrap();
+        let module = temp_dir.path().join("module.dylib");
+        fs::copy(fixture("module.dylib"), &module).unwrap();
+        let libs = find_external_dylibs(&module, &[], false).unwrap();
+        assert_eq!(libs.len(), 1);
+        assert_eq!(libs[0].realpath, None);
+        let libs = find_external_dylibs(&module, &[fixture("lib")], false).unwrap();
+        assert_eq!(libs.len(), 2);
+        assert!(libs.iter().all(|lib| lib.realpath.is_some()));
+    }
+
+    #[test]
+    fn test_rewrite_thin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let libbar = temp_dir.path().join("libbar.dylib");
+        fs::copy(fixture("lib/libbar.dylib"), &libbar).unwrap();
+        let size = fs::metadata(&libbar).unwrap().len();
+        rewrite(
+            &libbar,
+            &MachORewrite {
+                id: Some("@rpath/libbar.dylib".to_string()),
+                install_names: vec![(
+                    "/usr/lib/libSystem.B.dylib".to_string(),
+                    "@loader_path/a/much/longer/install/name/than/before/libSystem.B.dylib"
+                        .to_string(),
+                )],
+                remove_rpaths: true,
+            },
+        )
+        .unwrap();
+        let data = fs::read(&libbar).unwrap();
+        assert_eq!(data.len() as u64, size);
+        let macho = MachO::parse(&data, 0).unwrap();
+        assert_eq!(macho.name, Some("@rpath/libbar.dylib"));
+        assert_eq!(
+            macho.libs[1..],
+            [
+                "@loader_path/a/much/longer/install/name/than/before/libSystem.B.dylib",
+                "/System/Library/Frameworks/CoreFoundation.framework/Versions/A/CoreFoundation"
+            ]
+        );
+        assert!(macho.rpaths.is_empty());
+        // The sections are untouched
+        let original = fs::read(fixture("lib/libbar.dylib")).unwrap();
+        assert_eq!(data[0x400..], original[0x400..]);
+    }
+
+    #[test]
+    fn test_rewrite_fat() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let libfoo = temp_dir.path().join("libfoo.dylib");
+        fs::copy(fixture("lib/libfoo.dylib"), &libfoo).unwrap();
+        rewrite(
+            &libfoo,
+            &MachORewrite {
+                id: None,
+                install_names: vec![(
+                    "@loader_path/libbar.dylib".to_string(),
+                    "@loader_path/libbar-renamed.dylib".to_string(),
+                )],
+                remove_rpaths: false,
+            },
+        )
+        .unwrap();
+        let load_commands = LoadCommands::parse(&fs::read(&libfoo).unwrap()).unwrap();
+        assert_eq!(
+            load_commands.dylibs,
+            [
+                "/usr/lib/libSystem.B.dylib",
+                "@loader_path/libbar-renamed.dylib"
+            ]
+        );
+        match Mach::parse(&fs::read(&libfoo).unwrap()).unwrap() {
+            Mach::Fat(fat) => assert_eq!(fat.narches, 2),
+            Mach::Binary(_) => panic!("Expected a universal binary"),
+        }
+    }
+
+    #[test]
+    fn test_rewrite_not_enough_space() {
+        let mut data = fs::read(fixture("lib/libbar.dylib")).unwrap();
+        let rewrite = MachORewrite {
+            id: Some(format!("@rpath/{}.dylib", "x".repeat(1024))),
+            ..Default::default()
+        };
+        let err = rewrite_thin(&mut data, &rewrite).unwrap_err();
+        assert!(err.to_string().contains("headerpad_max_install_names"));
+    }
+}
@@ -1,11 +1,19 @@
 mod audit;
+mod elf_rewrite;
+mod explain;
+mod limited_api;
+pub mod macho;
 mod musllinux;
 pub mod patchelf;
+pub mod pe;
 mod platform_tag;
 mod policy;
 mod repair;
 
 pub use audit::*;
+pub use explain::audit_native_files;
+pub use limited_api::{find_limited_api_violations, LimitedApiCheck};
 pub use platform_tag::PlatformTag;
-pub use policy::Policy;
+pub use policy::{AuditPolicies, Policy};
 pub use repair::find_external_libs;
+pub(crate) use repair::sanitize_rpaths;
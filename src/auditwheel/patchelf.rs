@@ -1,3 +1,4 @@
+use super::elf_rewrite::{rewrite, ElfRewrite};
 use anyhow::{bail, Context, Result};
 use std::ffi::OsStr;
 use std::path::Path;
@@ -5,6 +6,101 @@ use std::process::Command;
 
 static MISSING_PATCHELF_ERROR: &str = "Failed to execute 'patchelf', did you install it? Hint: Try `pip install maturin[patchelf]` (or just `pip install patchelf`)";
 
+/// How ELF files are modified to repair wheels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Patchelf {
+    /// Rewrite the files in-process
+    #[default]
+    Builtin,
+    /// Use the external `patchelf` binary
+    External,
+}
+
+impl Patchelf {
+    /// Checks that the external `patchelf` is installed and recent enough, if we use it
+    pub fn verify(self) -> Result<()> {
+        match self {
+            Patchelf::Builtin => Ok(()),
+            Patchelf::External => verify_patchelf(),
+        }
+    }
+
+    /// Replace a declared dependency on a dynamic library with another one (`DT_NEEDED`)
+    pub fn replace_needed<O: AsRef<OsStr>, N: AsRef<OsStr>>(
+        self,
+        file: impl AsRef<Path>,
+        old_new_pairs: &[(O, N)],
+    ) -> Result<()> {
+        match self {
+            Patchelf::Builtin => {
+                let replace_needed = old_new_pairs
+                    .iter()
+                    .map(|(old, new)| Ok((to_string(old)?, to_string(new)?)))
+                    .collect::<Result<_>>()?;
+                rewrite(
+                    file,
+                    &ElfRewrite {
+                        replace_needed,
+                        ..Default::default()
+                    },
+                )
+            }
+            Patchelf::External => replace_needed(file, old_new_pairs),
+        }
+    }
+
+    /// Change `SONAME` of a dynamic library
+    pub fn set_soname<S: AsRef<OsStr>>(self, file: impl AsRef<Path>, soname: &S) -> Result<()> {
+        match self {
+            Patchelf::Builtin => rewrite(
+                file,
+                &ElfRewrite {
+                    soname: Some(to_string(soname)?),
+                    ..Default::default()
+                },
+            ),
+            Patchelf::External => set_soname(file, soname),
+        }
+    }
+
+    /// Remove a `RPATH` from executables and libraries
+    pub fn remove_rpath(self, file: impl AsRef<Path>) -> Result<()> {
+        match self {
+            Patchelf::Builtin => rewrite(
+                file,
+                &ElfRewrite {
+                    remove_rpath: true,
+                    ..Default::default()
+                },
+            ),
+            Patchelf::External => remove_rpath(file),
+        }
+    }
+
+    /// Change the `RPATH` of executables and libraries
+    pub fn set_rpath<S: AsRef<OsStr>>(self, file: impl AsRef<Path>, rpath: &S) -> Result<()> {
+        match self {
+            Patchelf::Builtin => rewrite(
+                file,
+                &ElfRewrite {
+                    remove_rpath: true,
+                    rpath: Some(to_string(rpath)?),
+                    ..Default::default()
+                },
+            ),
+            Patchelf::External => set_rpath(file, rpath),
+        }
+    }
+}
+
+fn to_string(value: impl AsRef<OsStr>) -> Result<String> {
+    let value = value.as_ref();
+    value
+        .to_str()
+        .map(ToString::to_string)
+        .with_context(|| format!("{value:?} is not valid utf-8"))
+}
+
 /// Verify patchelf version
 pub fn verify_patchelf() -> Result<()> {
     let output = Command::new("patchelf")
@@ -0,0 +1,592 @@
+//! A pure Rust equivalent of [delvewheel](https://github.com/adang1345/delvewheel): Finds the
+//! DLLs a PE file imports outside of the system and rewrites the import tables so the
+//! name-mangled copies of those DLLs in the wheel are loaded instead.
+
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use goblin::pe::section_table::SectionTable;
+use goblin::pe::PE;
+use lddtree::Library;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::path::{Path, PathBuf};
+
+const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+const IMAGE_DIRECTORY_ENTRY_BOUND_IMPORT: usize = 11;
+const IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT: usize = 13;
+/// `IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ`
+const SECTION_CHARACTERISTICS: u32 = 0x4000_0040;
+/// The name of the section we add for the renamed DLLs
+const SECTION_NAME: &[u8; 8] = b".maturin";
+
+/// The python and C runtime DLLs are provided by the python installation
+static IS_RUNTIME_DLL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(python3(\d+)?(_d)?|vcruntime140(_1)?(d)?|ucrtbased?|msvcrt)\.dll$").unwrap()
+});
+
+/// DLLs that are part of every Windows installation
+static SYSTEM_DLLS: &[&str] = &[
+    "advapi32.dll",
+    "bcrypt.dll",
+    "bcryptprimitives.dll",
+    "cfgmgr32.dll",
+    "comctl32.dll",
+    "comdlg32.dll",
+    "crypt32.dll",
+    "d2d1.dll",
+    "d3d11.dll",
+    "d3d12.dll",
+    "dbghelp.dll",
+    "dnsapi.dll",
+    "dwmapi.dll",
+    "dwrite.dll",
+    "dxgi.dll",
+    "gdi32.dll",
+    "hid.dll",
+    "imm32.dll",
+    "iphlpapi.dll",
+    "kernel32.dll",
+    "kernelbase.dll",
+    "mswsock.dll",
+    "ncrypt.dll",
+    "netapi32.dll",
+    "normaliz.dll",
+    "ntdll.dll",
+    "ole32.dll",
+    "oleaut32.dll",
+    "opengl32.dll",
+    "powrprof.dll",
+    "propsys.dll",
+    "psapi.dll",
+    "rpcrt4.dll",
+    "secur32.dll",
+    "setupapi.dll",
+    "shcore.dll",
+    "shell32.dll",
+    "shlwapi.dll",
+    "user32.dll",
+    "userenv.dll",
+    "uxtheme.dll",
+    "version.dll",
+    "windowscodecs.dll",
+    "winhttp.dll",
+    "wininet.dll",
+    "winmm.dll",
+    "winspool.drv",
+    "wldap32.dll",
+    "ws2_32.dll",
+    "wtsapi32.dll",
+];
+
+/// Whether the DLL is part of Windows or the python installation and must not be bundled
+fn is_system_dll(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    if name.starts_with("api-ms-win-")
+        || name.starts_with("ext-ms-")
+        || IS_RUNTIME_DLL.is_match(&name)
+        || SYSTEM_DLLS.contains(&name.as_str())
+    {
+        return true;
+    }
+    // When building on Windows, we can also ask the system itself
+    env::var_os("SystemRoot")
+        .map(|root| Path::new(&root).join("System32").join(&name).is_file())
+        .unwrap_or_default()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("Unexpected end of PE file")?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("Unexpected end of PE file")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// The file offsets of the PE headers we need to read and modify
+struct Headers {
+    /// Offset of the COFF file header
+    coff_header: usize,
+    /// Offset of the optional header
+    optional_header: usize,
+    /// Offset of the data directories in the optional header
+    data_directories: usize,
+    number_of_data_directories: usize,
+    image_base: u64,
+    file_alignment: usize,
+    section_alignment: usize,
+    sections: Vec<SectionTable>,
+}
+
+impl Headers {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let pe = PE::parse(data).context("Failed to parse PE file")?;
+        let optional_header = pe
+            .header
+            .optional_header
+            .context("PE file has no optional header")?;
+        let coff_header = pe.header.dos_header.pe_pointer as usize + 4;
+        let optional_header_offset = coff_header + 20;
+        // PE32+ has a 64-bit image base and stack and heap sizes
+        let data_directories = optional_header_offset + if pe.is_64 { 112 } else { 96 };
+        Ok(Self {
+            coff_header,
+            optional_header: optional_header_offset,
+            data_directories,
+            number_of_data_directories: optional_header.windows_fields.number_of_rva_and_sizes
+                as usize,
+            image_base: optional_header.windows_fields.image_base,
+            file_alignment: optional_header.windows_fields.file_alignment as usize,
+            section_alignment: optional_header.windows_fields.section_alignment as usize,
+            sections: pe.sections,
+        })
+    }
+
+    /// Returns the rva and size of a data directory, if it's present
+    fn data_directory(&self, data: &[u8], index: usize) -> Result<Option<(u32, u32)>> {
+        if index >= self.number_of_data_directories {
+            return Ok(None);
+        }
+        let offset = self.data_directories + 8 * index;
+        let rva = read_u32(data, offset)?;
+        let size = read_u32(data, offset + 4)?;
+        Ok((rva != 0 && size != 0).then_some((rva, size)))
+    }
+
+    fn clear_data_directory(&self, data: &mut [u8], index: usize) {
+        if index < self.number_of_data_directories {
+            let offset = self.data_directories + 8 * index;
+            data[offset..offset + 8].fill(0);
+        }
+    }
+
+    /// Translates a relative virtual address to an offset in the file
+    fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        self.sections.iter().find_map(|section| {
+            let start = section.virtual_address;
+            let end = start + section.virtual_size.max(section.size_of_raw_data);
+            if (start..end).contains(&rva) && rva - start < section.size_of_raw_data {
+                Some((rva - start + section.pointer_to_raw_data) as usize)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// A DLL in the import or delay import table
+#[derive(Debug)]
+struct ImportedDll {
+    name: String,
+    /// File offset of the field that points to the name
+    name_field: usize,
+    /// Whether the field contains a virtual address instead of a relative one, which is only
+    /// used by old delay import tables
+    is_va: bool,
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> Result<&str> {
+    let bytes = data.get(offset..).context("Unexpected end of PE file")?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).context("Invalid utf-8 in DLL name")
+}
+
+/// Reads the DLL names from the import and the delay import table
+fn imported_dlls(data: &[u8], headers: &Headers) -> Result<Vec<ImportedDll>> {
+    let mut dlls = Vec::new();
+    // IMAGE_IMPORT_DESCRIPTOR: OriginalFirstThunk, TimeDateStamp, ForwarderChain, Name, FirstThunk
+    if let Some((rva, _)) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_IMPORT)? {
+        let mut offset = headers
+            .rva_to_offset(rva)
+            .context("Invalid import table address")?;
+        loop {
+            let name_rva = read_u32(data, offset + 12)?;
+            if name_rva == 0 {
+                break;
+            }
+            let name_offset = headers
+                .rva_to_offset(name_rva)
+                .context("Invalid DLL name address in import table")?;
+            dlls.push(ImportedDll {
+                name: read_cstr(data, name_offset)?.to_string(),
+                name_field: offset + 12,
+                is_va: false,
+            });
+            offset += 20;
+        }
+    }
+    // IMAGE_DELAYLOAD_DESCRIPTOR: Attributes, DllNameRVA, ModuleHandleRVA, ImportAddressTableRVA,
+    // ImportNameTableRVA, BoundImportAddressTableRVA, UnloadInformationTableRVA, TimeDateStamp
+    if let Some((rva, _)) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT)? {
+        let mut offset = headers
+            .rva_to_offset(rva)
+            .context("Invalid delay import table address")?;
+        loop {
+            let attributes = read_u32(data, offset)?;
+            let name = read_u32(data, offset + 4)?;
+            if name == 0 {
+                break;
+            }
+            let is_va = attributes & 1 == 0;
+            let name_rva = if is_va {
+                (u64::from(name))
+                    .checked_sub(headers.image_base)
+                    .context("Invalid DLL name address in delay import table")?
+                    as u32
+            } else {
+                name
+            };
+            let name_offset = headers
+                .rva_to_offset(name_rva)
+                .context("Invalid DLL name address in delay import table")?;
+            dlls.push(ImportedDll {
+                name: read_cstr(data, name_offset)?.to_string(),
+                name_field: offset + 4,
+                is_va,
+            });
+            offset += 32;
+        }
+    }
+    Ok(dlls)
+}
+
+/// Returns the DLLs a PE file imports, including delay loaded ones
+fn read_imports(path: &Path) -> Result<Vec<String>> {
+    let data = fs::read(path)?;
+    let headers = Headers::parse(&data)?;
+    let mut names: Vec<String> = Vec::new();
+    for dll in imported_dlls(&data, &headers)? {
+        if !names.iter().any(|x| x.eq_ignore_ascii_case(&dll.name)) {
+            names.push(dll.name);
+        }
+    }
+    Ok(names)
+}
+
+/// Finds a file in a directory, ignoring the case like Windows does
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if path.is_file() {
+        return Some(path);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+        })
+}
+
+/// Finds all DLLs outside of the system that a PE file depends on, directly or through other
+/// DLLs
+///
+/// Next to the directory of the importing file, we search the library search paths passed to
+/// the linker, the `bin` directories next to them (import libraries are usually in `lib`, the
+/// DLLs in `bin`), and `PATH`. DLLs that can't be found are assumed to be provided by the system.
+pub fn find_external_dlls(artifact: &Path, ld_paths: &[PathBuf]) -> Result<Vec<Library>> {
+    let artifact_dir = artifact.parent().unwrap_or(Path::new("."));
+    let mut search_dirs = vec![artifact_dir.to_path_buf()];
+    for ld_path in ld_paths {
+        search_dirs.push(ld_path.clone());
+        if let Some(parent) = ld_path.parent() {
+            search_dirs.push(parent.join("bin"));
+        }
+    }
+    if let Some(path) = env::var_os("PATH") {
+        search_dirs.extend(env::split_paths(&path));
+    }
+
+    let mut queue: VecDeque<(String, PathBuf)> = read_imports(artifact)
+        .with_context(|| format!("Failed to read the imports of {}", artifact.display()))?
+        .into_iter()
+        .map(|name| (name, artifact.to_path_buf()))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut libraries = Vec::new();
+    while let Some((name, importer)) = queue.pop_front() {
+        if is_system_dll(&name) || !seen.insert(name.to_ascii_lowercase()) {
+            continue;
+        }
+        let importer_dir = importer.parent().unwrap_or(Path::new("."));
+        let Some(path) = std::iter::once(importer_dir)
+            .chain(search_dirs.iter().map(PathBuf::as_path))
+            .find_map(|dir| find_file(dir, &name))
+        else {
+            eprintln!(
+                "⚠️  Warning: Could not find {name} imported by {}, assuming it's provided by the system",
+                importer.display()
+            );
+            continue;
+        };
+        let realpath = fs::canonicalize(&path)?;
+        let needed = read_imports(&realpath)
+            .with_context(|| format!("Failed to read the imports of {}", realpath.display()))?;
+        for dll in &needed {
+            queue.push_back((dll.clone(), realpath.clone()));
+        }
+        libraries.push(Library {
+            name,
+            path,
+            realpath: Some(realpath),
+            needed,
+            rpath: Vec::new(),
+            runpath: Vec::new(),
+        });
+    }
+    Ok(libraries)
+}
+
+/// Appends a section with the given contents and returns its relative virtual address
+///
+/// The new section header must fit into the padding after the existing section headers.
+fn add_section(data: &mut Vec<u8>, headers: &Headers, contents: &[u8]) -> Result<u32> {
+    let number_of_sections = read_u16(data, headers.coff_header + 2)? as usize;
+    let size_of_optional_header = read_u16(data, headers.coff_header + 16)? as usize;
+    let size_of_headers = read_u32(data, headers.optional_header + 60)? as usize;
+    let section_header =
+        headers.optional_header + size_of_optional_header + 40 * number_of_sections;
+    let first_section = headers
+        .sections
+        .iter()
+        .filter(|section| section.size_of_raw_data > 0)
+        .map(|section| section.pointer_to_raw_data as usize)
+        .min()
+        .unwrap_or(size_of_headers);
+    if section_header + 40 > size_of_headers.min(first_section) {
+        bail!("Not enough space in the PE headers to add a section for the renamed DLLs");
+    }
+
+    // The signature becomes invalid anyway, and the certificates must be at the end of the file
+    if let Some((offset, size)) = headers.data_directory(data, IMAGE_DIRECTORY_ENTRY_SECURITY)? {
+        eprintln!("⚠️  Warning: Removing the now invalid signature of the PE file");
+        if (offset + size) as usize == data.len() {
+            data.truncate(offset as usize);
+        }
+        headers.clear_data_directory(data, IMAGE_DIRECTORY_ENTRY_SECURITY);
+    }
+
+    let virtual_address = headers
+        .sections
+        .iter()
+        .map(|section| {
+            let size = if section.virtual_size > 0 {
+                section.virtual_size
+            } else {
+                section.size_of_raw_data
+            };
+            align(
+                (section.virtual_address + size) as usize,
+                headers.section_alignment,
+            )
+        })
+        .max()
+        .unwrap_or(headers.section_alignment);
+    let pointer_to_raw_data = align(data.len(), headers.file_alignment);
+    let size_of_raw_data = align(contents.len(), headers.file_alignment);
+    data.resize(pointer_to_raw_data, 0);
+    data.extend_from_slice(contents);
+    data.resize(pointer_to_raw_data + size_of_raw_data, 0);
+
+    // IMAGE_SECTION_HEADER
+    let header = &mut data[section_header..section_header + 40];
+    header.fill(0);
+    header[..8].copy_from_slice(SECTION_NAME);
+    write_u32(header, 8, contents.len() as u32);
+    write_u32(header, 12, virtual_address as u32);
+    write_u32(header, 16, size_of_raw_data as u32);
+    write_u32(header, 20, pointer_to_raw_data as u32);
+    write_u32(header, 36, SECTION_CHARACTERISTICS);
+
+    write_u16(data, headers.coff_header + 2, number_of_sections as u16 + 1);
+    let size_of_image = align(virtual_address + contents.len(), headers.section_alignment);
+    write_u32(data, headers.optional_header + 56, size_of_image as u32);
+    let size_of_initialized_data = read_u32(data, headers.optional_header + 8)?;
+    write_u32(
+        data,
+        headers.optional_header + 8,
+        size_of_initialized_data + size_of_raw_data as u32,
+    );
+    Ok(virtual_address as u32)
+}
+
+/// Computes the PE checksum, skipping the checksum field itself
+fn checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum: u64 = 0;
+    for (i, chunk) in data.chunks(2).enumerate() {
+        if i * 2 == checksum_offset || i * 2 == checksum_offset + 2 {
+            continue;
+        }
+        let word = u64::from(chunk[0]) | u64::from(chunk.get(1).copied().unwrap_or(0)) << 8;
+        sum += word;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum as u32 + data.len() as u32
+}
+
+/// Replace the names of imported DLLs with other ones, comparing the old names case insensitive
+///
+/// The new names are stored in a new section, so they may be longer than the old ones.
+pub fn replace_needed(file: impl AsRef<Path>, old_new_pairs: &[(String, String)]) -> Result<()> {
+    let file = file.as_ref();
+    let mut data = fs::read(file)?;
+    let headers =
+        Headers::parse(&data).with_context(|| format!("Failed to parse {}", file.display()))?;
+    let mut replacements = Vec::new();
+    let mut strings = Vec::new();
+    for dll in imported_dlls(&data, &headers)? {
+        if let Some((_, new)) = old_new_pairs
+            .iter()
+            .find(|(old, _)| old.eq_ignore_ascii_case(&dll.name))
+        {
+            replacements.push((dll, strings.len()));
+            strings.extend_from_slice(new.as_bytes());
+            strings.push(0);
+        }
+    }
+    if replacements.is_empty() {
+        return Ok(());
+    }
+
+    let section_rva = add_section(&mut data, &headers, &strings)
+        .with_context(|| format!("Failed to rewrite the imports of {}", file.display()))?;
+    for (dll, offset) in replacements {
+        let rva = section_rva + offset as u32;
+        let value = if dll.is_va {
+            (headers.image_base + u64::from(rva)) as u32
+        } else {
+            rva
+        };
+        write_u32(&mut data, dll.name_field, value);
+    }
+    // Bound imports are only an optimization and refer to the old names
+    headers.clear_data_directory(&mut data, IMAGE_DIRECTORY_ENTRY_BOUND_IMPORT);
+    let checksum_offset = headers.optional_header + 64;
+    let checksum = checksum(&data, checksum_offset);
+    write_u32(&mut data, checksum_offset, checksum);
+    fs::write(file, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/pe")
+            .join(path)
+    }
+
+    #[test]
+    fn test_is_system_dll() {
+        assert!(is_system_dll("KERNEL32.dll"));
+        assert!(is_system_dll("api-ms-win-crt-runtime-l1-1-0.dll"));
+        assert!(is_system_dll("python3.dll"));
+        assert!(is_system_dll("python312.dll"));
+        assert!(is_system_dll("VCRUNTIME140_1.dll"));
+        assert!(!is_system_dll("libfoo.dll"));
+        assert!(!is_system_dll("msvcp140.dll"));
+    }
+
+    #[test]
+    fn test_read_imports() {
+        assert_eq!(
+            read_imports(&fixture("module.pyd")).unwrap(),
+            [
+                "KERNEL32.dll",
+                "python3.dll",
+                "api-ms-win-crt-runtime-l1-1-0.dll",
+                "VCRUNTIME140.dll",
+                "libfoo.dll"
+            ]
+        );
+        // The delay loaded dll
+        assert_eq!(
+            read_imports(&fixture("lib/libfoo.dll")).unwrap(),
+            ["KERNEL32.dll", "LIBBAR.dll"]
+        );
+    }
+
+    #[test]
+    fn test_find_external_dlls() {
+        let libs = find_external_dlls(&fixture("module.pyd"), &[fixture("lib")]).unwrap();
+        let names: Vec<_> = libs.iter().map(|lib| lib.name.as_str()).collect();
+        assert_eq!(names, ["libfoo.dll", "LIBBAR.dll"]);
+        let lib_dir = fs::canonicalize(fixture("lib")).unwrap();
+        // Found case insensitive
+        assert_eq!(libs[1].realpath, Some(lib_dir.join("libbar.dll")));
+
+        let libs = find_external_dlls(&fixture("module.pyd"), &[]).unwrap();
+        assert!(libs.is_empty());
+    }
+
+    #[test]
+    fn test_replace_needed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let module = temp_dir.path().join("module.pyd");
+        fs::copy(fixture("module.pyd"), &module).unwrap();
+        replace_needed(
+            &module,
+            &[("LIBFOO.DLL".to_string(), "libfoo-0123abcd.dll".to_string())],
+        )
+        .unwrap();
+        let imports = read_imports(&module).unwrap();
+        assert_eq!(imports[4], "libfoo-0123abcd.dll");
+        assert_eq!(
+            imports[..4],
+            read_imports(&fixture("module.pyd")).unwrap()[..4]
+        );
+
+        // goblin sees the new section and the renamed import as well
+        let data = fs::read(&module).unwrap();
+        let pe = PE::parse(&data).unwrap();
+        assert_eq!(pe.sections.len(), 2);
+        assert_eq!(&pe.sections[1].name, SECTION_NAME);
+        assert!(pe.libraries.contains(&"libfoo-0123abcd.dll"));
+        let headers = Headers::parse(&data).unwrap();
+        let checksum_offset = headers.optional_header + 64;
+        assert_eq!(
+            read_u32(&data, checksum_offset).unwrap(),
+            checksum(&data, checksum_offset)
+        );
+
+        // Delay imports
+        let libfoo = temp_dir.path().join("libfoo.dll");
+        fs::copy(fixture("lib/libfoo.dll"), &libfoo).unwrap();
+        replace_needed(
+            &libfoo,
+            &[("libbar.dll".to_string(), "libbar-4567cdef.dll".to_string())],
+        )
+        .unwrap();
+        assert_eq!(
+            read_imports(&libfoo).unwrap(),
+            ["KERNEL32.dll", "libbar-4567cdef.dll"]
+        );
+    }
+}
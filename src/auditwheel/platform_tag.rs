@@ -1,10 +1,10 @@
-use crate::auditwheel::Policy;
+use crate::auditwheel::AuditPolicies;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
 /// Decides how to handle manylinux and musllinux compliance
-#[derive(Serialize, Debug, Clone, Eq, PartialEq, Copy, Ord, PartialOrd)]
+#[derive(Serialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum PlatformTag {
     /// Use the manylinux_x_y tag
@@ -23,6 +23,9 @@ pub enum PlatformTag {
     },
     /// Use the native linux tag
     Linux,
+    /// Use a custom platform tag, e.g. for a private index, which must be defined in the
+    /// `audit-policy-file`
+    Custom(String),
 }
 
 impl PlatformTag {
@@ -41,16 +44,13 @@ impl PlatformTag {
         Self::Manylinux { x: 2, y: 17 }
     }
 
-    /// manylinux aliases
-    pub fn aliases(&self) -> Vec<String> {
+    /// manylinux and custom platform tag aliases
+    pub fn aliases(&self, policies: &AuditPolicies) -> Vec<String> {
         match self {
-            PlatformTag::Manylinux { .. } => {
-                if let Some(policy) = Policy::from_name(&self.to_string()) {
-                    policy.aliases
-                } else {
-                    Vec::new()
-                }
-            }
+            PlatformTag::Manylinux { .. } | PlatformTag::Custom(_) => policies
+                .get(&self.to_string())
+                .map(|policy| policy.aliases.clone())
+                .unwrap_or_default(),
             PlatformTag::Musllinux { .. } => Vec::new(),
             PlatformTag::Linux => Vec::new(),
         }
@@ -73,22 +73,29 @@ impl PlatformTag {
         matches!(self, PlatformTag::Musllinux { .. })
     }
 
+    /// Is this a custom platform tag
+    pub fn is_custom(&self) -> bool {
+        matches!(self, PlatformTag::Custom(_))
+    }
+
     /// Is it supported by Rust compiler and manylinux project
     pub fn is_supported(&self) -> bool {
         match self {
             PlatformTag::Manylinux { x, y } => (*x, *y) >= (2, 17),
             PlatformTag::Musllinux { .. } => true,
             PlatformTag::Linux => true,
+            PlatformTag::Custom(_) => true,
         }
     }
 }
 
 impl fmt::Display for PlatformTag {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             PlatformTag::Manylinux { x, y } => write!(f, "manylinux_{x}_{y}"),
             PlatformTag::Musllinux { x, y } => write!(f, "musllinux_{x}_{y}"),
             PlatformTag::Linux => write!(f, "linux"),
+            PlatformTag::Custom(name) => f.write_str(name),
         }
     }
 }
@@ -115,6 +122,9 @@ impl FromStr for PlatformTag {
                         .and_then(|y| y.parse::<u16>().ok())
                         .ok_or("invalid musllinux option")?;
                     Ok(PlatformTag::Musllinux { x, y })
+                } else if is_custom_tag_name(&value) {
+                    // Only the policies of the audit policy file know about custom tags
+                    Err("unknown platform tag, custom platform tags must be defined in an audit policy file")
                 } else {
                     let value = value.strip_prefix("manylinux_").unwrap_or(&value);
                     let mut parts = value.split('_');
@@ -133,6 +143,17 @@ impl FromStr for PlatformTag {
     }
 }
 
+/// Custom platform tags start with a letter and contain only lowercase letters, digits and
+/// underscores, and mustn't be confused with manylinux tags
+pub(crate) fn is_custom_tag_name(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_lowercase())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !value.starts_with("manylinux")
+        && !value.starts_with("musllinux")
+}
+
 impl<'de> Deserialize<'de> for PlatformTag {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -1,11 +1,15 @@
+use crate::auditwheel::platform_tag::is_custom_tag_name;
 use crate::auditwheel::PlatformTag;
 use crate::target::Arch;
+use anyhow::{anyhow, bail, Context, Result};
+use fs_err as fs;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 use std::cmp::{Ordering, PartialOrd};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::path::Path;
 
 /// The policies (allowed symbols) for the different manylinux tags, sorted from highest
 /// priority to lowest
@@ -76,10 +80,13 @@ impl PartialOrd for Policy {
 impl Policy {
     /// Get platform tag from this policy
     pub fn platform_tag(&self) -> PlatformTag {
-        self.name.parse().expect("unknown platform tag")
+        // The names of custom policies are validated when reading the policy file
+        self.name
+            .parse()
+            .unwrap_or_else(|_| PlatformTag::Custom(self.name.clone()))
     }
 
-    /// Get policy by it's platform tag name
+    /// Get built-in policy by it's platform tag name
     pub fn from_name(name: &str) -> Option<Self> {
         let policies = if name.starts_with("musllinux") {
             &MUSLLINUX_POLICIES
@@ -113,9 +120,207 @@ impl Policy {
     }
 }
 
+/// The policies to audit against: the built-in manylinux and musllinux policies with those from
+/// an `audit-policy-file` merged in, and the policies of the custom platform tags it defines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditPolicies {
+    manylinux: Vec<Policy>,
+    musllinux: Vec<Policy>,
+    custom: Vec<Policy>,
+}
+
+impl Default for AuditPolicies {
+    fn default() -> Self {
+        AuditPolicies {
+            manylinux: MANYLINUX_POLICIES.clone(),
+            musllinux: MUSLLINUX_POLICIES.clone(),
+            custom: Vec::new(),
+        }
+    }
+}
+
+impl AuditPolicies {
+    /// Merges the policies from an `audit-policy-file` with the built-in ones, replacing those
+    /// of the same name
+    pub fn new(policies: Vec<Policy>) -> Result<Self> {
+        validate_policies(&policies)?;
+        let of_kind = |kind| {
+            policies
+                .iter()
+                .filter(|policy| PolicyKind::of(policy) == kind)
+                .cloned()
+                .collect()
+        };
+        Ok(AuditPolicies {
+            manylinux: merge_policies(&MANYLINUX_POLICIES, of_kind(PolicyKind::Manylinux))?,
+            musllinux: merge_policies(&MUSLLINUX_POLICIES, of_kind(PolicyKind::Musllinux))?,
+            custom: merge_policies(&[], of_kind(PolicyKind::Custom))?,
+        })
+    }
+
+    /// Reads the policies from an `audit-policy-file` and merges them with the built-in ones
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::new(read_policy_file(path)?)
+            .with_context(|| format!("Invalid audit policy file {}", path.display()))
+    }
+
+    /// The manylinux policies, sorted from highest priority to lowest
+    pub fn manylinux(&self) -> &[Policy] {
+        &self.manylinux
+    }
+
+    /// The musllinux policies, sorted from highest priority to lowest
+    pub fn musllinux(&self) -> &[Policy] {
+        &self.musllinux
+    }
+
+    /// The policies of the custom platform tags, sorted from highest priority to lowest
+    pub fn custom(&self) -> &[Policy] {
+        &self.custom
+    }
+
+    /// Get policy by it's platform tag name or one of its aliases
+    pub fn get(&self, name: &str) -> Option<&Policy> {
+        self.manylinux
+            .iter()
+            .chain(&self.musllinux)
+            .chain(&self.custom)
+            .find(|p| p.name == name || p.aliases.iter().any(|alias| alias == name))
+    }
+
+    /// Parses a manylinux, musllinux or linux platform tag, or the name or an alias of one of the
+    /// custom platform tags
+    pub fn parse_platform_tag(&self, value: &str) -> Result<PlatformTag> {
+        let name = value.to_ascii_lowercase();
+        if let Some(policy) = self
+            .custom
+            .iter()
+            .find(|p| p.name == name || p.aliases.contains(&name))
+        {
+            return Ok(policy.platform_tag());
+        }
+        value
+            .parse()
+            .map_err(|err| anyhow!("Invalid platform tag {value}: {err}"))
+    }
+}
+
+/// The built-in policies and the custom ones of the same kind are merged with each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PolicyKind {
+    Manylinux,
+    Musllinux,
+    Custom,
+}
+
+impl PolicyKind {
+    fn of(policy: &Policy) -> Self {
+        if policy.name.starts_with("manylinux_") {
+            PolicyKind::Manylinux
+        } else if policy.name.starts_with("musllinux_") {
+            PolicyKind::Musllinux
+        } else {
+            PolicyKind::Custom
+        }
+    }
+}
+
+/// Replaces the policies with the same name and adds the others, sorted from highest priority to
+/// lowest
+fn merge_policies(builtin: &[Policy], custom: Vec<Policy>) -> Result<Vec<Policy>> {
+    let mut policies: Vec<Policy> = builtin
+        .iter()
+        .filter(|policy| custom.iter().all(|custom| custom.name != policy.name))
+        .cloned()
+        .collect();
+    policies.extend(custom);
+    policies.sort_by_key(|policy| -policy.priority);
+    for pair in policies.windows(2) {
+        if pair[0].priority == pair[1].priority {
+            bail!(
+                "The policies {} and {} both have priority {}",
+                pair[0].name,
+                pair[1].name,
+                pair[0].priority
+            );
+        }
+    }
+    let mut names = HashSet::new();
+    for name in policies
+        .iter()
+        .flat_map(|policy| std::iter::once(&policy.name).chain(&policy.aliases))
+    {
+        if !names.insert(name) {
+            bail!("The platform tag {name} is defined by more than one policy");
+        }
+    }
+    Ok(policies)
+}
+
+/// Reads additional policies from a JSON file in the format of auditwheel's `policy.json`
+fn read_policy_file(path: &Path) -> Result<Vec<Policy>> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse the audit policy file {}", path.display()))
+}
+
+fn validate_policies(policies: &[Policy]) -> Result<()> {
+    let known_archs: HashSet<&str> = MANYLINUX_POLICIES
+        .iter()
+        .chain(MUSLLINUX_POLICIES.iter())
+        .flat_map(|policy| policy.symbol_versions.keys().map(String::as_str))
+        .collect();
+    let mut names = HashSet::new();
+    for policy in policies {
+        let platform_tag: PlatformTag = match policy.name.parse() {
+            Ok(platform_tag) => platform_tag,
+            Err(_) if is_custom_tag_name(&policy.name) => PlatformTag::Custom(policy.name.clone()),
+            Err(err) => bail!("Invalid policy name {}: {err}", policy.name),
+        };
+        if platform_tag == PlatformTag::Linux {
+            bail!("The linux policy can't be overridden");
+        }
+        if platform_tag.to_string() != policy.name {
+            bail!(
+                "The policy {} must be named {platform_tag}, use aliases for other names",
+                policy.name
+            );
+        }
+        if !names.insert(&policy.name) {
+            bail!("The policy {} is defined more than once", policy.name);
+        }
+        if policy.priority <= 0 {
+            bail!(
+                "The priority of the policy {} must be greater than 0",
+                policy.name
+            );
+        }
+        for arch in policy.symbol_versions.keys() {
+            if !known_archs.contains(arch.as_str()) {
+                bail!(
+                    "The policy {} has symbol versions for the unknown architecture {arch}",
+                    policy.name
+                );
+            }
+        }
+        for library in policy.blacklist.keys() {
+            if !policy.lib_whitelist.contains(library) {
+                bail!(
+                    "The policy {} blacklists symbols of {library}, which isn't whitelisted",
+                    policy.name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Arch, Policy, MANYLINUX_POLICIES, MUSLLINUX_POLICIES};
+    use super::{
+        merge_policies, validate_policies, Arch, AuditPolicies, PlatformTag, Policy,
+        MANYLINUX_POLICIES, MUSLLINUX_POLICIES,
+    };
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -154,4 +359,128 @@ mod test {
         policy.fixup_musl_libc_so_name(Arch::Aarch64);
         assert!(policy.lib_whitelist.contains("libc.musl-aarch64.so.1"));
     }
+
+    fn custom_policy(json: &str) -> Policy {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_merge_policies() {
+        // Override the built-in manylinux_2_17 and add a policy for a distro baseline
+        let manylinux_2_17 = custom_policy(
+            r#"{"name": "manylinux_2_17", "aliases": ["manylinux2014"], "priority": 80,
+                "symbol_versions": {}, "lib_whitelist": ["libc.so.6", "libfoo.so.1"], "blacklist": {}}"#,
+        );
+        let manylinux_2_26 = custom_policy(
+            r#"{"name": "manylinux_2_26", "aliases": [], "priority": 68,
+                "symbol_versions": {}, "lib_whitelist": ["libc.so.6"], "blacklist": {}}"#,
+        );
+        validate_policies(&[manylinux_2_17.clone(), manylinux_2_26.clone()]).unwrap();
+        let merged = merge_policies(
+            &MANYLINUX_POLICIES,
+            vec![manylinux_2_17.clone(), manylinux_2_26],
+        )
+        .unwrap();
+        assert_eq!(merged.len(), MANYLINUX_POLICIES.len() + 1);
+        let names: Vec<&str> = merged.iter().map(|policy| policy.name.as_str()).collect();
+        assert_eq!(
+            names[..6],
+            [
+                "manylinux_2_5",
+                "manylinux_2_12",
+                "manylinux_2_17",
+                "manylinux_2_24",
+                "manylinux_2_26",
+                "manylinux_2_27"
+            ]
+        );
+        assert_eq!(merged[2], manylinux_2_17);
+
+        // Priorities must be unique
+        let clash = custom_policy(
+            r#"{"name": "manylinux_2_26", "aliases": [], "priority": 70,
+                "symbol_versions": {}, "lib_whitelist": [], "blacklist": {}}"#,
+        );
+        assert!(merge_policies(&MANYLINUX_POLICIES, vec![clash]).is_err());
+    }
+
+    #[test]
+    fn test_audit_policies() {
+        let builtin = AuditPolicies::default();
+        assert_eq!(builtin.manylinux(), MANYLINUX_POLICIES.as_slice());
+        assert!(builtin.custom().is_empty());
+        assert_eq!(
+            builtin.parse_platform_tag("manylinux2014").unwrap(),
+            PlatformTag::manylinux2014()
+        );
+        // Custom platform tags are only known from the policy file
+        assert!(builtin.parse_platform_tag("acme_el8").is_err());
+        assert!("acme_el8".parse::<PlatformTag>().is_err());
+
+        let acme_el8 = custom_policy(
+            r#"{"name": "acme_el8", "aliases": ["acme8"], "priority": 75,
+                "symbol_versions": {}, "lib_whitelist": ["libc.so.6"], "blacklist": {}}"#,
+        );
+        let manylinux_2_17 = custom_policy(
+            r#"{"name": "manylinux_2_17", "aliases": ["manylinux2014"], "priority": 80,
+                "symbol_versions": {}, "lib_whitelist": ["libc.so.6", "libfoo.so.1"], "blacklist": {}}"#,
+        );
+        let policies = AuditPolicies::new(vec![acme_el8.clone(), manylinux_2_17.clone()]).unwrap();
+        assert_eq!(policies.custom(), std::slice::from_ref(&acme_el8));
+        assert_eq!(policies.musllinux(), builtin.musllinux());
+        assert_eq!(policies.get("manylinux2014"), Some(&manylinux_2_17));
+        assert_eq!(policies.get("acme8"), Some(&acme_el8));
+        let acme_el8_tag = PlatformTag::Custom("acme_el8".to_string());
+        assert_eq!(
+            policies.parse_platform_tag("ACME_EL8").unwrap(),
+            acme_el8_tag
+        );
+        assert_eq!(policies.parse_platform_tag("acme8").unwrap(), acme_el8_tag);
+        assert_eq!(acme_el8.platform_tag(), acme_el8_tag);
+        assert_eq!(acme_el8_tag.aliases(&policies), ["acme8"]);
+        assert!(policies.parse_platform_tag("acme_el9").is_err());
+    }
+
+    #[test]
+    fn test_validate_policies() {
+        let custom_tag = custom_policy(
+            r#"{"name": "acme_el8", "aliases": [], "priority": 75,
+                "symbol_versions": {"x86_64": {"GLIBC": ["2.2.5"]}}, "lib_whitelist": ["libc.so.6"],
+                "blacklist": {"libc.so.6": ["memfd_create"]}}"#,
+        );
+        validate_policies(std::slice::from_ref(&custom_tag)).unwrap();
+
+        for (json, error) in [
+            (
+                r#"{"name": "manylinux2014", "aliases": [], "priority": 80,
+                    "symbol_versions": {}, "lib_whitelist": [], "blacklist": {}}"#,
+                "must be named manylinux_2_17",
+            ),
+            (
+                r#"{"name": "linux", "aliases": [], "priority": 80,
+                    "symbol_versions": {}, "lib_whitelist": [], "blacklist": {}}"#,
+                "can't be overridden",
+            ),
+            (
+                r#"{"name": "acme_el8", "aliases": [], "priority": 0,
+                    "symbol_versions": {}, "lib_whitelist": [], "blacklist": {}}"#,
+                "must be greater than 0",
+            ),
+            (
+                r#"{"name": "acme_el8", "aliases": [], "priority": 75,
+                    "symbol_versions": {"x86-64": {}}, "lib_whitelist": [], "blacklist": {}}"#,
+                "unknown architecture x86-64",
+            ),
+            (
+                r#"{"name": "acme_el8", "aliases": [], "priority": 75,
+                    "symbol_versions": {}, "lib_whitelist": [], "blacklist": {"libc.so.6": []}}"#,
+                "isn't whitelisted",
+            ),
+        ] {
+            let err = validate_policies(&[custom_policy(json)]).unwrap_err();
+            assert!(err.to_string().contains(error), "{err}");
+        }
+        let err = validate_policies(&[custom_tag.clone(), custom_tag]).unwrap_err();
+        assert!(err.to_string().contains("defined more than once"), "{err}");
+    }
 }
@@ -1,23 +1,39 @@
-use super::audit::AuditWheelError;
+use super::audit::{is_excluded, AuditWheelError};
 use crate::auditwheel::Policy;
 use anyhow::Result;
+use glob::Pattern;
 use lddtree::DependencyAnalyzer;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 /// Find external shared library dependencies
+///
+/// Libraries matching `exclude` aren't returned, and neither are the libraries only they depend on.
 #[allow(clippy::result_large_err)]
 pub fn find_external_libs(
     artifact: impl AsRef<Path>,
     policy: &Policy,
     sysroot: PathBuf,
     ld_paths: Vec<PathBuf>,
+    exclude: &[Pattern],
 ) -> Result<Vec<lddtree::Library>, AuditWheelError> {
     let dep_analyzer = DependencyAnalyzer::new(sysroot).library_paths(ld_paths);
     let deps = dep_analyzer
         .analyze(artifact)
         .map_err(AuditWheelError::DependencyAnalysisError)?;
+    // Walk the dependency tree without descending into the excluded libraries
+    let mut reachable = HashSet::new();
+    let mut queue: Vec<&String> = deps.needed.iter().collect();
+    while let Some(name) = queue.pop() {
+        if is_excluded(name, exclude) || !reachable.insert(name.as_str()) {
+            continue;
+        }
+        if let Some(lib) = deps.libraries.get(name) {
+            queue.extend(&lib.needed);
+        }
+    }
     let mut ext_libs = Vec::new();
-    for (_, lib) in deps.libraries {
+    for (_, lib) in deps.libraries.iter() {
         let name = &lib.name;
         // Skip dynamic linker/loader and white-listed libs
         if name.starts_with("ld-linux")
@@ -27,10 +43,107 @@ pub fn find_external_libs(
             || name.starts_with("ld-musl")
             || name.starts_with("libc.")
             || policy.lib_whitelist.contains(name)
+            || !reachable.contains(name.as_str())
         {
             continue;
         }
-        ext_libs.push(lib);
+        ext_libs.push(lib.clone());
     }
     Ok(ext_libs)
 }
+
+/// Splits the rpath entries of an elf file and keeps those that point to a location within the
+/// wheel, i.e. relative to `$ORIGIN` without leaving the wheel, dropping duplicates
+///
+/// `dir_in_wheel` is the directory of the elf file in the wheel.
+/// Returns the kept entries and the removed ones, which would leak paths of the build machine.
+pub(crate) fn sanitize_rpaths(
+    rpaths: &[String],
+    dir_in_wheel: &Path,
+) -> (Vec<String>, Vec<String>) {
+    let mut kept: Vec<String> = Vec::new();
+    let mut removed: Vec<String> = Vec::new();
+    for rpath in rpaths.iter().flat_map(|rpath| rpath.split(':')) {
+        if rpath.is_empty() || kept.iter().chain(&removed).any(|seen| seen == rpath) {
+            continue;
+        }
+        if points_into_wheel(rpath, dir_in_wheel) {
+            kept.push(rpath.to_string());
+        } else {
+            removed.push(rpath.to_string());
+        }
+    }
+    (kept, removed)
+}
+
+/// Whether an rpath entry resolves to a directory within the wheel, see
+/// <https://man7.org/linux/man-pages/man8/ld.so.8.html#DESCRIPTION> for the tokens
+fn points_into_wheel(rpath: &str, dir_in_wheel: &Path) -> bool {
+    let Some(rest) = rpath
+        .strip_prefix("$ORIGIN")
+        .or_else(|| rpath.strip_prefix("${ORIGIN}"))
+    else {
+        // Absolute paths and paths relative to the working directory are outside the wheel
+        return false;
+    };
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return false;
+    }
+    let mut dir: Vec<String> = dir_in_wheel
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    for part in rest.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if dir.pop().is_none() {
+                    return false;
+                }
+            }
+            part => dir.push(part.to_string()),
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::sanitize_rpaths;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+
+    #[test]
+    fn test_sanitize_rpaths() {
+        let rpaths = [
+            "$ORIGIN:/home/ci/target/release/deps".to_string(),
+            "$ORIGIN/../foo.libs".to_string(),
+            "${ORIGIN}/../../outside".to_string(),
+            "$ORIGIN".to_string(),
+            "lib".to_string(),
+            "$ORIGINAL".to_string(),
+            "/usr/lib64".to_string(),
+            "/home/ci/target/release/deps".to_string(),
+        ];
+        let (kept, removed) = sanitize_rpaths(&rpaths, Path::new("foo"));
+        assert_eq!(kept, ["$ORIGIN", "$ORIGIN/../foo.libs"]);
+        assert_eq!(
+            removed,
+            [
+                "/home/ci/target/release/deps",
+                "${ORIGIN}/../../outside",
+                "lib",
+                "$ORIGINAL",
+                "/usr/lib64",
+            ]
+        );
+
+        // Nested packages may point to their parents
+        let (kept, removed) = sanitize_rpaths(
+            &["$ORIGIN/../../bar.libs".to_string()],
+            Path::new("foo/sub"),
+        );
+        assert_eq!(kept, ["$ORIGIN/../../bar.libs"]);
+        assert!(removed.is_empty());
+    }
+}
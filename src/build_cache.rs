@@ -0,0 +1,518 @@
+use crate::build_context::hash_file;
+use crate::{
+    BuildContext, BuiltWheelMetadata, CargoOptions, CompressionOptions, LimitedApiCheck,
+    PythonInterpreter, SbomFormat,
+};
+use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use fs_err as fs;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Environment variables that change the build output without being visible in any file
+const RELEVANT_ENV_VARS: [&str; 6] = [
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "MACOSX_DEPLOYMENT_TARGET",
+    "SOURCE_DATE_EPOCH",
+    "PYO3_CONFIG_FILE",
+    "PYO3_CROSS_LIB_DIR",
+];
+
+/// The name of the file describing a cache entry
+const ENTRY_FILE: &str = "entry.json";
+
+/// A persistent, content addressed cache of built wheels
+///
+/// Every entry is a directory named after the hash of all the inputs of a build,
+/// containing the wheels and an `entry.json` that describes them.
+#[derive(Debug, Clone)]
+pub struct BuildCache {
+    root: PathBuf,
+    /// The hash of the sources, which only needs to be computed once per build, also when
+    /// building for several interpreters in parallel
+    source_hash: Arc<OnceLock<String>>,
+}
+
+/// The metadata of a cache entry, stored as `entry.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The wheel file names and their python tags
+    wheels: Vec<(String, String)>,
+    /// Unix timestamp of when the entry was created
+    created: u64,
+    /// Unix timestamp of when the entry was last used
+    last_used: u64,
+}
+
+/// All the inputs that determine the output of a build
+#[derive(Serialize)]
+struct CacheKeyInputs<'a> {
+    maturin_version: &'static str,
+    rustc_version: &'a str,
+    version: String,
+    target_triple: &'a str,
+    bridge: String,
+    source_hash: String,
+    cargo_lock: Option<String>,
+    cargo_options: CargoOptions,
+    tool_maturin: Option<serde_json::Value>,
+    interpreters: Vec<(String, &'a str)>,
+    platform_tags: Vec<String>,
+    release: bool,
+    strip: bool,
+    skip_auditwheel: bool,
+    use_patchelf: bool,
+    audit_policies: Option<String>,
+    audit_exclude: Vec<&'a str>,
+    limited_api_check: LimitedApiCheck,
+    sbom_formats: &'a [SbomFormat],
+    third_party_licenses: Vec<&'a str>,
+    universal2: bool,
+    reproducible: bool,
+    compression: CompressionOptions,
+    zig: bool,
+    env: BTreeMap<&'static str, String>,
+}
+
+impl BuildCache {
+    /// Opens the cache at the given directory, or the default location if none is given
+    ///
+    /// The default is `MATURIN_CACHE_DIR` if set, and `maturin` in the user's cache directory
+    /// otherwise
+    pub fn new(root: Option<PathBuf>) -> Result<Self> {
+        let root = match root.or_else(|| env::var_os("MATURIN_CACHE_DIR").map(PathBuf::from)) {
+            Some(root) => root,
+            None => dirs::cache_dir()
+                .context(
+                    "Failed to determine the user's cache directory, please set MATURIN_CACHE_DIR",
+                )?
+                .join("maturin"),
+        };
+        Ok(Self {
+            root,
+            source_hash: Arc::default(),
+        })
+    }
+
+    /// The directory the cache lives in
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn source_hash(&self, context: &BuildContext) -> Result<String> {
+        if let Some(source_hash) = self.source_hash.get() {
+            return Ok(source_hash.clone());
+        }
+        let source_hash = source_hash(context)?;
+        Ok(self.source_hash.get_or_init(|| source_hash).clone())
+    }
+
+    fn entries_dir(&self) -> PathBuf {
+        self.root.join("wheels")
+    }
+
+    /// Computes the cache key of building the wheels of `context` for `interpreters`
+    pub fn key(
+        &self,
+        context: &BuildContext,
+        interpreters: &[&PythonInterpreter],
+    ) -> Result<String> {
+        // The target directory doesn't change the output, and differs between parallel builds
+        let cargo_options = CargoOptions {
+            target_dir: None,
+            ..context.cargo_options.clone()
+        };
+        let cargo_lock = context.cargo_metadata.workspace_root.join("Cargo.lock");
+        let cargo_lock = if cargo_lock.is_file() {
+            Some(hash_file(&cargo_lock)?)
+        } else {
+            None
+        };
+        let tool_maturin = context
+            .pyproject_toml
+            .as_ref()
+            .and_then(|pyproject| pyproject.maturin())
+            .map(serde_json::to_value)
+            .transpose()?;
+        let audit_policies = context
+            .audit_policy_file
+            .as_deref()
+            .map(hash_file)
+            .transpose()?;
+        #[cfg(feature = "zig")]
+        let zig = context.zig;
+        #[cfg(not(feature = "zig"))]
+        let zig = false;
+        let inputs = CacheKeyInputs {
+            maturin_version: env!("CARGO_PKG_VERSION"),
+            rustc_version: &context.target.rustc_version.short_version_string,
+            version: context.metadata23.version.to_string(),
+            target_triple: context.target.target_triple(),
+            bridge: format!("{:?}", context.bridge()),
+            source_hash: self.source_hash(context)?,
+            cargo_lock,
+            cargo_options,
+            tool_maturin,
+            interpreters: interpreters
+                .iter()
+                .map(|interpreter| {
+                    (
+                        interpreter.environment_signature(),
+                        interpreter.ext_suffix.as_str(),
+                    )
+                })
+                .collect(),
+            platform_tags: context
+                .platform_tag
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            release: context.release,
+            strip: context.strip,
+            skip_auditwheel: context.skip_auditwheel,
+            use_patchelf: context.use_patchelf,
+            audit_policies,
+            audit_exclude: context
+                .audit_exclude
+                .iter()
+                .map(|pattern| pattern.as_str())
+                .collect(),
+            limited_api_check: context.limited_api_check,
+            sbom_formats: &context.sbom_formats,
+            third_party_licenses: context
+                .metadata23
+                .third_party_licenses
+                .iter()
+                .map(|license| license.path.as_str())
+                .collect(),
+            universal2: context.universal2,
+            reproducible: context.reproducible,
+            compression: context.compression,
+            zig,
+            env: RELEVANT_ENV_VARS
+                .into_iter()
+                .filter_map(|name| Some((name, env::var(name).ok()?)))
+                .collect(),
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&inputs)?);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Copies the wheels cached under `key` to `out` and returns them, or `None` on a cache miss
+    pub fn get(&self, key: &str, out: &Path) -> Result<Option<Vec<BuiltWheelMetadata>>> {
+        let entry_dir = self.entries_dir().join(key);
+        let Some(mut entry) = read_entry(&entry_dir) else {
+            return Ok(None);
+        };
+        if entry
+            .wheels
+            .iter()
+            .any(|(filename, _)| !entry_dir.join(filename).is_file())
+        {
+            debug!("Ignoring incomplete cache entry {}", entry_dir.display());
+            return Ok(None);
+        }
+        let mut wheels = Vec::new();
+        for (filename, tag) in &entry.wheels {
+            let wheel_path = out.join(filename);
+            fs::copy(entry_dir.join(filename), &wheel_path)?;
+            wheels.push((wheel_path, tag.clone()));
+        }
+        entry.last_used = unix_timestamp();
+        fs::write(entry_dir.join(ENTRY_FILE), serde_json::to_vec(&entry)?)?;
+        Ok(Some(wheels))
+    }
+
+    /// Stores the freshly built `wheels` under `key`
+    pub fn put(&self, key: &str, wheels: &[BuiltWheelMetadata]) -> Result<()> {
+        let entries_dir = self.entries_dir();
+        fs::create_dir_all(&entries_dir)?;
+        // Assemble the entry in a temporary directory and move it into place at the end,
+        // so that concurrent builds never see a partially written entry
+        let staging = tempfile::Builder::new()
+            .prefix(".tmp-")
+            .tempdir_in(&entries_dir)?;
+        let mut entry_wheels = Vec::new();
+        for (wheel_path, tag) in wheels {
+            let filename = wheel_path
+                .file_name()
+                .context("Wheel path has no file name")?;
+            fs::copy(wheel_path, staging.path().join(filename))?;
+            entry_wheels.push((filename.to_string_lossy().to_string(), tag.clone()));
+        }
+        let now = unix_timestamp();
+        let entry = CacheEntry {
+            wheels: entry_wheels,
+            created: now,
+            last_used: now,
+        };
+        fs::write(staging.path().join(ENTRY_FILE), serde_json::to_vec(&entry)?)?;
+        let entry_dir = entries_dir.join(key);
+        if entry_dir.exists() {
+            // Another build stored the same inputs in the meantime
+            return Ok(());
+        }
+        let staging = staging.into_path();
+        if let Err(err) = fs::rename(&staging, &entry_dir) {
+            let _ = fs::remove_dir_all(&staging);
+            if !entry_dir.exists() {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the location, number of entries and size of the cache
+    pub fn info(&self) -> Result<()> {
+        let entries = self.entries()?;
+        let wheels: usize = entries.iter().map(|(_, entry)| entry.wheels.len()).sum();
+        let size: u64 = entries
+            .iter()
+            .map(|(path, _)| dir_size(path))
+            .sum::<Result<u64>>()?;
+        eprintln!("📂 Build cache at {}", self.root.display());
+        eprintln!(
+            "📦 {} cached builds with {} wheels, {} in total",
+            entries.len(),
+            wheels,
+            ByteSize(size)
+        );
+        if let Some(oldest) = entries.iter().map(|(_, entry)| entry.last_used).min() {
+            let days = unix_timestamp().saturating_sub(oldest) / (24 * 60 * 60);
+            eprintln!("⏳ The least recently used entry was last used {days} days ago");
+        }
+        Ok(())
+    }
+
+    /// Removes all entries that haven't been used for `max_age`, and leftovers of
+    /// interrupted builds
+    pub fn prune(&self, max_age: Duration) -> Result<()> {
+        let cutoff = unix_timestamp().saturating_sub(max_age.as_secs());
+        let mut removed = 0;
+        let mut freed = 0;
+        if self.entries_dir().is_dir() {
+            for dir_entry in fs::read_dir(self.entries_dir())? {
+                let path = dir_entry?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                let stale = match read_entry(&path) {
+                    Some(entry) => entry.last_used < cutoff,
+                    // Leftover of an interrupted build or a corrupted entry
+                    None => true,
+                };
+                if stale {
+                    freed += dir_size(&path)?;
+                    fs::remove_dir_all(&path)?;
+                    removed += 1;
+                }
+            }
+        }
+        eprintln!(
+            "🧹 Removed {} cached builds, freeing {}",
+            removed,
+            ByteSize(freed)
+        );
+        Ok(())
+    }
+
+    /// Removes the whole cache
+    pub fn clean(&self) -> Result<()> {
+        if self.root.exists() {
+            fs::remove_dir_all(&self.root)?;
+            eprintln!("🧹 Removed the build cache at {}", self.root.display());
+        } else {
+            eprintln!("🧹 There is no build cache at {}", self.root.display());
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<(PathBuf, CacheEntry)>> {
+        let mut entries = Vec::new();
+        if !self.entries_dir().is_dir() {
+            return Ok(entries);
+        }
+        for dir_entry in fs::read_dir(self.entries_dir())? {
+            let path = dir_entry?.path();
+            if let Some(entry) = read_entry(&path) {
+                entries.push((path, entry));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn read_entry(entry_dir: &Path) -> Option<CacheEntry> {
+    let content = fs::read(entry_dir.join(ENTRY_FILE)).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for dir_entry in fs::read_dir(path)? {
+        let dir_entry = dir_entry?;
+        let metadata = dir_entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&dir_entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Hashes the sources of all local packages in the cargo workspace and of the python project
+///
+/// Files ignored by git as well as the target and output directories are skipped. Paths are
+/// hashed relative to the workspace root, so that the key doesn't depend on the checkout location.
+fn source_hash(context: &BuildContext) -> Result<String> {
+    let workspace_root = context.cargo_metadata.workspace_root.as_std_path();
+    let mut roots: BTreeSet<PathBuf> = context
+        .extension_modules
+        .iter()
+        .map(|module| &module.cargo_metadata)
+        .chain([&context.cargo_metadata])
+        .flat_map(|cargo_metadata| &cargo_metadata.packages)
+        .filter(|package| package.source.is_none())
+        .filter_map(|package| package.manifest_path.parent())
+        .map(|dir| dir.as_std_path().to_path_buf())
+        .collect();
+    roots.insert(context.project_layout.python_dir.clone());
+    if let Some(project_root) = context.pyproject_toml_path.parent() {
+        roots.insert(project_root.to_path_buf());
+    }
+
+    let skipped_dirs = [
+        context.target_dir.clone(),
+        context
+            .cargo_metadata
+            .target_directory
+            .clone()
+            .into_std_path_buf(),
+        context.out.clone(),
+    ];
+    let mut files = BTreeSet::new();
+    for root in roots.iter().filter(|root| root.is_dir()) {
+        let walker = WalkBuilder::new(root)
+            // Hidden files such as `.cargo/config.toml` can affect the build
+            .hidden(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        for entry in walker {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type().is_some_and(|ft| ft.is_file())
+                || skipped_dirs.iter().any(|dir| path.starts_with(dir))
+            {
+                continue;
+            }
+            files.insert(path.to_path_buf());
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let relative = file.strip_prefix(workspace_root).unwrap_or(&file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(hash_file(&file)?.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BuildOptions;
+    use clap::Parser;
+    use pep440_rs::Version;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_put_get_prune() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(Some(tmp_dir.path().join("cache"))).unwrap();
+        let build_dir = tmp_dir.path().join("build");
+        let out_dir = tmp_dir.path().join("out");
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::create_dir_all(&out_dir).unwrap();
+        let wheel = build_dir.join("foo-0.1.0-py3-none-any.whl");
+        fs::write(&wheel, b"wheel").unwrap();
+
+        assert!(cache.get("abc", &out_dir).unwrap().is_none());
+        cache.put("abc", &[(wheel, "py3".to_string())]).unwrap();
+        let wheels = cache.get("abc", &out_dir).unwrap().unwrap();
+        assert_eq!(
+            wheels,
+            vec![(
+                out_dir.join("foo-0.1.0-py3-none-any.whl"),
+                "py3".to_string()
+            )]
+        );
+        assert_eq!(fs::read(&wheels[0].0).unwrap(), b"wheel");
+
+        cache.prune(Duration::from_secs(60 * 60)).unwrap();
+        assert!(cache.get("abc", &out_dir).unwrap().is_some());
+        // Simulate an entry that hasn't been used for a while
+        let entry_dir = cache.entries_dir().join("abc");
+        let mut entry = read_entry(&entry_dir).unwrap();
+        entry.last_used -= 2 * 60 * 60;
+        fs::write(
+            entry_dir.join(ENTRY_FILE),
+            serde_json::to_vec(&entry).unwrap(),
+        )
+        .unwrap();
+        cache.prune(Duration::from_secs(60 * 60)).unwrap();
+        assert!(cache.get("abc", &out_dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_key_version() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(Some(tmp_dir.path().join("cache"))).unwrap();
+        let target_dir = tmp_dir.path().join("target");
+        let build_options = BuildOptions::try_parse_from([
+            "build",
+            "--manifest-path",
+            "test-crates/hello-world/Cargo.toml",
+            "--quiet",
+            "--target-dir",
+            target_dir.to_str().unwrap(),
+        ])
+        .unwrap();
+        let mut context = build_options
+            .into_build_context(false, false, false)
+            .unwrap();
+        let key = cache.key(&context, &[]).unwrap();
+        // The clones used for parallel builds reuse the hash of the sources
+        let cached_hash = cache.clone().source_hash.get().cloned();
+        assert_eq!(cached_hash, Some(source_hash(&context).unwrap()));
+        assert_eq!(cache.key(&context, &[]).unwrap(), key);
+
+        let wheel = tmp_dir.path().join("hello_world-0.1.0-py3-none-any.whl");
+        fs::write(&wheel, b"wheel").unwrap();
+        cache.put(&key, &[(wheel, "py3".to_string())]).unwrap();
+
+        // A version bump without any source change, e.g. from `dynamic = ["version"]`
+        context.metadata23.version = Version::from_str("0.2.0").unwrap();
+        let bumped_key = cache.key(&context, &[]).unwrap();
+        assert_ne!(bumped_key, key);
+        assert!(cache.get(&bumped_key, tmp_dir.path()).unwrap().is_none());
+    }
+}
@@ -1,22 +1,33 @@
-use crate::auditwheel::{get_policy_and_libs, patchelf, relpath};
-use crate::auditwheel::{PlatformTag, Policy};
+use crate::auditwheel::patchelf::{self, Patchelf};
+use crate::auditwheel::{
+    find_excluded_libs, find_limited_api_violations, get_policy_and_libs, macho, pe, relpath,
+    sanitize_rpaths,
+};
+use crate::auditwheel::{AuditPolicies, LimitedApiCheck, PlatformTag, Policy};
+#[cfg(feature = "build-cache")]
+use crate::build_cache::BuildCache;
 use crate::build_options::CargoOptions;
 use crate::compile::{warn_missing_py_init, CompileTarget};
+use crate::compression::CompressionOptions;
+use crate::debuginfo::{split_elf_debuginfo, write_debuginfo_archive};
+use crate::message::wheel_tags;
 use crate::module_writer::{
-    add_data, write_bin, write_bindings_module, write_cffi_module, write_python_part,
-    write_uniffi_module, write_wasm_launcher, WheelWriter,
+    add_data, extension_module_dir, write_bin, write_bindings_module, write_cffi_module,
+    write_python_part, write_uniffi_module, write_wasm_launcher, WheelWriter,
 };
 use crate::project_layout::ProjectLayout;
 use crate::python_interpreter::InterpreterKind;
+use crate::sbom::{sbom_creation_time, Sbom, TargetCfg};
 use crate::source_distribution::source_distribution;
 use crate::target::{Arch, Os};
 use crate::{
-    compile, pyproject_toml::Format, BuildArtifact, Metadata23, ModuleWriter, PyProjectToml,
-    PythonInterpreter, Target,
+    compile, pyproject_toml::Format, BuildArtifact, GraftedLibrary, Message, MessageFormat,
+    Metadata23, ModuleWriter, PyProjectToml, PythonInterpreter, SbomFormat, Target,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use cargo_metadata::Metadata;
 use fs_err as fs;
+use glob::Pattern;
 use ignore::overrides::{Override, OverrideBuilder};
 use indexmap::IndexMap;
 use lddtree::Library;
@@ -28,8 +39,12 @@ use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::{Display, Formatter};
 use std::io;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
 /// The way the rust code is used in the wheel
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -139,6 +154,35 @@ fn bin_wasi_helper(
     Ok(metadata23)
 }
 
+/// An additional native extension module that is built from its own crate into the same wheel,
+/// see `[[tool.maturin.extension-modules]]`
+#[derive(Clone)]
+pub struct ExtensionModule {
+    /// The full module name, e.g. `foo._simd`
+    pub module_name: String,
+    /// The name of the crate
+    pub crate_name: String,
+    /// The path to the Cargo.toml of the crate
+    pub manifest_path: PathBuf,
+    /// Features to activate
+    pub features: Vec<String>,
+    /// Activate all available features
+    pub all_features: bool,
+    /// Do not activate the `default` feature
+    pub no_default_features: bool,
+    /// Cargo.toml as resolved by [cargo_metadata]
+    pub cargo_metadata: Metadata,
+    /// The cargo targets to compile
+    pub compile_targets: Vec<CompileTarget>,
+}
+
+impl ExtensionModule {
+    /// The last part of the module name, which is the name of the shared library
+    pub fn extension_name(&self) -> &str {
+        self.module_name.rsplit('.').next().unwrap()
+    }
+}
+
 /// Contains all the metadata required to build the crate
 #[derive(Clone)]
 pub struct BuildContext {
@@ -162,6 +206,8 @@ pub struct BuildContext {
     pub module_name: String,
     /// The path to the Cargo.toml. Required for the cargo invocations
     pub manifest_path: PathBuf,
+    /// Additional extension modules that are built from other crates into the same wheel
+    pub extension_modules: Vec<ExtensionModule>,
     /// Directory for all generated artifacts
     pub target_dir: PathBuf,
     /// The directory to store the built wheels in. Defaults to a new "wheels"
@@ -173,6 +219,17 @@ pub struct BuildContext {
     pub strip: bool,
     /// Skip checking the linked libraries for manylinux/musllinux compliance
     pub skip_auditwheel: bool,
+    /// Use the external `patchelf` binary instead of the built-in ELF rewriter
+    pub use_patchelf: bool,
+    /// JSON file with additional or overriding audit policies
+    pub audit_policy_file: Option<PathBuf>,
+    /// The policies to audit the wheels against, including those from the `audit_policy_file`
+    pub audit_policies: AuditPolicies,
+    /// Libraries that are left as they are when repairing the wheel, because the system
+    /// provides them at runtime
+    pub audit_exclude: Vec<Pattern>,
+    /// What to do when an abi3 library uses symbols outside of the limited API
+    pub limited_api_check: LimitedApiCheck,
     /// When compiling for manylinux, use zig as linker to ensure glibc version compliance
     #[cfg(feature = "zig")]
     pub zig: bool,
@@ -186,8 +243,25 @@ pub struct BuildContext {
     pub universal2: bool,
     /// Build editable wheels
     pub editable: bool,
+    /// Write byte-for-byte reproducible wheels and source distributions
+    pub reproducible: bool,
+    /// The zip compression method and level of the wheels
+    pub compression: CompressionOptions,
+    /// Move the debug info into a separate `.debug.zip` archive next to each wheel
+    pub split_debuginfo: bool,
+    /// Whether to print machine-readable messages for the build steps
+    pub message_format: MessageFormat,
+    /// The formats of the software bill of materials in the wheels, none if empty
+    pub sbom_formats: Vec<SbomFormat>,
     /// Cargo build options
     pub cargo_options: CargoOptions,
+    /// Maximum number of interpreters to build wheels for concurrently
+    pub parallel_interpreters: usize,
+    /// Prefix for output lines, used to tell apart concurrent builds
+    pub log_prefix: Option<String>,
+    /// Reuse wheels from this cache if none of the build inputs changed
+    #[cfg(feature = "build-cache")]
+    pub build_cache: Option<BuildCache>,
 }
 
 /// The wheel file location and its Python version tag (e.g. `py3`).
@@ -206,8 +280,11 @@ impl BuildContext {
             .context("Failed to create the target directory for the wheels")?;
 
         let wheels = match self.bridge() {
-            BridgeModel::Bin(None) => self.build_bin_wheel(None)?,
-            BridgeModel::Bin(Some(..)) => self.build_bin_wheels(&self.interpreter)?,
+            BridgeModel::Bin(None) => self.cached_build(&[], || self.build_bin_wheel(None))?,
+            BridgeModel::Bin(Some(..)) => {
+                let interpreters: Vec<_> = self.interpreter.iter().collect();
+                self.cached_build(&interpreters, || self.build_bin_wheels(&self.interpreter))?
+            }
             BridgeModel::Bindings(..) => self.build_binding_wheels(&self.interpreter)?,
             BridgeModel::BindingsAbi3(major, minor) => {
                 let abi3_interps: Vec<_> = self
@@ -224,11 +301,10 @@ impl BuildContext {
                     .collect();
                 let mut built_wheels = Vec::new();
                 if !abi3_interps.is_empty() {
-                    built_wheels.extend(self.build_binding_wheel_abi3(
-                        &abi3_interps,
-                        *major,
-                        *minor,
-                    )?);
+                    let interpreters: Vec<_> = abi3_interps.iter().collect();
+                    built_wheels.extend(self.cached_build(&interpreters, || {
+                        self.build_binding_wheel_abi3(&abi3_interps, *major, *minor)
+                    })?);
                 }
                 if !non_abi3_interps.is_empty() {
                     let interp_names: HashSet<_> = non_abi3_interps
@@ -248,17 +324,95 @@ impl BuildContext {
                 }
                 built_wheels
             }
-            BridgeModel::Cffi => self.build_cffi_wheel()?,
-            BridgeModel::UniFfi => self.build_uniffi_wheel()?,
+            BridgeModel::Cffi => {
+                let interpreters: Vec<_> = self.interpreter.iter().collect();
+                self.cached_build(&interpreters, || self.build_cffi_wheel())?
+            }
+            BridgeModel::UniFfi => self.cached_build(&[], || self.build_uniffi_wheel())?,
         };
 
+        for (wheel_path, _) in &wheels {
+            self.emit(|| {
+                Ok(Message::WheelWritten {
+                    path: wheel_path.clone(),
+                    tags: wheel_tags(wheel_path),
+                    sha256: hash_file(wheel_path)?,
+                })
+            })?;
+        }
+
+        Ok(wheels)
+    }
+
+    /// Prints the message with `--message-format json`
+    pub(crate) fn emit(&self, message: impl FnOnce() -> Result<Message>) -> Result<()> {
+        if self.message_format == MessageFormat::Json {
+            message()?.print();
+        }
+        Ok(())
+    }
+
+    /// Returns the cached wheels if the build cache is enabled and none of the inputs of
+    /// building for `interpreters` changed, otherwise runs `build` and caches its wheels
+    #[cfg(feature = "build-cache")]
+    fn cached_build(
+        &self,
+        interpreters: &[&PythonInterpreter],
+        build: impl FnOnce() -> Result<Vec<BuiltWheelMetadata>>,
+    ) -> Result<Vec<BuiltWheelMetadata>> {
+        // Editable wheels point into the source tree, there's no point in caching them.
+        // The cache also doesn't store the debug info archives.
+        let Some(cache) = self
+            .build_cache
+            .as_ref()
+            .filter(|_| !self.editable && !self.split_debuginfo)
+        else {
+            return build();
+        };
+        let log_prefix = self.log_prefix.as_deref().unwrap_or_default();
+        let key = match cache.key(self, interpreters) {
+            Ok(key) => key,
+            Err(err) => {
+                eprintln!(
+                    "{log_prefix}⚠️  Warning: Failed to compute the build cache key: {err:#}"
+                );
+                return build();
+            }
+        };
+        if let Some(wheels) = cache.get(&key, &self.out)? {
+            for (wheel_path, _) in &wheels {
+                eprintln!("{log_prefix}💾 Using cached wheel {}", wheel_path.display());
+            }
+            return Ok(wheels);
+        }
+        let wheels = build()?;
+        if let Err(err) = cache.put(&key, &wheels) {
+            eprintln!(
+                "{log_prefix}⚠️  Warning: Failed to store the wheels in the build cache: {err:#}"
+            );
+        }
         Ok(wheels)
     }
 
+    #[cfg(not(feature = "build-cache"))]
+    fn cached_build(
+        &self,
+        _interpreters: &[&PythonInterpreter],
+        build: impl FnOnce() -> Result<Vec<BuiltWheelMetadata>>,
+    ) -> Result<Vec<BuiltWheelMetadata>> {
+        build()
+    }
+
     /// Bridge model
+    ///
+    /// With per-target bindings, this is the bridge model of the extension module, while
+    /// the binaries are added to its wheel
     pub fn bridge(&self) -> &BridgeModel {
-        // FIXME: currently we only allow multiple bin targets so bridges are all the same
-        &self.compile_targets[0].bridge_model
+        self.compile_targets
+            .iter()
+            .map(|target| &target.bridge_model)
+            .find(|bridge| !bridge.is_bin())
+            .unwrap_or(&self.compile_targets[0].bridge_model)
     }
 
     /// Builds a source distribution and returns the same metadata as [BuildContext::build_wheels]
@@ -271,6 +425,12 @@ impl BuildContext {
                 let sdist_path =
                     source_distribution(self, pyproject, self.excludes(Format::Sdist)?)
                         .context("Failed to build source distribution")?;
+                self.emit(|| {
+                    Ok(Message::SdistWritten {
+                        sha256: hash_file(&sdist_path)?,
+                        path: sdist_path.clone(),
+                    })
+                })?;
                 Ok(Some((sdist_path, "source".to_string())))
             }
             None => Ok(None),
@@ -299,32 +459,79 @@ impl BuildContext {
             }
         }
 
+        // only bin bindings allow linking to libpython, extension modules must not
+        let allow_linking_libpython = self.bridge().is_bin();
+        if self.target.is_macos() {
+            let ld_paths: Vec<PathBuf> = artifact.linked_paths.iter().map(PathBuf::from).collect();
+            let external_libs =
+                macho::find_external_dylibs(&artifact.path, &ld_paths, allow_linking_libpython)
+                    .context("Error finding the external dylibs to repair the wheel")?;
+            return Ok((Policy::default(), external_libs));
+        }
+        if self.target.is_windows() {
+            let ld_paths: Vec<PathBuf> = artifact.linked_paths.iter().map(PathBuf::from).collect();
+            let external_libs = pe::find_external_dlls(&artifact.path, &ld_paths)
+                .context("Error finding the external DLLs to repair the wheel")?;
+            return Ok((Policy::default(), external_libs));
+        }
+
         let mut musllinux: Vec<_> = platform_tag
             .iter()
             .filter(|tag| tag.is_musllinux())
-            .copied()
             .collect();
         musllinux.sort();
         let mut others: Vec<_> = platform_tag
             .iter()
             .filter(|tag| !tag.is_musllinux())
-            .copied()
             .collect();
         others.sort();
 
-        // only bin bindings allow linking to libpython, extension modules must not
-        let allow_linking_libpython = self.bridge().is_bin();
         if self.bridge().is_bin() && !musllinux.is_empty() {
             return get_policy_and_libs(
                 artifact,
                 Some(musllinux[0]),
+                &self.audit_policies,
                 &self.target,
                 allow_linking_libpython,
+                &self.audit_exclude,
             );
         }
 
         let tag = others.first().or_else(|| musllinux.first()).copied();
-        get_policy_and_libs(artifact, tag, &self.target, allow_linking_libpython)
+        get_policy_and_libs(
+            artifact,
+            tag,
+            &self.audit_policies,
+            &self.target,
+            allow_linking_libpython,
+            &self.audit_exclude,
+        )
+    }
+
+    /// The metadata for the wheel, with the libraries excluded from repairing the wheel that
+    /// the artifacts link added as `Requires-External`
+    fn wheel_metadata(&self, artifacts: &[BuildArtifact]) -> Result<Metadata23> {
+        let mut metadata23 = self.metadata23.clone();
+        if self.skip_auditwheel || !self.target.is_linux() {
+            return Ok(metadata23);
+        }
+        for artifact in artifacts {
+            for lib in find_excluded_libs(&artifact.path, &self.audit_exclude)? {
+                if !metadata23.requires_external.contains(&lib) {
+                    metadata23.requires_external.push(lib);
+                }
+            }
+        }
+        Ok(metadata23)
+    }
+
+    /// The tool to modify ELF files with
+    fn patchelf(&self) -> Patchelf {
+        if self.use_patchelf {
+            Patchelf::External
+        } else {
+            Patchelf::Builtin
+        }
     }
 
     /// Add library search paths in Cargo target directory rpath when building in editable mode
@@ -342,7 +549,7 @@ impl BuildContext {
                     }
                 }
                 let new_rpath = new_rpaths.join(":");
-                if let Err(err) = patchelf::set_rpath(&artifact.path, &new_rpath) {
+                if let Err(err) = self.patchelf().set_rpath(&artifact.path, &new_rpath) {
                     eprintln!(
                         "⚠️ Warning: Failed to set rpath for {}: {}",
                         artifact.path.display(),
@@ -354,20 +561,81 @@ impl BuildContext {
         Ok(())
     }
 
+    /// With `--split-debuginfo`, moves the debug info of the artifacts into a
+    /// `<name>-<version>-<tag>.debug.zip` archive next to the wheel
+    ///
+    /// The artifacts are replaced with stripped copies, so cargo's output stays intact
+    fn split_debuginfo(&self, tag: &str, artifacts: &mut [BuildArtifact]) -> Result<()> {
+        if !self.split_debuginfo || self.editable {
+            return Ok(());
+        }
+        let mut debug_infos = Vec::with_capacity(artifacts.len());
+        for artifact in artifacts.iter_mut() {
+            let debuginfo_dir = artifact.path.parent().unwrap().join("debuginfo");
+            fs::create_dir_all(&debuginfo_dir)?;
+            let stripped = debuginfo_dir.join(artifact.path.file_name().unwrap());
+            fs::copy(&artifact.path, &stripped)?;
+            debug_infos.push(split_elf_debuginfo(&stripped, self.strip).with_context(|| {
+                format!(
+                    "Failed to split the debug info of {}",
+                    artifact.path.display()
+                )
+            })?);
+            artifact.path = stripped;
+        }
+        let archive_path = self.out.join(format!(
+            "{}-{}-{}.debug.zip",
+            self.metadata23.get_distribution_escaped(),
+            self.metadata23.get_version_escaped(),
+            tag
+        ));
+        write_debuginfo_archive(
+            &archive_path,
+            &debug_infos,
+            self.compression,
+            self.reproducible,
+        )?;
+        eprintln!(
+            "{}🐛 Split debug info to {}",
+            self.log_prefix.as_deref().unwrap_or_default(),
+            archive_path.display()
+        );
+        Ok(())
+    }
+
+    /// Bundles the external libraries of the artifacts into the wheel
+    ///
+    /// The artifacts are given with their directory in the wheel, which the paths to the
+    /// bundled libraries are relative to.
     fn add_external_libs(
         &self,
         writer: &mut WheelWriter,
-        artifacts: &[&BuildArtifact],
+        artifacts: &[(&BuildArtifact, PathBuf)],
         ext_libs: &[Vec<Library>],
-    ) -> Result<()> {
+    ) -> Result<Vec<GraftedLibrary>> {
         if self.editable {
-            return self.add_rpath(artifacts);
+            let artifacts: Vec<_> = artifacts.iter().map(|(artifact, _)| *artifact).collect();
+            self.add_rpath(&artifacts)?;
+            return Ok(Vec::new());
         }
         if ext_libs.iter().all(|libs| libs.is_empty()) {
-            return Ok(());
+            if self.target.is_linux() && !self.skip_auditwheel {
+                let artifacts: Vec<(&Path, &Path)> = artifacts
+                    .iter()
+                    .map(|(artifact, artifact_dir)| {
+                        (artifact.path.as_path(), artifact_dir.as_path())
+                    })
+                    .collect();
+                sanitize_artifact_rpaths(self.patchelf(), &artifacts)?;
+            }
+            return Ok(Vec::new());
+        }
+        if self.target.is_macos() {
+            return self.add_external_dylibs(writer, artifacts, ext_libs);
+        }
+        if self.target.is_windows() {
+            return self.add_external_dlls(writer, artifacts, ext_libs);
         }
-
-        patchelf::verify_patchelf()?;
 
         // Put external libs to ${module_name}.libs directory
         // See https://github.com/pypa/auditwheel/issues/89
@@ -379,11 +647,47 @@ impl BuildContext {
             .unwrap_or_else(|| self.module_name.clone().into());
         libs_dir.push(".libs");
         let libs_dir = PathBuf::from(libs_dir);
+
+        let artifacts: Vec<(&Path, &Path)> = artifacts
+            .iter()
+            .map(|(artifact, artifact_dir)| (artifact.path.as_path(), artifact_dir.as_path()))
+            .collect();
+        let grafted =
+            graft_external_libs(writer, self.patchelf(), &libs_dir, &artifacts, ext_libs)?;
+        self.emit(|| {
+            Ok(Message::LibrariesGrafted {
+                libs_dir: libs_dir.clone(),
+                libraries: grafted.clone(),
+            })
+        })?;
+        Ok(grafted)
+    }
+
+    /// Copies the external dylibs into the wheel and points the install names of the artifacts
+    /// to them, like `delocate` does for macOS wheels
+    fn add_external_dylibs(
+        &self,
+        writer: &mut WheelWriter,
+        artifacts: &[(&BuildArtifact, PathBuf)],
+        ext_libs: &[Vec<Library>],
+    ) -> Result<Vec<GraftedLibrary>> {
+        // Put external libs to ${module_name}.dylibs directory
+        let mut libs_dir = self
+            .project_layout
+            .python_module
+            .as_ref()
+            .and_then(|py| py.file_name().map(|s| s.to_os_string()))
+            .unwrap_or_else(|| self.module_name.clone().into());
+        libs_dir.push(".dylibs");
+        let libs_dir = PathBuf::from(libs_dir);
         writer.add_directory(&libs_dir)?;
 
         let temp_dir = tempfile::tempdir()?;
-        let mut soname_map = HashMap::new();
-        let mut libs_copied = HashSet::new();
+        // The file names in the libs dir by the real path of the dylib
+        let mut file_names: HashMap<PathBuf, String> = HashMap::new();
+        // The file names in the libs dir by the install names used in the load commands
+        let mut install_names: HashMap<String, String> = HashMap::new();
+        let mut grafted = Vec::new();
         for lib in ext_libs.iter().flatten() {
             let lib_path = lib.realpath.clone().with_context(|| {
                 format!(
@@ -391,21 +695,22 @@ impl BuildContext {
                     lib.path.display()
                 )
             })?;
-            // Generate a new soname with a short hash
-            let short_hash = &hash_file(&lib_path)?[..8];
-            let (file_stem, file_ext) = lib.name.split_once('.').unwrap();
-            let new_soname = if !file_stem.ends_with(&format!("-{short_hash}")) {
-                format!("{file_stem}-{short_hash}.{file_ext}")
-            } else {
-                format!("{file_stem}.{file_ext}")
-            };
-
-            // Copy the original lib to a tmpdir and modify some of its properties
-            // for example soname and rpath
-            let dest_path = temp_dir.path().join(&new_soname);
-            fs::copy(&lib_path, &dest_path)?;
-            libs_copied.insert(lib_path);
+            if let Some(file_name) = file_names.get(&lib_path) {
+                install_names.insert(lib.name.clone(), file_name.clone());
+                continue;
+            }
+            let file_name = lib_path.file_name().unwrap().to_string_lossy().to_string();
+            if let Some((other, _)) = file_names.iter().find(|(_, name)| **name == file_name) {
+                bail!(
+                    "Cannot repair wheel, because the required libraries {} and {} have the \
+                     same file name",
+                    other.display(),
+                    lib_path.display()
+                );
+            }
 
+            let dest_path = temp_dir.path().join(&file_name);
+            fs::copy(&lib_path, &dest_path)?;
             // fs::copy copies permissions as well, and the original
             // file may have been read-only
             let mut perms = fs::metadata(&dest_path)?.permissions();
@@ -413,70 +718,186 @@ impl BuildContext {
             perms.set_readonly(false);
             fs::set_permissions(&dest_path, perms)?;
 
-            patchelf::set_soname(&dest_path, &new_soname)?;
-            if !lib.rpath.is_empty() || !lib.runpath.is_empty() {
-                patchelf::set_rpath(&dest_path, &libs_dir)?;
-            }
-            soname_map.insert(
-                lib.name.clone(),
-                (new_soname.clone(), dest_path.clone(), lib.needed.clone()),
-            );
+            grafted.push(GraftedLibrary {
+                name: lib.name.clone(),
+                path: lib_path.clone(),
+                grafted_name: file_name.clone(),
+            });
+            file_names.insert(lib_path, file_name.clone());
+            install_names.insert(lib.name.clone(), file_name);
+        }
+
+        // The grafted dylibs load each other from the same directory and don't need their
+        // rpaths anymore
+        let grafted_install_names: Vec<(String, String)> = install_names
+            .iter()
+            .map(|(old, file_name)| (old.clone(), format!("@loader_path/{file_name}")))
+            .collect();
+        for file_name in file_names.values() {
+            let path = temp_dir.path().join(file_name);
+            macho::rewrite(
+                &path,
+                &macho::MachORewrite {
+                    id: Some(format!("@rpath/{file_name}")),
+                    install_names: grafted_install_names.clone(),
+                    remove_rpaths: true,
+                },
+            )?;
+            writer.add_file_with_permissions(libs_dir.join(file_name), &path, 0o755)?;
         }
 
-        for (artifact, artifact_ext_libs) in artifacts.iter().zip(ext_libs) {
-            let artifact_deps: HashSet<_> = artifact_ext_libs.iter().map(|lib| &lib.name).collect();
-            let replacements = soname_map
+        eprintln!(
+            "🖨  Copied external shared libraries to package {} directory:",
+            libs_dir.display()
+        );
+        for lib_path in file_names.keys() {
+            eprintln!("    {}", lib_path.display());
+        }
+        self.emit(|| {
+            Ok(Message::LibrariesGrafted {
+                libs_dir: libs_dir.clone(),
+                libraries: grafted.clone(),
+            })
+        })?;
+
+        for (artifact, artifact_dir) in artifacts {
+            let loader_path = Path::new("@loader_path").join(relpath(&libs_dir, artifact_dir));
+            let artifact_install_names: Vec<(String, String)> = install_names
                 .iter()
-                .filter_map(|(k, v)| {
-                    if artifact_deps.contains(k) {
-                        Some((k, v.0.clone()))
-                    } else {
-                        None
-                    }
+                .map(|(old, file_name)| {
+                    let new = loader_path.join(file_name);
+                    (old.clone(), new.to_str().unwrap().to_string())
                 })
-                .collect::<Vec<_>>();
-            if !replacements.is_empty() {
-                patchelf::replace_needed(&artifact.path, &replacements[..])?;
-            }
+                .collect();
+            macho::rewrite(
+                &artifact.path,
+                &macho::MachORewrite {
+                    install_names: artifact_install_names,
+                    ..Default::default()
+                },
+            )?;
         }
+        Ok(grafted)
+    }
 
-        // we grafted in a bunch of libraries and modified their sonames, but
-        // they may have internal dependencies (DT_NEEDED) on one another, so
-        // we need to update those records so each now knows about the new
-        // name of the other.
-        for (new_soname, path, needed) in soname_map.values() {
-            let mut replacements = Vec::new();
-            for n in needed {
-                if soname_map.contains_key(n) {
-                    replacements.push((n, soname_map[n].0.clone()));
-                }
+    /// Copies the external DLLs into the wheel with unique names and points the imports of the
+    /// artifacts to them, like `delvewheel` does for Windows wheels
+    ///
+    /// Windows has no equivalent of rpath, so the `__init__.py` of the package adds the
+    /// directory with the DLLs to the DLL search path before the extension module is imported.
+    fn add_external_dlls(
+        &self,
+        writer: &mut WheelWriter,
+        artifacts: &[(&BuildArtifact, PathBuf)],
+        ext_libs: &[Vec<Library>],
+    ) -> Result<Vec<GraftedLibrary>> {
+        if self.bridge().is_bin() {
+            eprintln!(
+                "⚠️  Warning: Can't bundle DLLs with binaries, make sure that these DLLs are \
+                 installed on the system:"
+            );
+            for lib in ext_libs.iter().flatten() {
+                eprintln!("    {}", lib.path.display());
             }
-            if !replacements.is_empty() {
-                patchelf::replace_needed(path, &replacements[..])?;
+            return Ok(Vec::new());
+        }
+
+        // Put external libs to ${module_name}.libs directory
+        let package = self
+            .project_layout
+            .python_module
+            .as_ref()
+            .and_then(|py| py.file_name().map(|s| s.to_os_string()))
+            .unwrap_or_else(|| self.module_name.clone().into());
+        let mut libs_dir = package.clone();
+        libs_dir.push(".libs");
+        let libs_dir = PathBuf::from(libs_dir);
+        writer.add_directory(&libs_dir)?;
+
+        let temp_dir = tempfile::tempdir()?;
+        // The new file names by the names in the import tables, which windows compares case
+        // insensitive
+        let mut dll_names: HashMap<String, String> = HashMap::new();
+        let mut dlls = Vec::new();
+        let mut grafted = Vec::new();
+        for lib in ext_libs.iter().flatten() {
+            if dll_names.contains_key(&lib.name.to_ascii_lowercase()) {
+                continue;
             }
-            writer.add_file_with_permissions(libs_dir.join(new_soname), path, 0o755)?;
+            let lib_path = lib.realpath.clone().with_context(|| {
+                format!(
+                    "Cannot repair wheel, because required library {} could not be located.",
+                    lib.path.display()
+                )
+            })?;
+            // Generate a new name with a short hash, so that DLLs with the same name from other
+            // packages don't clash in the process
+            let short_hash = &hash_file(&lib_path)?[..8];
+            let (file_stem, file_ext) = lib.name.rsplit_once('.').unwrap_or((&lib.name, "dll"));
+            let new_name = format!("{file_stem}-{short_hash}.{file_ext}");
+
+            let dest_path = temp_dir.path().join(&new_name);
+            fs::copy(&lib_path, &dest_path)?;
+            // fs::copy copies permissions as well, and the original
+            // file may have been read-only
+            let mut perms = fs::metadata(&dest_path)?.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            perms.set_readonly(false);
+            fs::set_permissions(&dest_path, perms)?;
+
+            grafted.push(GraftedLibrary {
+                name: lib.name.clone(),
+                path: lib_path.clone(),
+                grafted_name: new_name.clone(),
+            });
+            dll_names.insert(lib.name.to_ascii_lowercase(), new_name.clone());
+            dlls.push((new_name, dest_path, lib_path));
+        }
+
+        let replacements: Vec<(String, String)> = dll_names.clone().into_iter().collect();
+        for (new_name, dest_path, _) in &dlls {
+            pe::replace_needed(dest_path, &replacements)?;
+            writer.add_file_with_permissions(libs_dir.join(new_name), dest_path, 0o755)?;
+        }
+        for (artifact, _) in artifacts {
+            pe::replace_needed(&artifact.path, &replacements)?;
         }
 
         eprintln!(
             "🖨  Copied external shared libraries to package {} directory:",
             libs_dir.display()
         );
-        for lib_path in libs_copied {
+        for (_, _, lib_path) in &dlls {
             eprintln!("    {}", lib_path.display());
         }
-
-        // Currently artifact .so file always resides at ${module_name}/${module_name}.so
-        let artifact_dir = Path::new(&self.module_name);
-        for artifact in artifacts {
-            let mut new_rpaths = patchelf::get_rpath(&artifact.path)?;
-            // TODO: clean existing rpath entries if it's not pointed to a location within the wheel
-            // See https://github.com/pypa/auditwheel/blob/353c24250d66951d5ac7e60b97471a6da76c123f/src/auditwheel/repair.py#L160
-            let new_rpath = Path::new("$ORIGIN").join(relpath(&libs_dir, artifact_dir));
-            new_rpaths.push(new_rpath.to_str().unwrap().to_string());
-            let new_rpath = new_rpaths.join(":");
-            patchelf::set_rpath(&artifact.path, &new_rpath)?;
-        }
-        Ok(())
+        self.emit(|| {
+            Ok(Message::LibrariesGrafted {
+                libs_dir: libs_dir.clone(),
+                libraries: grafted.clone(),
+            })
+        })?;
+
+        // Python 3.8+ only searches the directories added with `os.add_dll_directory` for the
+        // dependencies of extension modules, older versions use `PATH`
+        let preamble = format!(
+            r#"def _maturin_add_dll_directory():
+    import os
+
+    libs_dir = os.path.abspath(os.path.join(os.path.dirname(__file__), os.pardir, "{libs_dir}"))
+    if os.path.isdir(libs_dir):
+        if hasattr(os, "add_dll_directory"):
+            os.add_dll_directory(libs_dir)
+        else:
+            os.environ["PATH"] = libs_dir + os.pathsep + os.environ.get("PATH", "")
+
+
+_maturin_add_dll_directory()
+del _maturin_add_dll_directory
+"#,
+            libs_dir = libs_dir.display()
+        );
+        writer.add_init_py_preamble(PathBuf::from(package), preamble);
+        Ok(grafted)
     }
 
     fn add_pth(&self, writer: &mut WheelWriter) -> Result<()> {
@@ -486,6 +907,41 @@ impl BuildContext {
         Ok(())
     }
 
+    /// Writes the software bill of materials of the compiled crates and the grafted libraries
+    /// to `.dist-info/sboms/`, see PEP 770
+    fn add_sbom(&self, writer: &mut WheelWriter, grafted: &[GraftedLibrary]) -> Result<()> {
+        if self.editable || self.sbom_formats.is_empty() {
+            return Ok(());
+        }
+        let mut root_crates = vec![(&self.cargo_metadata, self.crate_name.as_str())];
+        root_crates.extend(
+            self.extension_modules
+                .iter()
+                .map(|module| (&module.cargo_metadata, module.crate_name.as_str())),
+        );
+        let sbom = Sbom::new(
+            &self.metadata23.name,
+            &self.metadata23.version.to_string(),
+            &root_crates,
+            &TargetCfg::new(
+                self.manifest_path.parent().unwrap(),
+                self.target.target_triple(),
+            )?,
+            grafted,
+        )
+        .context("Failed to generate the software bill of materials")?;
+        let created = sbom_creation_time(self.reproducible)?;
+        let sboms_dir = self.metadata23.get_dist_info_dir().join("sboms");
+        writer.add_directory(&sboms_dir)?;
+        for format in &self.sbom_formats {
+            writer.add_bytes(
+                sboms_dir.join(format.file_name()),
+                sbom.render(*format, &created).as_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+
     fn excludes(&self, format: Format) -> Result<Override> {
         let project_dir = match self.pyproject_toml_path.normalize() {
             Ok(pyproject_toml_path) => pyproject_toml_path.into_path_buf(),
@@ -531,7 +987,7 @@ impl BuildContext {
                 let mut tags = vec![];
                 for platform_tag in platform_tags {
                     tags.push(format!("{platform_tag}_{arch}"));
-                    for alias in platform_tag.aliases() {
+                    for alias in platform_tag.aliases(&self.audit_policies) {
                         tags.push(format!("{alias}_{arch}"));
                     }
                 }
@@ -647,28 +1103,36 @@ impl BuildContext {
 
     fn write_binding_wheel_abi3(
         &self,
-        artifact: BuildArtifact,
+        mut artifacts: Vec<BuildArtifact>,
         platform_tags: &[PlatformTag],
-        ext_libs: Vec<Library>,
+        ext_libs: Vec<Vec<Library>>,
         major: u8,
         min_minor: u8,
     ) -> Result<BuiltWheelMetadata> {
         let platform = self.get_platform_tag(platform_tags)?;
         let tag = format!("cp{major}{min_minor}-abi3-{platform}");
 
+        self.split_debuginfo(&tag, &mut artifacts)?;
+
+        let metadata23 = self.wheel_metadata(&artifacts)?;
         let mut writer = WheelWriter::new(
             &tag,
             &self.out,
-            &self.metadata23,
+            &metadata23,
             &[tag.clone()],
             self.excludes(Format::Wheel)?,
+            self.reproducible,
+            self.compression,
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
+        let artifact_dirs = self.bindings_artifact_dirs(&artifacts);
+        let grafted = self.add_external_libs(&mut writer, &artifact_dirs, &ext_libs)?;
+        self.add_sbom(&mut writer, &grafted)?;
 
         write_bindings_module(
             &mut writer,
             &self.project_layout,
-            &artifact.path,
+            &artifacts[0].path,
+            &self.extension_module_artifacts(&artifacts),
             self.interpreter.first(),
             true,
             &self.target,
@@ -676,6 +1140,7 @@ impl BuildContext {
             self.pyproject_toml.as_ref(),
         )
         .context("Failed to add the files to the wheel")?;
+        self.write_bins(&mut writer, &artifacts[1 + self.extension_modules.len()..])?;
 
         self.add_pth(&mut writer)?;
         add_data(&mut writer, self.project_layout.data.as_deref())?;
@@ -695,19 +1160,13 @@ impl BuildContext {
         // On windows, we have picked an interpreter to set the location of python.lib,
         // otherwise it's none
         let python_interpreter = interpreters.first();
-        let artifact = self.compile_cdylib(
-            python_interpreter,
-            Some(&self.project_layout.extension_name),
-        )?;
-        let (policy, external_libs) =
-            self.auditwheel(&artifact, &self.platform_tag, python_interpreter)?;
-        let platform_tags = if self.platform_tag.is_empty() {
-            vec![policy.platform_tag()]
-        } else {
-            self.platform_tag.clone()
-        };
+        let mut artifacts = self.compile_extension_modules(python_interpreter)?;
+        self.check_limited_api(&artifacts, major, min_minor)?;
+        artifacts.extend(self.compile_bins(python_interpreter)?);
+        let (platform_tags, external_libs) =
+            self.auditwheel_artifacts(&artifacts, python_interpreter)?;
         let (wheel_path, tag) = self.write_binding_wheel_abi3(
-            artifact,
+            artifacts,
             &platform_tags,
             external_libs,
             major,
@@ -725,28 +1184,70 @@ impl BuildContext {
         Ok(wheels)
     }
 
+    /// Checks that the abi3 extension modules only use symbols of the stable ABI of the minimum
+    /// Python version, since they would fail to load on Python versions that lack them
+    fn check_limited_api(&self, artifacts: &[BuildArtifact], major: u8, minor: u8) -> Result<()> {
+        if self.limited_api_check == LimitedApiCheck::Off {
+            return Ok(());
+        }
+        let mut message = String::new();
+        for artifact in artifacts {
+            let violations = find_limited_api_violations(&artifact.path, major, minor)?;
+            if violations.is_empty() {
+                continue;
+            }
+            message.push_str(&format!("\n    {}:", artifact.path.display()));
+            for violation in violations {
+                message.push_str(&format!("\n        {violation}"));
+            }
+        }
+        if message.is_empty() {
+            return Ok(());
+        }
+        if self.limited_api_check == LimitedApiCheck::Error {
+            bail!(
+                "The abi3 wheel for Python ≥ {major}.{minor} uses symbols outside of its limited \
+                 API, raise the abi3 version or pass `--limited-api-check warn` to build it \
+                 anyway:{message}"
+            );
+        }
+        eprintln!(
+            "⚠️  Warning: The abi3 wheel for Python ≥ {major}.{minor} uses symbols outside of its \
+             limited API, it may fail to import on some Python versions:{message}"
+        );
+        Ok(())
+    }
+
     fn write_binding_wheel(
         &self,
         python_interpreter: &PythonInterpreter,
-        artifact: BuildArtifact,
+        mut artifacts: Vec<BuildArtifact>,
         platform_tags: &[PlatformTag],
-        ext_libs: Vec<Library>,
+        ext_libs: Vec<Vec<Library>>,
     ) -> Result<BuiltWheelMetadata> {
         let tag = python_interpreter.get_tag(self, platform_tags)?;
 
+        self.split_debuginfo(&tag, &mut artifacts)?;
+
+        let metadata23 = self.wheel_metadata(&artifacts)?;
         let mut writer = WheelWriter::new(
             &tag,
             &self.out,
-            &self.metadata23,
+            &metadata23,
             &[tag.clone()],
             self.excludes(Format::Wheel)?,
+            self.reproducible,
+            self.compression,
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
+        let artifact_dirs = self.bindings_artifact_dirs(&artifacts);
+        let grafted = self.add_external_libs(&mut writer, &artifact_dirs, &ext_libs)?;
+        self.add_sbom(&mut writer, &grafted)?;
 
         write_bindings_module(
             &mut writer,
             &self.project_layout,
-            &artifact.path,
+            &artifacts[0].path,
+            &self.extension_module_artifacts(&artifacts),
             Some(python_interpreter),
             false,
             &self.target,
@@ -754,6 +1255,7 @@ impl BuildContext {
             self.pyproject_toml.as_ref(),
         )
         .context("Failed to add the files to the wheel")?;
+        self.write_bins(&mut writer, &artifacts[1 + self.extension_modules.len()..])?;
 
         self.add_pth(&mut writer)?;
         add_data(&mut writer, self.project_layout.data.as_deref())?;
@@ -775,27 +1277,104 @@ impl BuildContext {
         &self,
         interpreters: &[PythonInterpreter],
     ) -> Result<Vec<BuiltWheelMetadata>> {
+        if self.parallel_interpreters > 1 && interpreters.len() > 1 {
+            return self.build_binding_wheels_parallel(interpreters);
+        }
         let mut wheels = Vec::new();
         for python_interpreter in interpreters {
-            let artifact = self.compile_cdylib(
-                Some(python_interpreter),
-                Some(&self.project_layout.extension_name),
-            )?;
-            let (policy, external_libs) =
-                self.auditwheel(&artifact, &self.platform_tag, Some(python_interpreter))?;
-            let platform_tags = if self.platform_tag.is_empty() {
-                vec![policy.platform_tag()]
-            } else {
-                self.platform_tag.clone()
-            };
+            wheels.extend(self.build_binding_wheel(python_interpreter)?);
+        }
+
+        Ok(wheels)
+    }
+
+    /// Builds the wheels for up to `parallel_interpreters` interpreters at the same time
+    ///
+    /// Every interpreter gets its own cargo target directory so the builds don't wait on
+    /// each other's cargo lock, and the wheels are returned in the order of `interpreters`.
+    fn build_binding_wheels_parallel(
+        &self,
+        interpreters: &[PythonInterpreter],
+    ) -> Result<Vec<BuiltWheelMetadata>> {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new(
+            interpreters
+                .iter()
+                .map(|_| None)
+                .collect::<Vec<Option<Result<Vec<BuiltWheelMetadata>>>>>(),
+        );
+        let workers = self.parallel_interpreters.min(interpreters.len());
+        eprintln!(
+            "🧵 Building wheels for {} interpreters with {} parallel jobs",
+            interpreters.len(),
+            workers
+        );
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(python_interpreter) = interpreters.get(index) else {
+                            break;
+                        };
+                        let context = self.for_parallel_interpreter(python_interpreter);
+                        let result = context.build_binding_wheel(python_interpreter);
+                        if result.is_err() {
+                            failed.store(true, Ordering::SeqCst);
+                        }
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                });
+            }
+        });
+        let wheels = results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(wheels.into_iter().flatten().collect())
+    }
+
+    /// Returns a copy of this context that builds into a target subdirectory dedicated to
+    /// the given interpreter and prefixes its output with the interpreter
+    fn for_parallel_interpreter(&self, python_interpreter: &PythonInterpreter) -> BuildContext {
+        let target_dir = self
+            .target_dir
+            .join("maturin")
+            .join(python_interpreter.environment_signature());
+        let mut context = self.clone();
+        context.target_dir = target_dir.clone();
+        context.cargo_options.target_dir = Some(target_dir);
+        context.log_prefix = Some(format!(
+            "[{} {}.{}{}] ",
+            python_interpreter.interpreter_kind,
+            python_interpreter.major,
+            python_interpreter.minor,
+            python_interpreter.abiflags,
+        ));
+        context
+    }
+
+    fn build_binding_wheel(
+        &self,
+        python_interpreter: &PythonInterpreter,
+    ) -> Result<Vec<BuiltWheelMetadata>> {
+        self.cached_build(&[python_interpreter], || {
+            let mut artifacts = self.compile_extension_modules(Some(python_interpreter))?;
+            artifacts.extend(self.compile_bins(Some(python_interpreter))?);
+            let (platform_tags, external_libs) =
+                self.auditwheel_artifacts(&artifacts, Some(python_interpreter))?;
             let (wheel_path, tag) = self.write_binding_wheel(
                 python_interpreter,
-                artifact,
+                artifacts,
                 &platform_tags,
                 external_libs,
             )?;
             eprintln!(
-                "📦 Built wheel for {} {}.{}{} to {}",
+                "{}📦 Built wheel for {} {}.{}{} to {}",
+                self.log_prefix.as_deref().unwrap_or_default(),
                 python_interpreter.interpreter_kind,
                 python_interpreter.major,
                 python_interpreter.minor,
@@ -803,10 +1382,174 @@ impl BuildContext {
                 wheel_path.display()
             );
 
-            wheels.push((wheel_path, tag));
+            Ok(vec![(wheel_path, tag)])
+        })
+    }
+
+    /// Compiles the main extension module and the additional extension modules, returning
+    /// the artifacts in the order of [BuildContext::extension_modules] after the main one
+    ///
+    /// The artifacts of [BuildContext::compile_bins] go after these
+    fn compile_extension_modules(
+        &self,
+        python_interpreter: Option<&PythonInterpreter>,
+    ) -> Result<Vec<BuildArtifact>> {
+        let mut artifacts = vec![self.compile_cdylib(
+            python_interpreter,
+            Some(&self.project_layout.extension_name),
+        )?];
+        for module in &self.extension_modules {
+            let context = self.for_extension_module(module);
+            let artifact = context
+                .compile_cdylib(python_interpreter, Some(module.extension_name()))
+                .with_context(|| {
+                    format!(
+                        "Failed to build the extension module {}",
+                        module.module_name
+                    )
+                })?;
+            artifacts.push(artifact);
         }
+        Ok(artifacts)
+    }
 
-        Ok(wheels)
+    /// Compiles the binaries that go into the wheel of an extension module, which are the
+    /// targets with `bindings = "bin"` in `[[tool.maturin.targets]]`
+    fn compile_bins(
+        &self,
+        python_interpreter: Option<&PythonInterpreter>,
+    ) -> Result<Vec<BuildArtifact>> {
+        let targets: Vec<_> = self
+            .compile_targets
+            .iter()
+            .filter(|target| target.bridge_model.is_bin())
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+        compile(self, python_interpreter, &targets)
+            .context("Failed to build the binaries through cargo")?
+            .into_iter()
+            .map(|artifact| {
+                artifact
+                    .get("bin")
+                    .cloned()
+                    .context("Cargo didn't build a binary")
+            })
+            .collect()
+    }
+
+    /// Adds the binaries to the `.data/scripts` directory of the wheel
+    fn write_bins(&self, writer: &mut WheelWriter, artifacts: &[BuildArtifact]) -> Result<()> {
+        for artifact in artifacts {
+            let bin_name = artifact
+                .path
+                .file_name()
+                .context("Couldn't get the filename from the binary produced by cargo")?
+                .to_str()
+                .context("binary produced by cargo has non-utf8 filename")?;
+            write_bin(writer, &artifact.path, &self.metadata23, bin_name)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of this context that compiles the crate of an additional extension module
+    fn for_extension_module(&self, module: &ExtensionModule) -> BuildContext {
+        let mut context = self.clone();
+        context.extension_modules = Vec::new();
+        context.compile_targets = module.compile_targets.clone();
+        context.crate_name = module.crate_name.clone();
+        context.module_name = module.module_name.clone();
+        context.manifest_path = module.manifest_path.clone();
+        context.cargo_metadata = module.cargo_metadata.clone();
+        context.cargo_options.manifest_path = Some(module.manifest_path.clone());
+        context.cargo_options.features = module.features.clone();
+        context.cargo_options.all_features = module.all_features;
+        context.cargo_options.no_default_features = module.no_default_features;
+        context.project_layout.extension_name = module.extension_name().to_string();
+        context
+    }
+
+    /// The directory in the wheel that the paths to the bundled libraries of the cffi, uniffi
+    /// and bin artifacts are relative to
+    ///
+    /// Currently artifact .so file always resides at ${module_name}/${module_name}.so
+    fn module_dir(&self) -> PathBuf {
+        PathBuf::from(&self.module_name)
+    }
+
+    /// Pairs the artifacts of a bindings wheel with their directory in the wheel, see
+    /// [write_bindings_module]
+    ///
+    /// The main extension module lives in the rust module directory of the project layout,
+    /// the additional extension modules in the package of their module name.
+    fn bindings_artifact_dirs<'a>(
+        &self,
+        artifacts: &'a [BuildArtifact],
+    ) -> Vec<(&'a BuildArtifact, PathBuf)> {
+        let main_dir = match &self.project_layout.python_module {
+            Some(python_module) => self
+                .project_layout
+                .rust_module
+                .strip_prefix(python_module.parent().unwrap())
+                .unwrap()
+                .to_path_buf(),
+            None => PathBuf::from(&self.project_layout.extension_name),
+        };
+        let extension_module_dirs = self
+            .extension_modules
+            .iter()
+            .map(|module| extension_module_dir(&module.module_name));
+        let dirs = iter::once(main_dir)
+            .chain(extension_module_dirs)
+            .chain(iter::repeat_with(|| self.module_dir()));
+        artifacts.iter().zip(dirs).collect()
+    }
+
+    /// Pairs the additional extension modules with their artifacts, see
+    /// [BuildContext::compile_extension_modules]
+    fn extension_module_artifacts<'a>(
+        &'a self,
+        artifacts: &'a [BuildArtifact],
+    ) -> Vec<(&'a str, &'a Path)> {
+        self.extension_modules
+            .iter()
+            .zip(&artifacts[1..])
+            .map(|(module, artifact)| (module.module_name.as_str(), artifact.path.as_path()))
+            .collect()
+    }
+
+    /// Audits all artifacts of a wheel, returning the platform tags that all of them are
+    /// compatible with and the external libraries of each artifact
+    fn auditwheel_artifacts(
+        &self,
+        artifacts: &[BuildArtifact],
+        python_interpreter: Option<&PythonInterpreter>,
+    ) -> Result<(Vec<PlatformTag>, Vec<Vec<Library>>)> {
+        let mut policies = Vec::with_capacity(artifacts.len());
+        let mut ext_libs = Vec::with_capacity(artifacts.len());
+        for artifact in artifacts {
+            let (policy, external_libs) =
+                self.auditwheel(artifact, &self.platform_tag, python_interpreter)?;
+            self.emit(|| {
+                Ok(Message::AuditResult {
+                    artifact: artifact.path.clone(),
+                    policy: policy.name.clone(),
+                    policy_aliases: policy.aliases.clone(),
+                    external_libs: external_libs.iter().map(|lib| lib.name.clone()).collect(),
+                })
+            })?;
+            policies.push(policy);
+            ext_libs.push(external_libs);
+        }
+        let platform_tags = if self.platform_tag.is_empty() {
+            let policy = policies.iter().min_by_key(|p| p.priority).unwrap();
+            vec![policy.platform_tag()]
+        } else {
+            self.platform_tag.clone()
+        };
+        Ok((platform_tags, ext_libs))
     }
 
     /// Runs cargo build, extracts the cdylib from the output and returns the path to it
@@ -818,7 +1561,14 @@ impl BuildContext {
         python_interpreter: Option<&PythonInterpreter>,
         extension_name: Option<&str>,
     ) -> Result<BuildArtifact> {
-        let artifacts = compile(self, python_interpreter, &self.compile_targets)
+        // Binaries with their own bindings are compiled separately, see [BuildContext::compile_bins]
+        let targets: Vec<_> = self
+            .compile_targets
+            .iter()
+            .filter(|target| !target.bridge_model.is_bin())
+            .cloned()
+            .collect();
+        let artifacts = compile(self, python_interpreter, &targets)
             .context("Failed to build a native library through cargo")?;
         let error_msg = "Cargo didn't build a cdylib. Did you miss crate-type = [\"cdylib\"] \
                  in the lib section of your Cargo.toml?";
@@ -850,20 +1600,27 @@ impl BuildContext {
 
     fn write_cffi_wheel(
         &self,
-        artifact: BuildArtifact,
+        mut artifact: BuildArtifact,
         platform_tags: &[PlatformTag],
         ext_libs: Vec<Library>,
     ) -> Result<BuiltWheelMetadata> {
         let (tag, tags) = self.get_universal_tags(platform_tags)?;
 
+        self.split_debuginfo(&tag, std::slice::from_mut(&mut artifact))?;
+
+        let metadata23 = self.wheel_metadata(std::slice::from_ref(&artifact))?;
         let mut writer = WheelWriter::new(
             &tag,
             &self.out,
-            &self.metadata23,
+            &metadata23,
             &tags,
             self.excludes(Format::Wheel)?,
+            self.reproducible,
+            self.compression,
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
+        let grafted =
+            self.add_external_libs(&mut writer, &[(&artifact, self.module_dir())], &[ext_libs])?;
+        self.add_sbom(&mut writer, &grafted)?;
 
         write_cffi_module(
             &mut writer,
@@ -916,20 +1673,27 @@ impl BuildContext {
 
     fn write_uniffi_wheel(
         &self,
-        artifact: BuildArtifact,
+        mut artifact: BuildArtifact,
         platform_tags: &[PlatformTag],
         ext_libs: Vec<Library>,
     ) -> Result<BuiltWheelMetadata> {
         let (tag, tags) = self.get_universal_tags(platform_tags)?;
 
+        self.split_debuginfo(&tag, std::slice::from_mut(&mut artifact))?;
+
+        let metadata23 = self.wheel_metadata(std::slice::from_ref(&artifact))?;
         let mut writer = WheelWriter::new(
             &tag,
             &self.out,
-            &self.metadata23,
+            &metadata23,
             &tags,
             self.excludes(Format::Wheel)?,
+            self.reproducible,
+            self.compression,
         )?;
-        self.add_external_libs(&mut writer, &[&artifact], &[ext_libs])?;
+        let grafted =
+            self.add_external_libs(&mut writer, &[(&artifact, self.module_dir())], &[ext_libs])?;
+        self.add_sbom(&mut writer, &grafted)?;
 
         write_uniffi_module(
             &mut writer,
@@ -987,8 +1751,11 @@ impl BuildContext {
             bail!("Defining scripts and working with a binary doesn't mix well");
         }
 
+        let mut artifacts = artifacts.to_vec();
+        self.split_debuginfo(&tag, &mut artifacts)?;
+
         let mut artifacts_and_files = Vec::new();
-        for artifact in artifacts {
+        for artifact in &artifacts {
             // I wouldn't know of any case where this would be the wrong (and neither do
             // I know a better alternative)
             let bin_name = artifact
@@ -1013,7 +1780,7 @@ impl BuildContext {
         let metadata23 = if self.target.is_wasi() {
             bin_wasi_helper(&artifacts_and_files, self.metadata23.clone())?
         } else {
-            self.metadata23.clone()
+            self.wheel_metadata(&artifacts)?
         };
 
         let mut writer = WheelWriter::new(
@@ -1022,6 +1789,8 @@ impl BuildContext {
             &metadata23,
             &tags,
             self.excludes(Format::Wheel)?,
+            self.reproducible,
+            self.compression,
         )?;
 
         if self.project_layout.python_module.is_some() && self.target.is_wasi() {
@@ -1040,13 +1809,14 @@ impl BuildContext {
 
         let mut artifacts_ref = Vec::with_capacity(artifacts.len());
         for (artifact, bin_name) in &artifacts_and_files {
-            artifacts_ref.push(*artifact);
+            artifacts_ref.push((*artifact, self.module_dir()));
             write_bin(&mut writer, &artifact.path, &self.metadata23, bin_name)?;
             if self.target.is_wasi() {
                 write_wasm_launcher(&mut writer, &self.metadata23, bin_name)?;
             }
         }
-        self.add_external_libs(&mut writer, &artifacts_ref, ext_libs)?;
+        let grafted = self.add_external_libs(&mut writer, &artifacts_ref, ext_libs)?;
+        self.add_sbom(&mut writer, &grafted)?;
 
         self.add_pth(&mut writer)?;
         add_data(&mut writer, self.project_layout.data.as_deref())?;
@@ -1116,6 +1886,180 @@ impl BuildContext {
     }
 }
 
+/// Copies the external shared libraries into `libs_dir` in the wheel with unique sonames and
+/// points the artifacts to them, like `auditwheel repair` does
+///
+/// The artifacts are given as the file to patch and the directory it has in the wheel.
+pub(crate) fn graft_external_libs(
+    writer: &mut WheelWriter,
+    patchelf: Patchelf,
+    libs_dir: &Path,
+    artifacts: &[(&Path, &Path)],
+    ext_libs: &[Vec<Library>],
+) -> Result<Vec<GraftedLibrary>> {
+    patchelf.verify()?;
+    writer.add_directory(libs_dir)?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let mut soname_map = HashMap::new();
+    let mut libs_copied = HashSet::new();
+    let mut grafted = Vec::new();
+    // The rpath entries that pointed outside of the wheel by file in the wheel
+    let mut removed_rpaths: Vec<(PathBuf, Vec<String>)> = Vec::new();
+    for lib in ext_libs.iter().flatten() {
+        let lib_path = lib.realpath.clone().with_context(|| {
+            format!(
+                "Cannot repair wheel, because required library {} could not be located.",
+                lib.path.display()
+            )
+        })?;
+        // Generate a new soname with a short hash
+        let short_hash = &hash_file(&lib_path)?[..8];
+        let (file_stem, file_ext) = lib.name.split_once('.').unwrap();
+        let new_soname = if !file_stem.ends_with(&format!("-{short_hash}")) {
+            format!("{file_stem}-{short_hash}.{file_ext}")
+        } else {
+            format!("{file_stem}.{file_ext}")
+        };
+
+        // Copy the original lib to a tmpdir and modify some of its properties
+        // for example soname and rpath
+        let dest_path = temp_dir.path().join(&new_soname);
+        fs::copy(&lib_path, &dest_path)?;
+        if !soname_map.contains_key(&lib.name) {
+            grafted.push(GraftedLibrary {
+                name: lib.name.clone(),
+                path: lib_path.clone(),
+                grafted_name: new_soname.clone(),
+            });
+        }
+        libs_copied.insert(lib_path);
+
+        // fs::copy copies permissions as well, and the original
+        // file may have been read-only
+        let mut perms = fs::metadata(&dest_path)?.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        perms.set_readonly(false);
+        fs::set_permissions(&dest_path, perms)?;
+
+        patchelf.set_soname(&dest_path, &new_soname)?;
+        if !lib.rpath.is_empty() || !lib.runpath.is_empty() {
+            // The grafted libraries all live in the libs dir, so only `$ORIGIN` remains valid
+            let removed: Vec<String> = patchelf::get_rpath(&dest_path)?
+                .iter()
+                .flat_map(|rpath| rpath.split(':'))
+                .filter(|rpath| !rpath.is_empty() && *rpath != "$ORIGIN")
+                .map(ToString::to_string)
+                .collect();
+            if !removed.is_empty() && !soname_map.contains_key(&lib.name) {
+                removed_rpaths.push((libs_dir.join(&new_soname), removed));
+            }
+            patchelf.set_rpath(&dest_path, &"$ORIGIN")?;
+        }
+        soname_map.insert(
+            lib.name.clone(),
+            (new_soname.clone(), dest_path.clone(), lib.needed.clone()),
+        );
+    }
+
+    for ((artifact, _), artifact_ext_libs) in artifacts.iter().zip(ext_libs) {
+        let artifact_deps: HashSet<_> = artifact_ext_libs.iter().map(|lib| &lib.name).collect();
+        let replacements = soname_map
+            .iter()
+            .filter_map(|(k, v)| {
+                if artifact_deps.contains(k) {
+                    Some((k, v.0.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if !replacements.is_empty() {
+            patchelf.replace_needed(artifact, &replacements[..])?;
+        }
+    }
+
+    // we grafted in a bunch of libraries and modified their sonames, but
+    // they may have internal dependencies (DT_NEEDED) on one another, so
+    // we need to update those records so each now knows about the new
+    // name of the other.
+    for (new_soname, path, needed) in soname_map.values() {
+        let mut replacements = Vec::new();
+        for n in needed {
+            if soname_map.contains_key(n) {
+                replacements.push((n, soname_map[n].0.clone()));
+            }
+        }
+        if !replacements.is_empty() {
+            patchelf.replace_needed(path, &replacements[..])?;
+        }
+        writer.add_file_with_permissions(libs_dir.join(new_soname), path, 0o755)?;
+    }
+
+    eprintln!(
+        "🖨  Copied external shared libraries to package {} directory:",
+        libs_dir.display()
+    );
+    for lib_path in libs_copied {
+        eprintln!("    {}", lib_path.display());
+    }
+
+    for (artifact, artifact_dir) in artifacts {
+        // Like auditwheel, keep only the existing rpath entries that point to a location within
+        // the wheel, see https://github.com/pypa/auditwheel/blob/353c24250d66951d5ac7e60b97471a6da76c123f/src/auditwheel/repair.py#L160
+        let (mut new_rpaths, removed) =
+            sanitize_rpaths(&patchelf::get_rpath(artifact)?, artifact_dir);
+        if !removed.is_empty() {
+            removed_rpaths.push((artifact_dir.join(artifact.file_name().unwrap()), removed));
+        }
+        let new_rpath = Path::new("$ORIGIN").join(relpath(libs_dir, artifact_dir));
+        let new_rpath = new_rpath.to_str().unwrap().to_string();
+        if !new_rpaths.contains(&new_rpath) {
+            new_rpaths.push(new_rpath);
+        }
+        let new_rpath = new_rpaths.join(":");
+        patchelf.set_rpath(artifact, &new_rpath)?;
+    }
+
+    print_removed_rpaths(&removed_rpaths);
+    Ok(grafted)
+}
+
+/// Removes the rpath entries of the elf files that point outside of the wheel, for wheels
+/// without grafted libraries, where the rpaths of the build such as
+/// `/home/ci/target/release/deps` would otherwise remain
+///
+/// `artifacts` are the paths of the elf files and their directories in the wheel.
+pub(crate) fn sanitize_artifact_rpaths(
+    patchelf: Patchelf,
+    artifacts: &[(&Path, &Path)],
+) -> Result<()> {
+    let mut removed_rpaths = Vec::new();
+    for (artifact, artifact_dir) in artifacts {
+        let (kept, removed) = sanitize_rpaths(&patchelf::get_rpath(artifact)?, artifact_dir);
+        if removed.is_empty() {
+            continue;
+        }
+        if kept.is_empty() {
+            patchelf.remove_rpath(artifact)?;
+        } else {
+            patchelf.set_rpath(artifact, &kept.join(":"))?;
+        }
+        removed_rpaths.push((artifact_dir.join(artifact.file_name().unwrap()), removed));
+    }
+    print_removed_rpaths(&removed_rpaths);
+    Ok(())
+}
+
+fn print_removed_rpaths(removed_rpaths: &[(PathBuf, Vec<String>)]) {
+    if !removed_rpaths.is_empty() {
+        eprintln!("✂️  Removed rpath entries pointing outside of the wheel:");
+        for (path, removed) in removed_rpaths {
+            eprintln!("    {}: {}", path.display(), removed.join(", "));
+        }
+    }
+}
+
 /// Calculate the sha256 of a file
 pub fn hash_file(path: impl AsRef<Path>) -> Result<String, io::Error> {
     let mut file = fs::File::open(path.as_ref())?;
@@ -1263,7 +2207,7 @@ fn emcc_version() -> Result<String> {
 
 #[cfg(test)]
 mod test {
-    use super::macosx_deployment_target;
+    use super::*;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -1301,4 +2245,115 @@ mod test {
             ((11, 0), (11, 0))
         );
     }
+
+    /// Without grafted libraries, only the rpath entries pointing outside of the wheel are removed
+    #[test]
+    fn test_sanitize_artifact_rpaths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let outside = temp_dir.path().join("outside.so");
+        let mixed = temp_dir.path().join("mixed.so");
+        let inside = temp_dir.path().join("inside.so");
+        for (path, rpath) in [
+            (&outside, "/home/ci/target/release/deps"),
+            (
+                &mixed,
+                "$ORIGIN/../foo.libs:/home/ci/target/release/deps:$ORIGIN/../../..",
+            ),
+            (&inside, "$ORIGIN"),
+        ] {
+            fs::copy("test-data/elf/libfoo.so.1", path).unwrap();
+            Patchelf::Builtin.set_rpath(path, &rpath).unwrap();
+        }
+        let artifacts = [
+            (outside.as_path(), Path::new("foo")),
+            (mixed.as_path(), Path::new("foo/sub")),
+            (inside.as_path(), Path::new("foo")),
+        ];
+        sanitize_artifact_rpaths(Patchelf::Builtin, &artifacts).unwrap();
+        let rpaths: Vec<Vec<String>> = artifacts
+            .iter()
+            .map(|(artifact, _)| patchelf::get_rpath(artifact).unwrap())
+            .collect();
+        assert_eq!(
+            rpaths,
+            [
+                vec![],
+                vec!["$ORIGIN/../foo.libs".to_string()],
+                vec!["$ORIGIN".to_string()]
+            ]
+        );
+    }
+
+    /// The bundled libraries must be found from the directories of all extension modules,
+    /// including those in subpackages or at the top level
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_graft_external_libs_extension_module_dirs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        // `libfoo.so.1` links `libbar.so.1` and stands in for the extension modules
+        let module_dirs = [
+            PathBuf::from("foo"),
+            extension_module_dir("foo.sub._simd"),
+            extension_module_dir("_top"),
+        ];
+        assert_eq!(module_dirs[1], Path::new("foo/sub"));
+        assert_eq!(module_dirs[2], Path::new(""));
+        let artifacts: Vec<PathBuf> = (0..module_dirs.len())
+            .map(|index| {
+                let artifact = temp_dir.path().join(format!("module{index}.so"));
+                fs::copy("test-data/elf/libfoo.so.1", &artifact).unwrap();
+                artifact
+            })
+            .collect();
+        let libbar = Library {
+            name: "libbar.so.1".to_string(),
+            path: PathBuf::from("test-data/elf/libbar.so.1"),
+            realpath: Some(fs::canonicalize("test-data/elf/libbar.so.1").unwrap()),
+            needed: Vec::new(),
+            rpath: Vec::new(),
+            runpath: Vec::new(),
+        };
+        let ext_libs = vec![vec![libbar]; artifacts.len()];
+
+        let mut writer = WheelWriter::create(
+            temp_dir.path().join("foo-1.0.0-cp38-abi3-linux_x86_64.whl"),
+            Path::new("foo-1.0.0.dist-info"),
+            Override::empty(),
+            false,
+            CompressionOptions::default(),
+        )
+        .unwrap();
+        let artifact_dirs: Vec<(&Path, &Path)> = artifacts
+            .iter()
+            .zip(&module_dirs)
+            .map(|(artifact, dir)| (artifact.as_path(), dir.as_path()))
+            .collect();
+        let grafted = graft_external_libs(
+            &mut writer,
+            Patchelf::Builtin,
+            Path::new("foo.libs"),
+            &artifact_dirs,
+            &ext_libs,
+        )
+        .unwrap();
+        assert_eq!(grafted.len(), 1);
+
+        let rpaths: Vec<String> = artifacts
+            .iter()
+            .map(|artifact| {
+                let bytes = fs::read(artifact).unwrap();
+                let elf = goblin::elf::Elf::parse(&bytes).unwrap();
+                assert_eq!(elf.libraries, [grafted[0].grafted_name.as_str()]);
+                elf.rpaths.join(":")
+            })
+            .collect();
+        assert_eq!(
+            rpaths,
+            [
+                "$ORIGIN:$ORIGIN/../foo.libs",
+                "$ORIGIN:$ORIGIN/../../foo.libs",
+                "$ORIGIN:$ORIGIN/foo.libs",
+            ]
+        );
+    }
 }
@@ -1,20 +1,28 @@
-use crate::auditwheel::PlatformTag;
-use crate::build_context::BridgeModel;
+use crate::auditwheel::{AuditPolicies, LimitedApiCheck, PlatformTag};
+#[cfg(feature = "build-cache")]
+use crate::build_cache::BuildCache;
+use crate::build_context::{BridgeModel, ExtensionModule};
 use crate::compile::{CompileTarget, LIB_CRATE_TYPES};
+use crate::compression::{CompressionMethod, CompressionOptions};
 use crate::cross_compile::{find_sysconfigdata, parse_sysconfigdata};
+use crate::debuginfo::verify_objcopy;
+use crate::licenses::collect_third_party_licenses;
 use crate::project_layout::ProjectResolver;
-use crate::pyproject_toml::ToolMaturin;
+use crate::pyproject_toml::{PyProjectToml, ToolMaturin};
 use crate::python_interpreter::{InterpreterConfig, InterpreterKind, MINIMUM_PYTHON_MINOR};
-use crate::{BuildContext, PythonInterpreter, Target};
+use crate::sbom::TargetCfg;
+use crate::{BuildContext, Message, MessageFormat, PythonInterpreter, SbomFormat, Target};
 use anyhow::{bail, format_err, Context, Result};
 use cargo_metadata::{Metadata, Node};
 use cargo_options::heading;
+use glob::Pattern;
+use normpath::PathExt;
 use pep440_rs::VersionSpecifiers;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::debug;
 
 // This is used for BridgeModel::Bindings("pyo3-ffi") and BridgeModel::Bindings("pyo3").
@@ -164,7 +172,7 @@ pub struct BuildOptions {
         num_args = 0..,
         action = clap::ArgAction::Append
     )]
-    pub platform_tag: Vec<PlatformTag>,
+    pub platform_tag: Vec<String>,
 
     /// The python versions to build wheels for, given as the executables of
     /// interpreters such as `python3.9` or `/usr/bin/python3.8`.
@@ -175,6 +183,21 @@ pub struct BuildOptions {
     #[arg(short = 'f', long, conflicts_with = "interpreter")]
     pub find_interpreter: bool,
 
+    /// Build wheels for up to N interpreters concurrently
+    ///
+    /// Each interpreter is compiled in its own cargo target subdirectory and
+    /// its output is prefixed with the interpreter name
+    #[arg(long, value_name = "N")]
+    pub parallel_interpreters: Option<usize>,
+
+    /// Reuse previously built wheels when none of the build inputs changed
+    ///
+    /// The cache is stored in `MATURIN_CACHE_DIR`, or in maturin's directory in the
+    /// user's cache directory if unset. Use `maturin cache` to manage it.
+    #[cfg(feature = "build-cache")]
+    #[arg(long)]
+    pub build_cache: bool,
+
     /// Which kind of bindings to use.
     #[arg(short, long, value_parser = ["pyo3", "pyo3-ffi", "rust-cpython", "cffi", "uniffi", "bin"])]
     pub bindings: Option<String>,
@@ -188,6 +211,71 @@ pub struct BuildOptions {
     #[arg(long = "skip-auditwheel")]
     pub skip_auditwheel: bool,
 
+    /// Use the external `patchelf` binary instead of the built-in ELF rewriter to repair
+    /// manylinux/musllinux wheels
+    #[arg(long)]
+    pub use_patchelf: bool,
+
+    /// JSON file with additional or overriding manylinux/musllinux policies, which may also define
+    /// custom platform tags
+    #[arg(long, value_name = "FILE")]
+    pub policy_file: Option<PathBuf>,
+
+    /// Don't bundle the shared libraries matching this glob pattern, e.g. `libcuda.so*`, when
+    /// repairing manylinux/musllinux wheels, and list them as `Requires-External` instead
+    #[arg(long = "exclude-lib", value_name = "GLOB")]
+    pub exclude_lib: Vec<String>,
+
+    /// What to do when an abi3 library uses Python symbols that aren't part of the limited API
+    /// of its minimum Python version, defaults to warn
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub limited_api_check: Option<LimitedApiCheck>,
+
+    /// Build byte-for-byte reproducible wheels and source distributions
+    ///
+    /// Sorts the archive entries and normalizes their permissions, owners and
+    /// timestamps. Timestamps are taken from `SOURCE_DATE_EPOCH` if set.
+    #[arg(long)]
+    pub reproducible: bool,
+
+    /// Zip compression method of the wheel
+    #[arg(long, value_enum)]
+    pub compression: Option<CompressionMethod>,
+
+    /// Zip compression level of the wheel, 0-9 for deflated and 1-9 for bzip2
+    #[arg(long, value_name = "LEVEL")]
+    pub compression_level: Option<i32>,
+
+    /// Move the debug info into a `<name>-<version>-<tag>.debug.zip` archive next to each wheel
+    ///
+    /// The archive uses the `.build-id/xx/yyyy.debug` layout of gdb and debuginfod. Only
+    /// ELF targets are supported, and `objcopy` (or `OBJCOPY`) is required.
+    #[arg(long)]
+    pub split_debuginfo: bool,
+
+    /// Format of the software bill of materials written to `.dist-info/sboms/` in each wheel,
+    /// defaults to cyclonedx
+    #[arg(long, value_enum, value_delimiter = ',', action = clap::ArgAction::Append)]
+    pub sbom_format: Vec<SbomFormat>,
+
+    /// Don't write a software bill of materials into the wheels
+    #[arg(long, conflicts_with = "sbom_format")]
+    pub no_sbom: bool,
+
+    /// Bundle the licenses of the statically linked third-party crates into the wheels
+    ///
+    /// They are written to `.dist-info/licenses/third_party/<crate>-<version>/` and listed as
+    /// `License-File` in the metadata.
+    #[arg(long)]
+    pub third_party_licenses: bool,
+
+    /// The output format of the build progress
+    ///
+    /// `json` additionally prints one JSON message per line to stdout for every build step,
+    /// e.g. found interpreters and written wheels.
+    #[arg(long, value_enum, value_name = "FMT", default_value_t)]
+    pub message_format: MessageFormat,
+
     /// For manylinux targets, use zig to ensure compliance for the chosen manylinux version
     ///
     /// Default to manylinux2014/manylinux_2_17 if you do not specify an `--compatibility`
@@ -269,6 +357,9 @@ impl BuildOptions {
                             .get("ABIFLAGS")
                             .map(ToString::to_string)
                             .unwrap_or_default();
+                        let gil_disabled = sysconfig_data
+                            .get("Py_GIL_DISABLED")
+                            .is_some_and(|value| value == "1");
                         let ext_suffix = sysconfig_data
                             .get("EXT_SUFFIX")
                             .context("syconfig didn't define an `EXT_SUFFIX` ಠ_ಠ")?;
@@ -294,6 +385,7 @@ impl BuildOptions {
                                 abiflags,
                                 ext_suffix: ext_suffix.to_string(),
                                 pointer_width: None,
+                                gil_disabled,
                             },
                             executable: PathBuf::new(),
                             platform: None,
@@ -371,6 +463,7 @@ impl BuildOptions {
                                 abiflags: "".to_string(),
                                 ext_suffix: ".pyd".to_string(),
                                 pointer_width: None,
+                                gil_disabled: false,
                             },
                             executable: PathBuf::new(),
                             platform: None,
@@ -397,6 +490,7 @@ impl BuildOptions {
                                 abiflags: "".to_string(),
                                 ext_suffix: ".pyd".to_string(),
                                 pointer_width: None,
+                                gil_disabled: false,
                             },
                             executable: PathBuf::new(),
                             platform: None,
@@ -436,6 +530,7 @@ impl BuildOptions {
                                 abiflags: "".to_string(),
                                 ext_suffix: "".to_string(),
                                 pointer_width: None,
+                                gil_disabled: false,
                             },
                             executable: PathBuf::new(),
                             platform: None,
@@ -513,6 +608,20 @@ impl BuildOptions {
             }),
         )?;
 
+        let config_targets = pyproject.and_then(|x| x.targets());
+        let compile_targets =
+            filter_cargo_targets(&cargo_metadata, bridge, config_targets.as_deref())?;
+        if compile_targets.is_empty() {
+            bail!("No Cargo targets to build, please check your bindings configuration in pyproject.toml.");
+        }
+        // With per-target bindings, the extension module determines the kind of wheel
+        let bridge = compile_targets
+            .iter()
+            .map(|target| &target.bridge_model)
+            .find(|bridge| !bridge.is_bin())
+            .unwrap_or(&compile_targets[0].bridge_model)
+            .clone();
+
         if !bridge.is_bin() && project_layout.extension_name.contains('-') {
             bail!(
                 "The module name must not contain a minus `-` \
@@ -585,6 +694,36 @@ impl BuildOptions {
             };
             self.find_interpreters(&bridge, &interpreter, &target, None, generate_import_lib)?
         };
+        let interpreter = if let BridgeModel::BindingsAbi3(..) = bridge {
+            // Free-threaded builds don't have the stable api, so they can't load abi3 wheels
+            let (free_threaded, interpreter): (Vec<_>, Vec<_>) = interpreter
+                .into_iter()
+                .partition(|interp| interp.gil_disabled);
+            if let Some(interp) = free_threaded.first() {
+                if !self.find_interpreter {
+                    bail!(
+                        "{interp} is a free-threaded build, which doesn't support abi3. \
+                        Disable the abi3 feature of pyo3 to build for free-threaded python"
+                    );
+                }
+                for interp in &free_threaded {
+                    eprintln!(
+                        "⚠️  Warning: skipped free-threaded {interp}, which doesn't support abi3"
+                    );
+                }
+                if interpreter.is_empty() {
+                    bail!("Failed to find any python interpreter supporting abi3");
+                }
+            }
+            interpreter
+        } else {
+            interpreter
+        };
+        if self.message_format == MessageFormat::Json {
+            for interp in &interpreter {
+                Message::interpreter_found(interp).print();
+            }
+        }
 
         if cargo_options.args.is_empty() {
             // if not supplied on command line, try pyproject.toml
@@ -598,6 +737,70 @@ impl BuildOptions {
         let strip = pyproject.map(|x| x.strip()).unwrap_or_default() || strip;
         let skip_auditwheel =
             pyproject.map(|x| x.skip_auditwheel()).unwrap_or_default() || self.skip_auditwheel;
+        let audit_policy_file = self.policy_file.clone().or_else(|| {
+            pyproject.and_then(|x| x.audit_policy_file()).map(|path| {
+                pyproject_toml_maturin_options.push("audit-policy-file");
+                pyproject_toml_path.parent().unwrap().join(path)
+            })
+        });
+        let mut audit_exclude = self.exclude_lib.clone();
+        if let Some(patterns) = pyproject.and_then(|x| x.audit_exclude()) {
+            pyproject_toml_maturin_options.push("audit-exclude");
+            audit_exclude.extend(patterns.iter().cloned());
+        }
+        let audit_exclude = audit_exclude
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern)
+                    .with_context(|| format!("Invalid library glob pattern {pattern}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let limited_api_check = self
+            .limited_api_check
+            .or_else(|| pyproject.and_then(|x| x.limited_api_check()))
+            .unwrap_or_default();
+        let audit_policies = match &audit_policy_file {
+            Some(policy_file) => {
+                let policies = AuditPolicies::from_file(policy_file)?;
+                eprintln!("📜 Using audit policies from {}", policy_file.display());
+                policies
+            }
+            None => AuditPolicies::default(),
+        };
+        let reproducible =
+            pyproject.map(|x| x.reproducible()).unwrap_or_default() || self.reproducible;
+        let compression = CompressionOptions {
+            method: self
+                .compression
+                .or_else(|| pyproject.and_then(|x| x.compression()))
+                .unwrap_or_default(),
+            level: self
+                .compression_level
+                .or_else(|| pyproject.and_then(|x| x.compression_level())),
+        };
+        compression.validate()?;
+        let split_debuginfo =
+            pyproject.map(|x| x.split_debuginfo()).unwrap_or_default() || self.split_debuginfo;
+        if split_debuginfo && (target.is_windows() || target.is_macos() || target.is_wasi()) {
+            bail!("--split-debuginfo is only supported for ELF targets such as linux");
+        }
+        if split_debuginfo {
+            verify_objcopy()?;
+        }
+        let sbom_formats = if self.no_sbom || !pyproject.map(|x| x.sbom()).unwrap_or(true) {
+            Vec::new()
+        } else if !self.sbom_format.is_empty() {
+            self.sbom_format.clone()
+        } else if let Some(sbom_format) = pyproject.and_then(|x| x.sbom_format()) {
+            sbom_format.to_vec()
+        } else {
+            vec![SbomFormat::CycloneDx]
+        };
+        let mut seen = HashSet::new();
+        let sbom_formats: Vec<_> = sbom_formats
+            .into_iter()
+            .filter(|format| seen.insert(*format))
+            .collect();
         let platform_tags = if self.platform_tag.is_empty() {
             #[cfg(feature = "zig")]
             let use_zig = self.zig;
@@ -610,6 +813,8 @@ impl BuildOptions {
                     }
                     x.compatibility()
                 })
+                .map(|platform_tag| audit_policies.parse_platform_tag(platform_tag))
+                .transpose()?
                 .or(if use_zig {
                     if target.is_musl_libc() {
                         // Zig bundles musl 1.2
@@ -634,6 +839,9 @@ impl BuildOptions {
             }
         } else {
             self.platform_tag
+                .iter()
+                .map(|platform_tag| audit_policies.parse_platform_tag(platform_tag))
+                .collect::<Result<_>>()?
         };
 
         for platform_tag in &platform_tags {
@@ -664,14 +872,41 @@ impl BuildOptions {
             .clone()
             .unwrap_or_else(|| cargo_metadata.target_directory.clone().into_std_path_buf());
 
-        let config_targets = pyproject.and_then(|x| x.targets());
-        let compile_targets =
-            filter_cargo_targets(&cargo_metadata, bridge, config_targets.as_deref())?;
-        if compile_targets.is_empty() {
-            bail!("No Cargo targets to build, please check your bindings configuration in pyproject.toml.");
-        }
+        let extension_modules = resolve_extension_modules(
+            pyproject,
+            &pyproject_toml_path,
+            &cargo_options,
+            &bridge,
+            &module_name,
+        )?;
 
         let crate_name = cargo_toml.package.name;
+        let third_party_licenses = pyproject
+            .map(|x| x.third_party_licenses())
+            .unwrap_or_default()
+            || self.third_party_licenses;
+        let mut metadata23 = metadata23;
+        if third_party_licenses && !editable {
+            let mut root_crates = vec![(&cargo_metadata, crate_name.as_str())];
+            root_crates.extend(
+                extension_modules
+                    .iter()
+                    .map(|module| (&module.cargo_metadata, module.crate_name.as_str())),
+            );
+            let target_cfg =
+                TargetCfg::new(cargo_toml_path.parent().unwrap(), target.target_triple())?;
+            metadata23.third_party_licenses =
+                collect_third_party_licenses(&root_crates, &target_cfg)
+                    .context("Failed to collect the licenses of the third-party crates")?;
+        }
+
+        #[cfg(feature = "build-cache")]
+        let build_cache = if self.build_cache {
+            Some(BuildCache::new(None)?)
+        } else {
+            None
+        };
+
         Ok(BuildContext {
             target,
             compile_targets,
@@ -682,11 +917,17 @@ impl BuildOptions {
             crate_name,
             module_name,
             manifest_path: cargo_toml_path,
+            extension_modules,
             target_dir,
             out: wheel_dir,
             release,
             strip,
             skip_auditwheel,
+            use_patchelf: self.use_patchelf,
+            audit_policy_file,
+            audit_policies,
+            audit_exclude,
+            limited_api_check,
             #[cfg(feature = "zig")]
             zig: self.zig,
             platform_tag: platform_tags,
@@ -694,7 +935,16 @@ impl BuildOptions {
             cargo_metadata,
             universal2,
             editable,
+            reproducible,
+            compression,
+            split_debuginfo,
+            message_format: self.message_format,
+            sbom_formats,
             cargo_options,
+            parallel_interpreters: self.parallel_interpreters.unwrap_or(1).max(1),
+            log_prefix: None,
+            #[cfg(feature = "build-cache")]
+            build_cache,
         })
     }
 }
@@ -747,6 +997,112 @@ fn validate_bridge_type(
     Ok(())
 }
 
+/// Resolves the crates of `[[tool.maturin.extension-modules]]`, which must use the same bindings
+/// as the main crate
+fn resolve_extension_modules(
+    pyproject: Option<&PyProjectToml>,
+    pyproject_toml_path: &Path,
+    cargo_options: &CargoOptions,
+    bridge: &BridgeModel,
+    module_name: &str,
+) -> Result<Vec<ExtensionModule>> {
+    let configs = pyproject
+        .map(|pyproject| pyproject.extension_modules())
+        .unwrap_or_default();
+    if configs.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !matches!(
+        bridge,
+        BridgeModel::Bindings(..) | BridgeModel::BindingsAbi3(..)
+    ) {
+        bail!("Additional extension modules are only supported with pyo3 and rust-cpython bindings, but the main module uses {bridge} bindings");
+    }
+
+    let pyproject_dir = pyproject_toml_path.parent().unwrap();
+    let mut module_names = HashSet::from([module_name]);
+    let mut extension_modules = Vec::with_capacity(configs.len());
+    for config in configs {
+        if config.module_name.contains('-') {
+            bail!(
+                "The module name of the extension module {} must not contain a minus `-`",
+                config.module_name
+            );
+        }
+        if !module_names.insert(&config.module_name) {
+            bail!(
+                "The extension module {} is defined more than once",
+                config.module_name
+            );
+        }
+        let manifest_path = pyproject_dir.join(&config.manifest_path);
+        let manifest_path = manifest_path
+            .normalize()
+            .with_context(|| {
+                format!(
+                    "failed to normalize manifest path `{}` of the extension module {}",
+                    manifest_path.display(),
+                    config.module_name
+                )
+            })?
+            .into_path_buf();
+        let features = config.features.clone().unwrap_or_default();
+        let all_features = config.all_features.unwrap_or_default();
+        let no_default_features = config.no_default_features.unwrap_or_default();
+        let module_cargo_options = CargoOptions {
+            manifest_path: Some(manifest_path.clone()),
+            features: features.clone(),
+            all_features,
+            no_default_features,
+            ..cargo_options.clone()
+        };
+        let cargo_metadata =
+            ProjectResolver::resolve_cargo_metadata(&manifest_path, &module_cargo_options)?;
+        let module_bridge = find_bridge(&cargo_metadata, None).with_context(|| {
+            format!(
+                "Failed to detect the bindings of the extension module {}",
+                config.module_name
+            )
+        })?;
+        let compatible = match (bridge, &module_bridge) {
+            // The minimum python version may differ between pyo3 versions
+            (BridgeModel::Bindings(bindings, _), BridgeModel::Bindings(module_bindings, _)) => {
+                bindings == module_bindings
+            }
+            (bridge, module_bridge) => bridge == module_bridge,
+        };
+        if !compatible {
+            bail!(
+                "The extension module {} uses {module_bridge} bindings{}, but all extension modules \
+                 must use the same bindings and abi3 settings as the main module",
+                config.module_name,
+                if matches!(module_bridge, BridgeModel::BindingsAbi3(..)) {
+                    " with abi3"
+                } else {
+                    ""
+                }
+            );
+        }
+        let crate_name = cargo_metadata
+            .root_package()
+            .context("Expected the dependency graph to have a root package")?
+            .name
+            .clone();
+        let compile_targets = filter_cargo_targets(&cargo_metadata, bridge.clone(), None)?;
+        extension_modules.push(ExtensionModule {
+            module_name: config.module_name.clone(),
+            crate_name,
+            manifest_path,
+            features,
+            all_features,
+            no_default_features,
+            cargo_metadata,
+            compile_targets,
+        });
+    }
+    Ok(extension_modules)
+}
+
 fn filter_cargo_targets(
     cargo_metadata: &Metadata,
     bridge: BridgeModel,
@@ -759,57 +1115,94 @@ fn filter_cargo_targets(
         .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == root_pkg.id))
         .map(|node| node.features.clone())
         .unwrap_or_default();
-    let mut targets: Vec<_> = root_pkg
-        .targets
-        .iter()
-        .filter(|target| match bridge {
-            BridgeModel::Bin(_) => {
-                let is_bin = target.is_bin();
-                if target.required_features.is_empty() {
-                    is_bin
-                } else {
-                    // Check all required features are enabled for this bin target
-                    is_bin
-                        && target
-                            .required_features
-                            .iter()
-                            .all(|f| resolved_features.contains(f))
+    let bridge_targets = |bridge: BridgeModel| {
+        let mut targets: Vec<_> = root_pkg
+            .targets
+            .iter()
+            .filter(|target| match bridge {
+                BridgeModel::Bin(_) => {
+                    let is_bin = target.is_bin();
+                    if target.required_features.is_empty() {
+                        is_bin
+                    } else {
+                        // Check all required features are enabled for this bin target
+                        is_bin
+                            && target
+                                .required_features
+                                .iter()
+                                .all(|f| resolved_features.contains(f))
+                    }
                 }
-            }
-            _ => target.kind.contains(&"cdylib".to_string()),
-        })
-        .map(|target| CompileTarget {
-            target: target.clone(),
-            bridge_model: bridge.clone(),
-        })
-        .collect();
-    if targets.is_empty() && !bridge.is_bin() {
-        // No `crate-type = ["cdylib"]` in `Cargo.toml`
-        // Let's try compile one of the target with `--crate-type cdylib`
-        let lib_target = root_pkg.targets.iter().find(|target| {
-            target
-                .kind
-                .iter()
-                .any(|k| LIB_CRATE_TYPES.contains(&k.as_str()))
-        });
-        if let Some(target) = lib_target {
-            targets.push(CompileTarget {
+                _ => target.kind.contains(&"cdylib".to_string()),
+            })
+            .map(|target| CompileTarget {
                 target: target.clone(),
-                bridge_model: bridge,
+                bridge_model: bridge.clone(),
+            })
+            .collect();
+        if targets.is_empty() && !bridge.is_bin() {
+            // No `crate-type = ["cdylib"]` in `Cargo.toml`
+            // Let's try compile one of the target with `--crate-type cdylib`
+            let lib_target = root_pkg.targets.iter().find(|target| {
+                target
+                    .kind
+                    .iter()
+                    .any(|k| LIB_CRATE_TYPES.contains(&k.as_str()))
             });
+            if let Some(target) = lib_target {
+                targets.push(CompileTarget {
+                    target: target.clone(),
+                    bridge_model: bridge,
+                });
+            }
         }
-    }
+        targets
+    };
+    let config_target_matches = |config_target: &crate::pyproject_toml::CargoTarget,
+                                 target: &cargo_metadata::Target| {
+        let name_eq = config_target.name == target.name;
+        match &config_target.kind {
+            Some(kind) => name_eq && target.kind.contains(kind),
+            None => name_eq,
+        }
+    };
+
+    let mut targets = match config_targets {
+        // Every target with its own bindings
+        Some(config_targets) if config_targets.iter().any(|x| x.bindings.is_some()) => {
+            let mut targets = Vec::with_capacity(config_targets.len());
+            for config_target in config_targets {
+                let target_bridge = match config_target.bindings.as_deref() {
+                    Some(bindings) => find_bridge(cargo_metadata, Some(bindings))?,
+                    None => bridge.clone(),
+                };
+                let matched: Vec<_> = bridge_targets(target_bridge.clone())
+                    .into_iter()
+                    .filter(|CompileTarget { target, .. }| {
+                        config_target_matches(config_target, target)
+                    })
+                    .collect();
+                if matched.is_empty() {
+                    bail!(
+                        "No Cargo target named `{}` can be built with {} bindings, please check \
+                         `tool.maturin.targets` in your pyproject.toml",
+                        config_target.name,
+                        target_bridge
+                    );
+                }
+                targets.extend(matched);
+            }
+            targets
+        }
+        _ => bridge_targets(bridge),
+    };
 
     // Filter targets by config_targets
     if let Some(config_targets) = config_targets {
         targets.retain(|CompileTarget { target, .. }| {
-            config_targets.iter().any(|config_target| {
-                let name_eq = config_target.name == target.name;
-                match &config_target.kind {
-                    Some(kind) => name_eq && target.kind.contains(kind),
-                    None => name_eq,
-                }
-            })
+            config_targets
+                .iter()
+                .any(|config_target| config_target_matches(config_target, target))
         });
         if targets.is_empty() {
             bail!(
@@ -828,6 +1221,25 @@ fn filter_cargo_targets(
         }
     }
 
+    let non_bin_targets = targets
+        .iter()
+        .filter(|target| !target.bridge_model.is_bin())
+        .count();
+    if non_bin_targets > 1 {
+        bail!("Only one Cargo target can be built as an extension module, use `tool.maturin.extension-modules` for more");
+    }
+    if non_bin_targets == 1
+        && targets.iter().any(|target| target.bridge_model.is_bin())
+        && !targets.iter().any(|target| {
+            matches!(
+                target.bridge_model,
+                BridgeModel::Bindings(..) | BridgeModel::BindingsAbi3(..)
+            )
+        })
+    {
+        bail!("Binaries can only be combined with pyo3 or rust-cpython extension modules in one wheel");
+    }
+
     Ok(targets)
 }
 
@@ -1180,6 +1592,11 @@ fn find_interpreter_in_sysconfig(
         if python_ver.is_empty() {
             continue;
         }
+        // Eg: -i python3.13t for the free-threaded build
+        let (python_ver, free_threaded) = match python_ver.strip_suffix('t') {
+            Some(ver) if python_impl == InterpreterKind::CPython => (ver, true),
+            _ => (python_ver, false),
+        };
         let (ver_major, ver_minor) = python_ver
             .split_once('.')
             .context("Invalid python interpreter version")?;
@@ -1189,13 +1606,20 @@ fn find_interpreter_in_sysconfig(
         let ver_minor = ver_minor.parse::<usize>().with_context(|| {
             format!("Invalid python interpreter minor version '{ver_minor}', expect a digit")
         })?;
-        let sysconfig = InterpreterConfig::lookup_one(target, python_impl, (ver_major, ver_minor))
-            .with_context(|| {
-                format!("Failed to find a {python_impl} {ver_major}.{ver_minor} interpreter in known sysconfig")
-            })?;
+        let sysconfig = if free_threaded {
+            InterpreterConfig::lookup_one_free_threaded(target, (ver_major, ver_minor))
+                .with_context(|| {
+                    format!("Failed to find a free-threaded {python_impl} {ver_major}.{ver_minor}t interpreter in known sysconfig")
+                })?
+        } else {
+            InterpreterConfig::lookup_one(target, python_impl, (ver_major, ver_minor))
+                .with_context(|| {
+                    format!("Failed to find a {python_impl} {ver_major}.{ver_minor} interpreter in known sysconfig")
+                })?
+        };
         debug!(
-            "Found {} {}.{} in bundled sysconfig",
-            sysconfig.interpreter_kind, sysconfig.major, sysconfig.minor,
+            "Found {} {}.{}{} in bundled sysconfig",
+            sysconfig.interpreter_kind, sysconfig.major, sysconfig.minor, sysconfig.abiflags,
         );
         interpreters.push(PythonInterpreter::from_config(sysconfig.clone()));
     }
@@ -1348,6 +1772,7 @@ mod test {
     use std::path::Path;
 
     use super::*;
+    use crate::pyproject_toml::CargoTarget;
 
     #[test]
     fn test_find_bridge_pyo3() {
@@ -1457,6 +1882,63 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_filter_cargo_targets_bindings() {
+        let uniffi_proc_macro = MetadataCommand::new()
+            .manifest_path(Path::new("test-crates/uniffi-pure-proc-macro").join("Cargo.toml"))
+            .exec()
+            .unwrap();
+
+        // The binary isn't a cdylib, so it can't be built with the bridge of the crate
+        let config_targets = [CargoTarget {
+            name: "uniffi-bindgen".to_string(),
+            kind: None,
+            bindings: None,
+        }];
+        assert!(filter_cargo_targets(
+            &uniffi_proc_macro,
+            BridgeModel::UniFfi,
+            Some(&config_targets)
+        )
+        .is_err());
+
+        // The bindings of the target override the bridge of the crate
+        let config_targets = [CargoTarget {
+            name: "uniffi-bindgen".to_string(),
+            kind: Some("bin".to_string()),
+            bindings: Some("bin".to_string()),
+        }];
+        let targets = filter_cargo_targets(
+            &uniffi_proc_macro,
+            BridgeModel::UniFfi,
+            Some(&config_targets),
+        )
+        .unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].target.name, "uniffi-bindgen");
+        assert_eq!(targets[0].bridge_model, BridgeModel::Bin(None));
+
+        // Binaries can't be combined with a uniffi extension module
+        let config_targets = [
+            CargoTarget {
+                name: "uniffi_pure_proc_macro".to_string(),
+                kind: None,
+                bindings: Some("uniffi".to_string()),
+            },
+            CargoTarget {
+                name: "uniffi-bindgen".to_string(),
+                kind: None,
+                bindings: Some("bin".to_string()),
+            },
+        ];
+        assert!(filter_cargo_targets(
+            &uniffi_proc_macro,
+            BridgeModel::UniFfi,
+            Some(&config_targets)
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_old_extra_feature_args() {
         let cargo_extra_args = CargoOptions {
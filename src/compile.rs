@@ -2,17 +2,18 @@ use crate::build_context::BridgeModel;
 use crate::target::RUST_1_64_0;
 #[cfg(feature = "zig")]
 use crate::PlatformTag;
-use crate::{BuildContext, PythonInterpreter, Target};
+use crate::{BuildContext, Message, MessageFormat, PythonInterpreter, Target};
 use anyhow::{anyhow, bail, Context, Result};
 use fat_macho::FatWriter;
 use fs_err::{self as fs, File};
 use normpath::PathExt;
 use std::collections::HashMap;
 use std::env;
-use std::io::{BufReader, Read};
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str;
+use std::thread;
 use tracing::{debug, trace};
 
 /// The first version of pyo3 that supports building Windows abi3 wheel
@@ -274,7 +275,12 @@ fn cargo_build_command(
         cargo_rustc.args.extend(emscripten_args);
     }
 
-    if context.strip {
+    if context.split_debuginfo {
+        // The build-id identifies the debug info, and stripping happens after splitting it off
+        cargo_rustc
+            .args
+            .extend(["-C".to_string(), "link-arg=-Wl,--build-id".to_string()]);
+    } else if context.strip {
         cargo_rustc
             .args
             .extend(["-C".to_string(), "link-arg=-s".to_string()]);
@@ -467,9 +473,24 @@ fn compile_target(
         .get_program()
         .to_string_lossy()
         .starts_with("cross");
+    if context.log_prefix.is_some() {
+        // Capture stderr so that every line can be prefixed
+        build_command.stderr(Stdio::piped());
+    }
     let mut cargo_build = build_command
         .spawn()
         .context("Failed to run `cargo rustc`")?;
+    let stderr_forwarder = match (&context.log_prefix, cargo_build.stderr.take()) {
+        (Some(prefix), Some(stderr)) => {
+            let prefix = prefix.clone();
+            Some(thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    eprintln!("{prefix}{line}");
+                }
+            }))
+        }
+        _ => None,
+    };
 
     let mut artifacts = HashMap::new();
     let mut linked_paths = Vec::new();
@@ -524,6 +545,13 @@ fn compile_target(
                         } else {
                             filename.into()
                         };
+                        context.emit(|| {
+                            Ok(Message::CompilerArtifact {
+                                crate_name: crate_name.clone(),
+                                crate_type: crate_type.clone(),
+                                path: path.clone(),
+                            })
+                        })?;
                         let artifact = BuildArtifact {
                             path,
                             linked_paths: Vec::new(),
@@ -544,7 +572,22 @@ fn compile_target(
                 }
             }
             cargo_metadata::Message::CompilerMessage(msg) => {
-                println!("{}", msg.message);
+                let message = if let Some(prefix) = &context.log_prefix {
+                    msg.message
+                        .to_string()
+                        .lines()
+                        .map(|line| format!("{prefix}{line}"))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                } else {
+                    msg.message.to_string()
+                };
+                if context.message_format == MessageFormat::Json {
+                    // Keep stdout for the json messages
+                    eprintln!("{message}");
+                } else {
+                    println!("{message}");
+                }
             }
             _ => (),
         }
@@ -558,6 +601,9 @@ fn compile_target(
     let status = cargo_build
         .wait()
         .expect("Failed to wait on cargo child process");
+    if let Some(stderr_forwarder) = stderr_forwarder {
+        let _ = stderr_forwarder.join();
+    }
 
     if !status.success() {
         bail!(
@@ -0,0 +1,283 @@
+//! Compression settings for the wheel zip archive
+use anyhow::{bail, Result};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use zip::DateTime;
+
+/// Members at least this large are deflated in chunks on multiple threads
+const PARALLEL_THRESHOLD: usize = 8 * 1024 * 1024;
+/// The chunk size for parallel compression. This must not depend on the number of threads,
+/// otherwise the output would differ between machines
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The zip compression method of the files in a wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum CompressionMethod {
+    /// No compression
+    Stored,
+    /// Deflate, the default
+    Deflated,
+    /// Bzip2, which is smaller but slower and not supported by all installers
+    Bzip2,
+}
+
+impl Default for CompressionMethod {
+    fn default() -> Self {
+        // Unlike users which can use the develop subcommand, the tests have to go through
+        // packing a zip which pip than has to unpack. This makes this 2-3 times faster
+        if cfg!(feature = "faster-tests") {
+            Self::Stored
+        } else {
+            Self::Deflated
+        }
+    }
+}
+
+impl CompressionMethod {
+    /// The range of supported compression levels, `None` if the method has no levels
+    fn levels(self) -> Option<(i32, i32)> {
+        match self {
+            CompressionMethod::Stored => None,
+            CompressionMethod::Deflated => Some((0, 9)),
+            CompressionMethod::Bzip2 => Some((1, 9)),
+        }
+    }
+}
+
+/// The compression method and level of the files in a wheel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CompressionOptions {
+    /// The zip compression method
+    pub method: CompressionMethod,
+    /// The compression level, `None` for the default level of the method
+    pub level: Option<i32>,
+}
+
+impl CompressionOptions {
+    /// Checks that the level is supported by the method
+    pub fn validate(&self) -> Result<()> {
+        let Some(level) = self.level else {
+            return Ok(());
+        };
+        let method = format!("{:?}", self.method).to_lowercase();
+        match self.method.levels() {
+            None => bail!("The {method} compression method doesn't support compression levels"),
+            Some((min, max)) if !(min..=max).contains(&level) => bail!(
+                "Unsupported compression level {level} for {method}, expected a level between {min} and {max}"
+            ),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Uses the fastest compression level unless a level was chosen explicitly
+    pub fn or_fastest(self) -> Self {
+        Self {
+            method: self.method,
+            level: self
+                .level
+                .or_else(|| self.method.levels().map(|(min, _)| min.max(1))),
+        }
+    }
+
+    /// The zip options for this method and level
+    pub(crate) fn file_options(&self) -> zip::write::FileOptions {
+        let method = match self.method {
+            CompressionMethod::Stored => zip::CompressionMethod::Stored,
+            CompressionMethod::Deflated => zip::CompressionMethod::Deflated,
+            CompressionMethod::Bzip2 => zip::CompressionMethod::Bzip2,
+        };
+        zip::write::FileOptions::default()
+            .compression_method(method)
+            .compression_level(self.level)
+    }
+
+    /// Whether `bytes` is large enough to be compressed with [compress_parallel]
+    pub(crate) fn use_parallel(&self, bytes: &[u8]) -> bool {
+        self.method == CompressionMethod::Deflated
+            && bytes.len() >= PARALLEL_THRESHOLD
+            // The single entry archive doesn't support zip64, leave room for the deflate overhead
+            && bytes.len() <= i32::MAX as usize
+    }
+}
+
+/// Deflates `bytes` in chunks on multiple threads and wraps the result in a zip archive with
+/// a single entry, which can be copied into the wheel with [zip::ZipWriter::raw_copy_file_rename]
+///
+/// Like pigz, every chunk but the last one ends with a sync flush, so the concatenated chunks
+/// form a single deflate stream
+pub(crate) fn compress_parallel(
+    bytes: &[u8],
+    level: Option<i32>,
+    permissions: u32,
+    mtime: DateTime,
+) -> io::Result<Vec<u8>> {
+    let level = level.map_or_else(Compression::default, |level| Compression::new(level as u32));
+    let chunks: Vec<&[u8]> = bytes.chunks(CHUNK_SIZE).collect();
+    let threads = thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1)
+        .min(chunks.len());
+    let next = AtomicUsize::new(0);
+    let compressed = Mutex::new(vec![Vec::new(); chunks.len()]);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| -> io::Result<()> {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(chunk) = chunks.get(index) else {
+                            return Ok(());
+                        };
+                        let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                        encoder.write_all(chunk)?;
+                        let data = if index + 1 == chunks.len() {
+                            encoder.finish()?
+                        } else {
+                            encoder.flush()?;
+                            std::mem::take(encoder.get_mut())
+                        };
+                        compressed.lock().unwrap()[index] = data;
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("compression thread panicked"))
+    })?;
+    let compressed = compressed.into_inner().unwrap().concat();
+
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    Ok(single_entry_zip(
+        &compressed,
+        crc.sum(),
+        bytes.len() as u32,
+        permissions,
+        mtime,
+    ))
+}
+
+/// Writes a minimal zip archive with a single deflated entry, see APPNOTE.TXT 4.3
+fn single_entry_zip(
+    compressed: &[u8],
+    crc32: u32,
+    size: u32,
+    permissions: u32,
+    mtime: DateTime,
+) -> Vec<u8> {
+    // The entry is renamed when copying it into the wheel
+    const NAME: &[u8] = b"entry";
+    const VERSION: u16 = 20;
+    const DEFLATED: u16 = 8;
+    let compressed_size = compressed.len() as u32;
+
+    let mut zip = Vec::with_capacity(compressed.len() + 128);
+    let u16 = |zip: &mut Vec<u8>, value: u16| zip.extend_from_slice(&value.to_le_bytes());
+    let u32 = |zip: &mut Vec<u8>, value: u32| zip.extend_from_slice(&value.to_le_bytes());
+
+    // Local file header
+    u32(&mut zip, 0x04034b50);
+    u16(&mut zip, VERSION);
+    u16(&mut zip, 0); // flags
+    u16(&mut zip, DEFLATED);
+    u16(&mut zip, mtime.timepart());
+    u16(&mut zip, mtime.datepart());
+    u32(&mut zip, crc32);
+    u32(&mut zip, compressed_size);
+    u32(&mut zip, size);
+    u16(&mut zip, NAME.len() as u16);
+    u16(&mut zip, 0); // extra field length
+    zip.extend_from_slice(NAME);
+    zip.extend_from_slice(compressed);
+
+    // Central directory
+    let central_directory_start = zip.len() as u32;
+    u32(&mut zip, 0x02014b50);
+    u16(&mut zip, (3 << 8) | VERSION); // made by unix, so that the permissions are read
+    u16(&mut zip, VERSION);
+    u16(&mut zip, 0); // flags
+    u16(&mut zip, DEFLATED);
+    u16(&mut zip, mtime.timepart());
+    u16(&mut zip, mtime.datepart());
+    u32(&mut zip, crc32);
+    u32(&mut zip, compressed_size);
+    u32(&mut zip, size);
+    u16(&mut zip, NAME.len() as u16);
+    u16(&mut zip, 0); // extra field length
+    u16(&mut zip, 0); // comment length
+    u16(&mut zip, 0); // disk number
+    u16(&mut zip, 0); // internal attributes
+    u32(&mut zip, (0o100000 | permissions) << 16);
+    u32(&mut zip, 0); // local header offset
+    zip.extend_from_slice(NAME);
+    let central_directory_size = zip.len() as u32 - central_directory_start;
+
+    // End of central directory
+    u32(&mut zip, 0x06054b50);
+    u16(&mut zip, 0); // disk number
+    u16(&mut zip, 0); // disk with the central directory
+    u16(&mut zip, 1); // entries on this disk
+    u16(&mut zip, 1); // entries
+    u32(&mut zip, central_directory_size);
+    u32(&mut zip, central_directory_start);
+    u16(&mut zip, 0); // comment length
+    zip
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_validate() {
+        let options = |method, level| CompressionOptions { method, level };
+        assert!(options(CompressionMethod::Deflated, Some(9))
+            .validate()
+            .is_ok());
+        assert!(options(CompressionMethod::Deflated, Some(10))
+            .validate()
+            .is_err());
+        assert!(options(CompressionMethod::Bzip2, Some(0))
+            .validate()
+            .is_err());
+        assert!(options(CompressionMethod::Stored, None).validate().is_ok());
+        assert!(options(CompressionMethod::Stored, Some(1))
+            .validate()
+            .is_err());
+        assert_eq!(
+            options(CompressionMethod::Deflated, None)
+                .or_fastest()
+                .level,
+            Some(1)
+        );
+        assert_eq!(
+            options(CompressionMethod::Stored, None).or_fastest().level,
+            None
+        );
+    }
+
+    #[test]
+    fn test_compress_parallel() {
+        let bytes: Vec<u8> = (0..3 * CHUNK_SIZE + 12345)
+            .map(|i| (i % 251) as u8 ^ (i / 4096) as u8)
+            .collect();
+        let zip = compress_parallel(&bytes, Some(6), 0o755, DateTime::default()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        let mut file = archive.by_index(0).unwrap();
+        assert_eq!(file.compression(), zip::CompressionMethod::Deflated);
+        assert_eq!(file.unix_mode(), Some(0o100755));
+        let mut decompressed = Vec::new();
+        // Reading to the end also checks the crc
+        file.read_to_end(&mut decompressed).unwrap();
+        assert!(decompressed == bytes);
+    }
+}
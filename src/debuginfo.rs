@@ -0,0 +1,299 @@
+//! Splits the debug info off native libraries into a sidecar archive, see `--split-debuginfo`
+use crate::module_writer::zip_mtime;
+use crate::CompressionOptions;
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use fs_err::File;
+use goblin::elf::note::NT_GNU_BUILD_ID;
+use goblin::elf::Elf;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zip::ZipWriter;
+
+/// The debug info split off a native library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// The GNU build-id of the library as lowercase hex
+    pub build_id: String,
+    /// The `.debug` file with the debug info
+    pub path: PathBuf,
+}
+
+impl DebugInfo {
+    /// The path in the `.build-id` directory layout used by gdb, debuginfod and most symbol
+    /// servers, e.g. `.build-id/ab/cdef1234.debug`
+    pub fn archive_path(&self) -> String {
+        let (prefix, rest) = self.build_id.split_at(2.min(self.build_id.len()));
+        format!(".build-id/{prefix}/{rest}.debug")
+    }
+}
+
+/// Reads the GNU build-id note of an ELF file
+pub fn gnu_build_id(elf: &Elf, bytes: &[u8]) -> Option<String> {
+    let notes = elf
+        .iter_note_sections(bytes, Some(".note.gnu.build-id"))
+        .into_iter()
+        .chain(elf.iter_note_headers(bytes))
+        .flatten();
+    for note in notes {
+        let Ok(note) = note else {
+            continue;
+        };
+        if note.n_type == NT_GNU_BUILD_ID && note.name == "GNU" && !note.desc.is_empty() {
+            let mut build_id = String::with_capacity(note.desc.len() * 2);
+            for byte in note.desc {
+                write!(build_id, "{byte:02x}").unwrap();
+            }
+            return Some(build_id);
+        }
+    }
+    None
+}
+
+/// Whether the ELF file has DWARF debug info, which is missing when the cargo profile
+/// doesn't enable `debug`
+pub fn has_debug_info(elf: &Elf) -> bool {
+    elf.section_headers.iter().any(|section| {
+        matches!(
+            elf.shdr_strtab.get_at(section.sh_name),
+            Some(".debug_info" | ".zdebug_info")
+        )
+    })
+}
+
+/// The objcopy to split the debug info with, `OBJCOPY` if set
+fn objcopy_program() -> OsString {
+    env::var_os("OBJCOPY").unwrap_or_else(|| "objcopy".into())
+}
+
+fn missing_objcopy_error(objcopy: &OsStr) -> String {
+    format!(
+        "Failed to execute '{}', which is required for --split-debuginfo, did you install binutils? \
+         Hint: Set `OBJCOPY` to use a different objcopy, e.g. `llvm-objcopy`",
+        objcopy.to_string_lossy()
+    )
+}
+
+/// Checks that objcopy can be executed, so that a missing one is reported before compiling
+pub fn verify_objcopy() -> Result<()> {
+    verify_objcopy_program(&objcopy_program())
+}
+
+fn verify_objcopy_program(objcopy: &OsStr) -> Result<()> {
+    Command::new(objcopy)
+        .arg("--version")
+        .output()
+        .with_context(|| missing_objcopy_error(objcopy))?;
+    Ok(())
+}
+
+fn objcopy(objcopy: &OsStr, args: &[OsString]) -> Result<()> {
+    let output = Command::new(objcopy)
+        .args(args)
+        .output()
+        .with_context(|| missing_objcopy_error(objcopy))?;
+    if !output.status.success() {
+        bail!(
+            "{} {} failed: {}",
+            objcopy.to_string_lossy(),
+            args[0].to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Moves the debug info of the ELF file at `path` into `<path>.debug` and links the two
+/// with a `.gnu_debuglink` section. With `strip`, the symbol table is removed from `path`, too.
+pub fn split_elf_debuginfo(path: &Path, strip: bool) -> Result<DebugInfo> {
+    let bytes = fs::read(path)?;
+    let elf = Elf::parse(&bytes).with_context(|| {
+        format!(
+            "Splitting debug info requires an ELF file, but {} isn't one",
+            path.display()
+        )
+    })?;
+    let build_id = gnu_build_id(&elf, &bytes).with_context(|| {
+        format!(
+            "{} has no GNU build-id, which is required to split the debug info",
+            path.display()
+        )
+    })?;
+    if !has_debug_info(&elf) {
+        eprintln!(
+            "⚠️  Warning: {} has no debug info, so only the symbols are split off. \
+             Enable it with `debug = true` in the cargo profile, \
+             e.g. `CARGO_PROFILE_RELEASE_DEBUG=true`",
+            path.display()
+        );
+    }
+
+    let objcopy_program = objcopy_program();
+    let mut debug_path = path.as_os_str().to_os_string();
+    debug_path.push(".debug");
+    let debug_path = PathBuf::from(debug_path);
+    objcopy(
+        &objcopy_program,
+        &[
+            "--only-keep-debug".into(),
+            path.into(),
+            debug_path.clone().into(),
+        ],
+    )?;
+    let mut debuglink = OsString::from("--add-gnu-debuglink=");
+    debuglink.push(&debug_path);
+    objcopy(
+        &objcopy_program,
+        &[
+            if strip {
+                "--strip-all"
+            } else {
+                "--strip-debug"
+            }
+            .into(),
+            debuglink,
+            path.into(),
+        ],
+    )?;
+
+    Ok(DebugInfo {
+        build_id,
+        path: debug_path,
+    })
+}
+
+/// Writes the `.debug` files into a zip archive using the `.build-id` directory layout
+pub fn write_debuginfo_archive(
+    archive_path: &Path,
+    debug_infos: &[DebugInfo],
+    compression: CompressionOptions,
+    reproducible: bool,
+) -> Result<()> {
+    let mut debug_infos = debug_infos.to_vec();
+    debug_infos.sort_by(|a, b| a.build_id.cmp(&b.build_id));
+    debug_infos.dedup_by(|a, b| a.build_id == b.build_id);
+
+    let mut zip = ZipWriter::new(File::create(archive_path)?);
+    let mut options = compression.file_options().unix_permissions(0o644);
+    if let Ok(mtime) = zip_mtime(reproducible) {
+        options = options.last_modified_time(mtime);
+    }
+    for debug_info in &debug_infos {
+        zip.start_file(debug_info.archive_path(), options)?;
+        zip.write_all(&fs::read(&debug_info.path)?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `libmemfd.so.1` is built with `-g`, `libfoo.so.1` is stripped
+    fn copy_fixture(name: &str) -> (tempfile::TempDir, PathBuf) {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/elf");
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join(name);
+        fs::copy(fixtures.join(name), &path).unwrap();
+        (temp_dir, path)
+    }
+
+    fn section_names(path: &Path) -> Vec<String> {
+        let bytes = fs::read(path).unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        elf.section_headers
+            .iter()
+            .filter_map(|section| elf.shdr_strtab.get_at(section.sh_name))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_gnu_build_id() {
+        let (_temp_dir, path) = copy_fixture("libfoo.so.1");
+        let bytes = fs::read(path).unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        assert_eq!(
+            gnu_build_id(&elf, &bytes).as_deref(),
+            Some("56809f624c00abfb04e89f45c2274ac3c342be4e")
+        );
+        assert!(!has_debug_info(&elf));
+    }
+
+    #[test]
+    fn test_split_elf_debuginfo() {
+        if which::which(objcopy_program()).is_err() {
+            return;
+        }
+        let (_temp_dir, path) = copy_fixture("libmemfd.so.1");
+        assert!(section_names(&path).contains(&".debug_info".to_string()));
+
+        let debug_info = split_elf_debuginfo(&path, false).unwrap();
+        assert_eq!(
+            debug_info.build_id,
+            "9dd05b302456bf8494463f050f3f40353cef44a3"
+        );
+        assert_eq!(
+            debug_info.archive_path(),
+            ".build-id/9d/d05b302456bf8494463f050f3f40353cef44a3.debug"
+        );
+        assert_eq!(debug_info.path, path.with_file_name("libmemfd.so.1.debug"));
+        assert!(section_names(&debug_info.path).contains(&".debug_info".to_string()));
+
+        // The stripped library points to its debug info
+        let sections = section_names(&path);
+        assert!(sections.contains(&".gnu_debuglink".to_string()));
+        assert!(!sections.contains(&".debug_info".to_string()));
+        assert!(sections.contains(&".symtab".to_string()));
+        let bytes = fs::read(&path).unwrap();
+        let elf = Elf::parse(&bytes).unwrap();
+        assert_eq!(
+            gnu_build_id(&elf, &bytes).as_deref(),
+            Some(debug_info.build_id.as_str())
+        );
+    }
+
+    #[test]
+    fn test_split_elf_debuginfo_strip() {
+        if which::which(objcopy_program()).is_err() {
+            return;
+        }
+        let (_temp_dir, path) = copy_fixture("libmemfd.so.1");
+        split_elf_debuginfo(&path, true).unwrap();
+        let sections = section_names(&path);
+        assert!(sections.contains(&".gnu_debuglink".to_string()));
+        assert!(!sections.contains(&".symtab".to_string()));
+    }
+
+    #[test]
+    fn test_missing_objcopy() {
+        let err = verify_objcopy_program(OsStr::new("maturin-missing-objcopy")).unwrap_err();
+        assert!(
+            err.to_string().contains("'maturin-missing-objcopy'"),
+            "{err}"
+        );
+        let err =
+            objcopy(OsStr::new("maturin-missing-objcopy"), &["--version".into()]).unwrap_err();
+        assert!(
+            err.to_string().contains("'maturin-missing-objcopy'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_archive_path() {
+        let debug_info = DebugInfo {
+            build_id: "0ff1ce5b6f1c5d3a1ef6cd7b7c0e6a1a4e3b2c1d".to_string(),
+            path: PathBuf::from("libfoo.so.debug"),
+        };
+        assert_eq!(
+            debug_info.archive_path(),
+            ".build-id/0f/f1ce5b6f1c5d3a1ef6cd7b7c0e6a1a4e3b2c1d.debug"
+        );
+    }
+}
@@ -2,6 +2,7 @@ use crate::build_options::CargoOptions;
 use crate::target::Arch;
 use crate::BuildContext;
 use crate::BuildOptions;
+use crate::MessageFormat;
 use crate::PlatformTag;
 use crate::PythonInterpreter;
 use crate::Target;
@@ -257,12 +258,27 @@ pub fn develop(develop_options: DevelopOptions, venv_dir: &Path) -> Result<()> {
     let wheel_dir = TempDir::new().context("Failed to create temporary directory")?;
 
     let build_options = BuildOptions {
-        platform_tag: vec![PlatformTag::Linux],
+        platform_tag: vec![PlatformTag::Linux.to_string()],
         interpreter: vec![python.clone()],
         find_interpreter: false,
+        parallel_interpreters: None,
+        #[cfg(feature = "build-cache")]
+        build_cache: false,
         bindings,
         out: Some(wheel_dir.path().to_path_buf()),
         skip_auditwheel: false,
+        use_patchelf: false,
+        policy_file: None,
+        exclude_lib: Vec::new(),
+        limited_api_check: None,
+        reproducible: false,
+        compression: None,
+        compression_level: None,
+        split_debuginfo: false,
+        message_format: MessageFormat::Human,
+        sbom_format: Vec::new(),
+        no_sbom: false,
+        third_party_licenses: false,
         #[cfg(feature = "zig")]
         zig: false,
         cargo: CargoOptions {
@@ -271,7 +287,9 @@ pub fn develop(develop_options: DevelopOptions, venv_dir: &Path) -> Result<()> {
         },
     };
 
-    let build_context = build_options.into_build_context(release, strip, true)?;
+    let mut build_context = build_options.into_build_context(release, strip, true)?;
+    // The wheel is only unpacked once by the installer, so spend as little time as possible on it
+    build_context.compression = build_context.compression.or_fastest();
 
     let interpreter =
         PythonInterpreter::check_executable(&python, &target, build_context.bridge())?.ok_or_else(
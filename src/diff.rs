@@ -0,0 +1,619 @@
+//! Compares two wheels or source distributions, e.g. to find out what changed between two releases
+use crate::inspect::{DistributionKind, FileInfo, Inspection, NativeLibrary};
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use indexmap::{IndexMap, IndexSet};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A file whose contents differ between the two archives
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangedFile {
+    /// The path in the new archive
+    pub path: String,
+    /// The size in the old archive
+    pub old_size: u64,
+    /// The size in the new archive
+    pub new_size: u64,
+}
+
+/// A core metadata field with different values
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangedField {
+    /// The field name, e.g. `Summary`
+    pub field: String,
+    /// The values in the old archive, empty if the field was missing
+    pub old: Vec<String>,
+    /// The values in the new archive, empty if the field was missing
+    pub new: Vec<String>,
+}
+
+/// A native library that was added, removed or changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangedNativeLibrary {
+    /// The path in the new archive, or in the old archive if it was removed
+    pub path: String,
+    /// The size in the old archive, `None` if the library was added
+    pub old_size: Option<u64>,
+    /// The size in the new archive, `None` if the library was removed
+    pub new_size: Option<u64>,
+    /// The policy in the old archive
+    pub old_policy: Option<String>,
+    /// The policy in the new archive
+    pub new_policy: Option<String>,
+    /// The dynamically linked libraries only the new library needs
+    pub dependencies_added: Vec<String>,
+    /// The dynamically linked libraries only the old library needed
+    pub dependencies_removed: Vec<String>,
+}
+
+/// The differences between two wheels or source distributions, as reported by `maturin diff`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WheelDiff {
+    /// The old archive
+    pub old: PathBuf,
+    /// The new archive
+    pub new: PathBuf,
+    /// Files only in the new archive
+    pub files_added: Vec<String>,
+    /// Files only in the old archive
+    pub files_removed: Vec<String>,
+    /// Files in both archives with different contents
+    pub files_changed: Vec<ChangedFile>,
+    /// Core metadata fields with different values, except for `Requires-Dist`
+    pub metadata: Vec<ChangedField>,
+    /// `Requires-Dist` entries only in the new archive
+    pub requires_dist_added: Vec<String>,
+    /// `Requires-Dist` entries only in the old archive
+    pub requires_dist_removed: Vec<String>,
+    /// Wheel tags only in the new archive
+    pub tags_added: Vec<String>,
+    /// Wheel tags only in the old archive
+    pub tags_removed: Vec<String>,
+    /// Native libraries that were added, removed or changed
+    pub native_libraries: Vec<ChangedNativeLibrary>,
+}
+
+impl WheelDiff {
+    /// Reads both archives and compares them
+    pub fn new(old: &Path, new: &Path) -> Result<Self> {
+        Self::from_inspections(&Inspection::new(old)?, &Inspection::new(new)?)
+    }
+
+    /// Compares two already inspected archives
+    pub fn from_inspections(old: &Inspection, new: &Inspection) -> Result<Self> {
+        if old.kind != new.kind {
+            bail!(
+                "Can't compare a {} with a {}",
+                kind_name(old.kind),
+                kind_name(new.kind)
+            );
+        }
+
+        let old_files = files_by_key(old);
+        let new_files = files_by_key(new);
+        let files_added = new_files
+            .iter()
+            .filter(|(key, _)| !old_files.contains_key(*key))
+            .map(|(_, file)| file.path.clone())
+            .collect();
+        let files_removed = old_files
+            .iter()
+            .filter(|(key, _)| !new_files.contains_key(*key))
+            .map(|(_, file)| file.path.clone())
+            .collect();
+        let files_changed = new_files
+            .iter()
+            .filter_map(|(key, new_file)| {
+                let old_file = old_files.get(key)?;
+                (old_file.sha256 != new_file.sha256).then(|| ChangedFile {
+                    path: new_file.path.clone(),
+                    old_size: old_file.size,
+                    new_size: new_file.size,
+                })
+            })
+            .collect();
+
+        let old_fields = metadata_fields(old);
+        let new_fields = metadata_fields(new);
+        let fields: IndexSet<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+        let mut metadata = Vec::new();
+        for field in fields {
+            if field == "Requires-Dist" {
+                continue;
+            }
+            let old_values = old_fields.get(field).cloned().unwrap_or_default();
+            let new_values = new_fields.get(field).cloned().unwrap_or_default();
+            if old_values != new_values {
+                metadata.push(ChangedField {
+                    field: field.clone(),
+                    old: old_values,
+                    new: new_values,
+                });
+            }
+        }
+        let old_requires_dist = old_fields.get("Requires-Dist").cloned().unwrap_or_default();
+        let new_requires_dist = new_fields.get("Requires-Dist").cloned().unwrap_or_default();
+
+        let tags = |inspection: &Inspection| {
+            inspection
+                .wheel
+                .as_ref()
+                .map(|wheel| wheel.tags.clone())
+                .unwrap_or_default()
+        };
+        let (old_tags, new_tags) = (tags(old), tags(new));
+
+        Ok(Self {
+            old: old.path.clone(),
+            new: new.path.clone(),
+            files_added,
+            files_removed,
+            files_changed,
+            metadata,
+            requires_dist_added: difference(&new_requires_dist, &old_requires_dist),
+            requires_dist_removed: difference(&old_requires_dist, &new_requires_dist),
+            tags_added: difference(&new_tags, &old_tags),
+            tags_removed: difference(&old_tags, &new_tags),
+            native_libraries: diff_native_libraries(old, new),
+        })
+    }
+
+    /// Whether the two archives have the same contents
+    pub fn is_empty(&self) -> bool {
+        self.files_added.is_empty()
+            && self.files_removed.is_empty()
+            && self.files_changed.is_empty()
+            && self.metadata.is_empty()
+            && self.requires_dist_added.is_empty()
+            && self.requires_dist_removed.is_empty()
+            && self.tags_added.is_empty()
+            && self.tags_removed.is_empty()
+            && self.native_libraries.is_empty()
+    }
+
+    /// Prints a human readable report
+    pub fn print(&self) {
+        println!("--- {}", self.old.display());
+        println!("+++ {}", self.new.display());
+        if self.is_empty() {
+            println!();
+            println!("✅ No differences");
+            return;
+        }
+
+        if !self.tags_added.is_empty() || !self.tags_removed.is_empty() {
+            println!();
+            println!("🏷  Tags");
+            print_values(4, &self.tags_removed, &self.tags_added);
+        }
+
+        if !self.metadata.is_empty() {
+            println!();
+            println!("📋 Metadata");
+            for field in &self.metadata {
+                if field.field == "Description" {
+                    let lines = |values: &[String]| -> usize {
+                        values.iter().map(|value| value.lines().count()).sum()
+                    };
+                    println!(
+                        "    Description: {} lines -> {} lines",
+                        lines(&field.old),
+                        lines(&field.new)
+                    );
+                    continue;
+                }
+                println!("    {}", field.field);
+                print_values(
+                    8,
+                    &difference(&field.old, &field.new),
+                    &difference(&field.new, &field.old),
+                );
+            }
+        }
+
+        if !self.requires_dist_added.is_empty() || !self.requires_dist_removed.is_empty() {
+            println!();
+            println!("🔗 Requires-Dist");
+            print_values(4, &self.requires_dist_removed, &self.requires_dist_added);
+        }
+
+        if !self.files_added.is_empty()
+            || !self.files_removed.is_empty()
+            || !self.files_changed.is_empty()
+        {
+            println!();
+            println!("📁 Files");
+            for path in &self.files_removed {
+                println!("    - {path}");
+            }
+            for path in &self.files_added {
+                println!("    + {path}");
+            }
+            for file in &self.files_changed {
+                println!(
+                    "    ~ {} ({})",
+                    file.path,
+                    size_change(Some(file.old_size), Some(file.new_size))
+                );
+            }
+        }
+
+        if !self.native_libraries.is_empty() {
+            println!();
+            println!("⚙️  Native libraries");
+            for library in &self.native_libraries {
+                println!(
+                    "    {} ({})",
+                    library.path,
+                    size_change(library.old_size, library.new_size)
+                );
+                if library.old_policy != library.new_policy {
+                    println!(
+                        "        Policy: {} -> {}",
+                        library.old_policy.as_deref().unwrap_or("-"),
+                        library.new_policy.as_deref().unwrap_or("-")
+                    );
+                }
+                for dependency in &library.dependencies_removed {
+                    println!("        - Needs: {dependency}");
+                }
+                for dependency in &library.dependencies_added {
+                    println!("        + Needs: {dependency}");
+                }
+            }
+        }
+    }
+}
+
+fn kind_name(kind: DistributionKind) -> &'static str {
+    match kind {
+        DistributionKind::Wheel => "wheel",
+        DistributionKind::Sdist => "source distribution",
+    }
+}
+
+/// The short hash auditwheel repair puts into the names of grafted libraries,
+/// e.g. `libbar-1a2b3c4d.so.1`
+static GRAFTED_HASH: Lazy<Regex> = Lazy::new(|| Regex::new(r"-[0-9a-f]{8}\.").unwrap());
+
+/// The interpreter specific part of extension module file names, e.g. `.cpython-311-x86_64-linux-gnu`
+/// in `foo.cpython-311-x86_64-linux-gnu.so` or `.cp311-win_amd64` in `foo.cp311-win_amd64.pyd`
+static EXTENSION_SUFFIX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\.(cpython-|cp3|pypy|graalpy)[^./]*(\.(so|pyd))$").unwrap());
+
+/// The path used to match files between the two archives
+///
+/// The `.dist-info` and `.data` directories of wheels and the top level directory of source
+/// distributions contain the version, which would otherwise make every file show up as
+/// removed and added when comparing two releases. For the same reason, the hashes of grafted
+/// libraries and the interpreter specific suffixes of extension modules are removed.
+fn comparison_key(kind: DistributionKind, path: &str) -> String {
+    let Some((top_level, rest)) = path.split_once('/') else {
+        return normalize_file_name(path);
+    };
+    match kind {
+        DistributionKind::Sdist => rest.to_string(),
+        DistributionKind::Wheel => {
+            for suffix in [".dist-info", ".data"] {
+                if let Some(name) = top_level.strip_suffix(suffix) {
+                    let name = name.split_once('-').map_or(name, |(name, _)| name);
+                    return format!("{name}{suffix}/{}", normalize_file_name(rest));
+                }
+            }
+            normalize_file_name(path)
+        }
+    }
+}
+
+/// Removes the parts of a file name in a wheel that change with the build rather than the
+/// contents: the hash of grafted libraries in `.libs`/`.dylibs` and the interpreter specific
+/// suffix of extension modules
+fn normalize_file_name(path: &str) -> String {
+    let (parent, file_name) = match path.rsplit_once('/') {
+        Some((parent, file_name)) => (Some(parent), file_name),
+        None => (None, path),
+    };
+    let is_grafted =
+        parent.is_some_and(|parent| parent.ends_with(".libs") || parent.ends_with(".dylibs"));
+    let file_name = if is_grafted {
+        GRAFTED_HASH.replace(file_name, ".")
+    } else {
+        EXTENSION_SUFFIX.replace(file_name, "$2")
+    };
+    match parent {
+        Some(parent) => format!("{parent}/{file_name}"),
+        None => file_name.into_owned(),
+    }
+}
+
+fn files_by_key(inspection: &Inspection) -> IndexMap<String, &FileInfo> {
+    inspection
+        .files
+        .iter()
+        .map(|file| (comparison_key(inspection.kind, &file.path), file))
+        .collect()
+}
+
+fn metadata_fields(inspection: &Inspection) -> IndexMap<String, Vec<String>> {
+    let mut fields: IndexMap<String, Vec<String>> = IndexMap::new();
+    if let Some(metadata) = &inspection.metadata {
+        for (key, value) in metadata.to_vec() {
+            fields.entry(key).or_default().push(value);
+        }
+    }
+    fields
+}
+
+/// The values in `left` that aren't in `right`, keeping the order of `left`
+fn difference(left: &[String], right: &[String]) -> Vec<String> {
+    left.iter()
+        .filter(|value| !right.contains(value))
+        .cloned()
+        .collect()
+}
+
+fn diff_native_libraries(old: &Inspection, new: &Inspection) -> Vec<ChangedNativeLibrary> {
+    let by_key = |inspection: &Inspection| -> IndexMap<String, NativeLibrary> {
+        inspection
+            .native_libraries
+            .iter()
+            .map(|library| {
+                (
+                    comparison_key(inspection.kind, &library.path),
+                    library.clone(),
+                )
+            })
+            .collect()
+    };
+    let old_libraries = by_key(old);
+    let new_libraries = by_key(new);
+
+    let mut changes = Vec::new();
+    for (key, old_library) in &old_libraries {
+        if !new_libraries.contains_key(key) {
+            changes.push(ChangedNativeLibrary {
+                path: old_library.path.clone(),
+                old_size: Some(old_library.size),
+                new_size: None,
+                old_policy: old_library.policy.clone(),
+                new_policy: None,
+                dependencies_added: Vec::new(),
+                dependencies_removed: old_library.dependencies.clone(),
+            });
+        }
+    }
+    for (key, new_library) in &new_libraries {
+        let old_library = old_libraries.get(key);
+        let old_dependencies = old_library
+            .map(|library| library.dependencies.clone())
+            .unwrap_or_default();
+        let change = ChangedNativeLibrary {
+            path: new_library.path.clone(),
+            old_size: old_library.map(|library| library.size),
+            new_size: Some(new_library.size),
+            old_policy: old_library.and_then(|library| library.policy.clone()),
+            new_policy: new_library.policy.clone(),
+            dependencies_added: difference(&new_library.dependencies, &old_dependencies),
+            dependencies_removed: difference(&old_dependencies, &new_library.dependencies),
+        };
+        if change.old_size != change.new_size
+            || change.old_policy != change.new_policy
+            || !change.dependencies_added.is_empty()
+            || !change.dependencies_removed.is_empty()
+        {
+            changes.push(change);
+        }
+    }
+    changes
+}
+
+fn print_values(indent: usize, removed: &[String], added: &[String]) {
+    for value in removed {
+        println!("{:indent$}- {value}", "");
+    }
+    for value in added {
+        println!("{:indent$}+ {value}", "");
+    }
+}
+
+fn size_change(old: Option<u64>, new: Option<u64>) -> String {
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            let delta = if new >= old {
+                format!("+{}", ByteSize(new - old))
+            } else {
+                format!("-{}", ByteSize(old - new))
+            };
+            format!("{} -> {}, {delta}", ByteSize(old), ByteSize(new))
+        }
+        (None, Some(new)) => format!("added, {}", ByteSize(new)),
+        (Some(old), None) => format!("removed, {}", ByteSize(old)),
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inspect::{RecordStatus, WheelInfo};
+    use crate::Metadata23;
+    use pretty_assertions::assert_eq;
+
+    fn file(path: &str, sha256: &str, size: u64) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            size,
+            sha256: sha256.to_string(),
+            record: RecordStatus::Ok,
+        }
+    }
+
+    fn library(path: &str, size: u64, dependencies: &[&str]) -> NativeLibrary {
+        NativeLibrary {
+            path: path.to_string(),
+            format: "ELF".to_string(),
+            abi_tag: Some("abi3".to_string()),
+            size,
+            dependencies: dependencies.iter().map(ToString::to_string).collect(),
+            policy: Some("manylinux_2_17".to_string()),
+            audit_error: None,
+        }
+    }
+
+    fn inspection(
+        version: &str,
+        tag: &str,
+        requires_dist: &[&str],
+        files: Vec<FileInfo>,
+        native_libraries: Vec<NativeLibrary>,
+    ) -> Inspection {
+        let mut metadata = Metadata23::new("foo".to_string(), version.parse().unwrap());
+        metadata.requires_dist = requires_dist.iter().map(|x| x.parse().unwrap()).collect();
+        Inspection {
+            path: PathBuf::from(format!("foo-{version}-{tag}.whl")),
+            kind: DistributionKind::Wheel,
+            wheel: Some(WheelInfo {
+                tags: vec![tag.to_string()],
+                ..Default::default()
+            }),
+            metadata: Some(metadata),
+            files,
+            native_libraries,
+            policy: None,
+        }
+    }
+
+    #[test]
+    fn test_comparison_key() {
+        let cases = [
+            (
+                DistributionKind::Wheel,
+                "foo-0.1.0.dist-info/METADATA",
+                "foo.dist-info/METADATA",
+            ),
+            (
+                DistributionKind::Wheel,
+                "foo-0.1.0.data/scripts/foo",
+                "foo.data/scripts/foo",
+            ),
+            (
+                DistributionKind::Wheel,
+                "foo/__init__.py",
+                "foo/__init__.py",
+            ),
+            (
+                DistributionKind::Sdist,
+                "foo-0.1.0/src/lib.rs",
+                "src/lib.rs",
+            ),
+        ];
+        for (kind, path, expected) in cases {
+            assert_eq!(comparison_key(kind, path), expected);
+        }
+    }
+
+    #[test]
+    fn test_comparison_key_grafted_library() {
+        let cases = [
+            ("foo.libs/libbar-1a2b3c4d.so.1", "foo.libs/libbar.so.1"),
+            ("foo.libs/libbar-5e6f7a8b.so.1", "foo.libs/libbar.so.1"),
+            (
+                "foo.dylibs/libbar-1a2b3c4d.dylib",
+                "foo.dylibs/libbar.dylib",
+            ),
+            ("foo.libs/bar-1a2b3c4d.dll", "foo.libs/bar.dll"),
+            // Only grafted libraries get a hash
+            ("foo/libbar-1a2b3c4d.so", "foo/libbar-1a2b3c4d.so"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(comparison_key(DistributionKind::Wheel, path), expected);
+        }
+    }
+
+    #[test]
+    fn test_comparison_key_extension_suffix() {
+        let cases = [
+            ("foo/bar.cpython-311-x86_64-linux-gnu.so", "foo/bar.so"),
+            ("foo/bar.cpython-312-x86_64-linux-gnu.so", "foo/bar.so"),
+            ("foo/bar.cpython-313t-x86_64-linux-gnu.so", "foo/bar.so"),
+            ("foo/bar.cpython-311-darwin.so", "foo/bar.so"),
+            ("foo/bar.cp311-win_amd64.pyd", "foo/bar.pyd"),
+            ("foo/bar.pypy310-pp73-x86_64-linux-gnu.so", "foo/bar.so"),
+            ("bar.cpython-311-x86_64-linux-gnu.so", "bar.so"),
+            ("foo/bar.abi3.so", "foo/bar.abi3.so"),
+            ("foo.data/scripts/cp311", "foo.data/scripts/cp311"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(comparison_key(DistributionKind::Wheel, path), expected);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = inspection(
+            "0.1.0",
+            "cp38-abi3-manylinux_2_17_x86_64",
+            &["numpy>=1.16"],
+            vec![
+                file("foo/__init__.py", "a", 10),
+                file("foo/foo.abi3.so", "b", 100),
+                file("foo/old.py", "c", 1),
+                file("foo-0.1.0.dist-info/LICENSE", "d", 5),
+            ],
+            vec![library("foo/foo.abi3.so", 100, &["libc.so.6", "libm.so.6"])],
+        );
+        let new = inspection(
+            "0.2.0",
+            "cp38-abi3-manylinux_2_28_x86_64",
+            &["numpy>=1.16", "cffi"],
+            vec![
+                file("foo/__init__.py", "a", 10),
+                file("foo/foo.abi3.so", "e", 150),
+                file("foo/new.py", "f", 2),
+                file("foo-0.2.0.dist-info/LICENSE", "d", 5),
+            ],
+            vec![library("foo/foo.abi3.so", 150, &["libc.so.6", "libz.so.1"])],
+        );
+        let diff = WheelDiff::from_inspections(&old, &new).unwrap();
+        assert_eq!(diff.files_added, vec!["foo/new.py"]);
+        assert_eq!(diff.files_removed, vec!["foo/old.py"]);
+        assert_eq!(
+            diff.files_changed,
+            vec![ChangedFile {
+                path: "foo/foo.abi3.so".to_string(),
+                old_size: 100,
+                new_size: 150
+            }]
+        );
+        assert_eq!(
+            diff.metadata,
+            vec![ChangedField {
+                field: "Version".to_string(),
+                old: vec!["0.1.0".to_string()],
+                new: vec!["0.2.0".to_string()]
+            }]
+        );
+        assert_eq!(diff.requires_dist_added, vec!["cffi"]);
+        assert!(diff.requires_dist_removed.is_empty());
+        assert_eq!(diff.tags_added, vec!["cp38-abi3-manylinux_2_28_x86_64"]);
+        assert_eq!(diff.tags_removed, vec!["cp38-abi3-manylinux_2_17_x86_64"]);
+        assert_eq!(
+            diff.native_libraries,
+            vec![ChangedNativeLibrary {
+                path: "foo/foo.abi3.so".to_string(),
+                old_size: Some(100),
+                new_size: Some(150),
+                old_policy: Some("manylinux_2_17".to_string()),
+                new_policy: Some("manylinux_2_17".to_string()),
+                dependencies_added: vec!["libz.so.1".to_string()],
+                dependencies_removed: vec!["libm.so.6".to_string()],
+            }]
+        );
+
+        let same = WheelDiff::from_inspections(&old, &old).unwrap();
+        assert!(same.is_empty());
+    }
+}
@@ -7,6 +7,7 @@ use pretty_assertions::StrComparison;
 use schemars::schema_for;
 
 use crate::pyproject_toml::ToolMaturin;
+use crate::Message;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum, Default)]
 /// The mode to use when generating the JSON schema.
@@ -20,7 +21,7 @@ pub enum Mode {
     DryRun,
 }
 
-/// Generate the JSON schema for the `pyproject.toml` file.
+/// Generate the JSON schemas for the `pyproject.toml` file and the build messages.
 #[derive(Debug, clap::Parser)]
 pub struct GenerateJsonSchemaOptions {
     /// The mode to use when generating the JSON schema.
@@ -28,33 +29,38 @@ pub struct GenerateJsonSchemaOptions {
     pub mode: Mode,
 }
 
-/// Generate the JSON schema for the `pyproject.toml` file.
+/// Generate the JSON schemas for the `pyproject.toml` file and the `--message-format json`
+/// messages.
 pub fn generate_json_schema(args: GenerateJsonSchemaOptions) -> Result<()> {
-    let schema = schema_for!(ToolMaturin);
-    let schema_string = serde_json::to_string_pretty(&schema).unwrap();
-    let filename = "maturin.schema.json";
-    let schema_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(filename);
+    let schemas = [
+        ("maturin.schema.json", schema_for!(ToolMaturin)),
+        ("maturin-messages.schema.json", schema_for!(Message)),
+    ];
+    for (filename, schema) in schemas {
+        let schema_string = serde_json::to_string_pretty(&schema).unwrap();
+        let schema_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(filename);
 
-    match args.mode {
-        Mode::DryRun => {
-            println!("{schema_string}");
-        }
-        Mode::Check => {
-            let current = fs::read_to_string(schema_path)?;
-            if current == schema_string {
-                println!("Up-to-date: {filename}");
-            } else {
-                let comparison = StrComparison::new(&current, &schema_string);
-                bail!("{filename} changed, please run `cargo run --features schemars -- generate-json-schema`:\n{comparison}",);
+        match args.mode {
+            Mode::DryRun => {
+                println!("{schema_string}");
             }
-        }
-        Mode::Write => {
-            let current = fs::read_to_string(&schema_path)?;
-            if current == schema_string {
-                println!("Up-to-date: {filename}");
-            } else {
-                println!("Updating: {filename}");
-                fs::write(schema_path, schema_string.as_bytes())?;
+            Mode::Check => {
+                let current = fs::read_to_string(schema_path)?;
+                if current == schema_string {
+                    println!("Up-to-date: {filename}");
+                } else {
+                    let comparison = StrComparison::new(&current, &schema_string);
+                    bail!("{filename} changed, please run `cargo run --features schemars -- generate-json-schema`:\n{comparison}",);
+                }
+            }
+            Mode::Write => {
+                let current = fs::read_to_string(&schema_path).unwrap_or_default();
+                if current == schema_string {
+                    println!("Up-to-date: {filename}");
+                } else {
+                    println!("Updating: {filename}");
+                    fs::write(schema_path, schema_string.as_bytes())?;
+                }
             }
         }
     }
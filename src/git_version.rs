@@ -0,0 +1,201 @@
+//! Derive the version of a project from `git describe` for `version-source = "git"`
+
+use crate::metadata::pep440_to_semver;
+use anyhow::{bail, format_err, Context, Result};
+use pep440_rs::{LocalSegment, PreRelease, Version};
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+/// The parsed output of `git describe --tags --long --dirty --always`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GitDescribe {
+    /// The most recent tag, `None` if there are no tags
+    tag: Option<String>,
+    /// The number of commits since the tag
+    distance: u64,
+    /// The abbreviated commit hash
+    hash: String,
+    /// Whether the working tree has modifications
+    dirty: bool,
+}
+
+impl GitDescribe {
+    /// Parses e.g. `v1.2.3-4-gabc1234-dirty` or, without any tags, `abc1234`
+    fn parse(describe: &str) -> Result<Self> {
+        let describe = describe.trim();
+        let (describe, dirty) = match describe.strip_suffix("-dirty") {
+            Some(describe) => (describe, true),
+            None => (describe, false),
+        };
+        // Tags may contain dashes themselves, so we split from the right
+        let mut parts = describe.rsplitn(3, '-');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(hash), Some(distance), Some(tag)) if hash.starts_with('g') => Ok(Self {
+                tag: Some(tag.to_string()),
+                distance: distance
+                    .parse()
+                    .with_context(|| format!("Invalid `git describe` output `{describe}`"))?,
+                hash: hash[1..].to_string(),
+                dirty,
+            }),
+            (Some(hash), None, None) if !hash.is_empty() => Ok(Self {
+                tag: None,
+                distance: 0,
+                hash: hash.to_string(),
+                dirty,
+            }),
+            _ => bail!("Invalid `git describe` output `{describe}`"),
+        }
+    }
+
+    /// Turns the description into a PEP 440 version
+    ///
+    /// A clean checkout of a tag gets the version of the tag. Otherwise, we guess the next version
+    /// by bumping the pre-release number or the last release segment and mark it as dev release
+    /// with the number of commits since the tag and the commit hash as local version, e.g.
+    /// `1.2.4.dev3+gabc1234`.
+    fn to_version(&self) -> Result<Version> {
+        let tag_version = match &self.tag {
+            Some(tag) => {
+                let stripped = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+                Version::from_str(stripped).map_err(|err| {
+                    format_err!("git tag `{tag}` is not a valid PEP 440 version: {err}")
+                })?
+            }
+            None => Version::new([0, 0, 0]),
+        };
+        // Cargo.toml requires `major.minor.patch`
+        let mut release = tag_version.release().to_vec();
+        if release.len() < 3 {
+            release.resize(3, 0);
+        }
+        let mut version = tag_version.with_release(release);
+
+        if self.distance > 0 || self.dirty || self.tag.is_none() {
+            if self.tag.is_some() {
+                version = match version.pre() {
+                    Some(pre) => version.with_pre(Some(PreRelease {
+                        kind: pre.kind,
+                        number: pre.number + 1,
+                    })),
+                    None => {
+                        let mut release = version.release().to_vec();
+                        *release.last_mut().unwrap() += 1;
+                        version.with_release(release)
+                    }
+                };
+            }
+            let mut local = vec![LocalSegment::String(format!("g{}", self.hash))];
+            if self.dirty {
+                local.push(LocalSegment::String("dirty".to_string()));
+            }
+            version = version.with_dev(Some(self.distance)).with_local(local);
+        }
+
+        // The version is written to Cargo.toml in the source distribution
+        pep440_to_semver(&version).with_context(|| match &self.tag {
+            Some(tag) => format!("Unsupported version in git tag `{tag}`"),
+            None => "Unsupported version from git".to_string(),
+        })?;
+        Ok(version)
+    }
+}
+
+/// Runs a git command in the project root and returns its stdout
+fn git(project_root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(project_root)
+        .output()
+        .with_context(|| format!("Failed to run `git {}`", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed with {}\n--- Project Path: {}\n--- Stderr:\n{}",
+            args.join(" "),
+            output.status,
+            project_root.display(),
+            String::from_utf8_lossy(&output.stderr),
+        );
+    }
+    String::from_utf8(output.stdout).context("git printed invalid utf-8 ಠ_ಠ")
+}
+
+/// Derives a PEP 440 version from `git describe` in the project root
+pub(crate) fn version_from_git(project_root: &Path) -> Result<Version> {
+    let describe = git(
+        project_root,
+        &["describe", "--tags", "--long", "--dirty", "--always"],
+    )?;
+    let mut describe = GitDescribe::parse(&describe)?;
+    if describe.tag.is_none() {
+        // Without tags, `git describe` doesn't count the commits for us
+        describe.distance = git(project_root, &["rev-list", "--count", "HEAD"])?
+            .trim()
+            .parse()
+            .context("Failed to parse the output of `git rev-list --count HEAD`")?;
+    }
+    describe.to_version()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn version(describe: &str) -> String {
+        GitDescribe::parse(describe)
+            .unwrap()
+            .to_version()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_git_describe() {
+        assert_eq!(
+            GitDescribe::parse("v1.0.0-rc.1-3-gabc1234-dirty\n").unwrap(),
+            GitDescribe {
+                tag: Some("v1.0.0-rc.1".to_string()),
+                distance: 3,
+                hash: "abc1234".to_string(),
+                dirty: true,
+            }
+        );
+        assert_eq!(
+            GitDescribe::parse("abc1234").unwrap(),
+            GitDescribe {
+                tag: None,
+                distance: 0,
+                hash: "abc1234".to_string(),
+                dirty: false,
+            }
+        );
+        assert!(GitDescribe::parse("").is_err());
+    }
+
+    #[test]
+    fn test_git_describe_to_version() {
+        assert_eq!(version("v1.2.3-0-gabc1234"), "1.2.3");
+        assert_eq!(version("1.2-0-gabc1234"), "1.2.0");
+        assert_eq!(
+            version("v1.2.3-0-gabc1234-dirty"),
+            "1.2.4.dev0+gabc1234.dirty"
+        );
+        assert_eq!(version("v1.2.3-5-gabc1234"), "1.2.4.dev5+gabc1234");
+        assert_eq!(version("v1.0.0rc1-2-gabc1234"), "1.0.0rc2.dev2+gabc1234");
+        assert_eq!(
+            version("v1.0.0-alpha.1-2-gabc1234"),
+            "1.0.0a2.dev2+gabc1234"
+        );
+        assert_eq!(version("abc1234"), "0.0.0.dev0+gabc1234");
+        assert!(GitDescribe::parse("v1.0.0.post1-0-gabc1234")
+            .unwrap()
+            .to_version()
+            .is_err());
+        assert!(GitDescribe::parse("release-0-gabc1234")
+            .unwrap()
+            .to_version()
+            .is_err());
+    }
+}
@@ -0,0 +1,597 @@
+//! Reads back wheels and source distributions, e.g. to check what [crate::WheelWriter] put in them
+use crate::auditwheel::{audit_elf, AuditPolicies, Policy};
+use crate::python_interpreter::calculate_abi_tag;
+use crate::Metadata23;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytesize::ByteSize;
+use flate2::read::GzDecoder;
+use fs_err::File;
+use goblin::mach::Mach;
+use goblin::Object;
+use indexmap::IndexMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::{self, FromStr};
+
+/// The kind of a python package file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistributionKind {
+    /// A `.whl` file
+    Wheel,
+    /// A `.tar.gz` source distribution
+    Sdist,
+}
+
+/// The parsed `.dist-info/WHEEL` file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WheelInfo {
+    /// `Wheel-Version`
+    pub wheel_version: Option<String>,
+    /// `Generator`
+    pub generator: Option<String>,
+    /// `Root-Is-Purelib`
+    pub root_is_purelib: bool,
+    /// All `Tag` entries
+    pub tags: Vec<String>,
+}
+
+/// Whether a file matches its RECORD entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordStatus {
+    /// Hash and size match the RECORD
+    Ok,
+    /// Files without a hash in the RECORD, i.e. the RECORD itself, or sdist files
+    Unverified,
+    /// The file isn't listed in the RECORD
+    NotInRecord,
+    /// The file is listed in the RECORD, but missing from the archive
+    Missing,
+    /// The hash doesn't match the RECORD
+    HashMismatch,
+    /// The hash matches, but the size doesn't match the RECORD
+    SizeMismatch,
+}
+
+/// A file in the archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileInfo {
+    /// The path inside the archive
+    pub path: String,
+    /// The uncompressed size
+    pub size: u64,
+    /// The urlsafe base64 encoded sha256 of the contents, in the RECORD format
+    pub sha256: String,
+    /// Whether the file matches its RECORD entry
+    pub record: RecordStatus,
+}
+
+/// A native library or executable in the archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NativeLibrary {
+    /// The path inside the archive
+    pub path: String,
+    /// `ELF`, `Mach-O`, `Mach-O universal` or `PE`
+    pub format: String,
+    /// The python ABI tag derived from the extension module suffix, e.g. `cp310` or `abi3`
+    pub abi_tag: Option<String>,
+    /// The uncompressed size
+    pub size: u64,
+    /// The dynamically linked libraries
+    pub dependencies: Vec<String>,
+    /// The highest priority manylinux/musllinux policy the library satisfies, for ELF files
+    pub policy: Option<String>,
+    /// Why auditing the library failed, for ELF files
+    pub audit_error: Option<String>,
+}
+
+/// Everything `maturin inspect` found in a wheel or source distribution
+#[derive(Debug, Clone, Serialize)]
+pub struct Inspection {
+    /// The inspected file
+    pub path: PathBuf,
+    /// Wheel or sdist
+    pub kind: DistributionKind,
+    /// The parsed WHEEL file, for wheels
+    pub wheel: Option<WheelInfo>,
+    /// The parsed METADATA or PKG-INFO
+    pub metadata: Option<Metadata23>,
+    /// All files with their RECORD verification status
+    pub files: Vec<FileInfo>,
+    /// The native libraries and executables
+    pub native_libraries: Vec<NativeLibrary>,
+    /// The lowest policy of all native ELF libraries, which is the policy of the whole wheel
+    pub policy: Option<String>,
+}
+
+impl Inspection {
+    /// Reads and checks a wheel or a `.tar.gz` source distribution
+    pub fn new(path: &Path) -> Result<Self> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if file_name.ends_with(".whl") {
+            Self::from_wheel(path)
+        } else if file_name.ends_with(".tar.gz") {
+            Self::from_sdist(path)
+        } else {
+            bail!(
+                "Don't know how to inspect {}, expected a .whl or .tar.gz file",
+                path.display()
+            )
+        }
+    }
+
+    fn from_wheel(path: &Path) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)
+            .with_context(|| format!("Failed to open {} as zip archive", path.display()))?;
+        let mut contents = IndexMap::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let mut buffer = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut buffer)?;
+            contents.insert(entry.name().to_string(), buffer);
+        }
+
+        let dist_info_dir = contents
+            .keys()
+            .filter_map(|name| name.strip_suffix("/WHEEL"))
+            .find(|dir| dir.ends_with(".dist-info") && !dir.contains('/'))
+            .map(ToString::to_string)
+            .context("The wheel has no .dist-info/WHEEL file")?;
+        let read_text = |name: &str| -> Result<Option<String>> {
+            contents
+                .get(&format!("{dist_info_dir}/{name}"))
+                .map(|bytes| {
+                    String::from_utf8(bytes.clone())
+                        .with_context(|| format!("{dist_info_dir}/{name} is not valid utf-8"))
+                })
+                .transpose()
+        };
+        let wheel = read_text("WHEEL")?.map(|content| parse_wheel_file(&content));
+        let metadata = read_text("METADATA")?
+            .map(|content| Metadata23::from_str(&content))
+            .transpose()
+            .context("Failed to parse METADATA")?;
+        let record_path = format!("{dist_info_dir}/RECORD");
+        let record = read_text("RECORD")?
+            .map(|content| parse_record(&content))
+            .unwrap_or_default();
+
+        let mut files: Vec<FileInfo> = contents
+            .iter()
+            .map(|(name, bytes)| {
+                let sha256 = URL_SAFE_NO_PAD.encode(Sha256::digest(bytes));
+                let size = bytes.len() as u64;
+                let status = if name == &record_path
+                    || name.ends_with(".dist-info/RECORD.jws")
+                    || name.ends_with(".dist-info/RECORD.p7s")
+                {
+                    RecordStatus::Unverified
+                } else {
+                    match record.get(name) {
+                        None => RecordStatus::NotInRecord,
+                        Some((hash, _)) if hash.is_empty() => RecordStatus::Unverified,
+                        Some((hash, _)) if hash.strip_prefix("sha256=") != Some(&sha256) => {
+                            RecordStatus::HashMismatch
+                        }
+                        Some((_, recorded_size)) if *recorded_size != Some(size) => {
+                            RecordStatus::SizeMismatch
+                        }
+                        Some(_) => RecordStatus::Ok,
+                    }
+                };
+                FileInfo {
+                    path: name.clone(),
+                    size,
+                    sha256,
+                    record: status,
+                }
+            })
+            .collect();
+        for (name, (hash, size)) in &record {
+            if !contents.contains_key(name) {
+                files.push(FileInfo {
+                    path: name.clone(),
+                    size: size.unwrap_or_default(),
+                    sha256: hash.trim_start_matches("sha256=").to_string(),
+                    record: RecordStatus::Missing,
+                });
+            }
+        }
+
+        let native_libraries = inspect_native_libraries(&contents);
+        let policy = lowest_policy(&native_libraries);
+        Ok(Self {
+            path: path.to_path_buf(),
+            kind: DistributionKind::Wheel,
+            wheel,
+            metadata,
+            files,
+            native_libraries,
+            policy,
+        })
+    }
+
+    fn from_sdist(path: &Path) -> Result<Self> {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+        let mut contents = IndexMap::new();
+        for entry in archive
+            .entries()
+            .with_context(|| format!("Failed to open {} as tar.gz archive", path.display()))?
+        {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer)?;
+            contents.insert(name, buffer);
+        }
+
+        // PKG-INFO lives in the top level `{name}-{version}` directory
+        let metadata = contents
+            .iter()
+            .find(|(name, _)| {
+                name.strip_suffix("/PKG-INFO")
+                    .is_some_and(|dir| !dir.contains('/'))
+            })
+            .map(|(_, bytes)| -> Result<Metadata23> {
+                Metadata23::from_str(str::from_utf8(bytes)?)
+            })
+            .transpose()
+            .context("Failed to parse PKG-INFO")?;
+        let files = contents
+            .iter()
+            .map(|(name, bytes)| FileInfo {
+                path: name.clone(),
+                size: bytes.len() as u64,
+                sha256: URL_SAFE_NO_PAD.encode(Sha256::digest(bytes)),
+                record: RecordStatus::Unverified,
+            })
+            .collect();
+        let native_libraries = inspect_native_libraries(&contents);
+        let policy = lowest_policy(&native_libraries);
+        Ok(Self {
+            path: path.to_path_buf(),
+            kind: DistributionKind::Sdist,
+            wheel: None,
+            metadata,
+            files,
+            native_libraries,
+            policy,
+        })
+    }
+
+    /// Whether all files match the RECORD
+    pub fn record_is_valid(&self) -> bool {
+        self.files
+            .iter()
+            .all(|file| matches!(file.record, RecordStatus::Ok | RecordStatus::Unverified))
+    }
+
+    /// Prints a human readable report
+    pub fn print(&self) {
+        let kind = match self.kind {
+            DistributionKind::Wheel => "wheel",
+            DistributionKind::Sdist => "source distribution",
+        };
+        println!("📦 {} ({kind})", self.path.display());
+
+        if let Some(wheel) = &self.wheel {
+            println!();
+            println!("🏷  WHEEL");
+            if let Some(wheel_version) = &wheel.wheel_version {
+                println!("    Wheel-Version: {wheel_version}");
+            }
+            if let Some(generator) = &wheel.generator {
+                println!("    Generator: {generator}");
+            }
+            println!("    Root-Is-Purelib: {}", wheel.root_is_purelib);
+            for tag in &wheel.tags {
+                println!("    Tag: {tag}");
+            }
+        }
+
+        if let Some(metadata) = &self.metadata {
+            println!();
+            println!("📋 Metadata");
+            for (key, value) in metadata.to_vec() {
+                if key == "Description" {
+                    println!("    Description: {} lines", value.lines().count());
+                } else {
+                    println!("    {key}: {value}");
+                }
+            }
+        }
+
+        println!();
+        println!("📁 Files");
+        for file in &self.files {
+            let status = match file.record {
+                RecordStatus::Ok => "ok",
+                RecordStatus::Unverified => "-",
+                RecordStatus::NotInRecord => "not in RECORD",
+                RecordStatus::Missing => "missing",
+                RecordStatus::HashMismatch => "hash mismatch",
+                RecordStatus::SizeMismatch => "size mismatch",
+            };
+            println!(
+                "    {:>10}  {:<13}  {}",
+                ByteSize(file.size).to_string(),
+                status,
+                file.path
+            );
+        }
+        if self.kind == DistributionKind::Wheel {
+            if self.record_is_valid() {
+                println!("✅ All files match the RECORD");
+            } else {
+                println!("❌ Some files don't match the RECORD");
+            }
+        }
+
+        if !self.native_libraries.is_empty() {
+            println!();
+            println!("⚙️  Native libraries");
+            for library in &self.native_libraries {
+                let mut details = vec![library.format.clone(), ByteSize(library.size).to_string()];
+                if let Some(abi_tag) = &library.abi_tag {
+                    details.push(format!("ABI tag {abi_tag}"));
+                }
+                println!("    {} ({})", library.path, details.join(", "));
+                if let Some(policy) = &library.policy {
+                    println!("        Policy: {policy}");
+                }
+                if let Some(audit_error) = &library.audit_error {
+                    println!("        Audit failed: {audit_error}");
+                }
+                for dependency in &library.dependencies {
+                    println!("        Needs: {dependency}");
+                }
+            }
+        }
+
+        if let Some(policy) = &self.policy {
+            println!();
+            println!("🐧 Audited policy: {policy}");
+        }
+    }
+}
+
+/// Parses the `key: value` lines of a WHEEL file
+fn parse_wheel_file(content: &str) -> WheelInfo {
+    let mut wheel = WheelInfo::default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "Wheel-Version" => wheel.wheel_version = Some(value),
+            "Generator" => wheel.generator = Some(value),
+            "Root-Is-Purelib" => wheel.root_is_purelib = value.eq_ignore_ascii_case("true"),
+            "Tag" => wheel.tags.push(value),
+            _ => {}
+        }
+    }
+    wheel
+}
+
+/// Parses a RECORD file into a map from path to hash and size
+///
+/// Paths may contain commas, so the lines are split from the right
+pub(crate) fn parse_record(content: &str) -> IndexMap<String, (String, Option<u64>)> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let mut parts = line.rsplitn(3, ',');
+            let size = parts.next()?;
+            let hash = parts.next()?;
+            let path = parts.next()?;
+            let path = path
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .map(|path| path.replace("\"\"", "\""))
+                .unwrap_or_else(|| path.to_string());
+            Some((path, (hash.to_string(), size.trim().parse().ok())))
+        })
+        .collect()
+}
+
+/// The ABI tag of an extension module file name, e.g. `cp310` for
+/// `foo.cpython-310-x86_64-linux-gnu.so` or `abi3` for `foo.abi3.so`
+fn extension_abi_tag(path: &str) -> Option<String> {
+    let file_name = path.rsplit('/').next()?;
+    let (_, ext_suffix) = file_name.split_once('.')?;
+    let ext_suffix = format!(".{ext_suffix}");
+    if ext_suffix == ".abi3.so" {
+        Some("abi3".to_string())
+    } else {
+        calculate_abi_tag(&ext_suffix)
+    }
+}
+
+fn inspect_native_libraries(contents: &IndexMap<String, Vec<u8>>) -> Vec<NativeLibrary> {
+    let is_native = |bytes: &[u8]| {
+        bytes.starts_with(b"\x7fELF")
+            || bytes.starts_with(b"MZ")
+            || bytes.starts_with(&[0xca, 0xfe, 0xba, 0xbe])
+            || bytes.starts_with(&[0xcf, 0xfa, 0xed, 0xfe])
+            || bytes.starts_with(&[0xce, 0xfa, 0xed, 0xfe])
+    };
+    let native: Vec<_> = contents
+        .iter()
+        .filter(|(_, bytes)| is_native(bytes))
+        .collect();
+    // Libraries that ship in the archive, e.g. those grafted in by auditwheel repair
+    let bundled: HashSet<String> = native
+        .iter()
+        .filter_map(|(name, _)| name.rsplit('/').next())
+        .map(ToString::to_string)
+        .collect();
+    let policies = AuditPolicies::default();
+
+    let mut libraries = Vec::new();
+    for (name, bytes) in native {
+        let Ok(object) = Object::parse(bytes) else {
+            continue;
+        };
+        let mut library = NativeLibrary {
+            path: name.clone(),
+            format: String::new(),
+            abi_tag: extension_abi_tag(name),
+            size: bytes.len() as u64,
+            dependencies: Vec::new(),
+            policy: None,
+            audit_error: None,
+        };
+        match object {
+            Object::Elf(elf) => {
+                library.format = "ELF".to_string();
+                library.dependencies = elf.libraries.iter().map(ToString::to_string).collect();
+                match audit_elf(&elf, &bundled, &policies) {
+                    Ok(policy) => library.policy = Some(policy.name),
+                    Err(err) => library.audit_error = Some(err.to_string()),
+                }
+            }
+            Object::Mach(Mach::Binary(macho)) => {
+                library.format = "Mach-O".to_string();
+                library.dependencies = macho_dependencies(&macho);
+            }
+            Object::Mach(Mach::Fat(fat)) => {
+                library.format = "Mach-O universal".to_string();
+                let mut dependencies = Vec::new();
+                for arch in fat.into_iter().flatten() {
+                    if let goblin::mach::SingleArch::MachO(macho) = arch {
+                        for dependency in macho_dependencies(&macho) {
+                            if !dependencies.contains(&dependency) {
+                                dependencies.push(dependency);
+                            }
+                        }
+                    }
+                }
+                library.dependencies = dependencies;
+            }
+            Object::PE(pe) => {
+                library.format = "PE".to_string();
+                library.dependencies = pe.libraries.iter().map(ToString::to_string).collect();
+            }
+            _ => continue,
+        }
+        libraries.push(library);
+    }
+    libraries
+}
+
+fn macho_dependencies(macho: &goblin::mach::MachO) -> Vec<String> {
+    macho
+        .libs
+        .iter()
+        // goblin lists the library itself as `self`
+        .filter(|lib| **lib != "self")
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// The policy with the lowest priority is the one the whole wheel complies with
+fn lowest_policy(libraries: &[NativeLibrary]) -> Option<String> {
+    if libraries
+        .iter()
+        .any(|library| library.audit_error.is_some())
+    {
+        return Some("linux".to_string());
+    }
+    let priorities: HashMap<String, i64> = libraries
+        .iter()
+        .filter_map(|library| library.policy.as_ref())
+        .filter_map(|name| Some((name.clone(), Policy::from_name(name)?.priority)))
+        .collect();
+    priorities
+        .into_iter()
+        .min_by_key(|(_, priority)| *priority)
+        .map(|(name, _)| name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_record() {
+        let record = parse_record(
+            "foo/__init__.py,sha256=abc,12\n\
+             \"foo/a,b.txt\",sha256=def,3\n\
+             foo-0.1.0.dist-info/RECORD,,\n",
+        );
+        assert_eq!(
+            record.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "foo/__init__.py".to_string(),
+                    ("sha256=abc".to_string(), Some(12))
+                ),
+                (
+                    "foo/a,b.txt".to_string(),
+                    ("sha256=def".to_string(), Some(3))
+                ),
+                (
+                    "foo-0.1.0.dist-info/RECORD".to_string(),
+                    (String::new(), None)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_wheel_file() {
+        let wheel = parse_wheel_file(
+            "Wheel-Version: 1.0\nGenerator: maturin (1.5.1)\nRoot-Is-Purelib: false\n\
+             Tag: cp310-cp310-manylinux_2_17_x86_64\nTag: cp310-cp310-manylinux2014_x86_64\n",
+        );
+        assert_eq!(
+            wheel,
+            WheelInfo {
+                wheel_version: Some("1.0".to_string()),
+                generator: Some("maturin (1.5.1)".to_string()),
+                root_is_purelib: false,
+                tags: vec![
+                    "cp310-cp310-manylinux_2_17_x86_64".to_string(),
+                    "cp310-cp310-manylinux2014_x86_64".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_extension_abi_tag() {
+        let cases = [
+            ("foo/foo.cpython-310-x86_64-linux-gnu.so", Some("cp310")),
+            ("foo/foo.abi3.so", Some("abi3")),
+            ("foo.cp39-win_amd64.pyd", Some("cp39")),
+            (
+                "foo/foo.pypy39-pp73-x86_64-linux-gnu.so",
+                Some("pypy39_pp73"),
+            ),
+            ("foo.libs/libbar-1234abcd.so.1", None),
+            ("foo-0.1.0.data/scripts/foo", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(extension_abi_tag(path).as_deref(), expected, "{path}");
+        }
+    }
+}
@@ -11,6 +11,8 @@
 //!
 //! - upload: Uses ureq to add the upload command.
 //!
+//! - build-cache: Adds `--build-cache` and the cache command to reuse previously built wheels.
+//!
 //! - rustls: Makes ureq use the rustls stack so that we can build maturin in a CentOS 6
 //! docker container and which maturin itself manylinux compliant.
 //!
@@ -23,13 +25,20 @@
 
 #![deny(missing_docs)]
 
-pub use crate::build_context::{BridgeModel, BuildContext, BuiltWheelMetadata};
+#[cfg(feature = "build-cache")]
+pub use crate::build_cache::BuildCache;
+pub use crate::build_context::{BridgeModel, BuildContext, BuiltWheelMetadata, ExtensionModule};
 pub use crate::build_options::{BuildOptions, CargoOptions};
 pub use crate::cargo_toml::CargoToml;
 pub use crate::compile::{compile, BuildArtifact};
+pub use crate::compression::{CompressionMethod, CompressionOptions};
 pub use crate::develop::{develop, DevelopOptions};
+pub use crate::diff::WheelDiff;
 #[cfg(feature = "schemars")]
 pub use crate::generate_json_schema::{generate_json_schema, GenerateJsonSchemaOptions, Mode};
+pub use crate::inspect::Inspection;
+pub use crate::licenses::{LicenseSource, ThirdPartyLicense};
+pub use crate::message::{GraftedLibrary, Message, MessageFormat};
 pub use crate::metadata::{Metadata23, WheelMetadata};
 pub use crate::module_writer::{
     write_dist_info, ModuleWriter, PathWriter, SDistWriter, WheelWriter,
@@ -38,12 +47,17 @@ pub use crate::module_writer::{
 pub use crate::new_project::{init_project, new_project, GenerateProjectOptions};
 pub use crate::pyproject_toml::PyProjectToml;
 pub use crate::python_interpreter::PythonInterpreter;
+pub use crate::repair::{repair, RepairOptions};
+pub use crate::reproducible::verify_reproducible;
+pub use crate::sbom::SbomFormat;
 pub use crate::target::Target;
 #[cfg(feature = "upload")]
 pub use crate::upload::{upload, upload_ui, PublishOpt, Registry, UploadError};
-pub use auditwheel::PlatformTag;
+pub use auditwheel::{audit_native_files, AuditPolicies, LimitedApiCheck, PlatformTag};
 
 mod auditwheel;
+#[cfg(feature = "build-cache")]
+mod build_cache;
 mod build_context;
 mod build_options;
 mod cargo_toml;
@@ -51,9 +65,16 @@ mod cargo_toml;
 /// Generate CI configuration
 pub mod ci;
 mod compile;
+mod compression;
 mod cross_compile;
+mod debuginfo;
 mod develop;
+mod diff;
 mod generate_json_schema;
+mod git_version;
+mod inspect;
+mod licenses;
+mod message;
 mod metadata;
 mod module_writer;
 #[cfg(feature = "scaffolding")]
@@ -61,6 +82,9 @@ mod new_project;
 mod project_layout;
 pub mod pyproject_toml;
 mod python_interpreter;
+mod repair;
+mod reproducible;
+mod sbom;
 mod source_distribution;
 mod target;
 #[cfg(feature = "upload")]
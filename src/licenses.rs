@@ -0,0 +1,518 @@
+//! Licenses of the third-party crates that are statically linked into the native libraries
+use crate::sbom::{linked_crates, TargetCfg};
+use anyhow::{bail, Context, Result};
+use cargo_metadata::{Metadata, Package};
+use fs_err as fs;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// A license of a third-party crate, written to `.dist-info/licenses/` in wheels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThirdPartyLicense {
+    /// The path relative to `.dist-info/licenses/`, e.g. `third_party/libc-0.2.153/LICENSE-MIT`
+    pub path: String,
+    /// Where the license text comes from
+    pub source: LicenseSource,
+}
+
+/// Where the text of a [ThirdPartyLicense] comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseSource {
+    /// A license file shipped with the crate
+    File(PathBuf),
+    /// The SPDX license text of the declared license, for crates without license files
+    Text(&'static str),
+}
+
+/// The texts of the SPDX licenses common in the rust ecosystem
+const SPDX_LICENSE_TEXTS: &[(&str, &str)] = &[
+    ("0BSD", include_str!("licenses/0BSD.txt")),
+    ("Apache-2.0", include_str!("licenses/Apache-2.0.txt")),
+    ("BSD-2-Clause", include_str!("licenses/BSD-2-Clause.txt")),
+    ("BSD-3-Clause", include_str!("licenses/BSD-3-Clause.txt")),
+    ("BSL-1.0", include_str!("licenses/BSL-1.0.txt")),
+    ("ISC", include_str!("licenses/ISC.txt")),
+    ("MIT", include_str!("licenses/MIT.txt")),
+    ("Unlicense", include_str!("licenses/Unlicense.txt")),
+    ("Zlib", include_str!("licenses/Zlib.txt")),
+];
+
+/// File name prefixes of license files, matched case-insensitively
+const LICENSE_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE"];
+
+/// The license identifiers in an SPDX license expression, e.g. `MIT` and `Apache-2.0` for
+/// `MIT OR Apache-2.0`
+fn license_ids(expression: &str) -> Vec<&str> {
+    let mut ids = Vec::new();
+    let mut tokens = expression
+        // Cargo used to accept `MIT/Apache-2.0`
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+        .filter(|token| !token.is_empty());
+    while let Some(token) = tokens.next() {
+        match token {
+            "AND" | "OR" => {}
+            // The exception belongs to the license before it
+            "WITH" => {
+                tokens.next();
+            }
+            _ => ids.push(token.trim_end_matches('+')),
+        }
+    }
+    ids
+}
+
+/// The license files in the root of the crate and the `license-file` from its Cargo.toml
+fn crate_license_files(package: &Package) -> Result<Vec<PathBuf>> {
+    let crate_dir = package
+        .manifest_path
+        .parent()
+        .context("Invalid manifest path")?
+        .as_std_path();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(crate_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let file_name = file_name.to_uppercase();
+        if path.is_file()
+            && LICENSE_FILE_PREFIXES
+                .iter()
+                .any(|prefix| file_name.starts_with(prefix))
+        {
+            files.push(path);
+        }
+    }
+    if let Some(license_file) = &package.license_file {
+        let license_file = crate_dir.join(license_file);
+        if license_file.is_file()
+            && !files
+                .iter()
+                .any(|file| file.file_name() == license_file.file_name())
+        {
+            files.push(license_file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// SPDX license identifiers, see <https://spdx.org/licenses/>
+const SPDX_LICENSE_IDS: &[&str] = &[
+    "0BSD",
+    "AAL",
+    "AFL-3.0",
+    "AGPL-1.0",
+    "AGPL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.0",
+    "Apache-1.1",
+    "Apache-2.0",
+    "APSL-2.0",
+    "Artistic-2.0",
+    "Beerware",
+    "blessing",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-3-Clause-LBNL",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "bzip2-1.0.6",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-NC-4.0",
+    "CC-BY-NC-SA-4.0",
+    "CC-BY-ND-4.0",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CECILL-2.1",
+    "CECILL-B",
+    "CECILL-C",
+    "CNRI-Python",
+    "CPL-1.0",
+    "curl",
+    "ECL-2.0",
+    "EFL-2.0",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.1",
+    "EUPL-1.2",
+    "GFDL-1.3-only",
+    "GFDL-1.3-or-later",
+    "GPL-1.0-only",
+    "GPL-1.0-or-later",
+    "GPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "HPND",
+    "ICU",
+    "IJG",
+    "ISC",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "Libpng",
+    "libpng-2.0",
+    "LPPL-1.3c",
+    "MIT",
+    "MIT-0",
+    "MIT-CMU",
+    "MIT-Modern-Variant",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MPL-2.0-no-copyleft-exception",
+    "MS-PL",
+    "MS-RL",
+    "MulanPSL-2.0",
+    "NCSA",
+    "NTP",
+    "ODbL-1.0",
+    "OFL-1.1",
+    "OpenSSL",
+    "OSL-3.0",
+    "PostgreSQL",
+    "PSF-2.0",
+    "Python-2.0",
+    "Python-2.0.1",
+    "Ruby",
+    "Sleepycat",
+    "SSPL-1.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "Vim",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "zlib-acknowledgement",
+    "ZPL-2.0",
+    "ZPL-2.1",
+];
+
+/// SPDX license exception identifiers, see <https://spdx.org/licenses/exceptions-index.html>
+const SPDX_EXCEPTION_IDS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Bootloader-exception",
+    "Classpath-exception-2.0",
+    "Font-exception-2.0",
+    "GCC-exception-2.0",
+    "GCC-exception-3.1",
+    "GPL-3.0-linking-exception",
+    "GPL-3.0-linking-source-exception",
+    "LGPL-3.0-linking-exception",
+    "Libtool-exception",
+    "Linux-syscall-note",
+    "LLVM-exception",
+    "OCaml-LGPL-linking-exception",
+    "openvpn-openssl-exception",
+    "Qt-GPL-exception-1.0",
+    "Qt-LGPL-exception-1.1",
+    "Swift-exception",
+    "Universal-FOSS-exception-1.0",
+    "WxWindows-exception-3.1",
+];
+
+/// Validates and normalizes an SPDX license expression, e.g. `mit or apache-2.0` becomes
+/// `MIT OR Apache-2.0`
+///
+/// Unknown identifiers only cause a warning since the SPDX license list keeps growing.
+pub(crate) fn parse_license_expression(expression: &str) -> Result<String> {
+    let mut tokens = Vec::new();
+    for word in expression.split_whitespace() {
+        let mut rest = word;
+        while let Some(inner) = rest.strip_prefix('(') {
+            tokens.push("(");
+            rest = inner;
+        }
+        let mut closing = 0;
+        while let Some(inner) = rest.strip_suffix(')') {
+            closing += 1;
+            rest = inner;
+        }
+        if !rest.is_empty() {
+            tokens.push(rest);
+        }
+        tokens.extend(std::iter::repeat(")").take(closing));
+    }
+    let mut parser = ExpressionParser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    let normalized = parser.or_expression()?;
+    if let Some(token) = tokens.get(parser.pos) {
+        bail!("Unexpected `{token}` in license expression `{expression}`");
+    }
+    Ok(normalized)
+}
+
+/// A recursive descent parser for the grammar in annex D of the SPDX specification, where
+/// `AND` binds tighter than `OR`
+struct ExpressionParser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+}
+
+impl ExpressionParser<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn next_is(&self, operator: &str) -> bool {
+        self.tokens
+            .get(self.pos)
+            .is_some_and(|token| token.eq_ignore_ascii_case(operator))
+    }
+
+    fn or_expression(&mut self) -> Result<String> {
+        let mut expression = self.and_expression()?;
+        while self.next_is("OR") {
+            self.pos += 1;
+            expression = format!("{expression} OR {}", self.and_expression()?);
+        }
+        Ok(expression)
+    }
+
+    fn and_expression(&mut self) -> Result<String> {
+        let mut expression = self.simple_expression()?;
+        while self.next_is("AND") {
+            self.pos += 1;
+            expression = format!("{expression} AND {}", self.simple_expression()?);
+        }
+        Ok(expression)
+    }
+
+    fn simple_expression(&mut self) -> Result<String> {
+        match self.next() {
+            None => bail!("Expected a license identifier, found the end of the expression"),
+            Some("(") => {
+                let inner = self.or_expression()?;
+                match self.next() {
+                    Some(")") => Ok(format!("({inner})")),
+                    _ => bail!("Missing closing parenthesis in license expression"),
+                }
+            }
+            Some(token) => {
+                let license = normalize_id(token, SPDX_LICENSE_IDS, true)?;
+                if !self.next_is("WITH") {
+                    return Ok(license);
+                }
+                self.pos += 1;
+                let Some(token) = self.next() else {
+                    bail!("Expected a license exception after `WITH`");
+                };
+                let exception = normalize_id(token, SPDX_EXCEPTION_IDS, false)?;
+                Ok(format!("{license} WITH {exception}"))
+            }
+        }
+    }
+}
+
+/// Checks the syntax of a license or exception identifier and fixes its case if it is known
+fn normalize_id(token: &str, known_ids: &[&str], is_license: bool) -> Result<String> {
+    let is_idstring = |id: &str| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    };
+    if ["AND", "OR", "WITH", "(", ")"]
+        .iter()
+        .any(|operator| token.eq_ignore_ascii_case(operator))
+    {
+        bail!("Expected a license identifier, found `{token}`");
+    }
+    if is_license {
+        // Custom licenses, optionally from another SPDX document
+        let reference = token
+            .strip_prefix("DocumentRef-")
+            .and_then(|rest| rest.split_once(':'))
+            .filter(|(document, _)| is_idstring(document))
+            .map_or(token, |(_, reference)| reference);
+        if let Some(name) = reference.strip_prefix("LicenseRef-") {
+            if !is_idstring(name) {
+                bail!("Invalid license reference `{token}`");
+            }
+            return Ok(token.to_string());
+        }
+    }
+    let (id, plus) = match token.strip_suffix('+') {
+        Some(id) if is_license => (id, "+"),
+        _ => (token, ""),
+    };
+    if !is_idstring(id) {
+        bail!("Invalid SPDX identifier `{token}`, only letters, digits, `-` and `.` are allowed");
+    }
+    match known_ids
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(id))
+    {
+        Some(known) => Ok(format!("{known}{plus}")),
+        None => {
+            eprintln!(
+                "⚠️  Warning: `{id}` is not a known SPDX identifier, see https://spdx.org/licenses/"
+            );
+            Ok(token.to_string())
+        }
+    }
+}
+
+/// Collects the licenses of the third-party crates linked into the native libraries built
+/// from the `root_crates` for the target
+///
+/// Each crate gets a `third_party/<crate>-<version>/` directory with its license files, or the
+/// SPDX texts of its declared licenses if it doesn't ship any
+pub(crate) fn collect_third_party_licenses(
+    root_crates: &[(&Metadata, &str)],
+    target_cfg: &TargetCfg,
+) -> Result<Vec<ThirdPartyLicense>> {
+    let mut licenses = Vec::new();
+    let mut seen = HashSet::new();
+    for (metadata, crate_name) in root_crates {
+        for linked_crate in linked_crates(metadata, crate_name, target_cfg)? {
+            let package = linked_crate.package;
+            if metadata.workspace_members.contains(&package.id)
+                || !seen.insert((package.name.clone(), package.version.clone()))
+            {
+                continue;
+            }
+            let dir = format!("third_party/{}-{}", package.name, package.version);
+            let files = crate_license_files(package)?;
+            if !files.is_empty() {
+                for file in files {
+                    let file_name = file.file_name().unwrap().to_string_lossy();
+                    licenses.push(ThirdPartyLicense {
+                        path: format!("{dir}/{file_name}"),
+                        source: LicenseSource::File(file),
+                    });
+                }
+                continue;
+            }
+            let Some(expression) = &package.license else {
+                eprintln!(
+                    "⚠️  Warning: {} {} neither ships a license file nor declares a license",
+                    package.name, package.version
+                );
+                continue;
+            };
+            for id in license_ids(expression) {
+                match SPDX_LICENSE_TEXTS.iter().find(|(spdx_id, _)| *spdx_id == id) {
+                    Some((_, text)) => licenses.push(ThirdPartyLicense {
+                        path: format!("{dir}/{id}.txt"),
+                        source: LicenseSource::Text(text),
+                    }),
+                    None => eprintln!(
+                        "⚠️  Warning: {} {} doesn't ship a license file and maturin doesn't know the text of {}",
+                        package.name, package.version, id
+                    ),
+                }
+            }
+        }
+    }
+    Ok(licenses)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Target;
+    use cargo_metadata::MetadataCommand;
+    use std::path::Path;
+
+    #[test]
+    fn test_license_ids() {
+        assert_eq!(license_ids("MIT OR Apache-2.0"), ["MIT", "Apache-2.0"]);
+        assert_eq!(license_ids("MIT/Apache-2.0"), ["MIT", "Apache-2.0"]);
+        assert_eq!(
+            license_ids("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            ["MIT", "Apache-2.0", "Unicode-DFS-2016"]
+        );
+        assert_eq!(
+            license_ids("Apache-2.0 WITH LLVM-exception OR GPL-2.0+"),
+            ["Apache-2.0", "GPL-2.0"]
+        );
+    }
+
+    #[test]
+    fn test_parse_license_expression() {
+        assert_eq!(parse_license_expression("MIT").unwrap(), "MIT");
+        assert_eq!(
+            parse_license_expression("mit or apache-2.0").unwrap(),
+            "MIT OR Apache-2.0"
+        );
+        assert_eq!(
+            parse_license_expression("(MIT OR Apache-2.0)  AND\tUnicode-3.0").unwrap(),
+            "(MIT OR Apache-2.0) AND Unicode-3.0"
+        );
+        assert_eq!(
+            parse_license_expression("Apache-2.0 with llvm-exception OR GPL-2.0+").unwrap(),
+            "Apache-2.0 WITH LLVM-exception OR GPL-2.0+"
+        );
+        assert_eq!(
+            parse_license_expression("LicenseRef-Proprietary").unwrap(),
+            "LicenseRef-Proprietary"
+        );
+        assert_eq!(
+            parse_license_expression("DocumentRef-spdx-tool-1.2:LicenseRef-MIT-Style-2").unwrap(),
+            "DocumentRef-spdx-tool-1.2:LicenseRef-MIT-Style-2"
+        );
+        for invalid in [
+            "",
+            "MIT OR",
+            "MIT AND OR Apache-2.0",
+            "(MIT",
+            "MIT)",
+            "MIT Apache-2.0",
+            "MIT/Apache-2.0",
+            "The MIT License",
+            "LicenseRef-",
+            "Apache-2.0 WITH",
+        ] {
+            assert!(
+                parse_license_expression(invalid).is_err(),
+                "{invalid} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect_third_party_licenses() {
+        let metadata = MetadataCommand::new().exec().unwrap();
+        let target = Target::from_target_triple(None).unwrap();
+        let target_cfg = TargetCfg::new(Path::new("."), target.target_triple()).unwrap();
+        let licenses =
+            collect_third_party_licenses(&[(&metadata, "maturin")], &target_cfg).unwrap();
+        let anyhow = metadata
+            .packages
+            .iter()
+            .find(|package| package.name == "anyhow")
+            .unwrap();
+        let prefix = format!("third_party/anyhow-{}/", anyhow.version);
+        assert!(licenses
+            .iter()
+            .any(|license| license.path == format!("{prefix}LICENSE-MIT")));
+        // maturin's own license isn't third-party
+        assert!(!licenses
+            .iter()
+            .any(|license| license.path.starts_with("third_party/maturin-")));
+    }
+}
@@ -10,18 +10,23 @@ use cargo_zigbuild::Zig;
 #[cfg(feature = "cli-completion")]
 use clap::CommandFactory;
 use clap::{Parser, Subcommand};
-#[cfg(feature = "scaffolding")]
-use maturin::{ci::GenerateCI, init_project, new_project, GenerateProjectOptions};
+#[cfg(feature = "build-cache")]
+use maturin::BuildCache;
 use maturin::{
-    develop, write_dist_info, BridgeModel, BuildOptions, CargoOptions, DevelopOptions, PathWriter,
-    PlatformTag, PythonInterpreter, Target,
+    audit_native_files, develop, verify_reproducible, write_dist_info, AuditPolicies, BridgeModel,
+    BuildOptions, CargoOptions, DevelopOptions, Inspection, PathWriter, PlatformTag,
+    PythonInterpreter, RepairOptions, Target, WheelDiff,
 };
+#[cfg(feature = "scaffolding")]
+use maturin::{ci::GenerateCI, init_project, new_project, GenerateProjectOptions};
 #[cfg(feature = "schemars")]
 use maturin::{generate_json_schema, GenerateJsonSchemaOptions};
 #[cfg(feature = "upload")]
 use maturin::{upload_ui, PublishOpt};
 use std::env;
 use std::path::PathBuf;
+#[cfg(feature = "build-cache")]
+use std::time::Duration;
 use tracing::debug;
 
 #[derive(Debug, Parser)]
@@ -127,6 +132,77 @@ enum Opt {
         #[arg(value_name = "FILE")]
         files: Vec<PathBuf>,
     },
+    /// Show the contents of a wheel or source distribution
+    ///
+    /// Prints the WHEEL tags, the metadata, all files with their RECORD verification status,
+    /// and the native libraries with their ABI tags, dependencies and manylinux/musllinux policy
+    #[command(name = "inspect")]
+    Inspect {
+        /// The wheel or source distribution to inspect
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the native libraries in wheels for manylinux/musllinux compliance
+    ///
+    /// Prints the highest priority policy each ELF file complies with
+    #[command(name = "audit")]
+    Audit {
+        /// The wheels or shared libraries to audit
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+        /// Show for every higher priority policy which symbol versions, blacklisted symbols and
+        /// libraries disqualified it, and which source files or crates use the symbols as far as
+        /// the debug info tells
+        #[arg(long)]
+        explain: bool,
+        /// JSON file with additional or overriding manylinux/musllinux policies, which may also
+        /// define custom platform tags
+        #[arg(long, value_name = "FILE")]
+        policy_file: Option<PathBuf>,
+    },
+    /// Repair an existing wheel for manylinux/musllinux compliance
+    ///
+    /// Like `auditwheel repair`, copies the external shared libraries of the native files into
+    /// the wheel, points the files to them and retags the wheel with the new platform tag
+    #[command(name = "repair")]
+    Repair(RepairOptions),
+    /// Build twice and check that the wheels are byte-for-byte identical
+    ///
+    /// The second build compiles from scratch in a temporary target directory.
+    /// Implies `--reproducible`.
+    #[command(name = "verify-reproducible")]
+    VerifyReproducible {
+        /// Build artifacts in release mode, with optimizations
+        #[arg(short = 'r', long, help_heading = heading::COMPILATION_OPTIONS)]
+        release: bool,
+        /// Strip the library for minimum file size
+        #[arg(long)]
+        strip: bool,
+        /// Also build and check a source distribution
+        #[arg(long)]
+        sdist: bool,
+        #[command(flatten)]
+        build: BuildOptions,
+    },
+    /// Show the differences between two wheels or source distributions
+    Diff {
+        /// The old wheel or source distribution
+        #[arg(value_name = "OLD")]
+        old: PathBuf,
+        /// The new wheel or source distribution
+        #[arg(value_name = "NEW")]
+        new: PathBuf,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the build cache used by `--build-cache`
+    #[cfg(feature = "build-cache")]
+    #[command(subcommand)]
+    Cache(CacheCommand),
     /// Backend for the PEP 517 integration. Not for human consumption
     ///
     /// The commands are meant to be called from the python PEP 517
@@ -143,12 +219,46 @@ enum Opt {
     #[cfg(feature = "zig")]
     #[command(subcommand, hide = true)]
     Zig(Zig),
-    /// Generate the JSON schema for the `pyproject.toml` file.
+    /// Generate the JSON schemas for the `pyproject.toml` file and the build messages.
     #[cfg(feature = "schemars")]
     #[command(name = "generate-json-schema", hide = true)]
     GenerateJsonSchema(GenerateJsonSchemaOptions),
 }
 
+/// Manage the build cache used by `--build-cache`
+#[cfg(feature = "build-cache")]
+#[derive(Debug, Subcommand)]
+#[command(name = "cache")]
+enum CacheCommand {
+    /// Show the location, number of entries and size of the cache
+    #[command(name = "info")]
+    Info {
+        /// The cache directory, defaults to `MATURIN_CACHE_DIR` or maturin's directory in the
+        /// user's cache directory
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+    },
+    /// Remove cache entries that haven't been used recently
+    #[command(name = "prune")]
+    Prune {
+        /// The cache directory, defaults to `MATURIN_CACHE_DIR` or maturin's directory in the
+        /// user's cache directory
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+        /// Remove entries that haven't been used for this many days
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        max_age: u64,
+    },
+    /// Remove the whole cache
+    #[command(name = "clean")]
+    Clean {
+        /// The cache directory, defaults to `MATURIN_CACHE_DIR` or maturin's directory in the
+        /// user's cache directory
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+    },
+}
+
 /// Backend for the PEP 517 integration. Not for human consumption
 ///
 /// The commands are meant to be called from the python PEP 517
@@ -404,6 +514,53 @@ fn run() -> Result<()> {
                 .build_source_distribution()?
                 .context("Failed to build source distribution, pyproject.toml not found")?;
         }
+        Opt::Inspect { file, json } => {
+            let inspection = Inspection::new(&file)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&inspection)?);
+            } else {
+                inspection.print();
+            }
+        }
+        Opt::Audit {
+            files,
+            explain,
+            policy_file,
+        } => {
+            let policies = match policy_file {
+                Some(policy_file) => AuditPolicies::from_file(&policy_file)?,
+                None => AuditPolicies::default(),
+            };
+            audit_native_files(&files, explain, &policies)?
+        }
+        Opt::Repair(options) => {
+            maturin::repair(&options)?;
+        }
+        Opt::VerifyReproducible {
+            build,
+            release,
+            strip,
+            sdist,
+        } => {
+            let build_context = build.into_build_context(release, strip, false)?;
+            verify_reproducible(&build_context, sdist)?;
+        }
+        Opt::Diff { old, new, json } => {
+            let diff = WheelDiff::new(&old, &new)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                diff.print();
+            }
+        }
+        #[cfg(feature = "build-cache")]
+        Opt::Cache(subcommand) => match subcommand {
+            CacheCommand::Info { cache_dir } => BuildCache::new(cache_dir)?.info()?,
+            CacheCommand::Prune { cache_dir, max_age } => {
+                BuildCache::new(cache_dir)?.prune(Duration::from_secs(max_age * 24 * 60 * 60))?
+            }
+            CacheCommand::Clean { cache_dir } => BuildCache::new(cache_dir)?.clean()?,
+        },
         Opt::Pep517(subcommand) => pep517(subcommand)?,
         #[cfg(feature = "scaffolding")]
         Opt::InitProject { path, options } => init_project(path, options)?,
@@ -0,0 +1,175 @@
+//! Machine-readable build output, see `--message-format json`
+use crate::PythonInterpreter;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How to report the progress of a build
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    /// Human-readable messages on stderr
+    #[default]
+    Human,
+    /// Additionally print one JSON message per line to stdout for every build step
+    Json,
+}
+
+/// A library that was copied into the wheel because the native library depends on it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct GraftedLibrary {
+    /// The soname the native library depended on, e.g. `libz.so.1`
+    pub name: String,
+    /// The location the library was copied from
+    pub path: PathBuf,
+    /// The new unique soname of the copy inside the wheel, e.g. `libz-a1b2c3d4.so.1`
+    pub grafted_name: String,
+}
+
+/// A machine-readable message about a build step, printed with `--message-format json`
+///
+/// Every message is a single line of JSON on stdout, the kind of message is in the `reason`
+/// field. Fields may be added in the future, but existing ones are not renamed or removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Message {
+    /// A python interpreter to build wheels for was found
+    InterpreterFound {
+        /// `CPython`, `PyPy` or `GraalPy`
+        kind: String,
+        /// Comes from `sys.implementation.name`, e.g. `cpython`
+        implementation_name: String,
+        /// The python version as `major.minor`, e.g. `3.12`
+        version: String,
+        /// The abiflags, e.g. `t` for free-threaded python
+        abiflags: String,
+        /// The suffix of extension modules, e.g. `.cpython-312-x86_64-linux-gnu.so`
+        ext_suffix: String,
+        /// The path to the interpreter, `null` for the bundled sysconfigs used when cross compiling
+        executable: Option<PathBuf>,
+    },
+    /// Cargo built a native library or executable
+    CompilerArtifact {
+        /// The name of the crate
+        crate_name: String,
+        /// The crate type, e.g. `cdylib` or `bin`
+        crate_type: String,
+        /// The path to the built file
+        path: PathBuf,
+    },
+    /// A native library was checked against the manylinux/musllinux policies
+    AuditResult {
+        /// The path to the native library
+        artifact: PathBuf,
+        /// The name of the most compatible policy the library complies with, e.g. `manylinux_2_17`
+        policy: String,
+        /// Other names of that policy, e.g. `manylinux2014`
+        policy_aliases: Vec<String>,
+        /// The sonames of the libraries outside the policy that need to be copied into the wheel
+        external_libs: Vec<String>,
+    },
+    /// Libraries outside the policy were copied into the wheel
+    LibrariesGrafted {
+        /// The directory inside the wheel the libraries were copied to, e.g. `foo.libs`
+        libs_dir: PathBuf,
+        /// The copied libraries
+        libraries: Vec<GraftedLibrary>,
+    },
+    /// A wheel was written
+    WheelWritten {
+        /// The path to the wheel
+        path: PathBuf,
+        /// The expanded compatibility tags of the wheel, e.g. `cp312-cp312-manylinux_2_17_x86_64`
+        tags: Vec<String>,
+        /// The hex encoded sha256 of the wheel
+        sha256: String,
+    },
+    /// A source distribution was written
+    SdistWritten {
+        /// The path to the source distribution
+        path: PathBuf,
+        /// The hex encoded sha256 of the source distribution
+        sha256: String,
+    },
+}
+
+impl Message {
+    /// The message about a found interpreter
+    pub fn interpreter_found(interpreter: &PythonInterpreter) -> Self {
+        Message::InterpreterFound {
+            kind: interpreter.interpreter_kind.to_string(),
+            implementation_name: interpreter.implementation_name.clone(),
+            version: format!("{}.{}", interpreter.major, interpreter.minor),
+            abiflags: interpreter.abiflags.clone(),
+            ext_suffix: interpreter.ext_suffix.clone(),
+            executable: interpreter.runnable.then(|| interpreter.executable.clone()),
+        }
+    }
+
+    /// Prints the message as a single line of JSON to stdout
+    pub fn print(&self) {
+        println!("{}", serde_json::to_string(self).unwrap());
+    }
+}
+
+/// Expands the compressed tag set in the file name of a wheel, e.g.
+/// `foo-1.0-cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64.whl` has the tags
+/// `cp312-cp312-manylinux_2_17_x86_64` and `cp312-cp312-manylinux2014_x86_64`
+pub fn wheel_tags(wheel_path: &Path) -> Vec<String> {
+    let Some(stem) = wheel_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".whl"))
+    else {
+        return Vec::new();
+    };
+    let parts: Vec<_> = stem.split('-').collect();
+    let [.., python, abi, platform] = parts[..] else {
+        return Vec::new();
+    };
+    let mut tags = Vec::new();
+    for python in python.split('.') {
+        for abi in abi.split('.') {
+            for platform in platform.split('.') {
+                tags.push(format!("{python}-{abi}-{platform}"));
+            }
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wheel_tags() {
+        assert_eq!(
+            wheel_tags(Path::new(
+                "dist/foo-1.0-cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+            )),
+            [
+                "cp312-cp312-manylinux_2_17_x86_64",
+                "cp312-cp312-manylinux2014_x86_64"
+            ]
+        );
+        assert_eq!(
+            wheel_tags(Path::new("foo-1.0-1-py2.py3-none-any.whl")),
+            ["py2-none-any", "py3-none-any"]
+        );
+        assert!(wheel_tags(Path::new("foo-1.0.tar.gz")).is_empty());
+    }
+
+    #[test]
+    fn test_message_format() {
+        let message = Message::SdistWritten {
+            path: PathBuf::from("dist/foo-1.0.tar.gz"),
+            sha256: "abc".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"reason":"sdist-written","path":"dist/foo-1.0.tar.gz","sha256":"abc"}"#
+        );
+    }
+}
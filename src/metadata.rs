@@ -1,15 +1,16 @@
-use crate::PyProjectToml;
+use crate::licenses::parse_license_expression;
+use crate::{PyProjectToml, ThirdPartyLicense};
 use anyhow::{bail, format_err, Context, Result};
 use fs_err as fs;
 use indexmap::IndexMap;
-use pep440_rs::{Version, VersionSpecifiers};
+use pep440_rs::{PreReleaseKind, Version, VersionSpecifiers};
 use pep508_rs::{MarkerExpression, MarkerOperator, MarkerTree, MarkerValue, Requirement};
-use pyproject_toml::License;
+use pyproject_toml::{License, LicenseFiles};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Write as _;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::str;
 use std::str::FromStr;
 
@@ -26,7 +27,7 @@ pub struct WheelMetadata {
     pub module_name: String,
 }
 
-/// Python Package Metadata 2.3 as specified in
+/// Python Package Metadata 2.4 as specified in
 /// https://packaging.python.org/specifications/core-metadata/
 /// Maturin writes static metadata and does not support dynamic fields atm.
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -51,8 +52,16 @@ pub struct Metadata23 {
     pub maintainer: Option<String>,
     pub maintainer_email: Option<String>,
     pub license: Option<String>,
-    // https://peps.python.org/pep-0639/#license-file-multiple-use
+    /// An SPDX license expression, see PEP 639
+    pub license_expression: Option<String>,
+    // https://peps.python.org/pep-0639/#add-license-file-field
     pub license_files: Vec<PathBuf>,
+    /// The project root, `License-File` entries are relative to it
+    #[serde(skip)]
+    pub license_files_root: Option<PathBuf>,
+    /// Licenses of the statically linked third-party crates, only written to wheels
+    #[serde(skip)]
+    pub third_party_licenses: Vec<ThirdPartyLicense>,
     pub classifiers: Vec<String>,
     pub requires_dist: Vec<Requirement>,
     pub provides_dist: Vec<String>,
@@ -70,7 +79,7 @@ impl Metadata23 {
     /// Initializes with name, version and otherwise the defaults
     pub fn new(name: String, version: Version) -> Self {
         Self {
-            metadata_version: "2.3".to_string(),
+            metadata_version: "2.4".to_string(),
             name,
             version,
             platform: vec![],
@@ -86,7 +95,10 @@ impl Metadata23 {
             maintainer: None,
             maintainer_email: None,
             license: None,
+            license_expression: None,
             license_files: vec![],
+            license_files_root: None,
+            third_party_licenses: vec![],
             classifiers: vec![],
             requires_dist: vec![],
             provides_dist: vec![],
@@ -102,6 +114,141 @@ impl Metadata23 {
     }
 }
 
+/// Converts the SemVer version from Cargo.toml into a PEP 440 version
+///
+/// Pre-releases such as `1.2.0-alpha.3` or `1.0.0-rc.1` become `1.2.0a3` and `1.0.0rc1`, a
+/// trailing `dev.N` becomes a `.devN` suffix and build metadata (`1.0.0+build.5`) becomes a local
+/// version label. Forms we can only guess about, e.g. a pre-release without a number, are
+/// converted with a warning, while unknown pre-release kinds are an error.
+fn semver_to_pep440(version: &semver::Version) -> Result<Version> {
+    let mut pep440 = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    let mut local: Vec<String> = Vec::new();
+
+    if !version.pre.is_empty() {
+        let mut identifiers = version.pre.as_str().split('.').peekable();
+        let mut seen_pre = false;
+        let mut seen_dev = false;
+        while let Some(identifier) = identifiers.next() {
+            let lower = identifier.to_ascii_lowercase();
+            let kind_len = lower
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(lower.len());
+            let (kind, number) = lower.split_at(kind_len);
+            let pep440_kind = match kind {
+                "alpha" | "a" if !seen_pre && !seen_dev => "a",
+                "beta" | "b" if !seen_pre && !seen_dev => "b",
+                "rc" | "c" | "pre" | "preview" if !seen_pre && !seen_dev => "rc",
+                "dev" if !seen_dev => ".dev",
+                _ if seen_pre || seen_dev => {
+                    // Anything after the pre-release we can't map is kept as local version
+                    local.push(lower.clone());
+                    local.extend(identifiers.by_ref().map(|x| x.to_ascii_lowercase()));
+                    break;
+                }
+                _ => bail!(
+                    "The version `{version}` in Cargo.toml uses the pre-release `{}`, which can't \
+                     be converted to a python version. Note that rust uses \
+                     [SemVer](https://semver.org/) while python uses \
+                     [PEP 440](https://peps.python.org/pep-0440/), please use one of `alpha`, \
+                     `beta`, `rc` or `dev` as pre-release, e.g. `1.0.0-alpha.1`",
+                    version.pre
+                ),
+            };
+            let number = if !number.is_empty() {
+                number.parse::<u64>().with_context(|| {
+                    format!(
+                        "The version `{version}` in Cargo.toml uses the pre-release identifier \
+                         `{identifier}`, which can't be converted to a python version"
+                    )
+                })?
+            } else if let Some(next) = identifiers.next_if(|next| next.parse::<u64>().is_ok()) {
+                next.parse::<u64>()?
+            } else {
+                eprintln!(
+                    "⚠️  Warning: The pre-release `{identifier}` of version `{version}` in \
+                     Cargo.toml has no number, it will be treated as `{identifier}.0`"
+                );
+                0
+            };
+            write!(pep440, "{pep440_kind}{number}")?;
+            if pep440_kind == ".dev" {
+                seen_dev = true;
+            } else {
+                seen_pre = true;
+            }
+        }
+        if !local.is_empty() {
+            eprintln!(
+                "⚠️  Warning: The pre-release `{}` of version `{version}` in Cargo.toml is ambiguous, \
+                 the unrecognized parts are kept as local version `+{}`",
+                version.pre,
+                local.join(".")
+            );
+        }
+    }
+
+    if !version.build.is_empty() {
+        local.extend(
+            version
+                .build
+                .as_str()
+                .split(['.', '-'])
+                .map(|x| x.to_ascii_lowercase()),
+        );
+        eprintln!(
+            "⚠️  Warning: The build metadata of version `{version}` in Cargo.toml is converted to \
+             the local version `+{}`, note that PyPI rejects uploads with local versions",
+            local.join(".")
+        );
+    }
+    if !local.is_empty() {
+        pep440.push('+');
+        pep440.push_str(&local.join("."));
+    }
+
+    Version::from_str(&pep440).map_err(|err| {
+        format_err!(
+            "Rust version `{version}` used in Cargo.toml can't be converted to a valid python \
+             version: {err}. Note that rust uses [SemVer](https://semver.org/) while python uses \
+             [PEP 440](https://peps.python.org/pep-0440/), which have e.g. some differences \
+             when declaring prereleases."
+        )
+    })
+}
+
+/// Converts a PEP 440 version into the equivalent SemVer version for Cargo.toml, the inverse of
+/// [semver_to_pep440]
+pub(crate) fn pep440_to_semver(version: &Version) -> Result<semver::Version> {
+    if version.epoch() != 0 || version.post().is_some() || version.release().len() > 3 {
+        bail!(
+            "The version `{version}` can't be represented as SemVer version in Cargo.toml, \
+             only `major.minor.patch` with optional pre-release, dev release and local version \
+             are supported"
+        );
+    }
+    let release = |i: usize| version.release().get(i).copied().unwrap_or_default();
+    let mut pre = Vec::new();
+    if let Some(pre_release) = version.pre() {
+        let kind = match pre_release.kind {
+            PreReleaseKind::Alpha => "alpha",
+            PreReleaseKind::Beta => "beta",
+            PreReleaseKind::Rc => "rc",
+        };
+        pre.push(format!("{kind}.{}", pre_release.number));
+    }
+    if let Some(dev) = version.dev() {
+        pre.push(format!("dev.{dev}"));
+    }
+    let local: Vec<String> = version.local().iter().map(ToString::to_string).collect();
+    Ok(semver::Version {
+        major: release(0),
+        minor: release(1),
+        patch: release(2),
+        pre: semver::Prerelease::new(&pre.join("."))?,
+        build: semver::BuildMetadata::new(&local.join("."))?,
+    })
+}
+
 const PLAINTEXT_CONTENT_TYPE: &str = "text/plain; charset=UTF-8";
 const GFM_CONTENT_TYPE: &str = "text/markdown; charset=UTF-8; variant=GFM";
 
@@ -182,10 +329,20 @@ impl Metadata23 {
                 self.requires_python = Some(requires_python.clone());
             }
 
+            self.license_files_root = Some(pyproject_dir.to_path_buf());
             if let Some(license) = &project.license {
                 match license {
-                    // TODO: switch to License-Expression core metadata, see https://peps.python.org/pep-0639/#add-license-expression-field
-                    License::String(license_expr) => self.license = Some(license_expr.clone()),
+                    License::String(license_expr) => {
+                        let license_expr =
+                            parse_license_expression(license_expr).with_context(|| {
+                                format!(
+                                    "`project.license` in pyproject.toml is not a valid SPDX \
+                                     license expression: `{license_expr}`"
+                                )
+                            })?;
+                        self.license_expression = Some(license_expr);
+                        self.license = None;
+                    }
                     License::Table { file, text } => match (file, text) {
                         (Some(_), Some(_)) => {
                             bail!("file and text fields of 'project.license' are mutually-exclusive, only one of them should be specified");
@@ -194,27 +351,52 @@ impl Metadata23 {
                             let license_path = pyproject_dir.join(license_path);
                             self.license_files.push(license_path);
                         }
-                        (None, Some(license_text)) => self.license = Some(license_text.clone()),
+                        (None, Some(license_text)) => {
+                            self.license = Some(license_text.clone());
+                            self.license_expression = None;
+                        }
                         (None, None) => {}
                     },
                 }
             }
 
-            // Until PEP 639 is approved with metadata 2.3, we can assume a
-            // dynamic license-files (also awaiting full 2.2 metadata support)
-            // We're already emitting the License-Files metadata without issue.
-            // license-files.globs = ["LICEN[CS]E*", "COPYING*", "NOTICE*", "AUTHORS*"]
-            let license_include_targets = ["LICEN[CS]E*", "COPYING*", "NOTICE*", "AUTHORS*"];
+            // PEP 639 license files, relative to the project root
+            let (license_patterns, explicit) = match &project.license_files {
+                Some(LicenseFiles::Paths(paths)) => (
+                    paths
+                        .iter()
+                        .flatten()
+                        .map(|path| glob::Pattern::escape(path))
+                        .collect(),
+                    true,
+                ),
+                Some(LicenseFiles::Globs(globs)) => (globs.clone().unwrap_or_default(), true),
+                None => (
+                    ["LICEN[CS]E*", "COPYING*", "NOTICE*", "AUTHORS*"]
+                        .map(String::from)
+                        .to_vec(),
+                    false,
+                ),
+            };
             let escaped_manifest_string = glob::Pattern::escape(pyproject_dir.to_str().unwrap());
             let escaped_manifest_path = Path::new(&escaped_manifest_string);
-            for pattern in license_include_targets.iter() {
+            for pattern in &license_patterns {
+                if Path::new(pattern).is_absolute() || pattern.split('/').any(|part| part == "..") {
+                    bail!(
+                        "`project.license-files` must be relative to pyproject.toml and inside \
+                         the project, but `{pattern}` isn't"
+                    );
+                }
+                let mut matched = false;
                 for license_path in
-                    glob::glob(&escaped_manifest_path.join(pattern).to_string_lossy())?
+                    glob::glob(&escaped_manifest_path.join(pattern).to_string_lossy())
+                        .with_context(|| format!("Invalid license-files glob `{pattern}`"))?
                         .filter_map(Result::ok)
                 {
                     if !license_path.is_file() {
                         continue;
                     }
+                    matched = true;
                     // if the pyproject.toml specified the license file,
                     // then we won't list it as automatically included
                     if !self.license_files.contains(&license_path) {
@@ -222,6 +404,9 @@ impl Metadata23 {
                         self.license_files.push(license_path);
                     }
                 }
+                if explicit && !matched {
+                    bail!("`project.license-files` entry `{pattern}` doesn't match any file");
+                }
             }
 
             if let Some(authors) = &project.authors {
@@ -391,16 +576,22 @@ impl Metadata23 {
         } else {
             Vec::new()
         };
+        let (license, license_expression) = match &package.license {
+            // Cargo still accepts the deprecated `MIT/Apache-2.0` syntax
+            Some(license) => match parse_license_expression(&license.replace('/', " OR ")) {
+                Ok(license_expression) => (None, Some(license_expression)),
+                Err(err) => {
+                    eprintln!(
+                        "⚠️  Warning: `license` in Cargo.toml is not a valid SPDX license \
+                         expression, it will be written to the free-form License field: {err}"
+                    );
+                    (Some(license.clone()), None)
+                }
+            },
+            None => (None, None),
+        };
 
-        let version = Version::from_str(&package.version.to_string()).map_err(|err| {
-            format_err!(
-                "Rust version used in Cargo.toml is not a valid python version: {}. \
-                    Note that rust uses [SemVer](https://semver.org/) while python uses \
-                    [PEP 440](https://peps.python.org/pep-0440/), which have e.g. some differences \
-                    when declaring prereleases.",
-                err
-            )
-        })?;
+        let version = semver_to_pep440(&package.version)?;
         let metadata = Metadata23 {
             // name, version and metadata_version are added through metadata23::new()
             // Mapped from cargo metadata
@@ -421,8 +612,10 @@ impl Metadata23 {
                 Some(authors)
             },
             author_email,
-            license: package.license.clone(),
+            license,
+            license_expression,
             license_files,
+            license_files_root: Some(manifest_path.as_ref().to_path_buf()),
             project_url,
             ..Metadata23::new(name, version)
         };
@@ -464,7 +657,12 @@ impl Metadata23 {
         let license_files: Vec<String> = self
             .license_files
             .iter()
-            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .map(|path| self.license_file_entry(path))
+            .chain(
+                self.third_party_licenses
+                    .iter()
+                    .map(|license| license.path.clone()),
+            )
             .collect();
         add_vec("License-File", &license_files);
 
@@ -483,6 +681,7 @@ impl Metadata23 {
         add_option("Maintainer", &self.maintainer);
         add_option("Maintainer-email", &self.maintainer_email);
         add_option("License", &self.license.as_deref().map(fold_header));
+        add_option("License-Expression", &self.license_expression);
         add_option(
             "Requires-Python",
             &self
@@ -511,6 +710,34 @@ impl Metadata23 {
             .collect()
     }
 
+    /// The `License-File` entry of a license file, which is also its path in
+    /// `.dist-info/licenses/`
+    ///
+    /// Files in the project keep their path relative to the project root, files outside of it,
+    /// e.g. from a parent workspace, only keep their file name
+    pub fn license_file_entry(&self, path: &Path) -> String {
+        let relative = match &self.license_files_root {
+            Some(root) => path.strip_prefix(root).ok(),
+            // Parsed from a METADATA file
+            None if path.is_relative() => Some(path),
+            None => None,
+        };
+        // `../LICENSE` relative to the project root is outside of it
+        let relative = relative.filter(|relative| {
+            relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        });
+        match relative {
+            Some(relative) => relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            None => path.file_name().unwrap().to_string_lossy().to_string(),
+        }
+    }
+
     /// Writes the format for the metadata file inside wheels
     pub fn to_file_contents(&self) -> Result<String> {
         let mut fields = self.to_vec();
@@ -559,6 +786,69 @@ impl Metadata23 {
     }
 }
 
+impl FromStr for Metadata23 {
+    type Err = anyhow::Error;
+
+    /// Parses a METADATA or PKG-INFO file, e.g. from an existing wheel or source distribution
+    fn from_str(content: &str) -> Result<Self> {
+        let pkginfo: python_pkginfo::Metadata = content
+            .parse()
+            .context("Failed to parse the core metadata")?;
+        let version = Version::from_str(&pkginfo.version)
+            .map_err(|err| format_err!("Invalid version {}: {}", pkginfo.version, err))?;
+        let mut metadata = Metadata23::new(pkginfo.name, version);
+        metadata.metadata_version = pkginfo.metadata_version;
+        metadata.platform = pkginfo.platforms;
+        metadata.supported_platform = pkginfo.supported_platforms;
+        metadata.summary = pkginfo.summary;
+        metadata.description = pkginfo.description;
+        metadata.description_content_type = pkginfo.description_content_type;
+        metadata.keywords = pkginfo.keywords;
+        metadata.home_page = pkginfo.home_page;
+        metadata.download_url = pkginfo.download_url;
+        metadata.author = pkginfo.author;
+        metadata.author_email = pkginfo.author_email;
+        metadata.maintainer = pkginfo.maintainer;
+        metadata.maintainer_email = pkginfo.maintainer_email;
+        metadata.license = pkginfo.license;
+        metadata.license_expression = pkginfo.license_expression;
+        metadata.license_files = pkginfo
+            .license_files
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        metadata.classifiers = pkginfo.classifiers;
+        metadata.requires_dist = pkginfo
+            .requires_dist
+            .iter()
+            .map(|requirement| {
+                Requirement::from_str(requirement)
+                    .with_context(|| format!("Invalid Requires-Dist: {requirement}"))
+            })
+            .collect::<Result<_>>()?;
+        metadata.provides_dist = pkginfo.provides_dist;
+        metadata.obsoletes_dist = pkginfo.obsoletes_dist;
+        metadata.requires_python = pkginfo
+            .requires_python
+            .map(|requires_python| {
+                VersionSpecifiers::from_str(&requires_python)
+                    .map_err(|err| format_err!("Invalid Requires-Python {requires_python}: {err}"))
+            })
+            .transpose()?;
+        metadata.requires_external = pkginfo.requires_external;
+        metadata.project_url = pkginfo
+            .project_urls
+            .iter()
+            .map(|project_url| match project_url.split_once(',') {
+                Some((label, url)) => (label.trim().to_string(), url.trim().to_string()),
+                None => (String::new(), project_url.trim().to_string()),
+            })
+            .collect();
+        metadata.provides_extra = pkginfo.provides_extras;
+        Ok(metadata)
+    }
+}
+
 /// Escape email addresses with display name if necessary
 /// according to RFC 822 Section 3.3. "specials".
 fn escape_email_with_display_name(display_name: &str, email: &str) -> String {
@@ -641,6 +931,14 @@ mod test {
 
         expected.assert_eq(&actual);
 
+        // The written metadata must be parsed back into the same metadata, except for
+        // trailing newlines of the description
+        let parsed = Metadata23::from_str(&actual).unwrap();
+        assert_eq!(
+            parsed.to_file_contents().unwrap().trim_end(),
+            actual.trim_end()
+        );
+
         // get_dist_info_dir test checks against hard-coded values - check that they are as expected in the source first
         assert!(
             cargo_toml.contains("name = \"info-project\"")
@@ -679,7 +977,7 @@ mod test {
         );
 
         let expected = expect![[r#"
-            Metadata-Version: 2.3
+            Metadata-Version: 2.4
             Name: info-project
             Version: 0.1.0
             Summary: A test project
@@ -759,7 +1057,7 @@ mod test {
                     .unwrap(),
             ]
         );
-        assert_eq!(metadata.license.as_ref().unwrap(), "MIT");
+        assert_eq!(metadata.license_expression.as_ref().unwrap(), "MIT");
 
         let license_file = &metadata.license_files[0];
         assert_eq!(license_file.file_name().unwrap(), "LICENSE");
@@ -819,7 +1117,8 @@ mod test {
             .unwrap();
 
         // verify Cargo.toml value came through
-        assert_eq!(metadata.license.as_ref().unwrap(), "MIT");
+        assert_eq!(metadata.license_expression.as_ref().unwrap(), "MIT");
+        assert_eq!(metadata.license, None);
 
         // verify we have the total number of expected licenses
         assert_eq!(4, metadata.license_files.len());
@@ -831,6 +1130,85 @@ mod test {
         assert_eq!(metadata.license_files[1], manifest_dir.join("LICENSE"));
         assert_eq!(metadata.license_files[2], manifest_dir.join("NOTICE.md"));
         assert_eq!(metadata.license_files[3], manifest_dir.join("AUTHORS.txt"));
+
+        let content = metadata.to_file_contents().unwrap();
+        assert!(content.contains("Metadata-Version: 2.4\n"));
+        assert!(content.contains("License-Expression: MIT\n"));
+        assert!(content.contains("License-File: LICENCE.txt\n"));
+    }
+
+    #[test]
+    fn test_merge_metadata_license_files_globs() {
+        let manifest_dir = PathBuf::from("test-crates").join("license-test");
+        let cargo_metadata = MetadataCommand::new()
+            .manifest_path(manifest_dir.join("Cargo.toml"))
+            .exec()
+            .unwrap();
+        let mut metadata = Metadata23::from_cargo_toml(&manifest_dir, &cargo_metadata).unwrap();
+        let mut pyproject_toml = PyProjectToml::new(manifest_dir.join("pyproject.toml")).unwrap();
+        let project = pyproject_toml.project.as_mut().unwrap();
+        project.license = Some(License::String(
+            "mit and (apache-2.0 or bsd-2-clause)".into(),
+        ));
+        project.license_files = Some(LicenseFiles::Globs(Some(vec![
+            "LICENSE".to_string(),
+            "_vendor/**/LICEN[CS]E*".to_string(),
+        ])));
+        metadata
+            .merge_pyproject_toml(&manifest_dir, &pyproject_toml)
+            .unwrap();
+
+        assert_eq!(
+            metadata.license_expression.as_deref(),
+            Some("MIT AND (Apache-2.0 OR BSD-2-Clause)")
+        );
+        let entries: Vec<_> = metadata
+            .license_files
+            .iter()
+            .map(|path| metadata.license_file_entry(path))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                "LICENSE",
+                "_vendor/LICENCE",
+                "_vendor/LICENSE-APACHE.txt",
+                "_vendor/nested/LICENSE-BSD"
+            ]
+        );
+
+        // Globs must match files inside the project
+        let project = pyproject_toml.project.as_mut().unwrap();
+        project.license_files = Some(LicenseFiles::Globs(Some(vec!["MISSING*".to_string()])));
+        assert!(metadata
+            .merge_pyproject_toml(&manifest_dir, &pyproject_toml)
+            .is_err());
+        let project = pyproject_toml.project.as_mut().unwrap();
+        project.license_files = Some(LicenseFiles::Globs(Some(vec!["../LICENSE".to_string()])));
+        assert!(metadata
+            .merge_pyproject_toml(&manifest_dir, &pyproject_toml)
+            .is_err());
+        let project = pyproject_toml.project.as_mut().unwrap();
+        project.license = Some(License::String("MIT OR".into()));
+        project.license_files = None;
+        assert!(metadata
+            .merge_pyproject_toml(&manifest_dir, &pyproject_toml)
+            .is_err());
+    }
+
+    #[test]
+    fn test_license_file_entry() {
+        let mut metadata = Metadata23::new("foo".to_string(), Version::new([0, 1, 0]));
+        metadata.license_files_root = Some(PathBuf::from("workspace/py"));
+        let cases = [
+            ("workspace/py/LICENSE", "LICENSE"),
+            ("workspace/py/licenses/LICENSE-MIT", "licenses/LICENSE-MIT"),
+            ("workspace/py/../LICENSE", "LICENSE"),
+            ("workspace/LICENSE-APACHE", "LICENSE-APACHE"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(metadata.license_file_entry(Path::new(path)), expected);
+        }
     }
 
     #[test]
@@ -871,4 +1249,62 @@ mod test {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn test_semver_to_pep440() {
+        let cases = [
+            ("1.2.0", "1.2.0"),
+            ("1.2.0-alpha.3", "1.2.0a3"),
+            ("1.2.0-a3", "1.2.0a3"),
+            ("1.0.0-beta.2", "1.0.0b2"),
+            ("1.0.0-rc.1", "1.0.0rc1"),
+            ("1.0.0-RC1", "1.0.0rc1"),
+            ("1.0.0-pre.4", "1.0.0rc4"),
+            ("1.0.0-preview.4", "1.0.0rc4"),
+            ("1.0.0-beta", "1.0.0b0"),
+            ("1.0.0-dev.7", "1.0.0.dev7"),
+            ("1.0.0-alpha.1.dev.2", "1.0.0a1.dev2"),
+            ("1.0.0-alpha.1+build.5", "1.0.0a1+build.5"),
+            ("1.0.0+build-5", "1.0.0+build.5"),
+            ("1.0.0-alpha.1.nightly", "1.0.0a1+nightly"),
+        ];
+        for (semver, pep440) in cases {
+            let version = semver_to_pep440(&semver::Version::parse(semver).unwrap()).unwrap();
+            assert_eq!(version.to_string(), pep440, "{semver}");
+        }
+
+        for semver in ["1.0.0-nightly.1", "1.0.0-alpha1x"] {
+            assert!(
+                semver_to_pep440(&semver::Version::parse(semver).unwrap()).is_err(),
+                "{semver}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pep440_to_semver() {
+        for pep440 in [
+            "1.2.0",
+            "1.2.0a3",
+            "1.0.0rc1",
+            "1.2.4.dev5+gabc1234",
+            "1.0.0b2.dev1",
+        ] {
+            let version = Version::from_str(pep440).unwrap();
+            let semver = pep440_to_semver(&version).unwrap();
+            assert_eq!(semver_to_pep440(&semver).unwrap(), version, "{pep440}");
+        }
+        assert_eq!(
+            pep440_to_semver(&Version::from_str("1.2.4.dev5+gabc1234").unwrap())
+                .unwrap()
+                .to_string(),
+            "1.2.4-dev.5+gabc1234"
+        );
+        for pep440 in ["1!1.0.0", "1.0.0.post1", "1.0.0.0"] {
+            assert!(
+                pep440_to_semver(&Version::from_str(pep440).unwrap()).is_err(),
+                "{pep440}"
+            );
+        }
+    }
 }
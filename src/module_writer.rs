@@ -1,8 +1,10 @@
 //! The wheel format is (mostly) specified in PEP 427
+use crate::compression::{compress_parallel, CompressionOptions};
 use crate::project_layout::ProjectLayout;
 use crate::target::Os;
 use crate::{
-    pyproject_toml::Format, BridgeModel, Metadata23, PyProjectToml, PythonInterpreter, Target,
+    pyproject_toml::Format, BridgeModel, LicenseSource, Metadata23, PyProjectToml,
+    PythonInterpreter, Target,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
@@ -16,7 +18,8 @@ use ignore::WalkBuilder;
 use indexmap::IndexMap;
 use normpath::PathExt as _;
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
 use std::fmt::Write as _;
@@ -31,7 +34,7 @@ use std::process::{Command, Output};
 use std::str;
 use tempfile::{tempdir, TempDir};
 use tracing::debug;
-use zip::{self, DateTime, ZipWriter};
+use zip::{self, DateTime, ZipArchive, ZipWriter};
 
 /// Allows writing the module to a wheel or add it directly to the virtualenv
 pub trait ModuleWriter {
@@ -211,6 +214,12 @@ pub struct WheelWriter {
     record_file: PathBuf,
     wheel_path: PathBuf,
     excludes: Override,
+    reproducible: bool,
+    compression: CompressionOptions,
+    /// With `reproducible`, the files are collected here and written in a stable order on `finish`
+    pending: Vec<(String, Vec<u8>, u32)>,
+    /// Python code to insert into an `__init__.py`, given as path in the wheel and code
+    init_py_preamble: Option<(String, String)>,
 }
 
 impl ModuleWriter for WheelWriter {
@@ -231,27 +240,28 @@ impl ModuleWriter for WheelWriter {
         // The zip standard mandates using unix style paths
         let target = target.to_str().unwrap().replace('\\', "/");
 
-        // Unlike users which can use the develop subcommand, the tests have to go through
-        // packing a zip which pip than has to unpack. This makes this 2-3 times faster
-        let compression_method = if cfg!(feature = "faster-tests") {
-            zip::CompressionMethod::Stored
-        } else {
-            zip::CompressionMethod::Deflated
-        };
-
-        let mut options = zip::write::FileOptions::default()
-            .unix_permissions(permissions)
-            .compression_method(compression_method);
-        let mtime = self.mtime().ok();
-        if let Some(mtime) = mtime {
-            options = options.last_modified_time(mtime);
+        let mut bytes = Cow::Borrowed(bytes);
+        if self
+            .init_py_preamble
+            .as_ref()
+            .is_some_and(|(init_py, _)| *init_py == target)
+        {
+            let (_, preamble) = self.init_py_preamble.take().unwrap();
+            let source =
+                str::from_utf8(&bytes).with_context(|| format!("{target} is not valid utf-8"))?;
+            bytes = Cow::Owned(insert_python_preamble(source, &preamble).into_bytes());
         }
-
-        self.zip.start_file(target.clone(), options)?;
-        self.zip.write_all(bytes)?;
+        let bytes = bytes.as_ref();
 
         let hash = URL_SAFE_NO_PAD.encode(Sha256::digest(bytes));
-        self.record.push((target, hash, bytes.len()));
+        self.record.push((target.clone(), hash, bytes.len()));
+
+        if self.reproducible {
+            self.pending
+                .push((target, bytes.to_vec(), normalize_permissions(permissions)));
+        } else {
+            self.write_entry(&target, bytes, permissions)?;
+        }
 
         Ok(())
     }
@@ -267,6 +277,8 @@ impl WheelWriter {
         metadata23: &Metadata23,
         tags: &[String],
         excludes: Override,
+        reproducible: bool,
+        compression: CompressionOptions,
     ) -> Result<WheelWriter> {
         let wheel_path = wheel_dir.join(format!(
             "{}-{}-{}.whl",
@@ -275,21 +287,45 @@ impl WheelWriter {
             tag
         ));
 
-        let file = File::create(&wheel_path)?;
-
-        let mut builder = WheelWriter {
-            zip: ZipWriter::new(file),
-            record: Vec::new(),
-            record_file: metadata23.get_dist_info_dir().join("RECORD"),
+        let mut builder = WheelWriter::create(
             wheel_path,
+            &metadata23.get_dist_info_dir(),
             excludes,
-        };
+            reproducible,
+            compression,
+        )?;
 
         write_dist_info(&mut builder, metadata23, tags)?;
 
         Ok(builder)
     }
 
+    /// Create a new wheel file at `wheel_path` without adding any files to it
+    ///
+    /// The caller is responsible for adding the files of the given .dist-info directory except
+    /// for the RECORD, which is written on `finish`
+    pub(crate) fn create(
+        wheel_path: PathBuf,
+        dist_info_dir: &Path,
+        excludes: Override,
+        reproducible: bool,
+        compression: CompressionOptions,
+    ) -> Result<WheelWriter> {
+        let file = File::create(&wheel_path)?;
+
+        Ok(WheelWriter {
+            zip: ZipWriter::new(file),
+            record: Vec::new(),
+            record_file: dist_info_dir.join("RECORD"),
+            wheel_path,
+            excludes,
+            reproducible,
+            compression,
+            pending: Vec::new(),
+            init_py_preamble: None,
+        })
+    }
+
     /// Add a pth file to wheel root for editable installs
     pub fn add_pth(
         &mut self,
@@ -319,62 +355,197 @@ impl WheelWriter {
         Ok(())
     }
 
+    /// Inserts python code at the top of the `__init__.py` of the given package when it's added
+    /// to the wheel, which must happen later
+    pub fn add_init_py_preamble(&mut self, package: impl AsRef<Path>, preamble: String) {
+        let init_py = package.as_ref().join("__init__.py");
+        let init_py = init_py.to_str().unwrap().replace('\\', "/");
+        self.init_py_preamble = Some((init_py, preamble));
+    }
+
     /// Returns `true` if the given path should be excluded
     fn exclude(&self, path: impl AsRef<Path>) -> bool {
         self.excludes.matched(path.as_ref(), false).is_whitelist()
     }
 
-    /// Returns a DateTime representing the value SOURCE_DATE_EPOCH environment variable
-    /// Note that the earliest timestamp a zip file can represent is 1980-01-01
-    fn mtime(&self) -> Result<DateTime> {
-        let epoch: i64 = env::var("SOURCE_DATE_EPOCH")?.parse()?;
-        let dt = time::OffsetDateTime::from_unix_timestamp(epoch)?;
-        let min_dt = time::Date::from_calendar_date(1980, time::Month::January, 1)
-            .unwrap()
-            .midnight()
-            .assume_offset(time::UtcOffset::UTC);
-        let dt = dt.max(min_dt);
-
-        let dt = DateTime::try_from(dt).map_err(|_| anyhow!("Failed to build zip DateTime"))?;
-        Ok(dt)
+    fn write_entry(&mut self, target: &str, bytes: &[u8], permissions: u32) -> io::Result<()> {
+        let mtime = zip_mtime(self.reproducible).ok();
+        if self.compression.use_parallel(bytes) {
+            let mtime = mtime.unwrap_or_else(|| {
+                DateTime::try_from(time::OffsetDateTime::now_utc()).unwrap_or_default()
+            });
+            let single_entry =
+                compress_parallel(bytes, self.compression.level, permissions, mtime)?;
+            let mut single_entry = ZipArchive::new(io::Cursor::new(single_entry))?;
+            self.zip
+                .raw_copy_file_rename(single_entry.by_index_raw(0)?, target)?;
+            return Ok(());
+        }
+
+        let mut options = self
+            .compression
+            .file_options()
+            .unix_permissions(permissions);
+        if let Some(mtime) = mtime {
+            options = options.last_modified_time(mtime);
+        }
+
+        self.zip.start_file(target, options)?;
+        self.zip.write_all(bytes)
     }
 
     /// Creates the record file and finishes the zip
     pub fn finish(mut self) -> Result<PathBuf, io::Error> {
-        let compression_method = if cfg!(feature = "faster-tests") {
-            zip::CompressionMethod::Stored
-        } else {
-            zip::CompressionMethod::Deflated
-        };
-
-        let mut options = zip::write::FileOptions::default().compression_method(compression_method);
-        let mtime = self.mtime().ok();
-        if let Some(mtime) = mtime {
-            options = options.last_modified_time(mtime);
+        if let Some((init_py, _)) = &self.init_py_preamble {
+            eprintln!(
+                "⚠️  Warning: The wheel contains no {init_py}, so the code to load the bundled \
+                 libraries could not be added"
+            );
+        }
+        if self.reproducible {
+            let mut pending = std::mem::take(&mut self.pending);
+            pending.sort_by(|(a, ..), (b, ..)| wheel_entry_order(a).cmp(&wheel_entry_order(b)));
+            for (target, bytes, permissions) in pending {
+                self.write_entry(&target, &bytes, permissions)?;
+            }
+            self.record
+                .sort_by(|(a, ..), (b, ..)| wheel_entry_order(a).cmp(&wheel_entry_order(b)));
         }
 
         let record_filename = self.record_file.to_str().unwrap().replace('\\', "/");
         debug!("Adding {}", record_filename);
-        self.zip.start_file(&record_filename, options)?;
-        for (filename, hash, len) in self.record {
-            self.zip
-                .write_all(format!("{filename},sha256={hash},{len}\n").as_bytes())?;
+        let mut record = String::new();
+        for (filename, hash, len) in &self.record {
+            writeln!(record, "{filename},sha256={hash},{len}").unwrap();
         }
         // Write the record for the RECORD file itself
-        self.zip
-            .write_all(format!("{record_filename},,\n").as_bytes())?;
+        writeln!(record, "{record_filename},,").unwrap();
+        self.write_entry(&record_filename, record.as_bytes(), 0o644)?;
 
         self.zip.finish()?;
         Ok(self.wheel_path)
     }
 }
 
+/// Inserts code into a python module after the docstring and the `from __future__` imports,
+/// which both must come first
+fn insert_python_preamble(source: &str, preamble: &str) -> String {
+    let lines: Vec<&str> = source.split_inclusive('\n').collect();
+    // The index of the line before which the preamble goes
+    let mut position = 0;
+    let mut docstring_allowed = true;
+    // The end of a multi-line docstring or import we're currently in
+    let mut closing: Option<&str> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if let Some(end) = closing {
+            if line.contains(end) {
+                closing = None;
+                position = i + 1;
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if docstring_allowed {
+            docstring_allowed = false;
+            let string = line.trim_start_matches(['r', 'R', 'u', 'U']);
+            if let Some(quotes) = ["\"\"\"", "'''"]
+                .into_iter()
+                .find(|quotes| string.starts_with(quotes))
+            {
+                if string[3..].contains(quotes) {
+                    position = i + 1;
+                } else {
+                    closing = Some(quotes);
+                }
+                continue;
+            } else if string.starts_with(['"', '\'']) {
+                position = i + 1;
+                continue;
+            }
+        }
+        if line.starts_with("from __future__ import") {
+            if line.contains('(') && !line.contains(')') {
+                closing = Some(")");
+            } else {
+                position = i + 1;
+            }
+            continue;
+        }
+        break;
+    }
+
+    let mut output = lines[..position].concat();
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(preamble);
+    output.push_str(&lines[position..].concat());
+    output
+}
+
+/// Returns a DateTime representing the value SOURCE_DATE_EPOCH environment variable,
+/// falling back to the earliest possible timestamp for reproducible builds
+/// Note that the earliest timestamp a zip file can represent is 1980-01-01
+pub(crate) fn zip_mtime(reproducible: bool) -> Result<DateTime> {
+    let epoch = match source_date_epoch()? {
+        Some(epoch) => epoch,
+        None if reproducible => 0,
+        None => bail!("SOURCE_DATE_EPOCH is not set"),
+    };
+    let dt = time::OffsetDateTime::from_unix_timestamp(epoch)?;
+    let min_dt = time::Date::from_calendar_date(1980, time::Month::January, 1)
+        .unwrap()
+        .midnight()
+        .assume_offset(time::UtcOffset::UTC);
+    let dt = dt.max(min_dt);
+
+    let dt = DateTime::try_from(dt).map_err(|_| anyhow!("Failed to build zip DateTime"))?;
+    Ok(dt)
+}
+
+/// The value of the SOURCE_DATE_EPOCH environment variable, if set
+pub(crate) fn source_date_epoch() -> Result<Option<i64>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => Ok(Some(epoch.parse().with_context(|| {
+            format!("SOURCE_DATE_EPOCH must be a unix timestamp, got `{epoch}`")
+        })?)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Maps permissions to either 0o755 or 0o644, so that builds don't depend on the umask
+/// or the file system
+fn normalize_permissions(permissions: u32) -> u32 {
+    if permissions & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// Sorts the `.dist-info` directory to the end of the wheel as recommended by PEP 427,
+/// and everything else by path
+fn wheel_entry_order(path: &str) -> (bool, &str) {
+    let is_dist_info = path
+        .split_once('/')
+        .is_some_and(|(dir, _)| dir.ends_with(".dist-info"));
+    (is_dist_info, path)
+}
+
 /// Creates a .tar.gz archive containing the source distribution
 pub struct SDistWriter {
     tar: tar::Builder<GzEncoder<Vec<u8>>>,
     path: PathBuf,
     files: HashSet<PathBuf>,
     excludes: Override,
+    /// The mtime of all entries for reproducible builds, `None` otherwise
+    reproducible_mtime: Option<u64>,
+    /// With `reproducible_mtime`, the files are collected here and written sorted on `finish`
+    pending: BTreeMap<PathBuf, (Vec<u8>, u32)>,
 }
 
 impl ModuleWriter for SDistWriter {
@@ -398,17 +569,24 @@ impl ModuleWriter for SDistWriter {
             return Ok(());
         }
 
-        let mut header = tar::Header::new_gnu();
-        header.set_size(bytes.len() as u64);
-        header.set_mode(permissions);
-        header.set_cksum();
-        self.tar
-            .append_data(&mut header, target, bytes)
-            .context(format!(
-                "Failed to add {} bytes to sdist as {}",
-                bytes.len(),
-                target.display()
-            ))?;
+        if self.reproducible_mtime.is_some() {
+            self.pending.insert(
+                target.to_path_buf(),
+                (bytes.to_vec(), normalize_permissions(permissions)),
+            );
+        } else {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(bytes.len() as u64);
+            header.set_mode(permissions);
+            header.set_cksum();
+            self.tar
+                .append_data(&mut header, target, bytes)
+                .context(format!(
+                    "Failed to add {} bytes to sdist as {}",
+                    bytes.len(),
+                    target.display()
+                ))?;
+        }
         self.files.insert(target.to_path_buf());
         Ok(())
     }
@@ -425,13 +603,24 @@ impl ModuleWriter for SDistWriter {
         }
         debug!("Adding {} from {}", target.display(), source.display());
 
-        self.tar
-            .append_path_with_name(source, target)
-            .context(format!(
-                "Failed to add file from {} to sdist as {}",
-                source.display(),
-                target.display(),
-            ))?;
+        if self.reproducible_mtime.is_some() {
+            // Don't copy the owner and timestamps from the file system
+            let bytes = fs::read(source)?;
+            #[cfg(unix)]
+            let mode = source.metadata()?.permissions().mode();
+            #[cfg(not(unix))]
+            let mode = 0o644;
+            self.pending
+                .insert(target.to_path_buf(), (bytes, normalize_permissions(mode)));
+        } else {
+            self.tar
+                .append_path_with_name(source, target)
+                .context(format!(
+                    "Failed to add file from {} to sdist as {}",
+                    source.display(),
+                    target.display(),
+                ))?;
+        }
         self.files.insert(target.to_path_buf());
         Ok(())
     }
@@ -439,11 +628,15 @@ impl ModuleWriter for SDistWriter {
 
 impl SDistWriter {
     /// Create a source distribution .tar.gz which can be subsequently expanded
+    ///
+    /// With `reproducible`, the entries are sorted and their owner, permissions and mtime are
+    /// normalized, using SOURCE_DATE_EPOCH as mtime if set
     pub fn new(
         wheel_dir: impl AsRef<Path>,
         metadata23: &Metadata23,
         excludes: Override,
-    ) -> Result<Self, io::Error> {
+        reproducible: bool,
+    ) -> Result<Self> {
         let path = wheel_dir
             .as_ref()
             .normalize()?
@@ -457,11 +650,20 @@ impl SDistWriter {
         let enc = GzEncoder::new(Vec::new(), Compression::default());
         let tar = tar::Builder::new(enc);
 
+        let reproducible_mtime = if reproducible {
+            // Tar can't represent timestamps before the epoch
+            Some(source_date_epoch()?.unwrap_or_default().max(0) as u64)
+        } else {
+            None
+        };
+
         Ok(Self {
             tar,
             path,
             files: HashSet::new(),
             excludes,
+            reproducible_mtime,
+            pending: BTreeMap::new(),
         })
     }
 
@@ -471,7 +673,20 @@ impl SDistWriter {
     }
 
     /// Finished the .tar.gz archive
-    pub fn finish(self) -> Result<PathBuf, io::Error> {
+    pub fn finish(mut self) -> Result<PathBuf, io::Error> {
+        if let Some(mtime) = self.reproducible_mtime {
+            for (target, (bytes, permissions)) in std::mem::take(&mut self.pending) {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(bytes.len() as u64);
+                header.set_mode(permissions);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_mtime(mtime);
+                header.set_cksum();
+                self.tar
+                    .append_data(&mut header, &target, bytes.as_slice())?;
+            }
+        }
         let archive = self.tar.into_inner()?;
         fs::write(&self.path, archive.finish()?)?;
         Ok(self.path)
@@ -705,35 +920,42 @@ fn handle_cffi_call_result(
 }
 
 /// Copies the shared library into the module, which is the only extra file needed with bindings
+///
+/// `extension_modules` are the module names and shared libraries of additional extension
+/// modules, which are placed next to the python files of their parent package
 #[allow(clippy::too_many_arguments)]
 pub fn write_bindings_module(
     writer: &mut impl ModuleWriter,
     project_layout: &ProjectLayout,
     artifact: &Path,
+    extension_modules: &[(&str, &Path)],
     python_interpreter: Option<&PythonInterpreter>,
     is_abi3: bool,
     target: &Target,
     editable: bool,
     pyproject_toml: Option<&PyProjectToml>,
 ) -> Result<()> {
-    let ext_name = &project_layout.extension_name;
-    let so_filename = if is_abi3 {
-        if target.is_unix() {
-            format!("{ext_name}.abi3.so")
-        } else {
-            match python_interpreter {
-                Some(python_interpreter) if python_interpreter.is_windows_debug() => {
-                    format!("{ext_name}_d.pyd")
+    let library_name = |ext_name: &str| {
+        if is_abi3 {
+            if target.is_unix() {
+                format!("{ext_name}.abi3.so")
+            } else {
+                match python_interpreter {
+                    Some(python_interpreter) if python_interpreter.is_windows_debug() => {
+                        format!("{ext_name}_d.pyd")
+                    }
+                    // Apparently there is no tag for abi3 on windows
+                    _ => format!("{ext_name}.pyd"),
                 }
-                // Apparently there is no tag for abi3 on windows
-                _ => format!("{ext_name}.pyd"),
             }
+        } else {
+            let python_interpreter =
+                python_interpreter.expect("A python interpreter is required for non-abi3 build");
+            python_interpreter.get_library_name(ext_name)
         }
-    } else {
-        let python_interpreter =
-            python_interpreter.expect("A python interpreter is required for non-abi3 build");
-        python_interpreter.get_library_name(ext_name)
     };
+    let ext_name = &project_layout.extension_name;
+    let so_filename = library_name(ext_name);
 
     if !editable {
         write_python_part(writer, project_layout, pyproject_toml)
@@ -784,9 +1006,37 @@ if hasattr({ext_name}, "__all__"):
         writer.add_file_with_permissions(&module.join(so_filename), artifact, 0o755)?;
     }
 
+    for (module_name, artifact) in extension_modules {
+        let ext_name = module_name.rsplit('.').next().unwrap();
+        let so_filename = extension_module_dir(module_name).join(library_name(ext_name));
+        if editable && project_layout.python_module.is_some() {
+            let target = project_layout.python_dir.join(&so_filename);
+            debug!("Removing {}", target.display());
+            let _ = fs::remove_file(&target);
+
+            debug!("Copying {} to {}", artifact.display(), target.display());
+            fs::copy(artifact, &target).context(format!(
+                "Failed to copy {} to {}",
+                artifact.display(),
+                target.display()
+            ))?;
+        } else {
+            writer.add_file_with_permissions(&so_filename, artifact, 0o755)?;
+        }
+    }
+
     Ok(())
 }
 
+/// The directory of the shared library of an additional extension module in the wheel, which is
+/// its parent package, e.g. `foo/sub` for `foo.sub._simd` and the root for a top level module
+pub(crate) fn extension_module_dir(module_name: &str) -> PathBuf {
+    match module_name.rsplit_once('.') {
+        Some((package, _)) => package.split('.').collect(),
+        None => PathBuf::new(),
+    }
+}
+
 /// Creates the cffi module with the shared library, the cffi declarations and the cffi loader
 #[allow(clippy::too_many_arguments)]
 pub fn write_cffi_module(
@@ -1282,14 +1532,19 @@ pub fn write_dist_info(
         )?;
     }
 
-    if !metadata23.license_files.is_empty() {
-        let license_files_dir = dist_info_dir.join("license_files");
-        writer.add_directory(&license_files_dir)?;
-        for path in &metadata23.license_files {
-            let filename = path.file_name().with_context(|| {
-                format!("missing file name for license file {}", path.display())
-            })?;
-            writer.add_file(license_files_dir.join(filename), path)?;
+    // https://peps.python.org/pep-0639/#add-license-file-field
+    let licenses_dir = dist_info_dir.join("licenses");
+    for path in &metadata23.license_files {
+        let target = licenses_dir.join(metadata23.license_file_entry(path));
+        writer.add_directory(target.parent().unwrap())?;
+        writer.add_file(target, path)?;
+    }
+    for license in &metadata23.third_party_licenses {
+        let target = licenses_dir.join(&license.path);
+        writer.add_directory(target.parent().unwrap())?;
+        match &license.source {
+            LicenseSource::File(path) => writer.add_file(target, path)?,
+            LicenseSource::Text(text) => writer.add_bytes(target, text.as_bytes())?,
         }
     }
 
@@ -1371,7 +1626,7 @@ mod tests {
 
         // No excludes
         let tmp_dir = TempDir::new()?;
-        let mut writer = SDistWriter::new(&tmp_dir, &metadata, Override::empty())?;
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, Override::empty(), false)?;
         assert!(writer.files.is_empty());
         writer.add_bytes_with_permissions("test", &[], perm)?;
         assert_eq!(writer.files.len(), 1);
@@ -1383,7 +1638,7 @@ mod tests {
         let mut excludes = OverrideBuilder::new(&tmp_dir);
         excludes.add("test*")?;
         excludes.add("!test2")?;
-        let mut writer = SDistWriter::new(&tmp_dir, &metadata, excludes.build()?)?;
+        let mut writer = SDistWriter::new(&tmp_dir, &metadata, excludes.build()?, false)?;
         writer.add_bytes_with_permissions("test1", &[], perm)?;
         writer.add_bytes_with_permissions("test3", &[], perm)?;
         assert!(writer.files.is_empty());
@@ -1396,4 +1651,116 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn reproducible_writers() -> Result<()> {
+        let metadata = Metadata23::new("dummy".to_string(), Version::new([1, 0]));
+        let files: [(&str, &[u8], u32); 3] = [
+            ("dummy/__init__.py", b"", 0o664),
+            ("dummy/dummy.so", b"\x7fELF", 0o775),
+            ("dummy/a.py", b"a = 1", 0o600),
+        ];
+
+        let mut wheels = Vec::new();
+        let mut sdists = Vec::new();
+        for reverse in [false, true] {
+            let mut files = files.to_vec();
+            if reverse {
+                files.reverse();
+            }
+            let tmp_dir = TempDir::new()?;
+            let mut wheel = WheelWriter::new(
+                "py3-none-any",
+                tmp_dir.path(),
+                &metadata,
+                &[],
+                Override::empty(),
+                true,
+                CompressionOptions::default(),
+            )?;
+            let mut sdist = SDistWriter::new(&tmp_dir, &metadata, Override::empty(), true)?;
+            for (target, bytes, permissions) in files {
+                wheel.add_bytes_with_permissions(target, bytes, permissions)?;
+                sdist.add_bytes_with_permissions(target, bytes, permissions)?;
+            }
+            wheels.push(fs::read(wheel.finish()?)?);
+            sdists.push(fs::read(sdist.finish()?)?);
+        }
+        assert_eq!(wheels[0], wheels[1]);
+        assert_eq!(sdists[0], sdists[1]);
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(&wheels[0]))?;
+        let names = (0..archive.len())
+            .map(|i| Ok(archive.by_index(i)?.name().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            names,
+            [
+                "dummy/__init__.py",
+                "dummy/a.py",
+                "dummy/dummy.so",
+                "dummy-1.0.dist-info/METADATA",
+                "dummy-1.0.dist-info/WHEEL",
+                "dummy-1.0.dist-info/RECORD",
+            ]
+        );
+        let so = archive.by_name("dummy/dummy.so")?;
+        assert_eq!(so.unix_mode(), Some(0o100755));
+        assert_eq!(
+            so.last_modified().datepart(),
+            DateTime::default().datepart()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_python_preamble() {
+        let preamble = "import os\n";
+        assert_eq!(insert_python_preamble("", preamble), "import os\n");
+        assert_eq!(
+            insert_python_preamble("from .foo import *\n", preamble),
+            "import os\nfrom .foo import *\n"
+        );
+        assert_eq!(
+            insert_python_preamble(
+                "# comment\n\"\"\"Docs\n\nMore docs\"\"\"\n\nfrom __future__ import (\n    annotations,\n)\nimport sys\n",
+                preamble
+            ),
+            "# comment\n\"\"\"Docs\n\nMore docs\"\"\"\n\nfrom __future__ import (\n    annotations,\n)\nimport os\nimport sys\n"
+        );
+        assert_eq!(
+            insert_python_preamble("r'''Docs'''\nfrom __future__ import annotations", preamble),
+            "r'''Docs'''\nfrom __future__ import annotations\nimport os\n"
+        );
+    }
+
+    #[test]
+    fn wheel_init_py_preamble() -> Result<()> {
+        let metadata = Metadata23::new("dummy".to_string(), Version::new([1, 0]));
+        let tmp_dir = TempDir::new()?;
+        let mut wheel = WheelWriter::new(
+            "py3-none-any",
+            tmp_dir.path(),
+            &metadata,
+            &[],
+            Override::empty(),
+            false,
+            CompressionOptions::default(),
+        )?;
+        wheel.add_init_py_preamble("dummy", "import os\n".to_string());
+        wheel.add_bytes("dummy/sub/__init__.py", b"from . import a\n")?;
+        wheel.add_bytes("dummy/__init__.py", b"from . import a\n")?;
+        let mut archive = zip::ZipArchive::new(File::open(wheel.finish()?)?)?;
+        let mut init_py = String::new();
+        archive
+            .by_name("dummy/__init__.py")?
+            .read_to_string(&mut init_py)?;
+        assert_eq!(init_py, "import os\nfrom . import a\n");
+        let mut init_py = String::new();
+        archive
+            .by_name("dummy/sub/__init__.py")?
+            .read_to_string(&mut init_py)?;
+        assert_eq!(init_py, "from . import a\n");
+        Ok(())
+    }
 }
@@ -1,12 +1,16 @@
 use crate::build_options::{extract_cargo_metadata_args, CargoOptions};
+use crate::git_version::version_from_git;
+use crate::pyproject_toml::VersionSource;
 use crate::{CargoToml, Metadata23, PyProjectToml};
 use anyhow::{bail, format_err, Context, Result};
 use cargo_metadata::{Metadata, MetadataCommand};
+use fs_err as fs;
 use normpath::PathExt as _;
 use std::collections::HashSet;
 use std::env;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tracing::debug;
 
 const PYPROJECT_TOML: &str = "pyproject.toml";
@@ -112,6 +116,9 @@ impl ProjectResolver {
         if let Some(pyproject) = pyproject {
             let pyproject_dir = pyproject_file.parent().unwrap();
             metadata23.merge_pyproject_toml(pyproject_dir, pyproject)?;
+            if pyproject.version_source() == VersionSource::Git {
+                metadata23.version = Self::resolve_git_version(pyproject_dir, pyproject)?;
+            }
         }
 
         let crate_name = &cargo_toml.package.name;
@@ -312,7 +319,42 @@ impl ProjectResolver {
         }
     }
 
-    fn resolve_cargo_metadata(
+    /// Resolves the version for `version-source = "git"`
+    ///
+    /// A source distribution has no git history, so we read back the version we wrote to its
+    /// PKG-INFO instead
+    fn resolve_git_version(
+        pyproject_dir: &Path,
+        pyproject: &PyProjectToml,
+    ) -> Result<pep440_rs::Version> {
+        let project = pyproject.project.as_ref();
+        let is_dynamic = project
+            .and_then(|project| project.dynamic.as_ref())
+            .is_some_and(|dynamic| dynamic.iter().any(|field| field == "version"));
+        if project.is_some_and(|project| project.version.is_some()) || !is_dynamic {
+            bail!(
+                "`tool.maturin.version-source = \"git\"` requires `version` to be listed in \
+                 `project.dynamic` instead of being set in `[project]` in pyproject.toml"
+            );
+        }
+        let pkg_info = pyproject_dir.join("PKG-INFO");
+        if pkg_info.is_file() {
+            let metadata = Metadata23::from_str(&fs::read_to_string(&pkg_info)?)
+                .with_context(|| format!("Failed to parse {}", pkg_info.display()))?;
+            debug!(
+                "Using version {} from {}",
+                metadata.version,
+                pkg_info.display()
+            );
+            return Ok(metadata.version);
+        }
+        let version = version_from_git(pyproject_dir)
+            .context("Failed to determine the version from git for `version-source = \"git\"`")?;
+        debug!("Using version {} from git", version);
+        Ok(version)
+    }
+
+    pub(crate) fn resolve_cargo_metadata(
         manifest_path: &Path,
         cargo_options: &CargoOptions,
     ) -> Result<Metadata> {
@@ -1,6 +1,6 @@
 //! A pyproject.toml as specified in PEP 517
 
-use crate::PlatformTag;
+use crate::{CompressionMethod, LimitedApiCheck, SbomFormat};
 use anyhow::{Context, Result};
 use fs_err as fs;
 use pep440_rs::Version;
@@ -98,9 +98,25 @@ pub struct CargoTarget {
     pub name: String,
     /// Kind of target ("bin", "lib")
     pub kind: Option<String>,
-    // TODO: Add bindings option
-    // Bridge model, which kind of bindings to use
-    // pub bindings: Option<String>,
+    /// Bridge model, which kind of bindings to use for this target
+    pub bindings: Option<String>,
+}
+
+/// An additional native extension module that is built from its own crate
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ExtensionModuleConfig {
+    /// Module name, accepts setuptools style import name like `foo._simd`
+    pub module_name: String,
+    /// Path to the Cargo.toml of the crate, relative to pyproject.toml
+    pub manifest_path: PathBuf,
+    /// List of features to activate, the features of `[tool.maturin]` only apply to the main crate
+    pub features: Option<Vec<String>>,
+    /// Activate all available features
+    pub all_features: Option<bool>,
+    /// Do not activate the `default` feature
+    pub no_default_features: Option<bool>,
 }
 
 /// Target configuration
@@ -125,6 +141,18 @@ pub enum SdistGenerator {
     Git,
 }
 
+/// Where the version of a project with `dynamic = ["version"]` comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum VersionSource {
+    /// Use the version in Cargo.toml
+    #[default]
+    Cargo,
+    /// Derive the version from `git describe`
+    Git,
+}
+
 /// The `[tool.maturin]` section of a pyproject.toml
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -139,18 +167,45 @@ pub struct ToolMaturin {
     pub exclude: Option<Vec<GlobPattern>>,
     /// Bindings type
     pub bindings: Option<String>,
-    /// Platform compatibility
+    /// Platform compatibility, a manylinux, musllinux or linux tag, or a custom platform tag
+    /// defined in the `audit-policy-file`
     #[serde(alias = "manylinux")]
-    pub compatibility: Option<PlatformTag>,
+    pub compatibility: Option<String>,
     /// Skip audit wheel
     #[serde(default)]
     pub skip_auditwheel: bool,
+    /// JSON file with additional or overriding audit policies, relative to pyproject.toml
+    pub audit_policy_file: Option<PathBuf>,
+    /// Don't bundle the shared libraries matching these glob patterns into the wheel
+    pub audit_exclude: Option<Vec<String>>,
+    /// What to do when an abi3 library uses symbols outside of the limited API
+    pub limited_api_check: Option<LimitedApiCheck>,
     /// Strip the final binary
     #[serde(default)]
     pub strip: bool,
+    /// Build byte-for-byte reproducible wheels and source distributions
+    #[serde(default)]
+    pub reproducible: bool,
+    /// Zip compression method of the wheel
+    pub compression: Option<CompressionMethod>,
+    /// Zip compression level of the wheel
+    pub compression_level: Option<i32>,
+    /// Move the debug info into a separate `.debug.zip` archive next to each wheel
+    #[serde(default)]
+    pub split_debuginfo: bool,
+    /// Write a software bill of materials into the wheels, defaults to true
+    pub sbom: Option<bool>,
+    /// The formats of the software bill of materials, defaults to CycloneDX
+    pub sbom_format: Option<Vec<SbomFormat>>,
+    /// Bundle the licenses of the statically linked third-party crates into the wheels
+    #[serde(default)]
+    pub third_party_licenses: bool,
     /// Source distribution generator
     #[serde(default)]
     pub sdist_generator: SdistGenerator,
+    /// The source of the version when it's listed in `project.dynamic`
+    #[serde(default)]
+    pub version_source: VersionSource,
     /// The directory with python module, contains `<module_name>/__init__.py`
     pub python_source: Option<PathBuf>,
     /// Python packages to include
@@ -159,6 +214,8 @@ pub struct ToolMaturin {
     pub data: Option<PathBuf>,
     /// Cargo compile targets
     pub targets: Option<Vec<CargoTarget>>,
+    /// Additional native extension modules to build from other crates into the same wheel
+    pub extension_modules: Option<Vec<ExtensionModuleConfig>>,
     /// Target configuration
     #[serde(default, rename = "target")]
     pub target_config: HashMap<String, TargetConfig>,
@@ -185,6 +242,23 @@ pub struct ToolMaturin {
     pub rustc_args: Option<Vec<String>>,
 }
 
+/// PEP 639 was accepted with `project.license-files` as a list of globs, while the pyproject-toml
+/// crate only knows the `paths` and `globs` tables of the draft, so we rewrite the list into a
+/// `globs` table
+fn license_files_as_globs_table(contents: &str) -> Option<toml::Table> {
+    let mut table: toml::Table = toml::from_str(contents).ok()?;
+    let license_files = table
+        .get_mut("project")?
+        .as_table_mut()?
+        .get_mut("license-files")?;
+    if !license_files.is_array() {
+        return None;
+    }
+    let globs = license_files.clone();
+    *license_files = toml::Value::Table(toml::Table::from_iter([("globs".to_string(), globs)]));
+    Some(table)
+}
+
 /// A pyproject.toml as specified in PEP 517
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -209,7 +283,11 @@ impl PyProjectToml {
     pub fn new(pyproject_file: impl AsRef<Path>) -> Result<PyProjectToml> {
         let path = pyproject_file.as_ref();
         let contents = fs::read_to_string(path)?;
-        let pyproject = toml::from_str(&contents).with_context(|| {
+        let pyproject = match license_files_as_globs_table(&contents) {
+            Some(table) => table.try_into(),
+            None => toml::from_str(&contents),
+        }
+        .with_context(|| {
             format!(
                 "pyproject.toml at {} is invalid",
                 pyproject_file.as_ref().display()
@@ -250,8 +328,8 @@ impl PyProjectToml {
     }
 
     /// Returns the value of `[tool.maturin.compatibility]` in pyproject.toml
-    pub fn compatibility(&self) -> Option<PlatformTag> {
-        self.maturin()?.compatibility
+    pub fn compatibility(&self) -> Option<&str> {
+        self.maturin()?.compatibility.as_deref()
     }
 
     /// Returns the value of `[tool.maturin.skip-auditwheel]` in pyproject.toml
@@ -261,6 +339,23 @@ impl PyProjectToml {
             .unwrap_or_default()
     }
 
+    /// Returns the value of `[tool.maturin.audit-policy-file]` in pyproject.toml
+    pub fn audit_policy_file(&self) -> Option<&Path> {
+        self.maturin()
+            .and_then(|maturin| maturin.audit_policy_file.as_deref())
+    }
+
+    /// Returns the value of `[tool.maturin.audit-exclude]` in pyproject.toml
+    pub fn audit_exclude(&self) -> Option<&[String]> {
+        self.maturin()
+            .and_then(|maturin| maturin.audit_exclude.as_deref())
+    }
+
+    /// Returns the value of `[tool.maturin.limited-api-check]` in pyproject.toml
+    pub fn limited_api_check(&self) -> Option<LimitedApiCheck> {
+        self.maturin().and_then(|maturin| maturin.limited_api_check)
+    }
+
     /// Returns the value of `[tool.maturin.strip]` in pyproject.toml
     pub fn strip(&self) -> bool {
         self.maturin()
@@ -268,6 +363,57 @@ impl PyProjectToml {
             .unwrap_or_default()
     }
 
+    /// Returns the value of `[tool.maturin.reproducible]` in pyproject.toml
+    pub fn reproducible(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.reproducible)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.compression]` in pyproject.toml
+    pub fn compression(&self) -> Option<CompressionMethod> {
+        self.maturin().and_then(|maturin| maturin.compression)
+    }
+
+    /// Returns the value of `[tool.maturin.compression-level]` in pyproject.toml
+    pub fn compression_level(&self) -> Option<i32> {
+        self.maturin().and_then(|maturin| maturin.compression_level)
+    }
+
+    /// Returns the value of `[tool.maturin.split-debuginfo]` in pyproject.toml
+    pub fn split_debuginfo(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.split_debuginfo)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.sbom]` in pyproject.toml
+    pub fn sbom(&self) -> bool {
+        self.maturin()
+            .and_then(|maturin| maturin.sbom)
+            .unwrap_or(true)
+    }
+
+    /// Returns the value of `[tool.maturin.sbom-format]` in pyproject.toml
+    pub fn sbom_format(&self) -> Option<&[SbomFormat]> {
+        self.maturin()
+            .and_then(|maturin| maturin.sbom_format.as_deref())
+    }
+
+    /// Returns the value of `[tool.maturin.third-party-licenses]` in pyproject.toml
+    pub fn third_party_licenses(&self) -> bool {
+        self.maturin()
+            .map(|maturin| maturin.third_party_licenses)
+            .unwrap_or_default()
+    }
+
+    /// Returns the value of `[tool.maturin.version-source]` in pyproject.toml
+    pub fn version_source(&self) -> VersionSource {
+        self.maturin()
+            .map(|maturin| maturin.version_source)
+            .unwrap_or_default()
+    }
+
     /// Returns the value of `[tool.maturin.sdist-generator]` in pyproject.toml
     pub fn sdist_generator(&self) -> SdistGenerator {
         self.maturin()
@@ -297,6 +443,13 @@ impl PyProjectToml {
         self.maturin().and_then(|maturin| maturin.targets.clone())
     }
 
+    /// Returns the value of `[[tool.maturin.extension-modules]]` in pyproject.toml
+    pub fn extension_modules(&self) -> &[ExtensionModuleConfig] {
+        self.maturin()
+            .and_then(|maturin| maturin.extension_modules.as_deref())
+            .unwrap_or_default()
+    }
+
     /// Returns the value of `[tool.maturin.target.<target>]` in pyproject.toml
     pub fn target_config(&self, target: &str) -> Option<&TargetConfig> {
         self.maturin()
@@ -380,12 +533,13 @@ impl PyProjectToml {
 mod tests {
     use crate::{
         pyproject_toml::{Format, Formats, GlobPattern, ToolMaturin},
-        PyProjectToml,
+        PyProjectToml, SbomFormat,
     };
     use expect_test::expect;
     use fs_err as fs;
     use indoc::indoc;
     use pretty_assertions::assert_eq;
+    use pyproject_toml::LicenseFiles;
     use std::path::Path;
     use tempfile::TempDir;
 
@@ -409,12 +563,18 @@ mod tests {
             no-default-features = true
             locked = true
             rustc-args = ["-Z", "unstable-options"]
+            sbom-format = ["cyclonedx", "spdx"]
 
             [[tool.maturin.targets]]
             name = "pyo3_pure"
             kind = "lib"
             bindings = "pyo3"
 
+            [[tool.maturin.extension-modules]]
+            module-name = "foo._simd"
+            manifest-path = "simd/Cargo.toml"
+            features = ["avx2"]
+
             [tool.maturin.target."x86_64-apple-darwin"]
             macos-deployment-target = "10.12"
             "#,
@@ -441,8 +601,25 @@ mod tests {
             maturin.python_packages,
             Some(vec!["foo".to_string(), "bar".to_string()])
         );
+        assert!(pyproject.sbom());
+        assert_eq!(
+            pyproject.sbom_format(),
+            Some(&[SbomFormat::CycloneDx, SbomFormat::Spdx][..])
+        );
         let targets = maturin.targets.as_ref().unwrap();
         assert_eq!("pyo3_pure", targets[0].name);
+        assert_eq!(targets[0].bindings.as_deref(), Some("pyo3"));
+        let extension_modules = pyproject.extension_modules();
+        assert_eq!(extension_modules.len(), 1);
+        assert_eq!(extension_modules[0].module_name, "foo._simd");
+        assert_eq!(
+            extension_modules[0].manifest_path,
+            Path::new("simd/Cargo.toml")
+        );
+        assert_eq!(
+            extension_modules[0].features,
+            Some(vec!["avx2".to_string()])
+        );
         let target_config = pyproject.target_config("x86_64-apple-darwin").unwrap();
         assert_eq!(
             target_config.macos_deployment_target.as_deref(),
@@ -450,6 +627,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_tool_maturin_targets() {
+        let tmp_dir = TempDir::new().unwrap();
+        let pyproject_file = tmp_dir.path().join("pyproject.toml");
+
+        fs::write(
+            &pyproject_file,
+            r#"[build-system]
+            requires = ["maturin"]
+            build-backend = "maturin"
+
+            [[tool.maturin.targets]]
+            name = "pyo3_mixed"
+            bindings = "pyo3"
+
+            [[tool.maturin.targets]]
+            name = "print-hello"
+            kind = "bin"
+            bindings = "bin"
+
+            [[tool.maturin.targets]]
+            name = "helper"
+            "#,
+        )
+        .unwrap();
+        let pyproject = PyProjectToml::new(pyproject_file).unwrap();
+        let targets = pyproject.targets().unwrap();
+        assert_eq!(
+            targets
+                .iter()
+                .map(|target| (
+                    target.name.as_str(),
+                    target.kind.as_deref(),
+                    target.bindings.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("pyo3_mixed", None, Some("pyo3")),
+                ("print-hello", Some("bin"), Some("bin")),
+                ("helper", None, None),
+            ]
+        );
+    }
+
     #[test]
     fn test_warn_missing_maturin_version() {
         let with_constraint = PyProjectToml::new("test-crates/pyo3-pure/pyproject.toml").unwrap();
@@ -557,7 +778,7 @@ mod tests {
 
             [project]
             name = "..."
-            license-files = [ "license.txt",]
+            license-files = 1
             requires-python = ">=3.8"
             requires-dist = [ "maturin>=0.14", "...",]
             dependencies = [ "packaging", "...",]
@@ -577,10 +798,36 @@ mod tests {
         let expected = expect![[r#"
             TOML parse error at line 7, column 17
               |
-            7 | license-files = [ "license.txt",]
-              |                 ^^^^^^^^^^^^^^^^^
+            7 | license-files = 1
+              |                 ^
             wanted string or table
         "#]];
         expected.assert_eq(&inner_error.to_string());
     }
+
+    #[test]
+    fn test_license_files_list() {
+        let source = indoc!(
+            r#"[build-system]
+            requires = ["maturin>=1.0,<2.0"]
+            build-backend = "maturin"
+
+            [project]
+            name = "foo"
+            license = "MIT OR Apache-2.0"
+            license-files = ["LICENSE*", "vendor/**/LICENSE"]
+        "#
+        );
+        let temp_dir = TempDir::new().unwrap();
+        let pyproject_toml = temp_dir.path().join("pyproject.toml");
+        fs::write(&pyproject_toml, source).unwrap();
+        let pyproject = PyProjectToml::new(&pyproject_toml).unwrap();
+        assert_eq!(
+            pyproject.project.unwrap().license_files,
+            Some(LicenseFiles::Globs(Some(vec![
+                "LICENSE*".to_string(),
+                "vendor/**/LICENSE".to_string()
+            ])))
+        );
+    }
 }
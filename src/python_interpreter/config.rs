@@ -1,4 +1,7 @@
-use super::{InterpreterKind, MAXIMUM_PYPY_MINOR, MAXIMUM_PYTHON_MINOR, MINIMUM_PYTHON_MINOR};
+use super::{
+    InterpreterKind, MAXIMUM_FREE_THREADED_PYTHON_MINOR, MAXIMUM_PYPY_MINOR, MAXIMUM_PYTHON_MINOR,
+    MINIMUM_FREE_THREADED_PYTHON_MINOR, MINIMUM_PYTHON_MINOR,
+};
 use crate::target::{Arch, Os};
 use crate::Target;
 use anyhow::{format_err, Context, Result};
@@ -23,14 +26,20 @@ pub struct InterpreterConfig {
     pub interpreter_kind: InterpreterKind,
     /// For linux and mac, this contains the value of the abiflags, e.g. "m"
     /// for python3.7m or "dm" for python3.6dm. Since python3.8, the value is
-    /// empty. On windows, the value was always None.
+    /// empty. On windows, the value was always None. Free-threaded builds
+    /// add a "t", e.g. python3.13t, on all platforms.
     ///
-    /// See PEP 261 and PEP 393 for details
+    /// See PEP 261, PEP 393 and PEP 703 for details
     pub abiflags: String,
     /// Suffix to use for extension modules as given by sysconfig.
     pub ext_suffix: String,
     /// Pointer width
     pub pointer_width: Option<usize>,
+    /// Whether this is a free-threaded build with the GIL disabled, e.g. python3.13t
+    ///
+    /// See PEP 703 for details
+    #[serde(default)]
+    pub gil_disabled: bool,
 }
 
 impl InterpreterConfig {
@@ -86,6 +95,7 @@ impl InterpreterConfig {
                     abiflags,
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Linux, PyPy) => {
@@ -98,6 +108,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Macos, CPython) => {
@@ -115,6 +126,7 @@ impl InterpreterConfig {
                     abiflags,
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Macos, PyPy) => {
@@ -126,6 +138,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Windows, CPython) => {
@@ -147,6 +160,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Windows, PyPy) => {
@@ -162,6 +176,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::FreeBsd, CPython) => {
@@ -177,6 +192,7 @@ impl InterpreterConfig {
                     abiflags,
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::NetBsd, CPython) => {
@@ -188,6 +204,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::OpenBsd, CPython) => {
@@ -200,6 +217,7 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (Os::Emscripten, CPython) => {
@@ -212,12 +230,43 @@ impl InterpreterConfig {
                     abiflags: String::new(),
                     ext_suffix,
                     pointer_width: Some(target.pointer_width()),
+                    gil_disabled: false,
                 })
             }
             (_, _) => None,
         }
     }
 
+    /// Lookup a wellknown sysconfig for the free-threaded build of a given CPython version,
+    /// e.g. python3.13t
+    pub fn lookup_one_free_threaded(
+        target: &Target,
+        python_version: (usize, usize),
+    ) -> Option<Self> {
+        let (major, minor) = python_version;
+        if python_version < (3, MINIMUM_FREE_THREADED_PYTHON_MINOR) {
+            return None;
+        }
+        let config = Self::lookup_one(target, InterpreterKind::CPython, python_version)?;
+        // .cpython-313-x86_64-linux-gnu.so => .cpython-313t-x86_64-linux-gnu.so
+        // .cp313-win_amd64.pyd => .cp313t-win_amd64.pyd
+        let ext_suffix = [
+            format!(".cpython-{major}{minor}"),
+            format!(".cp{major}{minor}"),
+        ]
+        .into_iter()
+        .find_map(|soabi| {
+            let rest = config.ext_suffix.strip_prefix(&soabi)?;
+            Some(format!("{soabi}t{rest}"))
+        })?;
+        Some(Self {
+            abiflags: "t".to_string(),
+            ext_suffix,
+            gil_disabled: true,
+            ..config
+        })
+    }
+
     /// Lookup wellknown sysconfigs for a given target
     pub fn lookup_target(target: &Target) -> Vec<Self> {
         let mut configs = Vec::new();
@@ -231,6 +280,9 @@ impl InterpreterConfig {
                 }
             }
         }
+        for minor in MINIMUM_FREE_THREADED_PYTHON_MINOR..=MAXIMUM_FREE_THREADED_PYTHON_MINOR {
+            configs.extend(Self::lookup_one_free_threaded(target, (3, minor)));
+        }
         configs
     }
 
@@ -259,6 +311,7 @@ impl InterpreterConfig {
         let mut ext_suffix = None;
         let mut abi_tag = None;
         let mut pointer_width = None;
+        let mut build_flags: Option<String> = None;
 
         for (i, line) in lines.enumerate() {
             let line = line.context("failed to read line from config")?;
@@ -272,6 +325,7 @@ impl InterpreterConfig {
                 "ext_suffix" => parse_value!(ext_suffix, value),
                 "abi_tag" => parse_value!(abi_tag, value),
                 "pointer_width" => parse_value!(pointer_width, value),
+                "build_flags" => parse_value!(build_flags, value),
                 _ => continue,
             }
         }
@@ -287,9 +341,15 @@ impl InterpreterConfig {
         })?;
         let implementation = implementation.unwrap_or_else(|| "cpython".to_string());
         let interpreter_kind = implementation.parse().map_err(|e| format_err!("{}", e))?;
+        let gil_disabled = build_flags
+            .unwrap_or_default()
+            .split(',')
+            .any(|flag| flag.trim() == "Py_GIL_DISABLED");
         let abi_tag = match interpreter_kind {
             InterpreterKind::CPython => {
-                if (major, minor) >= (3, 8) {
+                if gil_disabled {
+                    abi_tag.unwrap_or_else(|| format!("{major}{minor}t"))
+                } else if (major, minor) >= (3, 8) {
                     abi_tag.unwrap_or_else(|| format!("{major}{minor}"))
                 } else {
                     abi_tag.unwrap_or_else(|| format!("{major}{minor}m"))
@@ -359,9 +419,16 @@ impl InterpreterConfig {
             major,
             minor,
             interpreter_kind,
-            abiflags: abiflags.unwrap_or_default(),
+            abiflags: abiflags.unwrap_or_else(|| {
+                if gil_disabled {
+                    "t".to_string()
+                } else {
+                    String::new()
+                }
+            }),
             ext_suffix,
             pointer_width,
+            gil_disabled,
         })
     }
 
@@ -372,12 +439,15 @@ impl InterpreterConfig {
 version={major}.{minor}
 shared=true
 abi3=false
-build_flags=WITH_THREAD
-suppress_build_script_link_lines=false"#,
+build_flags=WITH_THREAD"#,
             implementation = self.interpreter_kind,
             major = self.major,
             minor = self.minor,
         );
+        if self.gil_disabled {
+            content.push_str(",Py_GIL_DISABLED");
+        }
+        content.push_str("\nsuppress_build_script_link_lines=false");
         if let Some(pointer_width) = self.pointer_width {
             write!(content, "\npointer_width={pointer_width}").unwrap();
         }
@@ -596,6 +666,39 @@ mod test {
         assert_eq!(sysconfig.ext_suffix, ".pypy39-pp73-win_amd64.pyd");
     }
 
+    #[test]
+    fn test_well_known_sysconfigs_free_threaded() {
+        let sysconfig = InterpreterConfig::lookup_one_free_threaded(
+            &Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap(),
+            (3, 13),
+        )
+        .unwrap();
+        assert_eq!(sysconfig.abiflags, "t");
+        assert!(sysconfig.gil_disabled);
+        assert_eq!(sysconfig.ext_suffix, ".cpython-313t-x86_64-linux-gnu.so");
+
+        let sysconfig = InterpreterConfig::lookup_one_free_threaded(
+            &Target::from_target_triple(Some("aarch64-apple-darwin".to_string())).unwrap(),
+            (3, 13),
+        )
+        .unwrap();
+        assert_eq!(sysconfig.ext_suffix, ".cpython-313t-darwin.so");
+
+        let sysconfig = InterpreterConfig::lookup_one_free_threaded(
+            &Target::from_target_triple(Some("x86_64-pc-windows-msvc".to_string())).unwrap(),
+            (3, 13),
+        )
+        .unwrap();
+        assert_eq!(sysconfig.ext_suffix, ".cp313t-win_amd64.pyd");
+
+        // The GIL can only be disabled since 3.13
+        assert!(InterpreterConfig::lookup_one_free_threaded(
+            &Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap(),
+            (3, 12),
+        )
+        .is_none());
+    }
+
     #[test]
     fn test_well_known_sysconfigs_freebsd() {
         // CPython
@@ -744,4 +847,31 @@ mod test {
             pointer_width=64"#]];
         expected.assert_eq(&config_file);
     }
+
+    #[test]
+    fn test_pyo3_config_file_free_threaded() {
+        let sysconfig = InterpreterConfig::lookup_one_free_threaded(
+            &Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap(),
+            (3, 13),
+        )
+        .unwrap();
+        let config_file = sysconfig.pyo3_config_file();
+        let expected = expect![[r#"
+            implementation=CPython
+            version=3.13
+            shared=true
+            abi3=false
+            build_flags=WITH_THREAD,Py_GIL_DISABLED
+            suppress_build_script_link_lines=false
+            pointer_width=64"#]];
+        expected.assert_eq(&config_file);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pyo3-config.txt");
+        fs::write(&path, config_file).unwrap();
+        let target =
+            Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
+        let parsed = InterpreterConfig::from_pyo3_config(&path, &target).unwrap();
+        assert_eq!(parsed, sysconfig);
+    }
 }
@@ -30,6 +30,8 @@ metadata = {
     "interpreter": platform.python_implementation().lower(),
     "ext_suffix": ext_suffix,
     "soabi": sysconfig.get_config_var("SOABI") or None,
+    # Free-threaded builds (PEP 703), e.g. python3.13t
+    "gil_disabled": sysconfig.get_config_var("Py_GIL_DISABLED") == 1,
     "platform": sysconfig.get_platform(),
     # This one isn't technically necessary, but still very useful for sanity checks
     "system": platform.system().lower(),
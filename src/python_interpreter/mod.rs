@@ -22,6 +22,10 @@ const GET_INTERPRETER_METADATA: &str = include_str!("get_interpreter_metadata.py
 pub const MINIMUM_PYTHON_MINOR: usize = 7;
 /// Be liberal here to include preview versions
 pub const MAXIMUM_PYTHON_MINOR: usize = 12;
+/// The first python version with a free-threaded build, see PEP 703
+pub const MINIMUM_FREE_THREADED_PYTHON_MINOR: usize = 13;
+/// The free-threaded builds are only available as previews so far
+pub const MAXIMUM_FREE_THREADED_PYTHON_MINOR: usize = 13;
 pub const MAXIMUM_PYPY_MINOR: usize = 10;
 
 /// Identifies conditions where we do not want to build wheels
@@ -283,6 +287,7 @@ fn windows_python_info(executable: &Path) -> Result<Option<InterpreterConfig>> {
             abiflags: String::new(),
             ext_suffix: String::new(),
             pointer_width: Some(pointer_width),
+            gil_disabled: false,
         }))
     } else {
         Ok(None)
@@ -353,6 +358,9 @@ struct InterpreterMetadataMessage {
     // comes from `platform.system()`
     system: String,
     soabi: Option<String>,
+    // comes from `sysconfig.get_config_var("Py_GIL_DISABLED")`
+    #[serde(default)]
+    gil_disabled: bool,
 }
 
 /// The location and version of an interpreter
@@ -394,6 +402,7 @@ impl Deref for PythonInterpreter {
 /// The rules are as follows:
 ///  - python 3 + Unix: Use ABIFLAGS
 ///  - python 3 + Windows: No ABIFLAGS, return an empty string
+///  - free-threaded python 3 + Windows: return "t" to match the unix ABIFLAGS
 fn fun_with_abiflags(
     message: &InterpreterMetadataMessage,
     target: &Target,
@@ -422,7 +431,9 @@ fn fun_with_abiflags(
         // pypy and graalpy do not specify abi flags
         Ok("".to_string())
     } else if message.system == "windows" {
-        if matches!(message.abiflags.as_deref(), Some("") | None) {
+        if message.gil_disabled && matches!(message.abiflags.as_deref(), Some("" | "t") | None) {
+            Ok("t".to_string())
+        } else if matches!(message.abiflags.as_deref(), Some("") | None) {
             Ok("".to_string())
         } else {
             bail!("A python 3 interpreter on windows does not define abiflags in its sysconfig ಠ_ಠ")
@@ -443,8 +454,10 @@ fn fun_with_abiflags(
 
 impl PythonInterpreter {
     /// Does this interpreter have PEP 384 stable api aka. abi3 support?
+    ///
+    /// Free-threaded builds don't support the stable api
     pub fn has_stable_api(&self) -> bool {
-        if self.implementation_name.parse::<InterpreterKind>().is_err() {
+        if self.implementation_name.parse::<InterpreterKind>().is_err() || self.gil_disabled {
             false
         } else {
             match self.interpreter_kind {
@@ -502,6 +515,13 @@ impl PythonInterpreter {
                             abiflags = self.abiflags,
                             platform = platform
                         )
+                    } else if self.gil_disabled {
+                        format!(
+                            "cp{major}{minor}-cp{major}{minor}t-{platform}",
+                            major = self.major,
+                            minor = self.minor,
+                            platform = platform
+                        )
                     } else {
                         // On windows the abiflags are missing, but this seems to work
                         format!(
@@ -557,6 +577,8 @@ impl PythonInterpreter {
     /// Mac:     foobar.cpython-36m-darwin.so
     /// FreeBSD: foobar.cpython-36m.so
     ///
+    /// Free-threaded builds add a `t`, e.g. foobar.cpython-313t-x86_64-linux-gnu.so
+    ///
     /// For pypy3, we read importlib.machinery.EXTENSION_SUFFIXES[0].
     pub fn get_library_name(&self, base: &str) -> String {
         format!(
@@ -694,6 +716,7 @@ impl PythonInterpreter {
                     .ext_suffix
                     .context("syconfig didn't define an `EXT_SUFFIX` ಠ_ಠ")?,
                 pointer_width: None,
+                gil_disabled: message.gil_disabled,
             },
             executable,
             platform,
@@ -766,6 +789,19 @@ impl PythonInterpreter {
                 })
                 .map(|minor| format!("python3.{minor}"))
                 .collect();
+            // Free-threaded builds are installed next to the regular ones, e.g. python3.13t
+            executables.extend(
+                (min_python_minor.max(MINIMUM_FREE_THREADED_PYTHON_MINOR)
+                    ..=MAXIMUM_FREE_THREADED_PYTHON_MINOR)
+                    .filter(|minor| {
+                        requires_python
+                            .map(|requires_python| {
+                                requires_python.contains(&Version::new([3, *minor as u64]))
+                            })
+                            .unwrap_or(true)
+                    })
+                    .map(|minor| format!("python3.{minor}t")),
+            );
             // Also try to find PyPy for cffi and pyo3 bindings
             if *bridge == BridgeModel::Cffi
                 || bridge.is_bindings("pyo3")
@@ -898,9 +934,10 @@ impl PythonInterpreter {
     /// Used to trigger rebuilds for `pyo3` when the Python interpreter changes.
     pub fn environment_signature(&self) -> String {
         let pointer_width = self.pointer_width.unwrap_or(64);
+        let free_threaded = if self.gil_disabled { "t" } else { "" };
         format!(
-            "{}-{}.{}-{}bit",
-            self.implementation_name, self.major, self.minor, pointer_width
+            "{}-{}.{}{}-{}bit",
+            self.implementation_name, self.major, self.minor, free_threaded, pointer_width
         )
     }
 
@@ -951,7 +988,7 @@ impl fmt::Display for PythonInterpreter {
 }
 
 /// Calculate the ABI tag from EXT_SUFFIX
-fn calculate_abi_tag(ext_suffix: &str) -> Option<String> {
+pub(crate) fn calculate_abi_tag(ext_suffix: &str) -> Option<String> {
     let parts = ext_suffix.split('.').collect::<Vec<_>>();
     if parts.len() < 3 {
         // CPython3.7 and earlier uses ".pyd" on Windows.
@@ -961,7 +998,7 @@ fn calculate_abi_tag(ext_suffix: &str) -> Option<String> {
     let mut soabi_split = soabi.split('-');
     let abi = if soabi.starts_with("cpython") {
         // non-windows
-        format!("cp{}", soabi_split.nth(1).unwrap())
+        format!("cp{}", soabi_split.nth(1)?)
     } else if soabi.starts_with("cp") {
         // windows
         soabi_split.next().unwrap().to_string()
@@ -971,7 +1008,7 @@ fn calculate_abi_tag(ext_suffix: &str) -> Option<String> {
         soabi_split.take(3).collect::<Vec<_>>().join("-")
     } else if !soabi.is_empty() {
         // pyston, ironpython, others?
-        soabi_split.nth(1).unwrap().to_string()
+        soabi_split.nth(1)?.to_string()
     } else {
         return None;
     };
@@ -988,19 +1025,31 @@ mod tests {
         let target =
             Target::from_target_triple(Some("x86_64-unknown-linux-gnu".to_string())).unwrap();
         let pythons = PythonInterpreter::find_by_target(&target, None);
-        assert_eq!(pythons.len(), 10);
+        assert_eq!(pythons.len(), 11);
 
         let pythons = PythonInterpreter::find_by_target(
             &target,
             Some(&VersionSpecifiers::from_str(">=3.7").unwrap()),
         );
-        assert_eq!(pythons.len(), 10);
+        assert_eq!(pythons.len(), 11);
 
         let pythons = PythonInterpreter::find_by_target(
             &target,
             Some(&VersionSpecifiers::from_str(">=3.10").unwrap()),
         );
-        assert_eq!(pythons.len(), 4);
+        assert_eq!(pythons.len(), 5);
+
+        let free_threaded: Vec<_> = pythons
+            .iter()
+            .filter(|python| python.gil_disabled)
+            .collect();
+        assert_eq!(free_threaded.len(), 1);
+        assert_eq!(free_threaded[0].to_string(), "CPython 3.13t");
+        assert!(!free_threaded[0].has_stable_api());
+        assert_eq!(
+            free_threaded[0].environment_signature(),
+            "cpython-3.13t-64bit"
+        );
     }
 
     #[test]
@@ -1008,8 +1057,10 @@ mod tests {
         let cases = vec![
             (".cpython-37m-x86_64-linux-gnu.so", Some("cp37m")),
             (".cpython-310-x86_64-linux-gnu.so", Some("cp310")),
+            (".cpython-313t-x86_64-linux-gnu.so", Some("cp313t")),
             (".cpython-310-darwin.so", Some("cp310")),
             (".cp310-win_amd64.pyd", Some("cp310")),
+            (".cp313t-win_amd64.pyd", Some("cp313t")),
             (".cp39-mingw_x86_64.pyd", Some("cp39")),
             (".cpython-312-wasm32-wasi.so", Some("cp312")),
             (".cpython-38.so", Some("cp38")),
@@ -1021,6 +1072,8 @@ mod tests {
                 Some("graalpy_38_native"),
             ),
             (".pyston-23-x86_64-linux-gnu.so", Some("23")),
+            (".abi3.so", None),
+            (".cpython.so", None),
         ];
         for (ext_suffix, expected) in cases {
             assert_eq!(calculate_abi_tag(ext_suffix).as_deref(), expected);
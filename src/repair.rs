@@ -0,0 +1,601 @@
+//! Repairs existing wheels like `auditwheel repair`, i.e. grafts the external shared libraries
+//! into the wheel and retags it with the manylinux/musllinux tag it complies with
+use crate::auditwheel::patchelf::Patchelf;
+use crate::auditwheel::{
+    elf_arch, find_excluded_libs, get_policy_and_libs, AuditPolicies, PlatformTag, Policy,
+};
+use crate::build_context::{graft_external_libs, sanitize_artifact_rpaths};
+use crate::compile::BuildArtifact;
+use crate::module_writer::{ModuleWriter, WheelWriter};
+use crate::target::{Arch, Target};
+use crate::CompressionOptions;
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use fs_err::File;
+use glob::Pattern;
+use goblin::elf::Elf;
+use ignore::overrides::Override;
+use lddtree::Library;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Repair an existing wheel for manylinux/musllinux compliance
+#[derive(Debug, clap::Parser)]
+pub struct RepairOptions {
+    /// The wheel to repair
+    #[arg(value_name = "WHEEL")]
+    pub wheel: PathBuf,
+    /// The platform tag to repair the wheel for, e.g. `manylinux_2_17`
+    ///
+    /// Defaults to the highest priority tag the wheel complies with
+    #[arg(long = "plat", value_name = "PLATFORM_TAG")]
+    pub platform_tag: Option<String>,
+    /// The directory to write the repaired wheel to
+    #[arg(short, long, default_value = "wheelhouse")]
+    pub out: PathBuf,
+    /// Additional directories to search for the external shared libraries
+    #[arg(short = 'L', long = "lib-path", value_name = "DIR")]
+    pub lib_paths: Vec<PathBuf>,
+    /// Use the external `patchelf` binary instead of the built-in ELF rewriter
+    #[arg(long)]
+    pub use_patchelf: bool,
+    /// JSON file with additional or overriding manylinux/musllinux policies, which may also define
+    /// custom platform tags
+    #[arg(long, value_name = "FILE")]
+    pub policy_file: Option<PathBuf>,
+    /// Don't bundle the shared libraries matching this glob pattern, e.g. `libcuda.so*`, and
+    /// list them as `Requires-External` instead
+    #[arg(long = "exclude-lib", value_name = "GLOB")]
+    pub exclude_libs: Vec<String>,
+}
+
+/// A file in the wheel
+struct Entry {
+    name: String,
+    bytes: Vec<u8>,
+    permissions: u32,
+}
+
+/// Unpacks the wheel, grafts the external shared libraries of all ELF files into it,
+/// and writes the retagged wheel to the output directory
+///
+/// Returns the path of the repaired wheel
+pub fn repair(options: &RepairOptions) -> Result<PathBuf> {
+    let audit_policies = match &options.policy_file {
+        Some(policy_file) => AuditPolicies::from_file(policy_file)?,
+        None => AuditPolicies::default(),
+    };
+    let exclude = options
+        .exclude_libs
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).with_context(|| format!("Invalid library glob pattern {pattern}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let wheel_path = &options.wheel;
+    let mut entries = read_wheel(wheel_path)?;
+    let dist_info_dir = entries
+        .iter()
+        .filter_map(|entry| entry.name.strip_suffix("/WHEEL"))
+        .find(|dir| dir.ends_with(".dist-info") && !dir.contains('/'))
+        .map(ToString::to_string)
+        .context("The wheel has no .dist-info/WHEEL file")?;
+    let distribution = dist_info_dir.split('-').next().unwrap().to_string();
+
+    // Unpack the ELF files with their layout in the wheel, so `$ORIGIN` rpaths still resolve
+    let temp_dir = tempfile::tempdir()?;
+    let mut elf_files = Vec::new();
+    let mut target: Option<Target> = None;
+    for (index, entry) in entries.iter().enumerate() {
+        if !entry.bytes.starts_with(b"\x7fELF") {
+            continue;
+        }
+        let elf = Elf::parse(&entry.bytes)
+            .with_context(|| format!("Failed to parse ELF file {}", entry.name))?;
+        let triple = target_triple(&elf)
+            .with_context(|| format!("Unsupported architecture of {}", entry.name))?;
+        match &target {
+            Some(target) if target.target_triple() != triple => bail!(
+                "{} is built for {}, but other files in the wheel are built for {}",
+                entry.name,
+                triple,
+                target.target_triple()
+            ),
+            Some(_) => {}
+            None => target = Some(Target::from_target_triple(Some(triple))?),
+        }
+        let path = temp_dir.path().join(&entry.name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, &entry.bytes)?;
+        elf_files.push((index, path));
+    }
+    let Some(target) = target else {
+        bail!(
+            "{} contains no ELF files, there is nothing to repair",
+            wheel_path.display()
+        );
+    };
+
+    // musl wheels are tagged musllinux_1_2 by default, like in `maturin build`
+    let platform_tag = match &options.platform_tag {
+        Some(platform_tag) => Some(audit_policies.parse_platform_tag(platform_tag)?),
+        None if target.is_musl_libc() => Some(PlatformTag::Musllinux { x: 1, y: 2 }),
+        None => None,
+    };
+    // Libraries that already ship in the wheel don't need to be grafted again
+    let bundled: HashSet<&str> = entries
+        .iter()
+        .filter(|entry| entry.bytes.starts_with(b"\x7fELF"))
+        .filter_map(|entry| entry.name.rsplit('/').next())
+        .collect();
+    let linked_paths: Vec<String> = options
+        .lib_paths
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    let mut policies: Vec<Policy> = Vec::with_capacity(elf_files.len());
+    let mut ext_libs: Vec<Vec<Library>> = Vec::with_capacity(elf_files.len());
+    let mut excluded_libs: Vec<String> = Vec::new();
+    for (index, path) in &elf_files {
+        let artifact = BuildArtifact {
+            path: path.clone(),
+            linked_paths: linked_paths.clone(),
+        };
+        // only binaries are allowed to link libpython, extension modules must not
+        let allow_linking_libpython = entries[*index].name.contains(".data/scripts/");
+        let (policy, libs) = get_policy_and_libs(
+            &artifact,
+            platform_tag.as_ref(),
+            &audit_policies,
+            &target,
+            allow_linking_libpython,
+            &exclude,
+        )
+        .with_context(|| format!("Failed to audit {}", entries[*index].name))?;
+        for lib in find_excluded_libs(path, &exclude)? {
+            if !excluded_libs.contains(&lib) {
+                excluded_libs.push(lib);
+            }
+        }
+        policies.push(policy);
+        ext_libs.push(
+            libs.into_iter()
+                .filter(|lib| !bundled.contains(lib.name.as_str()))
+                .collect(),
+        );
+    }
+    let platform_tag = match platform_tag {
+        Some(platform_tag) => platform_tag,
+        None => policies
+            .iter()
+            .min_by_key(|policy| policy.priority)
+            .unwrap()
+            .platform_tag(),
+    };
+    if platform_tag == PlatformTag::Linux {
+        bail!(
+            "{} doesn't comply with any manylinux/musllinux policy and can't be repaired",
+            wheel_path.display()
+        );
+    }
+
+    let arch = target.get_platform_arch()?;
+    let mut platforms = vec![format!("{platform_tag}_{arch}")];
+    for alias in platform_tag.aliases(&audit_policies) {
+        platforms.push(format!("{alias}_{arch}"));
+    }
+    let file_name = retag_file_name(wheel_path, &platforms.join("."))?;
+    fs::create_dir_all(&options.out)?;
+    let out_path = options.out.join(file_name);
+    eprintln!(
+        "🩹 Repairing {} for {}",
+        wheel_path.display(),
+        platforms.join(", ")
+    );
+
+    let mut writer = WheelWriter::create(
+        out_path,
+        Path::new(&dist_info_dir),
+        Override::empty(),
+        false,
+        CompressionOptions::default(),
+    )?;
+    let patchelf = if options.use_patchelf {
+        Patchelf::External
+    } else {
+        Patchelf::Builtin
+    };
+    let artifacts: Vec<(&Path, &Path)> = elf_files
+        .iter()
+        .map(|(index, path)| {
+            let artifact_dir = Path::new(&entries[*index].name).parent().unwrap();
+            (path.as_path(), artifact_dir)
+        })
+        .collect();
+    if ext_libs.iter().any(|libs| !libs.is_empty()) {
+        let libs_dir = PathBuf::from(format!("{distribution}.libs"));
+        graft_external_libs(&mut writer, patchelf, &libs_dir, &artifacts, &ext_libs)?;
+    } else {
+        sanitize_artifact_rpaths(patchelf, &artifacts)?;
+    }
+    for (index, path) in &elf_files {
+        entries[*index].bytes = fs::read(path)?;
+    }
+
+    let wheel_file = format!("{dist_info_dir}/WHEEL");
+    let metadata_file = format!("{dist_info_dir}/METADATA");
+    let record_files = [
+        format!("{dist_info_dir}/RECORD"),
+        format!("{dist_info_dir}/RECORD.jws"),
+        format!("{dist_info_dir}/RECORD.p7s"),
+    ];
+    for entry in &entries {
+        if record_files.contains(&entry.name) {
+            continue;
+        }
+        if entry.name == wheel_file {
+            let content = String::from_utf8(entry.bytes.clone())
+                .with_context(|| format!("{wheel_file} is not valid utf-8"))?;
+            let content = retag_wheel_file(&content, &platforms);
+            writer.add_bytes_with_permissions(
+                &entry.name,
+                content.as_bytes(),
+                entry.permissions,
+            )?;
+        } else if entry.name == metadata_file && !excluded_libs.is_empty() {
+            let content = String::from_utf8(entry.bytes.clone())
+                .with_context(|| format!("{metadata_file} is not valid utf-8"))?;
+            let content = add_requires_external(&content, &excluded_libs);
+            writer.add_bytes_with_permissions(
+                &entry.name,
+                content.as_bytes(),
+                entry.permissions,
+            )?;
+        } else {
+            writer.add_bytes_with_permissions(&entry.name, &entry.bytes, entry.permissions)?;
+        }
+    }
+    let out_path = writer.finish()?;
+    eprintln!("📦 Repaired wheel written to {}", out_path.display());
+    Ok(out_path)
+}
+
+/// Reads all files of a wheel into memory
+fn read_wheel(path: &Path) -> Result<Vec<Entry>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .with_context(|| format!("Failed to open {} as zip archive", path.display()))?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        entries.push(Entry {
+            name: entry.name().to_string(),
+            bytes,
+            permissions: entry.unix_mode().map_or(0o644, |mode| mode & 0o777),
+        });
+    }
+    Ok(entries)
+}
+
+/// The rust target triple matching the architecture and libc of an ELF file
+fn target_triple(elf: &Elf) -> Option<String> {
+    let is_musl = elf
+        .libraries
+        .iter()
+        .any(|lib| lib.starts_with("libc.musl-"));
+    let env = if is_musl { "musl" } else { "gnu" };
+    let triple = match elf_arch(elf)? {
+        Arch::X86_64 => format!("x86_64-unknown-linux-{env}"),
+        Arch::X86 => format!("i686-unknown-linux-{env}"),
+        Arch::Aarch64 => format!("aarch64-unknown-linux-{env}"),
+        Arch::Armv7L => format!("armv7-unknown-linux-{env}eabihf"),
+        Arch::Powerpc64Le => format!("powerpc64le-unknown-linux-{env}"),
+        Arch::Powerpc64 => format!("powerpc64-unknown-linux-{env}"),
+        Arch::S390X => format!("s390x-unknown-linux-{env}"),
+        _ => return None,
+    };
+    Some(triple)
+}
+
+/// Replaces the platform tag in a wheel file name
+/// (`{distribution}-{version}(-{build tag})?-{python tag}-{abi tag}-{platform tag}.whl`)
+fn retag_file_name(wheel_path: &Path, platform: &str) -> Result<String> {
+    let file_name = wheel_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".whl"))
+        .with_context(|| format!("{} is not a wheel", wheel_path.display()))?;
+    let parts: Vec<&str> = file_name.rsplitn(4, '-').collect();
+    let [_, abi, python, prefix] = parts[..] else {
+        bail!("Invalid wheel file name {file_name}.whl");
+    };
+    Ok(format!("{prefix}-{python}-{abi}-{platform}.whl"))
+}
+
+/// Replaces the `Tag` entries of a WHEEL file with one per python and abi tag and platform
+fn retag_wheel_file(content: &str, platforms: &[String]) -> String {
+    let mut output = String::new();
+    let mut tagged = HashSet::new();
+    for line in content.lines() {
+        let tag = line
+            .strip_prefix("Tag:")
+            .and_then(|tag| tag.trim().rsplit_once('-'));
+        let Some((python_abi, _)) = tag else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+        if tagged.insert(python_abi.to_string()) {
+            for platform in platforms {
+                output.push_str(&format!("Tag: {python_abi}-{platform}\n"));
+            }
+        }
+    }
+    output
+}
+
+/// Adds a `Requires-External` entry for each library to the headers of a METADATA file,
+/// unless it's already listed
+fn add_requires_external(content: &str, libs: &[String]) -> String {
+    let (headers, body) = match content.find("\n\n") {
+        Some(pos) => content.split_at(pos + 1),
+        None => (content, ""),
+    };
+    let mut output = headers.to_string();
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    for lib in libs {
+        let existing = headers.lines().any(|line| {
+            line.strip_prefix("Requires-External:")
+                .is_some_and(|value| value.trim() == lib)
+        });
+        if !existing {
+            output.push_str(&format!("Requires-External: {lib}\n"));
+        }
+    }
+    output.push_str(body);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Inspection;
+
+    #[test]
+    fn test_retag_file_name() {
+        assert_eq!(
+            retag_file_name(
+                Path::new("dist/foo_bar-1.0.0-cp38-abi3-linux_x86_64.whl"),
+                "manylinux_2_17_x86_64.manylinux2014_x86_64"
+            )
+            .unwrap(),
+            "foo_bar-1.0.0-cp38-abi3-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+        );
+        assert_eq!(
+            retag_file_name(
+                Path::new("foo-1.0-1-py3-none-linux_aarch64.whl"),
+                "musllinux_1_2_aarch64"
+            )
+            .unwrap(),
+            "foo-1.0-1-py3-none-musllinux_1_2_aarch64.whl"
+        );
+        assert!(retag_file_name(Path::new("foo-1.0.tar.gz"), "linux_x86_64").is_err());
+    }
+
+    #[test]
+    fn test_retag_wheel_file() {
+        let content = "Wheel-Version: 1.0\n\
+                       Generator: maturin (1.5.1)\n\
+                       Root-Is-Purelib: false\n\
+                       Tag: cp38-abi3-linux_x86_64\n";
+        let platforms = [
+            "manylinux_2_17_x86_64".to_string(),
+            "manylinux2014_x86_64".to_string(),
+        ];
+        assert_eq!(
+            retag_wheel_file(content, &platforms),
+            "Wheel-Version: 1.0\n\
+             Generator: maturin (1.5.1)\n\
+             Root-Is-Purelib: false\n\
+             Tag: cp38-abi3-manylinux_2_17_x86_64\n\
+             Tag: cp38-abi3-manylinux2014_x86_64\n"
+        );
+    }
+
+    #[test]
+    fn test_add_requires_external() {
+        let content = "Metadata-Version: 2.1\n\
+                       Name: foo\n\
+                       Requires-External: libcuda.so.1\n\
+                       \n\
+                       # foo\n";
+        assert_eq!(
+            add_requires_external(
+                content,
+                &["libcuda.so.1".to_string(), "libnvidia-ml.so.1".to_string()]
+            ),
+            "Metadata-Version: 2.1\n\
+             Name: foo\n\
+             Requires-External: libcuda.so.1\n\
+             Requires-External: libnvidia-ml.so.1\n\
+             \n\
+             # foo\n"
+        );
+        assert_eq!(
+            add_requires_external("Name: foo", &["libcuda.so.1".to_string()]),
+            "Name: foo\nRequires-External: libcuda.so.1\n"
+        );
+    }
+
+    /// Writes a wheel with `test-data/elf/libfoo.so.1`, which links `libbar.so.1`, as extension
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn write_test_wheel(dir: &Path) -> PathBuf {
+        let wheel_path = dir.join("foo-1.0.0-cp38-abi3-linux_x86_64.whl");
+        let mut writer = WheelWriter::create(
+            wheel_path.clone(),
+            Path::new("foo-1.0.0.dist-info"),
+            Override::empty(),
+            false,
+            CompressionOptions::default(),
+        )
+        .unwrap();
+        writer
+            .add_bytes(
+                "foo-1.0.0.dist-info/WHEEL",
+                b"Wheel-Version: 1.0\nRoot-Is-Purelib: false\nTag: cp38-abi3-linux_x86_64\n",
+            )
+            .unwrap();
+        writer
+            .add_bytes(
+                "foo-1.0.0.dist-info/METADATA",
+                b"Metadata-Version: 2.1\nName: foo\nVersion: 1.0.0\n",
+            )
+            .unwrap();
+        writer
+            .add_file_with_permissions("foo/foo.so", "test-data/elf/libfoo.so.1", 0o755)
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_repair() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let wheel_path = write_test_wheel(temp_dir.path());
+        let repaired = repair(&RepairOptions {
+            wheel: wheel_path,
+            platform_tag: None,
+            out: temp_dir.path().join("wheelhouse"),
+            lib_paths: vec![PathBuf::from("test-data/elf")],
+            use_patchelf: false,
+            policy_file: None,
+            exclude_libs: Vec::new(),
+        })
+        .unwrap();
+        let file_name = repaired.file_name().unwrap().to_str().unwrap();
+        assert!(
+            file_name.starts_with("foo-1.0.0-cp38-abi3-manylinux_"),
+            "{file_name}"
+        );
+
+        let inspection = Inspection::new(&repaired).unwrap();
+        assert!(inspection.record_is_valid());
+        let grafted = inspection
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .find(|path| path.starts_with("foo.libs/libbar-") && path.ends_with(".so.1"))
+            .expect("libbar wasn't grafted");
+        let foo = inspection
+            .native_libraries
+            .iter()
+            .find(|library| library.path == "foo/foo.so")
+            .unwrap();
+        assert_eq!(
+            foo.dependencies,
+            vec![grafted.strip_prefix("foo.libs/").unwrap().to_string()]
+        );
+        assert!(inspection.wheel.unwrap().tags[0].starts_with("cp38-abi3-manylinux_"));
+
+        let entries = read_wheel(&repaired).unwrap();
+        let foo = entries
+            .iter()
+            .find(|entry| entry.name == "foo/foo.so")
+            .unwrap();
+        let elf = Elf::parse(&foo.bytes).unwrap();
+        assert_eq!(elf.rpaths, ["$ORIGIN:$ORIGIN/../foo.libs"]);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_repair_exclude_lib() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let wheel_path = write_test_wheel(temp_dir.path());
+        let repaired = repair(&RepairOptions {
+            wheel: wheel_path,
+            platform_tag: None,
+            out: temp_dir.path().join("wheelhouse"),
+            lib_paths: vec![PathBuf::from("test-data/elf")],
+            use_patchelf: false,
+            policy_file: None,
+            exclude_libs: vec!["libbar.so*".to_string()],
+        })
+        .unwrap();
+
+        let inspection = Inspection::new(&repaired).unwrap();
+        assert!(inspection.record_is_valid());
+        assert!(inspection
+            .files
+            .iter()
+            .all(|file| !file.path.starts_with("foo.libs/")));
+        let foo = inspection
+            .native_libraries
+            .iter()
+            .find(|library| library.path == "foo/foo.so")
+            .unwrap();
+        assert!(foo.dependencies.contains(&"libbar.so.1".to_string()));
+        let metadata = inspection.metadata.unwrap();
+        assert_eq!(metadata.requires_external, vec!["libbar.so.1"]);
+        assert!(inspection.wheel.unwrap().tags[0].starts_with("cp38-abi3-manylinux_"));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_repair_policy_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let wheel_path = write_test_wheel(temp_dir.path());
+        // A distro baseline that ships libbar, so it doesn't need to be grafted
+        let policy_file = temp_dir.path().join("policy.json");
+        fs::write(
+            &policy_file,
+            r#"[{"name": "acme_el8", "aliases": ["acme8"], "priority": 75,
+                "symbol_versions": {"x86_64": {"GLIBC": ["2.2.5"]}},
+                "lib_whitelist": ["libc.so.6", "libbar.so.1"], "blacklist": {}}]"#,
+        )
+        .unwrap();
+        let options = RepairOptions {
+            wheel: wheel_path,
+            platform_tag: Some("acme_el8".to_string()),
+            out: temp_dir.path().join("wheelhouse"),
+            lib_paths: vec![PathBuf::from("test-data/elf")],
+            use_patchelf: false,
+            policy_file: None,
+            exclude_libs: Vec::new(),
+        };
+
+        // Custom platform tags only exist in the policy file
+        let err = repair(&options).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("custom platform tags must be defined in an audit policy file"),
+            "{err:?}"
+        );
+
+        let repaired = repair(&RepairOptions {
+            policy_file: Some(policy_file),
+            ..options
+        })
+        .unwrap();
+        assert_eq!(
+            repaired.file_name().unwrap(),
+            "foo-1.0.0-cp38-abi3-acme_el8_x86_64.acme8_x86_64.whl"
+        );
+        let inspection = Inspection::new(&repaired).unwrap();
+        assert!(inspection.record_is_valid());
+        assert!(inspection
+            .files
+            .iter()
+            .all(|file| !file.path.starts_with("foo.libs/")));
+        assert_eq!(
+            inspection.wheel.unwrap().tags,
+            ["cp38-abi3-acme_el8_x86_64", "cp38-abi3-acme8_x86_64"]
+        );
+    }
+}
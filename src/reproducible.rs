@@ -0,0 +1,96 @@
+//! Checks that builds are byte-for-byte reproducible, see `maturin verify-reproducible`
+use crate::{BuildContext, Inspection, WheelDiff};
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Builds the wheels, and optionally the source distribution, twice and checks that both
+/// builds are byte-for-byte identical
+///
+/// The first build goes to the usual output directory, the second one is built from scratch
+/// in a temporary target directory and discarded afterwards
+pub fn verify_reproducible(build_context: &BuildContext, sdist: bool) -> Result<()> {
+    let mut build_context = build_context.clone();
+    build_context.reproducible = true;
+    // A cached wheel would trivially match
+    #[cfg(feature = "build-cache")]
+    {
+        build_context.build_cache = None;
+    }
+
+    eprintln!("🔁 Building for the first time");
+    let first = build(&build_context, sdist)?;
+
+    let second_out = TempDir::new()?;
+    let second_target_dir = TempDir::new()?;
+    let mut second_context = build_context.clone();
+    second_context.out = second_out.path().to_path_buf();
+    second_context.target_dir = second_target_dir.path().to_path_buf();
+    second_context.cargo_options.target_dir = Some(second_target_dir.path().to_path_buf());
+    eprintln!("🔁 Building for the second time");
+    let second = build(&second_context, sdist)?;
+
+    let mut mismatches = 0;
+    for first_path in &first {
+        let file_name = first_path.file_name().unwrap();
+        let Some(second_path) = second
+            .iter()
+            .find(|path| path.file_name() == Some(file_name))
+        else {
+            eprintln!(
+                "❌ {} was not built the second time",
+                file_name.to_string_lossy()
+            );
+            mismatches += 1;
+            continue;
+        };
+        if fs::read(first_path)? == fs::read(second_path)? {
+            eprintln!("✅ {} is reproducible", file_name.to_string_lossy());
+        } else {
+            eprintln!("❌ {} differs between builds", file_name.to_string_lossy());
+            print_differences(first_path, second_path)?;
+            mismatches += 1;
+        }
+    }
+    for second_path in &second {
+        if !first
+            .iter()
+            .any(|path| path.file_name() == second_path.file_name())
+        {
+            eprintln!(
+                "❌ {} was only built the second time",
+                second_path.file_name().unwrap().to_string_lossy()
+            );
+            mismatches += 1;
+        }
+    }
+
+    if mismatches > 0 {
+        bail!("{mismatches} of {} files are not reproducible", first.len());
+    }
+    Ok(())
+}
+
+fn build(build_context: &BuildContext, sdist: bool) -> Result<Vec<PathBuf>> {
+    let mut built = build_context.build_wheels()?;
+    if sdist {
+        built.push(
+            build_context
+                .build_source_distribution()?
+                .context("Failed to build source distribution, pyproject.toml not found")?,
+        );
+    }
+    Ok(built.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Explains a mismatch, falling back to the raw difference if the contents are identical
+fn print_differences(first: &Path, second: &Path) -> Result<()> {
+    let diff = WheelDiff::from_inspections(&Inspection::new(first)?, &Inspection::new(second)?)?;
+    if diff.is_empty() {
+        eprintln!("    The contents are identical, the archive metadata differs");
+    } else {
+        diff.print();
+    }
+    Ok(())
+}
@@ -0,0 +1,491 @@
+//! Software bill of materials for the wheels, written to `.dist-info/sboms/` per PEP 770
+use crate::build_context::hash_file;
+use crate::module_writer::source_date_epoch;
+use crate::GraftedLibrary;
+use anyhow::{bail, Context, Result};
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
+use cargo_platform::{Cfg, Platform};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::process::Command;
+use time::OffsetDateTime;
+
+/// The format of a software bill of materials
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON, written to `.dist-info/sboms/maturin.cdx.json`
+    #[serde(rename = "cyclonedx")]
+    #[value(name = "cyclonedx")]
+    CycloneDx,
+    /// SPDX 2.3 JSON, written to `.dist-info/sboms/maturin.spdx.json`
+    #[serde(rename = "spdx")]
+    #[value(name = "spdx")]
+    Spdx,
+}
+
+impl SbomFormat {
+    /// The file name inside `.dist-info/sboms/`
+    pub fn file_name(self) -> &'static str {
+        match self {
+            SbomFormat::CycloneDx => "maturin.cdx.json",
+            SbomFormat::Spdx => "maturin.spdx.json",
+        }
+    }
+}
+
+/// The creation time of the documents in RFC 3339 format, `SOURCE_DATE_EPOCH` if set
+pub(crate) fn sbom_creation_time(reproducible: bool) -> Result<String> {
+    let created = match source_date_epoch()? {
+        Some(epoch) => OffsetDateTime::from_unix_timestamp(epoch)?,
+        None if reproducible => OffsetDateTime::UNIX_EPOCH,
+        None => OffsetDateTime::now_utc(),
+    };
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        created.year(),
+        u8::from(created.month()),
+        created.day(),
+        created.hour(),
+        created.minute(),
+        created.second()
+    ))
+}
+
+/// A rust crate or shared library shipped in a wheel
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Component {
+    /// Unique within the document, the purl for crates
+    bom_ref: String,
+    name: String,
+    version: Option<String>,
+    purl: Option<String>,
+    /// SPDX license expression
+    license: Option<String>,
+    /// The resolved cargo features
+    features: Vec<String>,
+    /// Hex encoded sha256, only for shared libraries
+    sha256: Option<String>,
+    /// The bom refs of the direct dependencies
+    depends_on: Vec<String>,
+}
+
+/// The rust crates compiled into the native libraries of a wheel and the shared libraries
+/// copied into it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbom {
+    name: String,
+    version: String,
+    /// The bom refs of the root crates and the grafted libraries
+    roots: Vec<String>,
+    components: Vec<Component>,
+}
+
+fn crate_purl(package: &Package) -> String {
+    format!("pkg:cargo/{}@{}", package.name, package.version)
+}
+
+/// The cfg values of the target, to skip dependencies that are specific to other platforms
+pub(crate) struct TargetCfg {
+    triple: String,
+    cfgs: Vec<Cfg>,
+}
+
+impl TargetCfg {
+    /// Queries `rustc --print cfg` for the target, with the rustc and rustflags cargo would use
+    /// for the project in `manifest_dir`
+    pub(crate) fn new(manifest_dir: &Path, target_triple: &str) -> Result<Self> {
+        let cargo_config = cargo_config2::Config::load_with_cwd(manifest_dir)?;
+        let rustc = cargo_config.rustc();
+        let rustflags = cargo_config
+            .rustflags(target_triple)?
+            .unwrap_or_default()
+            .flags;
+        let output = Command::new(&rustc.path)
+            .args(&rustc.args)
+            .args(["--print", "cfg", "--target", target_triple])
+            .args(rustflags)
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to run {} to get the target cfg",
+                    rustc.path.display()
+                )
+            })?;
+        if !output.status.success() {
+            bail!(
+                "{} --print cfg --target {} failed: {}",
+                rustc.path.display(),
+                target_triple,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let stdout = String::from_utf8(output.stdout).context("rustc output is not valid utf-8")?;
+        let cfgs = stdout
+            .lines()
+            .map(|line| line.parse())
+            .collect::<Result<_, _>>()
+            .context("Failed to parse the output of rustc --print cfg")?;
+        Ok(Self {
+            triple: target_triple.to_string(),
+            cfgs,
+        })
+    }
+
+    fn matches(&self, platform: Option<&Platform>) -> bool {
+        platform.map_or(true, |platform| platform.matches(&self.triple, &self.cfgs))
+    }
+}
+
+/// A crate that is compiled into a native library
+pub(crate) struct LinkedCrate<'a> {
+    pub package: &'a Package,
+    /// The resolved cargo features
+    pub features: &'a [String],
+    /// The linked crates it directly depends on
+    pub dependencies: Vec<&'a Package>,
+}
+
+/// Collects the crates reachable from the workspace member `crate_name` through normal
+/// dependencies, starting with the crate itself
+///
+/// Proc macros and build dependencies only run at build time, so they are left out, as are
+/// dependencies for other targets
+pub(crate) fn linked_crates<'a>(
+    metadata: &'a Metadata,
+    crate_name: &str,
+    target_cfg: &TargetCfg,
+) -> Result<Vec<LinkedCrate<'a>>> {
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .context("Expected cargo to resolve dependencies")?;
+    let packages: HashMap<&PackageId, &Package> = metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package))
+        .collect();
+    let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let root = metadata
+        .packages
+        .iter()
+        .find(|package| {
+            package.name == crate_name && metadata.workspace_members.contains(&package.id)
+        })
+        .with_context(|| format!("Couldn't find the crate {crate_name} in cargo metadata"))?;
+
+    let mut linked = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([&root.id]);
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let package = packages[id];
+        let node = nodes
+            .get(id)
+            .with_context(|| format!("Crate {} is missing in cargo metadata", package.name))?;
+        let mut dependencies = Vec::new();
+        for dep in &node.deps {
+            let is_normal = dep.dep_kinds.iter().any(|kind| {
+                kind.kind == DependencyKind::Normal && target_cfg.matches(kind.target.as_ref())
+            });
+            let dep_package = packages[&dep.pkg];
+            let is_proc_macro = dep_package
+                .targets
+                .iter()
+                .any(|target| target.kind.iter().any(|kind| kind == "proc-macro"));
+            if is_normal && !is_proc_macro {
+                dependencies.push(dep_package);
+                queue.push_back(&dep.pkg);
+            }
+        }
+        linked.push(LinkedCrate {
+            package,
+            features: &node.features,
+            dependencies,
+        });
+    }
+    Ok(linked)
+}
+
+impl Sbom {
+    /// Collects the crates linked into the native libraries built from the `root_crates`
+    pub fn new(
+        name: &str,
+        version: &str,
+        root_crates: &[(&Metadata, &str)],
+        target_cfg: &TargetCfg,
+        grafted: &[GraftedLibrary],
+    ) -> Result<Self> {
+        let mut roots = Vec::new();
+        let mut components: Vec<Component> = Vec::new();
+        let mut seen = HashSet::new();
+        for (metadata, crate_name) in root_crates {
+            let linked = linked_crates(metadata, crate_name, target_cfg)?;
+            roots.push(crate_purl(linked[0].package));
+            for linked_crate in linked {
+                let package = linked_crate.package;
+                if !seen.insert(crate_purl(package)) {
+                    continue;
+                }
+                components.push(Component {
+                    bom_ref: crate_purl(package),
+                    name: package.name.clone(),
+                    version: Some(package.version.to_string()),
+                    purl: Some(crate_purl(package)),
+                    license: package.license.clone(),
+                    features: linked_crate.features.to_vec(),
+                    sha256: None,
+                    depends_on: linked_crate
+                        .dependencies
+                        .into_iter()
+                        .map(crate_purl)
+                        .collect(),
+                });
+            }
+        }
+        for library in grafted {
+            let bom_ref = format!("lib:{}", library.grafted_name);
+            roots.push(bom_ref.clone());
+            components.push(Component {
+                bom_ref,
+                name: library.name.clone(),
+                version: None,
+                purl: None,
+                license: None,
+                features: Vec::new(),
+                sha256: Some(hash_file(&library.path)?),
+                depends_on: Vec::new(),
+            });
+        }
+        components.sort_by(|a, b| a.bom_ref.cmp(&b.bom_ref));
+        Ok(Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            roots,
+            components,
+        })
+    }
+
+    /// Renders the document in the given format
+    ///
+    /// `created` is the RFC 3339 creation time, which is only part of SPDX documents
+    pub fn render(&self, format: SbomFormat, created: &str) -> String {
+        let document = match format {
+            SbomFormat::CycloneDx => self.cyclonedx(),
+            SbomFormat::Spdx => self.spdx(created),
+        };
+        serde_json::to_string_pretty(&document).unwrap()
+    }
+
+    fn pypi_purl(&self) -> String {
+        // purls use the normalized name, see PEP 503
+        let name = self.name.to_lowercase().replace(['_', '.'], "-");
+        format!("pkg:pypi/{}@{}", name, self.version)
+    }
+
+    fn cyclonedx(&self) -> Value {
+        let components: Vec<_> = self
+            .components
+            .iter()
+            .map(|component| {
+                let mut value = json!({
+                    "type": "library",
+                    "bom-ref": component.bom_ref,
+                    "name": component.name,
+                });
+                if let Some(version) = &component.version {
+                    value["version"] = json!(version);
+                }
+                if let Some(purl) = &component.purl {
+                    value["purl"] = json!(purl);
+                }
+                if let Some(license) = &component.license {
+                    value["licenses"] = json!([{ "expression": license }]);
+                }
+                if let Some(sha256) = &component.sha256 {
+                    value["hashes"] = json!([{ "alg": "SHA-256", "content": sha256 }]);
+                }
+                if !component.features.is_empty() {
+                    let properties: Vec<_> = component
+                        .features
+                        .iter()
+                        .map(|feature| json!({ "name": "cargo:feature", "value": feature }))
+                        .collect();
+                    value["properties"] = json!(properties);
+                }
+                value
+            })
+            .collect();
+        let mut dependencies = vec![json!({
+            "ref": self.pypi_purl(),
+            "dependsOn": self.roots,
+        })];
+        dependencies.extend(self.components.iter().map(|component| {
+            json!({
+                "ref": component.bom_ref,
+                "dependsOn": component.depends_on,
+            })
+        }));
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+                "component": {
+                    "type": "library",
+                    "bom-ref": self.pypi_purl(),
+                    "name": self.name,
+                    "version": self.version,
+                    "purl": self.pypi_purl(),
+                },
+            },
+            "components": components,
+            "dependencies": dependencies,
+        })
+    }
+
+    fn spdx(&self, created: &str) -> Value {
+        // SPDX ids may only contain letters, numbers, `.` and `-`
+        let spdx_id = |bom_ref: &str| {
+            let id: String = bom_ref
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect();
+            format!("SPDXRef-{id}")
+        };
+        let package_id = spdx_id(&self.pypi_purl());
+        let mut packages = vec![json!({
+            "name": self.name,
+            "SPDXID": package_id,
+            "versionInfo": self.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": self.pypi_purl(),
+            }],
+        })];
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": package_id,
+        })];
+        for root in &self.roots {
+            relationships.push(json!({
+                "spdxElementId": package_id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id(root),
+            }));
+        }
+        for component in &self.components {
+            let mut package = json!({
+                "name": component.name,
+                "SPDXID": spdx_id(&component.bom_ref),
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+            });
+            if let Some(version) = &component.version {
+                package["versionInfo"] = json!(version);
+            }
+            if let Some(purl) = &component.purl {
+                package["externalRefs"] = json!([{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl,
+                }]);
+            }
+            if let Some(sha256) = &component.sha256 {
+                package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]);
+            }
+            packages.push(package);
+            for dependency in &component.depends_on {
+                relationships.push(json!({
+                    "spdxElementId": spdx_id(&component.bom_ref),
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": spdx_id(dependency),
+                }));
+            }
+        }
+        let name = format!("{}-{}", self.name, self.version);
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": name,
+            "documentNamespace": format!("https://spdx.org/spdxdocs/{name}"),
+            "creationInfo": {
+                "created": created,
+                "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Target;
+    use cargo_metadata::MetadataCommand;
+
+    #[test]
+    fn test_sbom() {
+        let metadata = MetadataCommand::new().exec().unwrap();
+        let version = env!("CARGO_PKG_VERSION");
+        let target = Target::from_target_triple(None).unwrap();
+        let target_cfg = TargetCfg::new(Path::new("."), target.target_triple()).unwrap();
+        let sbom = Sbom::new(
+            "maturin",
+            version,
+            &[(&metadata, "maturin")],
+            &target_cfg,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(sbom.roots, [format!("pkg:cargo/maturin@{version}")]);
+        let names: HashSet<_> = sbom.components.iter().map(|c| c.name.as_str()).collect();
+        assert!(names.contains("serde"));
+        assert!(names.contains("anyhow"));
+        // Proc macros and dev dependencies don't end up in the binary
+        assert!(!names.contains("serde_derive"));
+        assert!(!names.contains("expect-test"));
+        if !cfg!(windows) {
+            assert!(!names.contains("windows-sys"));
+        }
+
+        let cyclonedx: Value =
+            serde_json::from_str(&sbom.render(SbomFormat::CycloneDx, "")).unwrap();
+        assert_eq!(cyclonedx["bomFormat"], "CycloneDX");
+        assert_eq!(
+            cyclonedx["metadata"]["component"]["purl"],
+            format!("pkg:pypi/maturin@{version}")
+        );
+        let spdx: Value =
+            serde_json::from_str(&sbom.render(SbomFormat::Spdx, "1980-01-01T00:00:00Z")).unwrap();
+        assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+        assert_eq!(
+            spdx["packages"].as_array().unwrap().len(),
+            sbom.components.len() + 1
+        );
+    }
+}
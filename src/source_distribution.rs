@@ -1,5 +1,6 @@
+use crate::metadata::pep440_to_semver;
 use crate::module_writer::{add_data, ModuleWriter};
-use crate::pyproject_toml::SdistGenerator;
+use crate::pyproject_toml::{SdistGenerator, VersionSource};
 use crate::{pyproject_toml::Format, BuildContext, PyProjectToml, SDistWriter};
 use anyhow::{bail, Context, Result};
 use cargo_metadata::{Metadata, MetadataCommand, PackageId};
@@ -44,14 +45,28 @@ fn parse_toml_file(path: &Path, kind: &str) -> Result<toml_edit::Document> {
 ///
 /// We only want to add path dependencies that are actually used
 /// to reduce the size of the source distribution.
+///
+/// With `version-source = "git"`, the package version is replaced by the version from git so
+/// building from the source distribution doesn't need git.
 fn rewrite_cargo_toml(
     manifest_path: impl AsRef<Path>,
     known_path_deps: &HashMap<String, PathDependency>,
+    package_version: Option<&semver::Version>,
 ) -> Result<String> {
     let manifest_path = manifest_path.as_ref();
     debug!("Rewriting Cargo.toml at {}", manifest_path.display());
     let mut document = parse_toml_file(manifest_path, "Cargo.toml")?;
 
+    if let Some(package_version) = package_version {
+        let package = document
+            .get_mut("package")
+            .and_then(|x| x.as_table_like_mut())
+            .with_context(|| {
+                format!("`[package]` must be a table in {}", manifest_path.display())
+            })?;
+        package.insert("version", toml_edit::value(package_version.to_string()));
+    }
+
     // Update workspace members
     if let Some(workspace) = document.get_mut("workspace").and_then(|x| x.as_table_mut()) {
         if let Some(members) = workspace.get_mut("members").and_then(|x| x.as_array()) {
@@ -103,11 +118,39 @@ fn rewrite_cargo_toml(
     Ok(document.to_string())
 }
 
+/// Replace the version of the root crate in Cargo.lock for `version-source = "git"`, so the
+/// lock file stays in sync with the rewritten Cargo.toml
+fn rewrite_cargo_lock(
+    cargo_lock_path: &Path,
+    package: &cargo_metadata::Package,
+    package_version: &semver::Version,
+) -> Result<String> {
+    let mut document = parse_toml_file(cargo_lock_path, "Cargo.lock")?;
+    if let Some(packages) = document
+        .get_mut("package")
+        .and_then(|x| x.as_array_of_tables_mut())
+    {
+        for entry in packages.iter_mut() {
+            // Path dependencies, including the root crate, don't have a source
+            if entry.get("name").and_then(|x| x.as_str()) == Some(&package.name)
+                && entry.get("version").and_then(|x| x.as_str())
+                    == Some(&package.version.to_string())
+                && !entry.contains_key("source")
+            {
+                entry.insert("version", toml_edit::value(package_version.to_string()));
+            }
+        }
+    }
+    Ok(document.to_string())
+}
+
 /// When `pyproject.toml` is inside the Cargo workspace root,
-/// we need to update `tool.maturin.manifest-path` in `pyproject.toml`.
+/// we need to update `tool.maturin.manifest-path` in `pyproject.toml`,
+/// as well as the `manifest-path` of the additional extension modules.
 fn rewrite_pyproject_toml(
     pyproject_toml_path: &Path,
     relative_manifest_path: &Path,
+    relative_extension_manifest_paths: &HashMap<String, PathBuf>,
 ) -> Result<String> {
     let mut data = parse_toml_file(pyproject_toml_path, "pyproject.toml")?;
     let tool = data
@@ -137,6 +180,24 @@ fn rewrite_pyproject_toml(
         toml_edit::value(relative_manifest_path.to_str().unwrap()),
     );
 
+    if let Some(extension_modules) = maturin
+        .get_mut("extension-modules")
+        .and_then(|x| x.as_array_of_tables_mut())
+    {
+        for extension_module in extension_modules.iter_mut() {
+            let relative_manifest_path = extension_module
+                .get("module-name")
+                .and_then(|x| x.as_str())
+                .and_then(|module_name| relative_extension_manifest_paths.get(module_name));
+            if let Some(relative_manifest_path) = relative_manifest_path {
+                extension_module.insert(
+                    "manifest-path",
+                    toml_edit::value(relative_manifest_path.to_str().unwrap()),
+                );
+            }
+        }
+    }
+
     Ok(data.to_string())
 }
 
@@ -151,6 +212,7 @@ fn add_crate_to_source_distribution(
     known_path_deps: &HashMap<String, PathDependency>,
     root_crate: bool,
     skip_cargo_toml: bool,
+    package_version: Option<&semver::Version>,
 ) -> Result<()> {
     let manifest_path = manifest_path.as_ref();
     let output = Command::new("cargo")
@@ -198,6 +260,9 @@ fn add_crate_to_source_distribution(
                 // pyproject.toml is handled separately because it has be to put in the root dir
                 // of source distribution
                 false
+            } else if package_version.is_some() && target == Path::new("Cargo.lock") {
+                // Cargo.lock is rewritten with the version from git and added separately
+                false
             } else if matches!(target.extension(), Some(ext) if ext == "pyc" || ext == "pyd" || ext == "so") {
                 // Technically, `cargo package --list` should handle this,
                 // but somehow it doesn't on Alpine Linux running in GitHub Actions,
@@ -217,7 +282,8 @@ fn add_crate_to_source_distribution(
     let cargo_toml_path = prefix.join(manifest_path.file_name().unwrap());
 
     if root_crate {
-        let rewritten_cargo_toml = rewrite_cargo_toml(manifest_path, known_path_deps)?;
+        let rewritten_cargo_toml =
+            rewrite_cargo_toml(manifest_path, known_path_deps, package_version)?;
         writer.add_bytes(cargo_toml_path, rewritten_cargo_toml.as_bytes())?;
     } else if !skip_cargo_toml {
         writer.add_file(cargo_toml_path, manifest_path)?;
@@ -369,12 +435,43 @@ fn add_cargo_package_files_to_sdist(
     pyproject_toml_path: &Path,
     writer: &mut SDistWriter,
     root_dir: &Path,
+    package_version: Option<&semver::Version>,
 ) -> Result<()> {
     let manifest_path = &build_context.manifest_path;
     let workspace_root = &build_context.cargo_metadata.workspace_root;
     let workspace_manifest_path = workspace_root.join("Cargo.toml");
 
-    let known_path_deps = find_path_deps(&build_context.cargo_metadata)?;
+    let mut known_path_deps = find_path_deps(&build_context.cargo_metadata)?;
+    // The crates of additional extension modules are packaged just like path dependencies
+    for extension_module in &build_context.extension_modules {
+        for (name, path_dep) in find_path_deps(&extension_module.cargo_metadata)? {
+            known_path_deps.entry(name).or_insert(path_dep);
+        }
+        let module_workspace_root = extension_module.cargo_metadata.workspace_root.as_std_path();
+        // Use the member path as key, so the crate is kept in the workspace `Cargo.toml`
+        let member_name = extension_module
+            .manifest_path
+            .parent()
+            .unwrap()
+            .strip_prefix(module_workspace_root)
+            .unwrap()
+            .to_slash()
+            .unwrap()
+            .to_string();
+        let readme = extension_module
+            .cargo_metadata
+            .root_package()
+            .and_then(|package| package.readme.clone())
+            .map(|readme| readme.into_std_path_buf());
+        known_path_deps.insert(
+            member_name,
+            PathDependency {
+                manifest_path: extension_module.manifest_path.clone(),
+                workspace_root: module_workspace_root.to_path_buf(),
+                readme,
+            },
+        );
+    }
     debug!(
         "Found path dependencies: {:?}",
         known_path_deps.keys().collect::<Vec<_>>()
@@ -412,6 +509,7 @@ fn add_cargo_package_files_to_sdist(
             &known_path_deps,
             false,
             skip_cargo_toml,
+            None,
         )
         .with_context(|| {
             format!(
@@ -467,6 +565,7 @@ fn add_cargo_package_files_to_sdist(
         &known_path_deps,
         true,
         false,
+        package_version,
     )?;
     // Handle possible relative readme field in Cargo.toml
     if let Some(readme) = main_crate.readme.as_ref() {
@@ -501,7 +600,16 @@ fn add_cargo_package_files_to_sdist(
                 pyproject_root
             };
         let relative_cargo_lock = cargo_lock_path.strip_prefix(project_root).unwrap();
-        writer.add_file(root_dir.join(relative_cargo_lock), &cargo_lock_path)?;
+        if let Some(package_version) = package_version {
+            let rewritten_cargo_lock =
+                rewrite_cargo_lock(&cargo_lock_path, main_crate, package_version)?;
+            writer.add_bytes(
+                root_dir.join(relative_cargo_lock),
+                rewritten_cargo_lock.as_bytes(),
+            )?;
+        } else {
+            writer.add_file(root_dir.join(relative_cargo_lock), &cargo_lock_path)?;
+        }
         if use_workspace_cargo_lock {
             let relative_workspace_cargo_toml = relative_cargo_lock.with_file_name("Cargo.toml");
             let mut deps_to_keep = known_path_deps.clone();
@@ -520,7 +628,8 @@ fn add_cargo_package_files_to_sdist(
                     readme: None,
                 },
             );
-            let workspace_cargo_toml = rewrite_cargo_toml(workspace_manifest_path, &deps_to_keep)?;
+            let workspace_cargo_toml =
+                rewrite_cargo_toml(workspace_manifest_path, &deps_to_keep, None)?;
             writer.add_bytes(
                 root_dir.join(relative_workspace_cargo_toml),
                 workspace_cargo_toml.as_bytes(),
@@ -539,9 +648,22 @@ fn add_cargo_package_files_to_sdist(
     let pyproject_dir = pyproject_toml_path.parent().unwrap();
     if pyproject_dir != sdist_root {
         // rewrite `tool.maturin.manifest-path` in pyproject.toml
+        let relative_extension_manifest_paths = build_context
+            .extension_modules
+            .iter()
+            .map(|extension_module| {
+                let relative_manifest_path = extension_module
+                    .manifest_path
+                    .strip_prefix(&sdist_root)
+                    .unwrap()
+                    .to_path_buf();
+                (extension_module.module_name.clone(), relative_manifest_path)
+            })
+            .collect();
         let rewritten_pyproject_toml = rewrite_pyproject_toml(
             pyproject_toml_path,
             &relative_main_crate_manifest_dir.join("Cargo.toml"),
+            &relative_extension_manifest_paths,
         )?;
         writer.add_bytes(
             root_dir.join("pyproject.toml"),
@@ -612,20 +734,35 @@ pub fn source_distribution(
             )
         })?
         .into_path_buf();
-    let metadata23 = &build_context.metadata23;
-    let mut writer = SDistWriter::new(&build_context.out, metadata23, excludes)?;
+    // The licenses of the third-party crates only apply to the compiled wheels
+    let mut metadata23 = build_context.metadata23.clone();
+    metadata23.third_party_licenses.clear();
+    let metadata23 = &metadata23;
+    let mut writer = SDistWriter::new(
+        &build_context.out,
+        metadata23,
+        excludes,
+        build_context.reproducible,
+    )?;
     let root_dir = PathBuf::from(format!(
         "{}-{}",
         &metadata23.get_distribution_escaped(),
         &metadata23.get_version_escaped()
     ));
 
+    // Building from the source distribution must not need git to determine the version
+    let package_version = match pyproject.version_source() {
+        VersionSource::Cargo => None,
+        VersionSource::Git => Some(pep440_to_semver(&metadata23.version)?),
+    };
+
     match pyproject.sdist_generator() {
         SdistGenerator::Cargo => add_cargo_package_files_to_sdist(
             build_context,
             &pyproject_toml_path,
             &mut writer,
             &root_dir,
+            package_version.as_ref(),
         )?,
         SdistGenerator::Git => {
             add_git_tracked_files_to_sdist(&pyproject_toml_path, &mut writer, &root_dir)?
@@ -648,13 +785,21 @@ pub fn source_distribution(
         if let Some(pyproject_toml::ReadMe::RelativePath(readme)) = project.readme.as_ref() {
             writer.add_file(root_dir.join(readme), pyproject_dir.join(readme))?;
         }
-        if let Some(pyproject_toml::License::Table {
-            file: Some(license),
-            text: None,
-        }) = project.license.as_ref()
-        {
-            writer.add_file(root_dir.join(license), pyproject_dir.join(license))?;
+    }
+    // License files must be at their `License-File` path, see PEP 639. Those outside of the
+    // project, e.g. in the workspace root, go to the top level just like in the wheel
+    let mut license_entries: HashMap<String, &Path> = HashMap::new();
+    for license_file in &metadata23.license_files {
+        let entry = metadata23.license_file_entry(license_file);
+        if let Some(other) = license_entries.insert(entry.clone(), license_file) {
+            bail!(
+                "The license files {} and {} would both be written to `{}` in the source distribution",
+                other.display(),
+                license_file.display(),
+                entry
+            );
         }
+        writer.add_file(root_dir.join(&entry), license_file)?;
     }
 
     let mut include = |pattern| -> Result<()> {
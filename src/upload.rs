@@ -445,6 +445,7 @@ pub fn upload(registry: &Registry, wheel_path: &Path) -> Result<(), UploadError>
     add_option("maintainer", &metadata.maintainer);
     add_option("maintainer_email", &metadata.maintainer_email);
     add_option("license", &metadata.license);
+    add_option("license_expression", &metadata.license_expression);
     add_option("keywords", &metadata.keywords);
     add_option("home_page", &metadata.home_page);
     add_option("download_url", &metadata.download_url);
@@ -469,6 +470,7 @@ pub fn upload(registry: &Registry, wheel_path: &Path) -> Result<(), UploadError>
     add_vec("obsoletes_dist", &metadata.obsoletes_dist);
     add_vec("requires_external", &metadata.requires_external);
     add_vec("project_urls", &metadata.project_urls);
+    add_vec("license_files", &metadata.license_files);
 
     let wheel = File::open(wheel_path)?;
     let wheel_name = wheel_path
@@ -0,0 +1,5 @@
+from .pyo3_mixed_bin import get_21
+
+
+def get_42() -> int:
+    return 2 * get_21()
@@ -0,0 +1,7 @@
+#!/usr/bin/env python3
+
+import pyo3_mixed_extension_modules
+
+assert pyo3_mixed_extension_modules.get_42() == 42
+
+print("SUCCESS")
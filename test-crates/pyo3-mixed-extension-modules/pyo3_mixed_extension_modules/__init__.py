@@ -0,0 +1,6 @@
+from ._core import get_21
+from ._simd import double
+
+
+def get_42() -> int:
+    return double(get_21())
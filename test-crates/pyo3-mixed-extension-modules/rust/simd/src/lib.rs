@@ -0,0 +1,15 @@
+use pyo3::prelude::*;
+
+#[cfg(feature = "double")]
+#[pyfunction]
+fn double(x: usize) -> usize {
+    x * 2
+}
+
+#[pymodule]
+fn _simd(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    #[cfg(feature = "double")]
+    m.add_function(wrap_pyfunction!(double, m)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+# Builds the small x86_64 linux ELF fixtures for the ELF rewriting and auditing tests:
+# `main` needs `libfoo.so.1` which needs `libbar.so.1`, found through its runpath.
+# `libmemfd.so.1` keeps its debug info and uses `memfd_create`, which needs glibc 2.27
+# `libabi3.so.1` imports Python symbols from different versions of the stable ABI and one outside of it
+set -euo pipefail
+cd "$(dirname "$0")"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+echo 'int bar(void) { return 42; }' > "$tmp/bar.c"
+echo 'int bar(void); int foo(void) { return bar() + 1; }' > "$tmp/foo.c"
+echo 'int foo(void); int main(void) { return foo() == 43 ? 0 : 1; }' > "$tmp/main.c"
+
+cc -shared -fPIC -s -o libbar.so.1 -Wl,-soname,libbar.so.1 "$tmp/bar.c"
+ln -sf "$PWD/libbar.so.1" "$tmp/libbar.so"
+cc -shared -fPIC -s -o libfoo.so.1 -Wl,-soname,libfoo.so.1 -Wl,--enable-new-dtags \
+  -Wl,-rpath,'$ORIGIN' "$tmp/foo.c" -L"$tmp" -lbar
+ln -sf "$PWD/libfoo.so.1" "$tmp/libfoo.so"
+cc -pie -fPIE -s -o main "$tmp/main.c" -L"$tmp" -lfoo -Wl,-rpath-link,"$PWD"
+
+printf '#define _GNU_SOURCE\n#include <sys/mman.h>\nint make_fd(void) { return memfd_create("x", 0); }\n' > "$tmp/memfd.c"
+(cd "$tmp" && cc -shared -fPIC -g -O2 -o "$OLDPWD/libmemfd.so.1" -Wl,-soname,libmemfd.so.1 memfd.c)
+
+printf 'int PyArg_ParseTuple(void);\nint PyObject_GetBuffer(void);\nint _PyObject_GetDictPtr(void);\nint call(void) { return PyArg_ParseTuple() + PyObject_GetBuffer() + _PyObject_GetDictPtr(); }\n' > "$tmp/abi3.c"
+(cd "$tmp" && cc -shared -fPIC -s -o "$OLDPWD/libabi3.so.1" abi3.c)
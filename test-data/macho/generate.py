@@ -0,0 +1,162 @@
+#!/usr/bin/env python3
+"""Generates the minimal Mach-O fixtures for the macOS wheel repair tests
+
+The files only consist of the header, the load commands and some zeroed section data,
+which is all that maturin reads or rewrites.
+"""
+
+import struct
+from pathlib import Path
+
+MH_MAGIC_64 = 0xFEEDFACF
+MH_DYLIB = 6
+CPU_TYPE_X86_64 = 0x01000007
+CPU_TYPE_ARM64 = 0x0100000C
+LC_SEGMENT_64 = 0x19
+LC_ID_DYLIB = 0xD
+LC_LOAD_DYLIB = 0xC
+LC_RPATH = 0x8000001C
+LC_CODE_SIGNATURE = 0x1D
+
+# Offset of the first section, the space between the load commands and it is the header padding
+TEXT_OFFSET = 0x400
+TEXT_SIZE = 0x10
+LINKEDIT_SIZE = 0x10
+
+
+def padded(string: str) -> bytes:
+    data = string.encode() + b"\0"
+    return data + b"\0" * (-len(data) % 8)
+
+
+def segment(name: str, fileoff: int, filesize: int, sections: list) -> bytes:
+    cmdsize = 72 + 80 * len(sections)
+    data = struct.pack(
+        "<II16sQQQQiiII",
+        LC_SEGMENT_64,
+        cmdsize,
+        name.encode(),
+        fileoff,
+        filesize,
+        fileoff,
+        filesize,
+        5,
+        5,
+        len(sections),
+        0,
+    )
+    for sectname, offset, size in sections:
+        data += struct.pack(
+            "<16s16sQQIIIIIIII",
+            sectname.encode(),
+            name.encode(),
+            offset,
+            size,
+            offset,
+            4,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        )
+    return data
+
+
+def dylib(cmd: int, name: str) -> bytes:
+    name = padded(name)
+    return struct.pack("<IIIIII", cmd, 24 + len(name), 24, 2, 0x10000, 0x10000) + name
+
+
+def rpath(path: str) -> bytes:
+    path = padded(path)
+    return struct.pack("<III", LC_RPATH, 12 + len(path), 12) + path
+
+
+def macho(cputype: int, install_id: str, deps: list, rpaths: list, signed: bool) -> bytes:
+    linkedit_offset = TEXT_OFFSET + TEXT_SIZE
+    commands = [
+        segment("__TEXT", 0, linkedit_offset, [("__text", TEXT_OFFSET, TEXT_SIZE)]),
+        segment("__LINKEDIT", linkedit_offset, LINKEDIT_SIZE, []),
+        dylib(LC_ID_DYLIB, install_id),
+    ]
+    commands += [dylib(LC_LOAD_DYLIB, dep) for dep in deps]
+    commands += [rpath(path) for path in rpaths]
+    if signed:
+        commands.append(
+            struct.pack("<IIII", LC_CODE_SIGNATURE, 16, linkedit_offset, LINKEDIT_SIZE)
+        )
+    load_commands = b"".join(commands)
+    header = struct.pack(
+        "<IiiIIIII",
+        MH_MAGIC_64,
+        cputype,
+        3,
+        MH_DYLIB,
+        len(commands),
+        len(load_commands),
+        0x85,
+        0,
+    )
+    data = header + load_commands
+    assert len(data) <= TEXT_OFFSET
+    return data + b"\0" * (linkedit_offset + LINKEDIT_SIZE - len(data))
+
+
+def fat(*arches: bytes) -> bytes:
+    align = 12
+    offset = 1 << align
+    header = struct.pack(">II", 0xCAFEBABE, len(arches))
+    body = b""
+    for arch in arches:
+        cputype, cpusubtype = struct.unpack("<ii", arch[4:12])
+        header += struct.pack(">iiIII", cputype, cpusubtype, offset, len(arch), align)
+        padding = b"\0" * (-len(arch) % (1 << align))
+        body += arch + padding
+        offset += len(arch) + len(padding)
+    return header + b"\0" * ((1 << align) - len(header)) + body
+
+
+def main():
+    root = Path(__file__).parent
+    (root / "lib").mkdir(exist_ok=True)
+    # The extension module links libfoo through its rpath
+    (root / "module.dylib").write_bytes(
+        macho(
+            CPU_TYPE_X86_64,
+            "/tmp/target/release/deps/libmodule.dylib",
+            ["/usr/lib/libSystem.B.dylib", "@rpath/libfoo.dylib"],
+            ["@loader_path/lib"],
+            False,
+        )
+    )
+    # libfoo is a universal2 library depending on libbar next to it
+    foo = [
+        macho(
+            cputype,
+            "@rpath/libfoo.dylib",
+            ["/usr/lib/libSystem.B.dylib", "@loader_path/libbar.dylib"],
+            [],
+            signed,
+        )
+        for cputype, signed in [(CPU_TYPE_X86_64, False), (CPU_TYPE_ARM64, True)]
+    ]
+    (root / "lib" / "libfoo.dylib").write_bytes(fat(*foo))
+    # libbar has an absolute install name and rpath from the machine it was built on
+    (root / "lib" / "libbar.dylib").write_bytes(
+        macho(
+            CPU_TYPE_X86_64,
+            "/usr/local/lib/libbar.dylib",
+            [
+                "/usr/lib/libSystem.B.dylib",
+                "/System/Library/Frameworks/CoreFoundation.framework/Versions/A/CoreFoundation",
+            ],
+            ["/usr/local/lib"],
+            False,
+        )
+    )
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,168 @@
+#!/usr/bin/env python3
+"""Generates the minimal PE fixtures for the Windows wheel repair tests
+
+The files only consist of the headers and a single section with the import tables,
+which is all that maturin reads or rewrites.
+"""
+
+import struct
+from pathlib import Path
+
+FILE_ALIGNMENT = 0x200
+SECTION_ALIGNMENT = 0x1000
+SECTION_RVA = 0x1000
+IMAGE_FILE_MACHINE_AMD64 = 0x8664
+IMAGE_FILE_DLL = 0x2000
+IMAGE_FILE_EXECUTABLE_IMAGE = 0x0002
+IMAGE_FILE_LARGE_ADDRESS_AWARE = 0x0020
+IMAGE_DIRECTORY_ENTRY_IMPORT = 1
+IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT = 13
+
+
+def align(value: int, alignment: int) -> int:
+    return (value + alignment - 1) // alignment * alignment
+
+
+class Section:
+    """Builds the contents of the section and hands out RVAs for the data in it"""
+
+    def __init__(self):
+        self.data = bytearray()
+
+    def add(self, data: bytes, alignment: int = 8) -> int:
+        self.data += b"\0" * (align(len(self.data), alignment) - len(self.data))
+        rva = SECTION_RVA + len(self.data)
+        self.data += data
+        return rva
+
+    def patch(self, rva: int, data: bytes):
+        offset = rva - SECTION_RVA
+        self.data[offset : offset + len(data)] = data
+
+
+def import_tables(section: Section, imports: list, delay_imports: list) -> tuple:
+    """Writes the import and delay import descriptors, each dll imports one function"""
+
+    def thunks(function: str) -> int:
+        hint_name = section.add(struct.pack("<H", 0) + function.encode() + b"\0", 2)
+        return section.add(struct.pack("<QQ", hint_name, 0))
+
+    import_dir = section.add(b"\0" * 20 * (len(imports) + 1), 4)
+    for i, (dll, function) in enumerate(imports):
+        name = section.add(dll.encode() + b"\0", 2)
+        ilt = thunks(function)
+        iat = thunks(function)
+        descriptor = struct.pack("<IIIII", ilt, 0, 0, name, iat)
+        section.patch(import_dir + 20 * i, descriptor)
+
+    delay_dir = 0
+    if delay_imports:
+        delay_dir = section.add(b"\0" * 32 * (len(delay_imports) + 1), 4)
+        for i, (dll, function) in enumerate(delay_imports):
+            name = section.add(dll.encode() + b"\0", 2)
+            handle = section.add(b"\0" * 8)
+            int_ = thunks(function)
+            iat = thunks(function)
+            # Attributes 1 means the fields are RVAs
+            descriptor = struct.pack("<IIIIIIII", 1, name, handle, iat, int_, 0, 0, 0)
+            section.patch(delay_dir + 32 * i, descriptor)
+    return (
+        (import_dir, 20 * (len(imports) + 1)),
+        (delay_dir, 32 * (len(delay_imports) + 1) if delay_imports else 0),
+    )
+
+
+def pe(imports: list, delay_imports: list = (), dll: bool = True) -> bytes:
+    section = Section()
+    (import_rva, import_size), (delay_rva, delay_size) = import_tables(
+        section, imports, list(delay_imports)
+    )
+    raw_size = align(len(section.data), FILE_ALIGNMENT)
+    virtual_size = len(section.data)
+
+    dos_header = b"MZ" + b"\0" * 58 + struct.pack("<I", 0x40)
+    characteristics = IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE
+    if dll:
+        characteristics |= IMAGE_FILE_DLL
+    coff_header = struct.pack(
+        "<HHIIIHH", IMAGE_FILE_MACHINE_AMD64, 1, 0, 0, 0, 240, characteristics
+    )
+    data_directories = [(0, 0)] * 16
+    data_directories[IMAGE_DIRECTORY_ENTRY_IMPORT] = (import_rva, import_size)
+    data_directories[IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT] = (delay_rva, delay_size)
+    size_of_image = SECTION_RVA + align(virtual_size, SECTION_ALIGNMENT)
+    optional_header = struct.pack(
+        "<HBBIIIIIQIIHHHHHHIIIIHHQQQQII",
+        0x20B,  # PE32+
+        14,
+        0,
+        0,  # SizeOfCode
+        raw_size,  # SizeOfInitializedData
+        0,
+        0,  # AddressOfEntryPoint
+        0,  # BaseOfCode
+        0x180000000,  # ImageBase
+        SECTION_ALIGNMENT,
+        FILE_ALIGNMENT,
+        6,
+        0,
+        0,
+        0,
+        6,
+        0,
+        0,
+        size_of_image,
+        FILE_ALIGNMENT,  # SizeOfHeaders
+        0,  # CheckSum
+        3,  # IMAGE_SUBSYSTEM_WINDOWS_CUI
+        0x160,
+        0x100000,
+        0x1000,
+        0x100000,
+        0x1000,
+        0,
+        16,
+    ) + b"".join(struct.pack("<II", rva, size) for rva, size in data_directories)
+    section_header = struct.pack(
+        "<8sIIIIIIHHI",
+        b".idata",
+        virtual_size,
+        SECTION_RVA,
+        raw_size,
+        FILE_ALIGNMENT,
+        0,
+        0,
+        0,
+        0,
+        0xC0000040,  # initialized data, read, write
+    )
+    headers = dos_header + b"PE\0\0" + coff_header + optional_header + section_header
+    assert len(headers) <= FILE_ALIGNMENT
+    headers += b"\0" * (FILE_ALIGNMENT - len(headers))
+    return headers + bytes(section.data) + b"\0" * (raw_size - len(section.data))
+
+
+def main():
+    root = Path(__file__).parent
+    (root / "lib").mkdir(exist_ok=True)
+    # The extension module links the system, python and the crt, which are all skipped, and libfoo
+    (root / "module.pyd").write_bytes(
+        pe(
+            [
+                ("KERNEL32.dll", "GetLastError"),
+                ("python3.dll", "PyModule_Create2"),
+                ("api-ms-win-crt-runtime-l1-1-0.dll", "_initterm"),
+                ("VCRUNTIME140.dll", "memcpy"),
+                ("libfoo.dll", "foo"),
+            ]
+        )
+    )
+    # libfoo delay loads libbar, which is next to it
+    (root / "lib" / "libfoo.dll").write_bytes(
+        pe([("KERNEL32.dll", "GetLastError")], [("LIBBAR.dll", "bar")])
+    )
+    (root / "lib" / "libbar.dll").write_bytes(pe([("KERNEL32.dll", "GetLastError")]))
+
+
+if __name__ == "__main__":
+    main()
@@ -131,6 +131,33 @@ pub fn invalid_manylinux_does_not_panic() -> Result<()> {
     Ok(())
 }
 
+/// Custom platform tags only exist when an audit policy file defines them
+pub fn undefined_custom_platform_tag() -> Result<()> {
+    // The first argument is ignored by clap
+    let cli = vec![
+        "build",
+        "-m",
+        "test-crates/hello-world/Cargo.toml",
+        "--compatibility",
+        "acme_el8",
+        "--target-dir",
+        "test-crates/targets/undefined_custom_platform_tag",
+        "--out",
+        "test-crates/targets/undefined_custom_platform_tag",
+    ];
+    let options: BuildOptions = BuildOptions::try_parse_from(cli)?;
+    let Err(err) = options.into_build_context(false, cfg!(feature = "faster-tests"), false) else {
+        bail!("Should have errored");
+    };
+    assert_eq!(
+        err.to_string(),
+        "Invalid platform tag acme_el8: unknown platform tag, \
+        custom platform tags must be defined in an audit policy file"
+    );
+
+    Ok(())
+}
+
 /// The user set `python-source` in pyproject.toml, but there is no python module in there
 pub fn warn_on_missing_python_source() -> Result<()> {
     let output = Command::new(env!("CARGO_BIN_EXE_maturin"))
@@ -88,6 +88,75 @@ pub fn test_musl() -> Result<bool> {
     Ok(true)
 }
 
+/// Builds pyo3-mixed for two interpreters at the same time with `--parallel-interpreters`,
+/// given that at least two python versions are installed
+///
+/// The bool in the Ok() response says whether the test was actually run
+pub fn test_parallel_interpreters() -> Result<bool> {
+    // The versions supported by the pyo3 version of pyo3-mixed
+    let interpreters: Vec<String> = (7..=12)
+        .rev()
+        .map(|minor| format!("python3.{minor}"))
+        .filter(|python| which::which(python).is_ok())
+        .take(2)
+        .collect();
+    if interpreters.len() < 2 {
+        return Ok(false);
+    }
+
+    // The first arg gets ignored
+    let mut cli = vec![
+        "build",
+        "--manifest-path",
+        "test-crates/pyo3-mixed/Cargo.toml",
+        "--parallel-interpreters",
+        "2",
+        "--compatibility",
+        "linux",
+        "--quiet",
+        "--target-dir",
+        "test-crates/targets/test_parallel_interpreters",
+        "--out",
+        "test-crates/wheels/test_parallel_interpreters",
+        "--interpreter",
+    ];
+    cli.extend(interpreters.iter().map(String::as_str));
+    let options: BuildOptions = BuildOptions::try_parse_from(&cli)?;
+    let build_context = options.into_build_context(false, cfg!(feature = "faster-tests"), false)?;
+    let expected_tags: Vec<String> = build_context
+        .interpreter
+        .iter()
+        .map(|interpreter| format!("cp{}{}", interpreter.major, interpreter.minor))
+        .collect();
+    assert_eq!(expected_tags.len(), 2);
+
+    // The wheels come back in the order of the interpreters, not in the order they finished
+    let wheels = build_context.build_wheels()?;
+    let tags: Vec<&str> = wheels.iter().map(|(_, tag)| tag.as_str()).collect();
+    assert_eq!(tags, expected_tags);
+    for ((wheel_path, _), tag) in wheels.iter().zip(&expected_tags) {
+        let filename = wheel_path.file_name().unwrap().to_str().unwrap();
+        assert!(
+            filename.starts_with(&format!("pyo3_mixed-2.1.5-{tag}-{tag}-")),
+            "unexpected wheel {filename}"
+        );
+        ZipArchive::new(File::open(wheel_path)?)?;
+    }
+
+    // A failing interpreter fails the whole build, here pyo3 rejects the python version
+    let mut build_context = build_context;
+    build_context.interpreter[1].config.minor = 5;
+    let err = build_context
+        .build_wheels()
+        .expect_err("Building for a missing interpreter should fail");
+    assert!(
+        format!("{err:?}").contains("Cargo build finished with"),
+        "unexpected error: {err:?}"
+    );
+
+    Ok(true)
+}
+
 /// Test that we ignore non-existent Cargo.lock file listed by `cargo package --list`,
 /// which seems to only occur with workspaces.
 /// See https://github.com/rust-lang/cargo/issues/7938#issuecomment-593280660 and
@@ -198,7 +267,7 @@ fn build_wheel_files(package: impl AsRef<Path>, unique_name: &str) -> Result<Zip
             target_dir: Some(PathBuf::from(format!("test-crates/targets/{unique_name}"))),
             ..Default::default()
         },
-        platform_tag: vec![PlatformTag::Linux],
+        platform_tag: vec![PlatformTag::Linux.to_string()],
         ..Default::default()
     };
 
@@ -250,6 +319,40 @@ pub fn check_wheel_files(
     Ok(())
 }
 
+/// Checks that a wheel built from a library and a `bin` target contains both the native
+/// extension module, e.g. `foo/foo.abi3.so`, and the executable in `.data/scripts`
+pub fn check_wheel_with_extension_and_bin(
+    package: impl AsRef<Path>,
+    extension_module: &str,
+    script: &str,
+    unique_name: &str,
+) -> Result<()> {
+    let mut wheel = build_wheel_files(package, unique_name)?;
+    let file_names: Vec<String> = wheel.file_names().map(ToString::to_string).collect();
+    let is_extension_module = |file: &&String| {
+        file.starts_with(&format!("{extension_module}."))
+            && matches!(Path::new(file).extension(), Some(ext) if ext == "so" || ext == "pyd")
+    };
+    assert!(
+        file_names.iter().any(|file| is_extension_module(&file)),
+        "Missing the extension module {extension_module} in {file_names:?}"
+    );
+    let script = if cfg!(windows) {
+        format!("{script}.exe")
+    } else {
+        script.to_string()
+    };
+    let script_path = file_names
+        .iter()
+        .find(|file| file.ends_with(&format!(".data/scripts/{script}")))
+        .with_context(|| format!("Missing the script {script} in {file_names:?}"))?;
+    let mode = wheel.by_name(script_path)?.unix_mode().unwrap_or_default();
+    if cfg!(unix) {
+        assert_eq!(mode & 0o111, 0o111, "{script_path} isn't executable");
+    }
+    Ok(())
+}
+
 pub fn abi3_python_interpreter_args() -> Result<()> {
     // Case 1: maturin build without `-i`, should work
     let options = BuildOptions::try_parse_from(vec![
@@ -249,6 +249,17 @@ fn integration_pyo3_mixed_submodule() {
     ));
 }
 
+#[test]
+fn integration_pyo3_mixed_extension_modules() {
+    handle_result(integration::test_integration(
+        "test-crates/pyo3-mixed-extension-modules/rust/core",
+        None,
+        "integration-pyo3-mixed-extension-modules",
+        false,
+        None,
+    ));
+}
+
 #[test]
 fn integration_pyo3_mixed_with_path_dep() {
     handle_result(integration::test_integration(
@@ -490,6 +501,11 @@ fn invalid_manylinux_does_not_panic() {
     handle_result(errors::invalid_manylinux_does_not_panic())
 }
 
+#[test]
+fn undefined_custom_platform_tag() {
+    handle_result(errors::undefined_custom_platform_tag())
+}
+
 #[test]
 fn warn_on_missing_python_source() {
     handle_result(errors::warn_on_missing_python_source())
@@ -504,6 +520,14 @@ fn musl() {
     }
 }
 
+#[test]
+fn parallel_interpreters() {
+    let ran = handle_result(other::test_parallel_interpreters());
+    if !ran {
+        eprintln!("⚠️  Warning: less than two python versions installed, test didn't run");
+    }
+}
+
 #[test]
 fn workspace_cargo_lock() {
     handle_result(other::test_workspace_cargo_lock())
@@ -747,6 +771,46 @@ fn pyo3_mixed_include_exclude_wheel_files() {
     ))
 }
 
+#[test]
+fn pyo3_mixed_bin_wheel_files() {
+    handle_result(other::check_wheel_with_extension_and_bin(
+        "test-crates/pyo3-mixed-bin",
+        "pyo3_mixed_bin/pyo3_mixed_bin",
+        "pyo3-mixed-bin-cli",
+        "wheel-files-pyo3-mixed-bin",
+    ))
+}
+
+#[test]
+fn pyo3_mixed_extension_modules_sdist() {
+    handle_result(other::test_source_distribution(
+        "test-crates/pyo3-mixed-extension-modules/rust/core",
+        SdistGenerator::Cargo,
+        expect![[r#"
+            {
+                "pyo3_mixed_extension_modules-2.1.3/PKG-INFO",
+                "pyo3_mixed_extension_modules-2.1.3/pyo3_mixed_extension_modules/__init__.py",
+                "pyo3_mixed_extension_modules-2.1.3/pyproject.toml",
+                "pyo3_mixed_extension_modules-2.1.3/rust/Cargo.lock",
+                "pyo3_mixed_extension_modules-2.1.3/rust/Cargo.toml",
+                "pyo3_mixed_extension_modules-2.1.3/rust/core/Cargo.toml",
+                "pyo3_mixed_extension_modules-2.1.3/rust/core/src/lib.rs",
+                "pyo3_mixed_extension_modules-2.1.3/rust/simd/Cargo.toml",
+                "pyo3_mixed_extension_modules-2.1.3/rust/simd/src/lib.rs",
+            }
+        "#]],
+        Some((
+            Path::new("pyo3_mixed_extension_modules-2.1.3/rust/Cargo.toml"),
+            expect![[r#"
+                [workspace]
+                resolver = "2"
+                members = ["core", "simd"]
+            "#]],
+        )),
+        "sdist-pyo3-mixed-extension-modules",
+    ))
+}
+
 #[test]
 fn workspace_sdist() {
     handle_result(other::test_source_distribution(
@@ -767,6 +831,35 @@ fn workspace_sdist() {
     ))
 }
 
+#[test]
+fn workspace_license_file_sdist() {
+    handle_result(other::test_source_distribution(
+        "test-crates/workspace-license-file/py",
+        SdistGenerator::Cargo,
+        expect![[r#"
+            {
+                "py-0.1.0/Cargo.lock",
+                "py-0.1.0/Cargo.toml",
+                "py-0.1.0/LICENSE",
+                "py-0.1.0/PKG-INFO",
+                "py-0.1.0/py/Cargo.toml",
+                "py-0.1.0/py/src/main.rs",
+                "py-0.1.0/pyproject.toml",
+            }
+        "#]],
+        Some((
+            Path::new("py-0.1.0/PKG-INFO"),
+            expect![[r#"
+            Metadata-Version: 2.4
+            Name: py
+            Version: 0.1.0
+            License-File: LICENSE
+        "#]],
+        )),
+        "sdist-workspace-license-file",
+    ))
+}
+
 #[test]
 fn workspace_with_path_dep_sdist() {
     handle_result(other::test_source_distribution(